@@ -0,0 +1,16 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Ship protoc with the build so contributors don't need it installed
+    if std::env::var_os("PROTOC").is_none() {
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?) };
+    }
+    let well_known = protoc_bin_vendored::include_path()?;
+
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(
+            &["proto/lightning.proto"],
+            &[std::path::Path::new("proto"), &well_known],
+        )?;
+    Ok(())
+}
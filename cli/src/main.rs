@@ -27,6 +27,48 @@ struct PaymentChannel {
     is_open: bool,
     created_at: String,
     multisig_address: String,
+    #[serde(default)]
+    commitment_type: String,
+    #[serde(default)]
+    commitment_fee: u64,
+    limits: Option<ChannelLimits>,
+    #[serde(default)]
+    sync_error: Option<String>,
+    #[serde(default)]
+    peer_online: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChannelLimits {
+    channel_reserve: u64,
+    dust_limit: u64,
+    max_in_flight: u64,
+    max_accepted_htlcs: u16,
+    htlc_minimum: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitmentTransaction {
+    sequence: u64,
+    my_balance: u64,
+    peer_balance: u64,
+    fee: u64,
+    created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelDetail {
+    #[serde(flatten)]
+    channel: PaymentChannel,
+    commitments: Vec<CommitmentTransaction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelStats {
+    payment_count: u64,
+    volume_out: u64,
+    volume_in: u64,
+    last_activity: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,15 +82,170 @@ struct PaymentRecord {
     is_offline: bool,
 }
 
+#[derive(Debug, Deserialize)]
+struct OutboxEntry {
+    channel_id: String,
+    sequence: u64,
+    kind: String,
+    payment_id: Option<String>,
+    created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentPage {
+    payments: Vec<PaymentRecord>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct PaymentQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    direction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_offline: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u32>,
+}
+
 #[derive(Debug, Serialize)]
 struct OpenChannelRequest {
     peer_node_id: String,
     capacity: u64,
+    commitment_type: String,
 }
 
 #[derive(Debug, Serialize)]
 struct SendPaymentRequest {
     amount: u64,
+    invoice_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateInvoiceRequest {
+    amount: u64,
+    memo: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Invoice {
+    id: String,
+    amount: u64,
+    memo: String,
+    created_at: String,
+    settled_at: Option<String>,
+    payment_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChannelBackup {
+    backup: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeSeed {
+    seed: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportedBundle {
+    file: String, // hex
+    chunks: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportBundleRequest {
+    file: Option<String>,
+    chunks: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportBundleResponse {
+    channel_id: String,
+    updates: usize,
+    reply: Option<ExportedBundle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeerInfo {
+    peer_id: String,
+    node_id: Option<String>,
+    addresses: Vec<String>,
+    connected_since: String,
+    latency_ms: f64,
+    #[serde(default)]
+    protocol_version: Option<u16>,
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeRecord {
+    node_id: String,
+    peer_id: String,
+    addresses: Vec<String>,
+    published_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeerBan {
+    peer_id: String,
+    reason: String,
+    ban_count: u32,
+    banned_at: String,
+    expires_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BanPeerRequest {
+    reason: Option<String>,
+    duration_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct MintTokenRequest {
+    scope: String,
+    expires_in_secs: Option<i64>,
+    max_amount: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiToken {
+    id: String,
+    scope: String,
+    caveats: Vec<String>,
+    created_at: String,
+    revoked_at: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+/// Builds an error from a failed response, using the node's JSON error body when present.
+async fn api_error(action: &str, response: reqwest::Response) -> Box<dyn std::error::Error> {
+    let status = response.status();
+    match response.json::<ErrorResponse>().await {
+        Ok(error) => format!("Failed to {}: {} ({})", action, error.message, error.code).into(),
+        Err(_) => format!("Failed to {}: {}", action, status).into(),
+    }
 }
 
 struct LightningCli {
@@ -57,36 +254,98 @@ struct LightningCli {
 }
 
 impl LightningCli {
-    fn new(base_url: String) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            base_url,
+    fn new(
+        base_url: String,
+        token: Option<String>,
+        pinned_cert: Option<Vec<u8>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value =
+                reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token.trim()))?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+
+        let mut builder = reqwest::Client::builder().default_headers(headers);
+        if let Some(pem) = pinned_cert {
+            // Trust only the node's certificate, not the system roots
+            builder = builder
+                .add_root_certificate(reqwest::Certificate::from_pem(&pem)?)
+                .tls_built_in_root_certs(false);
         }
+
+        Ok(Self {
+            client: builder.build()?,
+            base_url,
+        })
     }
 
     async fn get_node_info(&self) -> Result<NodeInfo, Box<dyn std::error::Error>> {
         let url = format!("{}/api/node/info", self.base_url);
         let response = self.client.get(&url).send().await?;
-        let node_info: NodeInfo = response.json().await?;
-        Ok(node_info)
+
+        if response.status().is_success() {
+            let node_info: NodeInfo = response.json().await?;
+            Ok(node_info)
+        } else {
+            Err(api_error("get node info", response).await)
+        }
     }
 
     async fn list_channels(&self) -> Result<Vec<PaymentChannel>, Box<dyn std::error::Error>> {
         let url = format!("{}/api/channels", self.base_url);
         let response = self.client.get(&url).send().await?;
-        let channels: Vec<PaymentChannel> = response.json().await?;
-        Ok(channels)
+
+        if response.status().is_success() {
+            let channels: Vec<PaymentChannel> = response.json().await?;
+            Ok(channels)
+        } else {
+            Err(api_error("list channels", response).await)
+        }
+    }
+
+    async fn get_channel(
+        &self,
+        channel_id: &str,
+    ) -> Result<ChannelDetail, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/channels/{}", self.base_url, channel_id);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let detail: ChannelDetail = response.json().await?;
+            Ok(detail)
+        } else {
+            Err(api_error("get channel", response).await)
+        }
+    }
+
+    async fn get_channel_stats(
+        &self,
+        channel_id: &str,
+    ) -> Result<ChannelStats, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/channels/{}/stats", self.base_url, channel_id);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let stats: ChannelStats = response.json().await?;
+            Ok(stats)
+        } else {
+            Err(api_error("get channel stats", response).await)
+        }
     }
 
     async fn open_channel(
         &self,
         peer_node_id: String,
         capacity: u64,
+        commitment_type: String,
     ) -> Result<PaymentChannel, Box<dyn std::error::Error>> {
         let url = format!("{}/api/channels", self.base_url);
         let request = OpenChannelRequest {
             peer_node_id,
             capacity,
+            commitment_type,
         };
 
         let response = self.client.post(&url).json(&request).send().await?;
@@ -95,7 +354,7 @@ impl LightningCli {
             let channel: PaymentChannel = response.json().await?;
             Ok(channel)
         } else {
-            Err(format!("Failed to open channel: {}", response.status()).into())
+            Err(api_error("open channel", response).await)
         }
     }
 
@@ -103,9 +362,10 @@ impl LightningCli {
         &self,
         channel_id: String,
         amount: u64,
+        invoice_id: Option<String>,
     ) -> Result<PaymentRecord, Box<dyn std::error::Error>> {
         let url = format!("{}/api/channels/{}/payments", self.base_url, channel_id);
-        let request = SendPaymentRequest { amount };
+        let request = SendPaymentRequest { amount, invoice_id };
 
         let response = self.client.post(&url).json(&request).send().await?;
 
@@ -113,18 +373,42 @@ impl LightningCli {
             let payment: PaymentRecord = response.json().await?;
             Ok(payment)
         } else {
-            Err(format!("Failed to send payment: {}", response.status()).into())
+            Err(api_error("send payment", response).await)
         }
     }
 
     async fn list_payments(
         &self,
-        channel_id: String,
-    ) -> Result<Vec<PaymentRecord>, Box<dyn std::error::Error>> {
-        let url = format!("{}/api/channels/{}/payments", self.base_url, channel_id);
-        let response = self.client.get(&url).send().await?;
-        let payments: Vec<PaymentRecord> = response.json().await?;
-        Ok(payments)
+        query: &PaymentQuery,
+    ) -> Result<PaymentPage, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/payments", self.base_url);
+        let response = self.client.get(&url).query(query).send().await?;
+
+        if response.status().is_success() {
+            let page: PaymentPage = response.json().await?;
+            Ok(page)
+        } else {
+            Err(api_error("list payments", response).await)
+        }
+    }
+
+    async fn get_outbox(
+        &self,
+        channel_id: Option<String>,
+    ) -> Result<Vec<OutboxEntry>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/outbox", self.base_url);
+        let mut request = self.client.get(&url);
+        if let Some(channel_id) = channel_id {
+            request = request.query(&[("channel_id", channel_id)]);
+        }
+        let response = request.send().await?;
+
+        if response.status().is_success() {
+            let entries: Vec<OutboxEntry> = response.json().await?;
+            Ok(entries)
+        } else {
+            Err(api_error("list outbox", response).await)
+        }
     }
 
     async fn close_channel(&self, channel_id: String) -> Result<(), Box<dyn std::error::Error>> {
@@ -134,7 +418,243 @@ impl LightningCli {
         if response.status().is_success() {
             Ok(())
         } else {
-            Err(format!("Failed to close channel: {}", response.status()).into())
+            Err(api_error("close channel", response).await)
+        }
+    }
+
+    async fn export_updates(
+        &self,
+        channel_id: String,
+    ) -> Result<ExportedBundle, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/channels/{}/export", self.base_url, channel_id);
+        let response = self.client.post(&url).send().await?;
+
+        if response.status().is_success() {
+            let bundle: ExportedBundle = response.json().await?;
+            Ok(bundle)
+        } else {
+            Err(api_error("export updates", response).await)
+        }
+    }
+
+    async fn import_updates(
+        &self,
+        request: &ImportBundleRequest,
+    ) -> Result<ImportBundleResponse, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/import", self.base_url);
+        let response = self.client.post(&url).json(request).send().await?;
+
+        if response.status().is_success() {
+            let imported: ImportBundleResponse = response.json().await?;
+            Ok(imported)
+        } else {
+            Err(api_error("import updates", response).await)
+        }
+    }
+
+    async fn export_backup(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/backup", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let backup: ChannelBackup = response.json().await?;
+            Ok(hex::decode(backup.backup)?)
+        } else {
+            Err(api_error("export backup", response).await)
+        }
+    }
+
+    async fn export_seed(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/backup/seed", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let seed: NodeSeed = response.json().await?;
+            Ok(seed.seed)
+        } else {
+            Err(api_error("export seed", response).await)
+        }
+    }
+
+    async fn restore_backup(
+        &self,
+        data: &[u8],
+    ) -> Result<Vec<PaymentChannel>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/backup/restore", self.base_url);
+        let request = ChannelBackup {
+            backup: hex::encode(data),
+        };
+
+        let response = self.client.post(&url).json(&request).send().await?;
+
+        if response.status().is_success() {
+            let channels: Vec<PaymentChannel> = response.json().await?;
+            Ok(channels)
+        } else {
+            Err(api_error("restore backup", response).await)
+        }
+    }
+
+    async fn list_tokens(&self) -> Result<Vec<ApiToken>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/tokens", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let tokens: Vec<ApiToken> = response.json().await?;
+            Ok(tokens)
+        } else {
+            Err(api_error("list tokens", response).await)
+        }
+    }
+
+    async fn mint_token(
+        &self,
+        request: &MintTokenRequest,
+    ) -> Result<ApiToken, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/tokens", self.base_url);
+        let response = self.client.post(&url).json(request).send().await?;
+
+        if response.status().is_success() {
+            let token: ApiToken = response.json().await?;
+            Ok(token)
+        } else {
+            Err(api_error("mint token", response).await)
+        }
+    }
+
+    async fn create_invoice(
+        &self,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<Invoice, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/invoices", self.base_url);
+        let request = CreateInvoiceRequest { amount, memo };
+
+        let response = self.client.post(&url).json(&request).send().await?;
+
+        if response.status().is_success() {
+            let invoice: Invoice = response.json().await?;
+            Ok(invoice)
+        } else {
+            Err(api_error("create invoice", response).await)
+        }
+    }
+
+    async fn list_invoices(&self) -> Result<Vec<Invoice>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/invoices", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let invoices: Vec<Invoice> = response.json().await?;
+            Ok(invoices)
+        } else {
+            Err(api_error("list invoices", response).await)
+        }
+    }
+
+    async fn list_peers(&self) -> Result<Vec<PeerInfo>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let peers: Vec<PeerInfo> = response.json().await?;
+            Ok(peers)
+        } else {
+            Err(api_error("list peers", response).await)
+        }
+    }
+
+    async fn connect_peer(
+        &self,
+        address: Option<String>,
+        node_id: Option<String>,
+    ) -> Result<PeerInfo, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&serde_json::json!({ "address": address, "node_id": node_id }))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let peer: PeerInfo = response.json().await?;
+            Ok(peer)
+        } else {
+            Err(api_error("connect peer", response).await)
+        }
+    }
+
+    async fn disconnect_peer(&self, peer_id: String) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers/{}", self.base_url, peer_id);
+        let response = self.client.delete(&url).send().await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(api_error("disconnect peer", response).await)
+        }
+    }
+
+    async fn lookup_node(&self, node_id: &str) -> Result<NodeRecord, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers/lookup/{}", self.base_url, node_id);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let record: NodeRecord = response.json().await?;
+            Ok(record)
+        } else {
+            Err(api_error("look up node", response).await)
+        }
+    }
+
+    async fn list_bans(&self) -> Result<Vec<PeerBan>, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers/bans", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            let bans: Vec<PeerBan> = response.json().await?;
+            Ok(bans)
+        } else {
+            Err(api_error("list bans", response).await)
+        }
+    }
+
+    async fn ban_peer(
+        &self,
+        peer_id: String,
+        request: &BanPeerRequest,
+    ) -> Result<PeerBan, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers/{}/ban", self.base_url, peer_id);
+        let response = self.client.post(&url).json(request).send().await?;
+
+        if response.status().is_success() {
+            let ban: PeerBan = response.json().await?;
+            Ok(ban)
+        } else {
+            Err(api_error("ban peer", response).await)
+        }
+    }
+
+    async fn unban_peer(&self, peer_id: String) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/api/peers/{}/ban", self.base_url, peer_id);
+        let response = self.client.delete(&url).send().await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(api_error("unban peer", response).await)
+        }
+    }
+
+    async fn revoke_token(&self, token_id: String) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/api/tokens/{}", self.base_url, token_id);
+        let response = self.client.delete(&url).send().await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(api_error("revoke token", response).await)
         }
     }
 }
@@ -160,6 +680,19 @@ async fn main() -> anyhow::Result<()> {
                 .help("Lightning node server URL")
                 .default_value("http://localhost:3000"),
         )
+        .arg(
+            Arg::new("token_file")
+                .long("token-file")
+                .value_name("FILE")
+                .help("API token file written by the node")
+                .default_value("./data/lightning.token"),
+        )
+        .arg(
+            Arg::new("tls_cert")
+                .long("tls-cert")
+                .value_name("FILE")
+                .help("Pin the node's TLS certificate (e.g. ./data/lightning.cert) for https:// servers"),
+        )
         .subcommand(Command::new("info").about("Display node information"))
         .subcommand(
             Command::new("channels")
@@ -169,63 +702,385 @@ async fn main() -> anyhow::Result<()> {
                     Command::new("open")
                         .about("Open a new payment channel")
                         .arg(
-                            Arg::new("peer")
-                                .long("peer")
+                            Arg::new("peer")
+                                .long("peer")
+                                .value_name("NODE_ID")
+                                .help("Peer node ID")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("capacity")
+                                .long("capacity")
+                                .value_name("BTC")
+                                .help("Channel capacity in BTC")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("anchors")
+                                .long("anchors")
+                                .help("Use anchor outputs so a force-close can be fee-bumped")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show a channel with its commitment history and statistics")
+                        .arg(
+                            Arg::new("channel_id")
+                                .long("channel-id")
+                                .value_name("ID")
+                                .help("Channel ID to show")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("close").about("Close a payment channel").arg(
+                        Arg::new("channel_id")
+                            .long("channel-id")
+                            .value_name("ID")
+                            .help("Channel ID to close")
+                            .required(true),
+                    ),
+                ),
+        )
+        .subcommand(
+            Command::new("pay")
+                .about("Send a Lightning payment")
+                .arg(
+                    Arg::new("channel_id")
+                        .long("channel-id")
+                        .value_name("ID")
+                        .help("Channel ID to send payment through")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .long("amount")
+                        .value_name("BTC")
+                        .help("Payment amount in BTC")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("invoice")
+                        .long("invoice")
+                        .value_name("ID")
+                        .help("Invoice ID to settle at the peer"),
+                ),
+        )
+        .subcommand(
+            Command::new("payments")
+                .about("List payment history, newest first")
+                .arg(
+                    Arg::new("channel_id")
+                        .long("channel-id")
+                        .value_name("ID")
+                        .help("Only payments on this channel"),
+                )
+                .arg(
+                    Arg::new("direction")
+                        .long("direction")
+                        .value_name("DIRECTION")
+                        .help("incoming or outgoing"),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .value_name("RFC3339")
+                        .help("Only payments at or after this time (e.g. 2024-01-01T00:00:00Z)"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .value_name("RFC3339")
+                        .help("Only payments before this time"),
+                )
+                .arg(
+                    Arg::new("min_amount")
+                        .long("min-amount")
+                        .value_name("BTC")
+                        .help("Smallest amount to include"),
+                )
+                .arg(
+                    Arg::new("max_amount")
+                        .long("max-amount")
+                        .value_name("BTC")
+                        .help("Largest amount to include"),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .help("Only payments the peer has not acknowledged yet")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("online"),
+                )
+                .arg(
+                    Arg::new("online")
+                        .long("online")
+                        .help("Only payments the peer has acknowledged")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("oldest_first")
+                        .long("oldest-first")
+                        .help("Sort oldest first")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_name("N")
+                        .help("Payments per page (default 50, max 500)"),
+                )
+                .arg(
+                    Arg::new("cursor")
+                        .long("cursor")
+                        .value_name("CURSOR")
+                        .help("Continue from a previous page"),
+                ),
+        )
+        .subcommand(
+            Command::new("outbox")
+                .about("List channel updates the peer has not acknowledged yet")
+                .arg(
+                    Arg::new("channel_id")
+                        .long("channel-id")
+                        .value_name("ID")
+                        .help("Only updates on this channel"),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export unacknowledged channel updates for a peer that can't be reached")
+                .arg(
+                    Arg::new("channel_id")
+                        .long("channel-id")
+                        .value_name("ID")
+                        .help("Channel to export")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Bundle file to write")
+                        .default_value("updates.bundle"),
+                )
+                .arg(
+                    Arg::new("qr")
+                        .long("qr")
+                        .help("Print the bundle as QR-sized text chunks instead")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import a peer's update bundle and write the reply to carry back")
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .value_name("FILE")
+                        .help("Bundle file to import")
+                        .conflicts_with("chunk"),
+                )
+                .arg(
+                    Arg::new("chunk")
+                        .long("chunk")
+                        .value_name("TEXT")
+                        .help("A scanned LNO:... chunk; repeat for every chunk of the bundle")
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("reply")
+                        .long("reply")
+                        .value_name("FILE")
+                        .help("Where to write the reply bundle")
+                        .default_value("reply.bundle"),
+                ),
+        )
+        .subcommand(
+            Command::new("invoices")
+                .about("Create or list invoices")
+                .subcommand(Command::new("list").about("List invoices"))
+                .subcommand(
+                    Command::new("create")
+                        .about("Create an invoice for a peer to pay")
+                        .arg(
+                            Arg::new("amount")
+                                .long("amount")
+                                .value_name("BTC")
+                                .help("Invoice amount in BTC")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("memo")
+                                .long("memo")
+                                .value_name("TEXT")
+                                .help("Description shown to the payer"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("backup")
+                .about("Export or restore the static channel backup")
+                .subcommand(
+                    Command::new("export").about("Write the encrypted channel backup to a file").arg(
+                        Arg::new("output")
+                            .long("output")
+                            .value_name("FILE")
+                            .help("Backup file to write")
+                            .default_value("channel.backup"),
+                    ),
+                )
+                .subcommand(
+                    Command::new("seed")
+                        .about("Print the node seed; set it as NODE_SEED before restoring"),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Restore channels from a backup and ask peers to force-close")
+                        .arg(
+                            Arg::new("file")
+                                .long("file")
+                                .value_name("FILE")
+                                .help("Backup file to restore")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("peers")
+                .about("Manage P2P peer connections")
+                .subcommand(Command::new("list").about("List connected peers"))
+                .subcommand(
+                    Command::new("connect")
+                        .about("Dial a peer by address, or by node ID through the DHT")
+                        .arg(
+                            Arg::new("address")
+                                .long("address")
+                                .value_name("MULTIADDR")
+                                .help(
+                                    "Peer address, e.g. /ip4/192.168.1.20/tcp/4001/p2p/12D3KooW...",
+                                )
+                                .required_unless_present("node_id")
+                                .conflicts_with("node_id"),
+                        )
+                        .arg(
+                            Arg::new("node_id")
+                                .long("node-id")
+                                .value_name("NODE_ID")
+                                .help("Lightning node ID to look up in the DHT"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("lookup")
+                        .about("Find a node's addresses in the DHT")
+                        .arg(
+                            Arg::new("node_id")
+                                .long("node-id")
                                 .value_name("NODE_ID")
-                                .help("Peer node ID")
+                                .help("Lightning node ID")
                                 .required(true),
-                        )
+                        ),
+                )
+                .subcommand(
+                    Command::new("disconnect").about("Close all connections to a peer").arg(
+                        Arg::new("peer_id")
+                            .long("peer-id")
+                            .value_name("PEER_ID")
+                            .help("libp2p peer ID")
+                            .required(true),
+                    ),
+                )
+                .subcommand(Command::new("bans").about("List banned peers"))
+                .subcommand(
+                    Command::new("ban")
+                        .about("Disconnect a peer and refuse it from now on")
                         .arg(
-                            Arg::new("capacity")
-                                .long("capacity")
-                                .value_name("BTC")
-                                .help("Channel capacity in BTC")
+                            Arg::new("peer_id")
+                                .long("peer-id")
+                                .value_name("PEER_ID")
+                                .help("libp2p peer ID")
                                 .required(true),
+                        )
+                        .arg(
+                            Arg::new("reason")
+                                .long("reason")
+                                .value_name("TEXT")
+                                .help("Why the peer is banned"),
+                        )
+                        .arg(
+                            Arg::new("duration")
+                                .long("duration")
+                                .value_name("SECONDS")
+                                .help("Ban length; permanent if omitted"),
                         ),
                 )
                 .subcommand(
-                    Command::new("close").about("Close a payment channel").arg(
-                        Arg::new("channel_id")
-                            .long("channel-id")
-                            .value_name("ID")
-                            .help("Channel ID to close")
+                    Command::new("unban").about("Lift a peer's ban").arg(
+                        Arg::new("peer_id")
+                            .long("peer-id")
+                            .value_name("PEER_ID")
+                            .help("libp2p peer ID")
                             .required(true),
                     ),
                 ),
         )
         .subcommand(
-            Command::new("pay")
-                .about("Send a Lightning payment")
-                .arg(
-                    Arg::new("channel_id")
-                        .long("channel-id")
-                        .value_name("ID")
-                        .help("Channel ID to send payment through")
-                        .required(true),
+            Command::new("tokens")
+                .about("Manage API tokens (requires an admin token)")
+                .subcommand(Command::new("list").about("List minted tokens"))
+                .subcommand(
+                    Command::new("mint")
+                        .about("Mint a new scoped token")
+                        .arg(
+                            Arg::new("scope")
+                                .long("scope")
+                                .value_name("SCOPE")
+                                .help("readonly, invoice or admin")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("expires_in")
+                                .long("expires-in")
+                                .value_name("SECONDS")
+                                .help("Token lifetime in seconds"),
+                        )
+                        .arg(
+                            Arg::new("max_amount")
+                                .long("max-amount")
+                                .value_name("SATS")
+                                .help("Largest payment or channel the token may create"),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .value_name("FILE")
+                                .help("Write the token to a file instead of printing it"),
+                        ),
                 )
-                .arg(
-                    Arg::new("amount")
-                        .long("amount")
-                        .value_name("BTC")
-                        .help("Payment amount in BTC")
-                        .required(true),
-                ),
-        )
-        .subcommand(
-            Command::new("payments")
-                .about("List payments for a channel")
-                .arg(
-                    Arg::new("channel_id")
-                        .long("channel-id")
-                        .value_name("ID")
-                        .help("Channel ID to list payments for")
-                        .required(true),
+                .subcommand(
+                    Command::new("revoke").about("Revoke a token").arg(
+                        Arg::new("id")
+                            .long("id")
+                            .value_name("ID")
+                            .help("Token ID to revoke")
+                            .required(true),
+                    ),
                 ),
         )
         .get_matches();
 
     let server_url = matches.get_one::<String>("server").unwrap().clone();
-    let cli = LightningCli::new(server_url);
+    let token_file = matches.get_one::<String>("token_file").unwrap();
+    let token = std::fs::read_to_string(token_file).ok();
+    let pinned_cert = match matches.get_one::<String>("tls_cert") {
+        Some(path) => Some(
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read TLS certificate {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let cli = LightningCli::new(server_url, token, pinned_cert)
+        .map_err(|e| anyhow!("Failed to configure client: {}", e))?;
 
     let result = match matches.subcommand() {
         Some(("info", _)) => match cli.get_node_info().await {
@@ -265,6 +1120,15 @@ async fn main() -> anyhow::Result<()> {
                                     "Status:        {}",
                                     if channel.is_open { "Open" } else { "Closed" }
                                 );
+                                if let Some(error) = &channel.sync_error {
+                                    println!("Out of sync:   {} (force-close required)", error);
+                                }
+                                if channel.is_open {
+                                    println!(
+                                        "Peer Online:   {}",
+                                        if channel.peer_online { "Yes" } else { "No" }
+                                    );
+                                }
                                 println!(
                                     "Capacity:      {:.8} BTC",
                                     satoshis_to_btc(channel.capacity)
@@ -277,6 +1141,16 @@ async fn main() -> anyhow::Result<()> {
                                     "Peer Balance:  {:.8} BTC",
                                     satoshis_to_btc(channel.peer_balance)
                                 );
+                                println!(
+                                    "Chain Fee:     {} sats ({})",
+                                    channel.commitment_fee, channel.commitment_type
+                                );
+                                if let Some(limits) = &channel.limits {
+                                    println!(
+                                        "Reserve:       {} sats (dust limit {} sats)",
+                                        limits.channel_reserve, limits.dust_limit
+                                    );
+                                }
                                 println!("Sequence:      {}", channel.sequence_number);
                                 println!("Created:       {}", channel.created_at);
                             }
@@ -308,7 +1182,16 @@ async fn main() -> anyhow::Result<()> {
                         capacity_btc, capacity_sats
                     );
 
-                    match cli.open_channel(peer, capacity_sats).await {
+                    let commitment_type = if open_matches.get_flag("anchors") {
+                        "anchors"
+                    } else {
+                        "legacy"
+                    };
+
+                    match cli
+                        .open_channel(peer, capacity_sats, commitment_type.to_string())
+                        .await
+                    {
                         Ok(channel) => {
                             println!("✅ Channel opened successfully!");
                             println!("Channel ID: {}", channel.id);
@@ -319,6 +1202,69 @@ async fn main() -> anyhow::Result<()> {
                     }
                 }
 
+                Some(("show", show_matches)) => {
+                    let channel_id = show_matches.get_one::<String>("channel_id").unwrap();
+
+                    let detail = cli.get_channel(channel_id).await;
+                    let stats = cli.get_channel_stats(channel_id).await;
+                    match (detail, stats) {
+                        (Ok(detail), Ok(stats)) => {
+                            let channel = detail.channel;
+                            println!("⚡ Channel {}", channel.id);
+                            println!("━━━━━━━━━━━━━━━━━━━");
+                            println!("Peer:          {}", channel.peer_node_id);
+                            println!(
+                                "Status:        {}",
+                                if channel.is_open { "Open" } else { "Closed" }
+                            );
+                            if let Some(error) = &channel.sync_error {
+                                println!("Out of sync:   {} (force-close required)", error);
+                            }
+                            if channel.is_open {
+                                println!(
+                                    "Peer Online:   {}",
+                                    if channel.peer_online { "Yes" } else { "No" }
+                                );
+                            }
+                            println!("Funding TXID:  {}", channel.funding_txid);
+                            println!(
+                                "Capacity:      {:.8} BTC",
+                                satoshis_to_btc(channel.capacity)
+                            );
+                            println!(
+                                "My Balance:    {:.8} BTC",
+                                satoshis_to_btc(channel.my_balance)
+                            );
+                            println!(
+                                "Peer Balance:  {:.8} BTC",
+                                satoshis_to_btc(channel.peer_balance)
+                            );
+                            println!();
+                            println!("Payments:      {}", stats.payment_count);
+                            println!("Sent:          {:.8} BTC", satoshis_to_btc(stats.volume_out));
+                            println!("Received:      {:.8} BTC", satoshis_to_btc(stats.volume_in));
+                            println!(
+                                "Last Activity: {}",
+                                stats.last_activity.unwrap_or_else(|| "never".to_string())
+                            );
+                            println!();
+                            println!("Commitments:");
+                            for commitment in detail.commitments {
+                                println!(
+                                    "  #{:<4} me {} / peer {} sats, fee {} sats ({})",
+                                    commitment.sequence,
+                                    commitment.my_balance,
+                                    commitment.peer_balance,
+                                    commitment.fee,
+                                    commitment.created_at
+                                );
+                            }
+                            Ok(())
+                        }
+                        (Err(e), _) | (_, Err(e)) => Err(e),
+                    }
+                }
+
                 Some(("close", close_matches)) => {
                     let channel_id = close_matches
                         .get_one::<String>("channel_id")
@@ -359,14 +1305,16 @@ async fn main() -> anyhow::Result<()> {
             println!("Sending payment through channel: {}", channel_id);
             println!("Amount: {:.8} BTC ({} satoshis)", amount_btc, amount_sats);
 
-            match cli.send_payment(channel_id, amount_sats).await {
+            let invoice_id = pay_matches.get_one::<String>("invoice").cloned();
+
+            match cli.send_payment(channel_id, amount_sats, invoice_id).await {
                 Ok(payment) => {
                     println!("✅ Payment sent successfully!");
                     println!("Payment ID: {}", payment.id);
                     println!("Sequence: {}", payment.sequence);
                     println!("Timestamp: {}", payment.timestamp);
                     if payment.is_offline {
-                        println!("📱 Sent offline - will be synced when online");
+                        println!("📱 Queued until the peer acknowledges it (see lightning-cli outbox)");
                     }
                     Ok(())
                 }
@@ -375,15 +1323,44 @@ async fn main() -> anyhow::Result<()> {
         }
 
         Some(("payments", payments_matches)) => {
-            let channel_id = payments_matches
-                .get_one::<String>("channel_id")
-                .unwrap()
-                .clone();
+            let btc_arg = |name: &str| -> anyhow::Result<Option<u64>> {
+                payments_matches
+                    .get_one::<String>(name)
+                    .map(|s| s.parse::<f64>().map(btc_to_satoshis))
+                    .transpose()
+                    .map_err(|_| anyhow!("Invalid --{}", name.replace('_', "-")))
+            };
+            let is_offline = if payments_matches.get_flag("offline") {
+                Some(true)
+            } else if payments_matches.get_flag("online") {
+                Some(false)
+            } else {
+                None
+            };
+            let query = PaymentQuery {
+                channel_id: payments_matches.get_one::<String>("channel_id").cloned(),
+                direction: payments_matches.get_one::<String>("direction").cloned(),
+                from: payments_matches.get_one::<String>("from").cloned(),
+                to: payments_matches.get_one::<String>("to").cloned(),
+                min_amount: btc_arg("min_amount")?,
+                max_amount: btc_arg("max_amount")?,
+                is_offline,
+                order: payments_matches
+                    .get_flag("oldest_first")
+                    .then(|| "asc".to_string()),
+                cursor: payments_matches.get_one::<String>("cursor").cloned(),
+                limit: payments_matches
+                    .get_one::<String>("limit")
+                    .map(|s| s.parse::<u32>())
+                    .transpose()
+                    .map_err(|_| anyhow!("Invalid --limit"))?,
+            };
 
-            match cli.list_payments(channel_id).await {
-                Ok(payments) => {
+            match cli.list_payments(&query).await {
+                Ok(page) => {
+                    let payments = page.payments;
                     if payments.is_empty() {
-                        println!("No payments found for this channel.");
+                        println!("No payments found.");
                     } else {
                         println!("💸 Payment History");
                         println!("━━━━━━━━━━━━━━━━━━");
@@ -405,12 +1382,436 @@ async fn main() -> anyhow::Result<()> {
                             println!("   {} - {}", payment.timestamp, payment.id);
                         }
                     }
+                    if let Some(cursor) = page.next_cursor {
+                        println!();
+                        println!("More payments available: --cursor {}", cursor);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Some(("outbox", outbox_matches)) => {
+            let channel_id = outbox_matches.get_one::<String>("channel_id").cloned();
+            match cli.get_outbox(channel_id).await {
+                Ok(entries) => {
+                    if entries.is_empty() {
+                        println!("Outbox empty - peers have every update.");
+                    } else {
+                        println!("📬 Pending Updates");
+                        println!("━━━━━━━━━━━━━━━━━━");
+                        for entry in entries {
+                            println!(
+                                "{} seq {} on {}{}",
+                                entry.kind,
+                                entry.sequence,
+                                entry.channel_id,
+                                entry
+                                    .payment_id
+                                    .map(|id| format!(" (payment {})", id))
+                                    .unwrap_or_default()
+                            );
+                            println!("   queued {}", entry.created_at);
+                        }
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Some(("export", export_matches)) => {
+            let channel_id = export_matches.get_one::<String>("channel_id").unwrap().clone();
+            let output = export_matches.get_one::<String>("output").unwrap().clone();
+
+            match cli.export_updates(channel_id).await {
+                Ok(bundle) => {
+                    if export_matches.get_flag("qr") {
+                        println!("📦 {} chunks - show each as a QR code, in any order:", bundle.chunks.len());
+                        for chunk in bundle.chunks {
+                            println!("{}", chunk);
+                        }
+                    } else {
+                        std::fs::write(&output, hex::decode(bundle.file)?)?;
+                        println!("📦 Update bundle written to {}", output);
+                    }
+                    println!("Import it on the peer with 'lightning-cli import', then import its reply here.");
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+
+        Some(("import", import_matches)) => {
+            let request = match (
+                import_matches.get_one::<String>("file"),
+                import_matches.get_many::<String>("chunk"),
+            ) {
+                (Some(file), _) => ImportBundleRequest {
+                    file: Some(hex::encode(std::fs::read(file)?)),
+                    chunks: None,
+                },
+                (None, Some(chunks)) => ImportBundleRequest {
+                    file: None,
+                    chunks: Some(chunks.cloned().collect()),
+                },
+                (None, None) => {
+                    eprintln!("Provide --file or one --chunk per QR code.");
+                    process::exit(1);
+                }
+            };
+            let reply_file = import_matches.get_one::<String>("reply").unwrap().clone();
+
+            match cli.import_updates(&request).await {
+                Ok(imported) => {
+                    println!(
+                        "✅ Imported {} updates on channel {}",
+                        imported.updates, imported.channel_id
+                    );
+                    if let Some(reply) = imported.reply {
+                        std::fs::write(&reply_file, hex::decode(reply.file)?)?;
+                        println!("↩️  Reply written to {} - import it on the peer. As QR chunks:", reply_file);
+                        for chunk in reply.chunks {
+                            println!("{}", chunk);
+                        }
+                    }
                     Ok(())
                 }
                 Err(e) => Err(e),
             }
         }
 
+        Some(("backup", backup_matches)) => match backup_matches.subcommand() {
+            Some(("export", export_matches)) => {
+                let output = export_matches.get_one::<String>("output").unwrap().clone();
+
+                match cli.export_backup().await {
+                    Ok(data) => match std::fs::write(&output, &data) {
+                        Ok(_) => {
+                            println!("✅ Channel backup written to {}", output);
+                            println!("Keep it together with your node seed (lightning-cli backup seed) - it is useless without it");
+                            Ok(())
+                        }
+                        Err(e) => Err(e.into()),
+                    },
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("seed", _)) => match cli.export_seed().await {
+                Ok(seed) => {
+                    println!("{}", seed);
+                    eprintln!("Anyone with this seed controls the node's funds - store it offline");
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+
+            Some(("restore", restore_matches)) => {
+                let file = restore_matches.get_one::<String>("file").unwrap().clone();
+                let data = std::fs::read(&file)?;
+
+                match cli.restore_backup(&data).await {
+                    Ok(channels) => {
+                        if channels.is_empty() {
+                            println!("No channels to restore - all are already known to this node.");
+                        } else {
+                            println!("♻️  Restored {} channels", channels.len());
+                            for channel in channels {
+                                println!(
+                                    "{}  {:.8} BTC  peer {}",
+                                    channel.id,
+                                    satoshis_to_btc(channel.capacity),
+                                    channel.peer_node_id
+                                );
+                            }
+                            println!("Force-close requested from each peer; funds return on-chain once they broadcast.");
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            _ => {
+                eprintln!("Unknown backup subcommand. Use 'lightning-cli backup --help' for usage.");
+                process::exit(1);
+            }
+        },
+
+        Some(("invoices", invoices_matches)) => match invoices_matches.subcommand() {
+            Some(("list", _)) | None => match cli.list_invoices().await {
+                Ok(invoices) => {
+                    if invoices.is_empty() {
+                        println!("No invoices found.");
+                    } else {
+                        println!("🧾 Invoices");
+                        println!("━━━━━━━━━━━");
+                        for invoice in invoices {
+                            let status = match (&invoice.settled_at, &invoice.payment_id) {
+                                (Some(settled_at), Some(payment_id)) => {
+                                    format!("settled {} by payment {}", settled_at, payment_id)
+                                }
+                                _ => "open".to_string(),
+                            };
+                            println!();
+                            println!("Invoice ID:  {}", invoice.id);
+                            println!("Amount:      {:.8} BTC", satoshis_to_btc(invoice.amount));
+                            println!("Memo:        {}", invoice.memo);
+                            println!("Created:     {}", invoice.created_at);
+                            println!("Status:      {}", status);
+                        }
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+
+            Some(("create", create_matches)) => {
+                let amount_btc: f64 = create_matches
+                    .get_one::<String>("amount")
+                    .unwrap()
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid invoice amount"))?;
+                let memo = create_matches.get_one::<String>("memo").cloned();
+
+                match cli.create_invoice(btc_to_satoshis(amount_btc), memo).await {
+                    Ok(invoice) => {
+                        println!("✅ Invoice created!");
+                        println!("Invoice ID: {}", invoice.id);
+                        println!("Amount: {:.8} BTC", satoshis_to_btc(invoice.amount));
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            _ => {
+                eprintln!("Unknown invoices subcommand. Use 'lightning-cli invoices --help' for usage.");
+                process::exit(1);
+            }
+        },
+
+        Some(("peers", peers_matches)) => match peers_matches.subcommand() {
+            Some(("list", _)) | None => match cli.list_peers().await {
+                Ok(peers) => {
+                    if peers.is_empty() {
+                        println!("No connected peers.");
+                        return Ok(());
+                    }
+                    println!("🌐 Connected Peers");
+                    println!("━━━━━━━━━━━━━━━━━");
+                    for peer in peers {
+                        println!();
+                        println!("Peer ID:   {}", peer.peer_id);
+                        println!(
+                            "Node ID:   {}",
+                            peer.node_id.as_deref().unwrap_or("(not identified yet)")
+                        );
+                        println!("Addresses: {}", peer.addresses.join(", "));
+                        println!("Since:     {}", peer.connected_since);
+                        println!("Latency:   {:.1} ms", peer.latency_ms);
+                        match peer.protocol_version {
+                            Some(version) => println!(
+                                "Protocol:  v{} ({})",
+                                version,
+                                if peer.features.is_empty() {
+                                    "no optional features".to_string()
+                                } else {
+                                    peer.features.join(", ")
+                                }
+                            ),
+                            None => println!("Protocol:  (no init yet)"),
+                        }
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+
+            Some(("connect", connect_matches)) => {
+                let address = connect_matches.get_one::<String>("address").cloned();
+                let node_id = connect_matches.get_one::<String>("node_id").cloned();
+
+                match cli.connect_peer(address, node_id).await {
+                    Ok(peer) => {
+                        println!("✅ Connected to {}", peer.peer_id);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("disconnect", disconnect_matches)) => {
+                let peer_id = disconnect_matches.get_one::<String>("peer_id").unwrap().clone();
+
+                match cli.disconnect_peer(peer_id.clone()).await {
+                    Ok(_) => {
+                        println!("✅ Disconnected from {}", peer_id);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("lookup", lookup_matches)) => {
+                let node_id = lookup_matches.get_one::<String>("node_id").unwrap();
+
+                match cli.lookup_node(node_id).await {
+                    Ok(record) => {
+                        println!("🔎 Node {}", record.node_id);
+                        println!("Peer ID:   {}", record.peer_id);
+                        println!("Addresses: {}", record.addresses.join(", "));
+                        println!("Published: {}", record.published_at);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("bans", _)) => match cli.list_bans().await {
+                Ok(bans) => {
+                    if bans.is_empty() {
+                        println!("No banned peers.");
+                        return Ok(());
+                    }
+                    println!("🚫 Banned Peers");
+                    println!("━━━━━━━━━━━━━━━");
+                    for ban in bans {
+                        println!();
+                        println!("Peer ID:   {}", ban.peer_id);
+                        println!("Reason:    {}", ban.reason);
+                        println!("Since:     {}", ban.banned_at);
+                        println!(
+                            "Until:     {}",
+                            ban.expires_at.as_deref().unwrap_or("(permanent)")
+                        );
+                        println!("Bans:      {}", ban.ban_count);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+
+            Some(("ban", ban_matches)) => {
+                let peer_id = ban_matches.get_one::<String>("peer_id").unwrap().clone();
+                let duration_secs = ban_matches
+                    .get_one::<String>("duration")
+                    .map(|s| s.parse::<u64>())
+                    .transpose()
+                    .map_err(|_| anyhow!("Invalid --duration"))?;
+                let request = BanPeerRequest {
+                    reason: ban_matches.get_one::<String>("reason").cloned(),
+                    duration_secs,
+                };
+
+                match cli.ban_peer(peer_id, &request).await {
+                    Ok(ban) => {
+                        match ban.expires_at {
+                            Some(expires_at) => {
+                                println!("✅ Banned {} until {}", ban.peer_id, expires_at)
+                            }
+                            None => println!("✅ Banned {} permanently", ban.peer_id),
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("unban", unban_matches)) => {
+                let peer_id = unban_matches.get_one::<String>("peer_id").unwrap().clone();
+
+                match cli.unban_peer(peer_id.clone()).await {
+                    Ok(_) => {
+                        println!("✅ Unbanned {}", peer_id);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            _ => {
+                eprintln!("Unknown peers subcommand. Use 'lightning-cli peers --help' for usage.");
+                process::exit(1);
+            }
+        },
+
+        Some(("tokens", tokens_matches)) => match tokens_matches.subcommand() {
+            Some(("list", _)) => match cli.list_tokens().await {
+                Ok(tokens) => {
+                    println!("🔑 API Tokens");
+                    println!("━━━━━━━━━━━━━");
+                    for token in tokens {
+                        let status = match &token.revoked_at {
+                            Some(revoked_at) => format!("revoked {}", revoked_at),
+                            None => "active".to_string(),
+                        };
+                        println!();
+                        println!("Token ID:  {}", token.id);
+                        println!("Scope:     {}", token.scope);
+                        println!("Caveats:   {}", token.caveats.join(", "));
+                        println!("Created:   {}", token.created_at);
+                        println!("Status:    {}", status);
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+
+            Some(("mint", mint_matches)) => {
+                let expires_in_secs = mint_matches
+                    .get_one::<String>("expires_in")
+                    .map(|s| s.parse::<i64>())
+                    .transpose()
+                    .map_err(|_| anyhow!("Invalid --expires-in"))?;
+                let max_amount = mint_matches
+                    .get_one::<String>("max_amount")
+                    .map(|s| s.parse::<u64>())
+                    .transpose()
+                    .map_err(|_| anyhow!("Invalid --max-amount"))?;
+                let request = MintTokenRequest {
+                    scope: mint_matches.get_one::<String>("scope").unwrap().clone(),
+                    expires_in_secs,
+                    max_amount,
+                };
+
+                match cli.mint_token(&request).await {
+                    Ok(minted) => {
+                        let token = minted.token.unwrap_or_default();
+                        println!("✅ Minted {} token {}", minted.scope, minted.id);
+                        match mint_matches.get_one::<String>("output") {
+                            Some(output) => {
+                                std::fs::write(output, token)?;
+                                println!("Token written to {}", output);
+                            }
+                            None => println!("{}", token),
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Some(("revoke", revoke_matches)) => {
+                let token_id = revoke_matches.get_one::<String>("id").unwrap().clone();
+
+                match cli.revoke_token(token_id.clone()).await {
+                    Ok(_) => {
+                        println!("✅ Token {} revoked", token_id);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            _ => {
+                eprintln!("Unknown tokens subcommand. Use 'lightning-cli tokens --help' for usage.");
+                process::exit(1);
+            }
+        },
+
         _ => {
             eprintln!("No subcommand provided. Use '--help' for usage information.");
             process::exit(1);
@@ -0,0 +1,15 @@
+ALTER TABLE channels ADD COLUMN closing_txid TEXT;
+
+CREATE TABLE IF NOT EXISTS channel_monitors (
+    channel_id TEXT PRIMARY KEY,
+    funding_txid TEXT NOT NULL,
+    funding_vout INTEGER NOT NULL DEFAULT 0,
+    is_resolved BOOLEAN NOT NULL DEFAULT FALSE,
+    spend_txid TEXT,
+    spend_kind TEXT CHECK (spend_kind IN ('cooperative', 'current', 'revoked', 'unknown')),
+    resolution_txid TEXT,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (channel_id) REFERENCES channels (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_channel_monitors_is_resolved ON channel_monitors(is_resolved);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS tower_blobs (
+    id TEXT PRIMARY KEY,
+    hint TEXT NOT NULL,
+    blob TEXT NOT NULL,
+    received_at DATETIME NOT NULL,
+    breach_txid TEXT,
+    justice_txid TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_tower_blobs_hint ON tower_blobs(hint);
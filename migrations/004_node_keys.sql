@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS node_keys (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    seed TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
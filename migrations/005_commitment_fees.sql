@@ -0,0 +1,6 @@
+ALTER TABLE channels ADD COLUMN commitment_type TEXT NOT NULL DEFAULT 'legacy' CHECK (commitment_type IN ('legacy', 'anchors'));
+ALTER TABLE channels ADD COLUMN feerate_per_kw INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE channels ADD COLUMN commitment_fee INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE channels ADD COLUMN is_funder BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE commitment_transactions ADD COLUMN fee INTEGER NOT NULL DEFAULT 0;
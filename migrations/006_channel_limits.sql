@@ -0,0 +1,8 @@
+ALTER TABLE channels ADD COLUMN channel_reserve INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE channels ADD COLUMN dust_limit INTEGER NOT NULL DEFAULT 546;
+ALTER TABLE channels ADD COLUMN max_in_flight INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE channels ADD COLUMN max_accepted_htlcs INTEGER NOT NULL DEFAULT 483;
+ALTER TABLE channels ADD COLUMN htlc_minimum INTEGER NOT NULL DEFAULT 1;
+
+-- Existing channels get the defaults a new channel of the same capacity would
+UPDATE channels SET channel_reserve = MAX(capacity / 100, 546), max_in_flight = capacity;
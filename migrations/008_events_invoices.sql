@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel_id TEXT,
+    event TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS invoices (
+    id TEXT PRIMARY KEY,
+    amount INTEGER NOT NULL,
+    memo TEXT NOT NULL DEFAULT '',
+    created_at DATETIME NOT NULL,
+    settled_at DATETIME,
+    payment_id TEXT
+);
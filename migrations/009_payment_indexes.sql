@@ -0,0 +1,9 @@
+-- Keyset pagination orders by (timestamp, id); filters narrow by channel or direction first
+CREATE INDEX IF NOT EXISTS idx_payments_timestamp_id ON payments(timestamp, id);
+CREATE INDEX IF NOT EXISTS idx_payments_channel_timestamp ON payments(channel_id, timestamp, id);
+CREATE INDEX IF NOT EXISTS idx_payments_direction_timestamp ON payments(direction, timestamp, id);
+CREATE INDEX IF NOT EXISTS idx_payments_amount ON payments(amount);
+
+-- Superseded by the composite indexes above
+DROP INDEX IF EXISTS idx_payments_channel_id;
+DROP INDEX IF EXISTS idx_payments_timestamp;
@@ -0,0 +1,7 @@
+-- Peers we have dialed successfully (or were given as bootstrap peers); redialed on startup
+CREATE TABLE IF NOT EXISTS known_peers (
+    peer_id TEXT PRIMARY KEY,
+    address TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    last_connected_at DATETIME
+);
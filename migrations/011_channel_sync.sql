@@ -0,0 +1,2 @@
+-- Set when channel_reestablish finds our state and the peer's irreconcilable
+ALTER TABLE channels ADD COLUMN sync_error TEXT;
@@ -0,0 +1,13 @@
+-- Channel updates we sent, kept for retransmission; delivered once the peer acks their sequence
+CREATE TABLE IF NOT EXISTS outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel_id TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    payment_id TEXT,
+    message TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    delivered_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_channel_sequence ON outbox(channel_id, sequence);
@@ -0,0 +1,2 @@
+-- Peer's counter-signature of our commitment, received over P2P or in an imported bundle
+ALTER TABLE commitment_transactions ADD COLUMN peer_signature TEXT;
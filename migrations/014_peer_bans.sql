@@ -0,0 +1,8 @@
+-- Peers banned for misbehaviour or by the operator; expired rows are kept to escalate repeat bans
+CREATE TABLE IF NOT EXISTS peer_bans (
+    peer_id TEXT PRIMARY KEY,
+    reason TEXT NOT NULL,
+    ban_count INTEGER NOT NULL,
+    banned_at DATETIME NOT NULL,
+    expires_at DATETIME
+);
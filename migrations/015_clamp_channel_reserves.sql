@@ -0,0 +1,4 @@
+-- 006 gave channels under 2184 sat a reserve above a quarter of their
+-- capacity, which validation rejects; clamp them as new channels are
+UPDATE channels SET channel_reserve = capacity / 4 WHERE channel_reserve > capacity / 4;
+UPDATE channels SET dust_limit = channel_reserve WHERE dust_limit > channel_reserve;
@@ -0,0 +1,4 @@
+-- Blobs are stored per client token (a hash of it) so each client's quota can be enforced
+ALTER TABLE tower_blobs ADD COLUMN client TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS idx_tower_blobs_client ON tower_blobs(client);
@@ -0,0 +1,4 @@
+-- Txid of the peer's commitment at each state, so a broadcast of it can be recognised
+ALTER TABLE commitment_transactions ADD COLUMN remote_txid TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_commitment_transactions_remote_txid ON commitment_transactions(remote_txid);
@@ -1,84 +1,446 @@
 use crate::LightningNode;
+use crate::auth::{AuthContext, Scope, TokenRecord};
+use crate::channel::{
+    ChannelLimits, ChannelStats, CommitmentTransaction, Invoice, OutboxEntry, PaymentChannel,
+    PaymentFilter, PaymentPage,
+};
+use crate::dht::NodeRecord;
+use crate::error::NodeError;
+use crate::events::EventBus;
+use crate::features::Feature;
+use crate::fees::CommitmentType;
+use crate::health::HealthReport;
+use crate::metrics::METRICS;
+use crate::openapi;
+use crate::p2p::PeerInfo;
+use crate::scoring::PeerBan;
+use crate::sneakernet::{self, ExportedBundle};
 use axum::{
-    Router,
+    Extension, Router,
     extract::{
-        Path, State, WebSocketUpgrade,
+        Path, Query, Request, State, WebSocketUpgrade,
+        rejection::{JsonRejection, QueryRejection},
         ws::{Message, WebSocket},
     },
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    http::{Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{Json, Response},
+    routing::{delete, get, post},
 };
+use chrono::{TimeDelta, Utc};
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use libp2p::{Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
 use tower_http::cors::CorsLayer;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+/// A client that hasn't finished the TLS handshake by then is dropped, so
+/// stalled connections don't pile up.
+const TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Pause after a failed accept (e.g. out of file descriptors) before retrying.
+const ACCEPT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct OpenChannelRequest {
     peer_node_id: String,
     capacity: u64,
+    #[serde(default)]
+    commitment_type: CommitmentType,
+    // Optional overrides of the default channel limits
+    channel_reserve: Option<u64>,
+    dust_limit: Option<u64>,
+    max_in_flight: Option<u64>,
+    max_accepted_htlcs: Option<u16>,
+    htlc_minimum: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl OpenChannelRequest {
+    fn limits(&self) -> ChannelLimits {
+        let mut limits = ChannelLimits::for_capacity(self.capacity);
+        if let Some(channel_reserve) = self.channel_reserve {
+            limits.channel_reserve = channel_reserve;
+        }
+        if let Some(dust_limit) = self.dust_limit {
+            limits.dust_limit = dust_limit;
+        }
+        if let Some(max_in_flight) = self.max_in_flight {
+            limits.max_in_flight = max_in_flight;
+        }
+        if let Some(max_accepted_htlcs) = self.max_accepted_htlcs {
+            limits.max_accepted_htlcs = max_accepted_htlcs;
+        }
+        if let Some(htlc_minimum) = self.htlc_minimum {
+            limits.htlc_minimum = htlc_minimum;
+        }
+        limits
+    }
+}
+
+/// A channel together with every commitment signed for it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChannelDetail {
+    #[serde(flatten)]
+    channel: PaymentChannel,
+    commitments: Vec<CommitmentTransaction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SendPaymentRequest {
     amount: u64,
+    invoice_id: Option<String>, // settles this invoice at the peer
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateInvoiceRequest {
+    amount: u64,
+    memo: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OutboxQuery {
+    channel_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventStreamQuery {
+    cursor: Option<i64>,      // resume after this event id
+    channels: Option<String>, // comma-separated channel ids to filter on
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConnectPeerRequest {
+    /// Multiaddr to dial, e.g. /ip4/192.168.1.20/tcp/4001/p2p/12D3KooW...
+    address: Option<String>,
+    /// Node id to look up in the DHT instead of giving an address
+    node_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BanPeerRequest {
+    reason: Option<String>,
+    duration_secs: Option<u64>, // permanent if absent
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateFeeRequest {
+    feerate_per_kw: Option<u64>, // defaults to the chain backend's estimate
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BumpFeeRequest {
+    feerate_per_kw: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BumpFeeResponse {
+    txid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ChannelBackup {
+    backup: String, // hex-encoded encrypted static channel backup
+}
+
+/// The node seed, needed together with a channel backup to restore the node.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NodeSeed {
+    seed: String, // hex, pass it back as NODE_SEED on a fresh database
+}
+
+/// A peer's update bundle, either as the whole file or as its QR chunks.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportBundleRequest {
+    file: Option<String>, // hex
+    chunks: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportBundleResponse {
+    channel_id: String,
+    updates: usize,
+    /// Counter-signatures and ack to carry back to the peer
+    reply: Option<ExportedBundle>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MintTokenRequest {
+    scope: Scope,
+    expires_in_secs: Option<i64>,
+    max_amount: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MintTokenResponse {
+    token: String,
+    #[serde(flatten)]
+    record: TokenRecord,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct NodeInfo {
     node_id: String,
     public_key: String,
     bitcoin_address: String,
-    connected_peers: Vec<String>, // This will be empty since we don't have P2P access
+    /// Peer ids of the nodes we are connected to
+    connected_peers: Vec<String>,
 }
 
 pub struct ApiServer {
     node: LightningNode,
-    // Optional: Add a channel to communicate with P2P node
-    p2p_sender: Option<mpsc::UnboundedSender<crate::p2p::P2PMessage>>,
+    tls: Option<Arc<ServerConfig>>,
 }
 
 impl ApiServer {
     pub fn new(node: LightningNode) -> Self {
-        Self {
-            node,
-            p2p_sender: None,
-        }
+        Self { node, tls: None }
     }
 
-    // Optional: Method to set P2P sender for communication
-    pub fn with_p2p_sender(
-        mut self,
-        sender: mpsc::UnboundedSender<crate::p2p::P2PMessage>,
-    ) -> Self {
-        self.p2p_sender = Some(sender);
+    /// Serve HTTPS (and WSS) with this certificate instead of plain HTTP.
+    pub fn with_tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
         self
     }
 
-    pub async fn start(&self, addr: &str) -> anyhow::Result<()> {
-        let app = Router::new()
+    fn router(&self) -> Router {
+        Router::new()
             .route("/api/node/info", get(get_node_info))
             .route("/api/channels", get(get_channels))
             .route("/api/channels", post(open_channel))
+            .route("/api/channels/:id", get(get_channel))
+            .route("/api/channels/:id", delete(delete_channel))
+            .route("/api/channels/:id/stats", get(get_channel_stats))
             .route("/api/channels/:id/payments", post(send_payment))
             .route("/api/channels/:id/payments", get(get_payments))
+            .route("/api/payments", get(list_payments))
+            .route("/api/outbox", get(get_outbox))
             .route("/api/channels/:id/close", post(close_channel))
+            .route("/api/channels/:id/force-close", post(force_close_channel))
+            .route("/api/channels/:id/fee", post(update_fee))
+            .route("/api/channels/:id/bump-fee", post(bump_fee))
+            .route("/api/channels/:id/export", post(export_updates))
+            .route("/api/import", post(import_updates))
+            .route("/api/backup", get(export_backup))
+            .route("/api/backup/seed", get(export_seed))
+            .route("/api/backup/restore", post(restore_backup))
+            .route("/api/tokens", get(list_tokens))
+            .route("/api/tokens", post(mint_token))
+            .route("/api/tokens/:id", delete(revoke_token))
+            .route("/api/invoices", get(get_invoices))
+            .route("/api/invoices", post(create_invoice))
+            .route("/api/peers", get(list_peers))
+            .route("/api/peers", post(connect_peer))
+            .route("/api/peers/:id", delete(disconnect_peer))
+            .route("/api/peers/bans", get(list_bans))
+            .route("/api/peers/lookup/:node_id", get(lookup_node))
+            .route("/api/peers/:id/ban", post(ban_peer))
+            .route("/api/peers/:id/ban", delete(unban_peer))
             .route("/ws", get(websocket_handler))
+            .route("/metrics", get(metrics))
+            .route_layer(middleware::from_fn_with_state(
+                self.node.clone(),
+                require_token,
+            ))
+            // Added after the auth layer so clients can fetch it before holding a token
+            .route("/api/openapi.json", get(openapi_spec))
+            // Probes for supervisors and load balancers, which hold no token
+            .route("/health", get(health))
+            .route("/ready", get(ready))
             .layer(CorsLayer::permissive())
-            .with_state(self.node.clone());
+            .with_state(self.node.clone())
+    }
 
+    pub async fn start(&self, addr: &str) -> anyhow::Result<()> {
+        let app = self.router();
         let listener = tokio::net::TcpListener::bind(addr).await?;
-        println!("API server listening on {}", addr);
-        axum::serve(listener, app).await?;
-        Ok(())
+        match &self.tls {
+            Some(config) => {
+                println!("API server listening on https://{}", addr);
+                serve_tls(listener, app, config.clone(), TLS_HANDSHAKE_TIMEOUT).await
+            }
+            None => {
+                println!("API server listening on {}", addr);
+                axum::serve(listener, app).await?;
+                Ok(())
+            }
+        }
     }
 }
 
+async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    config: Arc<ServerConfig>,
+    handshake_timeout: std::time::Duration,
+) -> anyhow::Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+
+    loop {
+        // Errors such as running out of file descriptors pass; back off briefly
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Failed to accept API connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(handshake_timeout, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        eprintln!("TLS handshake with {} failed: {}", remote, e);
+                        return;
+                    }
+                    Err(_) => {
+                        eprintln!("TLS handshake with {} timed out", remote);
+                        return;
+                    }
+                };
+
+            // Upgrades are needed for the WebSocket endpoint
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+                .with_upgrades()
+                .await
+            {
+                eprintln!("API connection from {} failed: {}", remote, e);
+            }
+        });
+    }
+}
+
+/// Scope needed for a request: reads need `readonly`, invoice endpoints need
+/// `invoice`, and anything that moves funds or manages tokens needs `admin`.
+pub(crate) fn required_scope(method: &Method, path: &str) -> Scope {
+    if path.starts_with("/api/invoices") {
+        Scope::Invoice
+    } else if method == Method::GET
+        && !path.starts_with("/api/backup")
+        && !path.starts_with("/api/tokens")
+    {
+        Scope::Readonly
+    } else {
+        Scope::Admin
+    }
+}
+
+async fn require_token(
+    State(node): State<LightningNode>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, NodeError> {
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    // Browsers cannot set headers on WebSocket requests
+    let query_token = match request.uri().path() {
+        "/ws" => request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("access_token="))
+        }),
+        _ => None,
+    };
+
+    let token = header_token
+        .or(query_token)
+        .ok_or_else(|| NodeError::Unauthorized("Missing bearer token".to_string()))?;
+
+    let auth = node.auth.authenticate(token).await?;
+    let required = required_scope(request.method(), request.uri().path());
+    if !auth.scope.allows(required) {
+        return Err(NodeError::Forbidden(format!(
+            "A {} token cannot access this endpoint; {} scope required",
+            auth.scope.as_str(),
+            required.as_str()
+        )));
+    }
+
+    request.extensions_mut().insert(auth);
+    Ok(next.run(request).await)
+}
+
+async fn openapi_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(openapi::document())
+}
+
+#[utoipa::path(
+    get, path = "/health",
+    responses(
+        (status = 200, description = "Database and P2P are up", body = HealthReport),
+        (status = 503, description = "Database or P2P is down", body = HealthReport)
+    ),
+    security(())
+)]
+async fn health(State(node): State<LightningNode>) -> (StatusCode, Json<HealthReport>) {
+    let report = node.health.report().await;
+    let status = if report.is_live() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+#[utoipa::path(
+    get, path = "/ready",
+    responses(
+        (status = 200, description = "Every configured subsystem is up", body = HealthReport),
+        (status = 503, description = "Starting up, or a subsystem is down", body = HealthReport)
+    ),
+    security(())
+)]
+async fn ready(State(node): State<LightningNode>) -> (StatusCode, Json<HealthReport>) {
+    let report = node.health.report().await;
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+#[utoipa::path(
+    get, path = "/metrics",
+    responses((status = 200, description = "Prometheus text exposition format", body = String, content_type = "text/plain"))
+)]
+async fn metrics() -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(),
+    )
+}
+
+#[utoipa::path(
+    get, path = "/api/node/info",
+    responses((status = 200, body = NodeInfo))
+)]
 async fn get_node_info(State(node): State<LightningNode>) -> Json<NodeInfo> {
-    // Since we don't have access to P2P node, connected_peers will be empty
-    // In a full implementation, you'd need a way to query P2P status
-    let connected_peers = Vec::new();
+    // Empty rather than an error while the P2P task is down
+    let connected_peers = node
+        .p2p
+        .peers()
+        .await
+        .map(|peers| peers.into_iter().map(|peer| peer.peer_id).collect())
+        .unwrap_or_default();
 
     Json(NodeInfo {
         node_id: node.node_id.clone(),
@@ -88,6 +450,10 @@ async fn get_node_info(State(node): State<LightningNode>) -> Json<NodeInfo> {
     })
 }
 
+#[utoipa::path(
+    get, path = "/api/channels",
+    responses((status = 200, body = Vec<PaymentChannel>))
+)]
 async fn get_channels(
     State(node): State<LightningNode>,
 ) -> Json<Vec<crate::channel::PaymentChannel>> {
@@ -101,125 +467,866 @@ async fn get_channels(
     )
 }
 
+#[utoipa::path(
+    get, path = "/api/channels/{id}",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, body = ChannelDetail))
+)]
+async fn get_channel(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<Json<ChannelDetail>, NodeError> {
+    let channel_manager = node.channel_manager.read().await;
+    let channel = channel_manager
+        .get_channel(&channel_id)
+        .cloned()
+        .ok_or_else(|| NodeError::ChannelNotFound(channel_id.clone()))?;
+    let commitments = channel_manager.get_channel_commitments(&channel_id).await?;
+
+    Ok(Json(ChannelDetail {
+        channel,
+        commitments,
+    }))
+}
+
+#[utoipa::path(
+    get, path = "/api/channels/{id}/stats",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, body = ChannelStats))
+)]
+async fn get_channel_stats(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<Json<ChannelStats>, NodeError> {
+    let channel_manager = node.channel_manager.read().await;
+    Ok(Json(channel_manager.get_channel_stats(&channel_id).await?))
+}
+
+#[utoipa::path(
+    post, path = "/api/channels",
+    request_body = OpenChannelRequest,
+    responses((status = 200, body = PaymentChannel))
+)]
 async fn open_channel(
     State(node): State<LightningNode>,
-    Json(req): Json<OpenChannelRequest>,
-) -> Result<Json<crate::channel::PaymentChannel>, StatusCode> {
-    let mut channel_manager = node.channel_manager.write().await;
+    Extension(auth): Extension<AuthContext>,
+    payload: Result<Json<OpenChannelRequest>, JsonRejection>,
+) -> Result<Json<crate::channel::PaymentChannel>, NodeError> {
+    let Json(req) = payload?;
+    auth.check_amount(req.capacity)?;
+    if req.commitment_type == CommitmentType::Anchors {
+        node.p2p
+            .check_peer_supports(&req.peer_node_id, Feature::Anchors)
+            .await?;
+    }
+    let limits = req.limits();
 
-    match channel_manager
-        .open_channel(req.peer_node_id, req.capacity)
+    let channel = node
+        .channel_manager
+        .write()
         .await
-    {
-        Ok(channel) => {
-            // Note: P2P broadcasting is removed since we don't have access to P2P node
-            // In a full implementation, you'd send a message via a channel to the P2P task
-            println!(
-                "Channel opened: {} (P2P broadcast not implemented)",
-                channel.id
-            );
-
-            Ok(Json(channel))
-        }
-        Err(e) => {
-            eprintln!("Failed to open channel: {}", e);
-            Err(StatusCode::BAD_REQUEST)
-        }
-    }
+        .open_channel(req.peer_node_id, req.capacity, req.commitment_type, limits)
+        .await?;
+    node.p2p
+        .keep_channel_peer_connected(&channel.peer_node_id)
+        .await;
+
+    println!("Channel opened: {}", channel.id);
+
+    Ok(Json(channel))
 }
 
+#[utoipa::path(
+    post, path = "/api/channels/{id}/payments",
+    params(("id" = String, Path, description = "Channel ID")),
+    request_body = SendPaymentRequest,
+    responses((status = 200, body = PaymentRecord))
+)]
 async fn send_payment(
     Path(channel_id): Path<String>,
     State(node): State<LightningNode>,
-    Json(req): Json<SendPaymentRequest>,
-) -> Result<Json<crate::channel::PaymentRecord>, StatusCode> {
+    Extension(auth): Extension<AuthContext>,
+    payload: Result<Json<SendPaymentRequest>, JsonRejection>,
+) -> Result<Json<crate::channel::PaymentRecord>, NodeError> {
+    let Json(req) = payload?;
+    auth.check_amount(req.amount)?;
     let mut channel_manager = node.channel_manager.write().await;
 
-    match channel_manager.send_payment(&channel_id, req.amount).await {
-        Ok(payment) => {
-            // Note: P2P broadcasting is removed since we don't have access to P2P node
-            // In a full implementation, you'd send a message via a channel to the P2P task
-            println!(
-                "Payment sent: {} sats on channel {} (P2P broadcast not implemented)",
-                req.amount, channel_id
-            );
+    let payment = channel_manager
+        .send_payment(&channel_id, req.amount, req.invoice_id)
+        .await?;
 
-            Ok(Json(payment))
-        }
-        Err(e) => {
-            eprintln!("Failed to send payment: {}", e);
-            Err(StatusCode::BAD_REQUEST)
-        }
-    }
+    println!(
+        "Payment sent: {} sats on channel {}",
+        req.amount, channel_id
+    );
+
+    Ok(Json(payment))
+}
+
+#[utoipa::path(
+    get, path = "/api/payments",
+    params(PaymentFilter),
+    responses((status = 200, body = PaymentPage))
+)]
+async fn list_payments(
+    State(node): State<LightningNode>,
+    query: Result<Query<PaymentFilter>, QueryRejection>,
+) -> Result<Json<PaymentPage>, NodeError> {
+    let Query(filter) = query?;
+    let channel_manager = node.channel_manager.read().await;
+    Ok(Json(channel_manager.list_payments(&filter).await?))
 }
 
+/// Keeps the plain array this endpoint always returned: every matching payment,
+/// or at most `limit`. Paging with cursors is on `/api/payments?channel_id=`.
+#[utoipa::path(
+    get, path = "/api/channels/{id}/payments",
+    params(("id" = String, Path, description = "Channel ID"), PaymentFilter),
+    responses((status = 200, body = Vec<PaymentRecord>))
+)]
 async fn get_payments(
     Path(channel_id): Path<String>,
     State(node): State<LightningNode>,
-) -> Result<Json<Vec<crate::channel::PaymentRecord>>, StatusCode> {
+    query: Result<Query<PaymentFilter>, QueryRejection>,
+) -> Result<Json<Vec<crate::channel::PaymentRecord>>, NodeError> {
+    let Query(mut filter) = query?;
     let channel_manager = node.channel_manager.read().await;
-
-    match channel_manager.get_channel_payments(&channel_id).await {
-        Ok(payments) => Ok(Json(payments)),
-        Err(e) => {
-            eprintln!("Failed to get payments: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+    if channel_manager.get_channel(&channel_id).is_none() {
+        return Err(NodeError::ChannelNotFound(channel_id));
     }
+
+    filter.channel_id = Some(channel_id);
+    Ok(Json(channel_manager.collect_payments(&filter).await?))
+}
+
+#[utoipa::path(
+    get, path = "/api/outbox",
+    params(OutboxQuery),
+    responses((status = 200, body = Vec<OutboxEntry>))
+)]
+async fn get_outbox(
+    State(node): State<LightningNode>,
+    query: Result<Query<OutboxQuery>, QueryRejection>,
+) -> Result<Json<Vec<OutboxEntry>>, NodeError> {
+    let Query(query) = query?;
+    let channel_manager = node.channel_manager.read().await;
+    Ok(Json(
+        channel_manager
+            .get_pending_outbox(query.channel_id.as_deref())
+            .await?,
+    ))
 }
 
+#[utoipa::path(
+    post, path = "/api/channels/{id}/close",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, description = "Channel closed"))
+)]
 async fn close_channel(
     Path(channel_id): Path<String>,
     State(node): State<LightningNode>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, NodeError> {
+    let mut channel_manager = node.channel_manager.write().await;
+    channel_manager.close_channel(&channel_id).await?;
+
+    println!("Channel closed: {}", channel_id);
+
+    Ok(StatusCode::OK)
+}
+
+/// Same as `POST /api/channels/{id}/close`.
+#[utoipa::path(
+    delete, path = "/api/channels/{id}",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, description = "Channel closed"))
+)]
+async fn delete_channel(
+    path: Path<String>,
+    state: State<LightningNode>,
+) -> Result<StatusCode, NodeError> {
+    close_channel(path, state).await
+}
+
+#[utoipa::path(
+    post, path = "/api/channels/{id}/force-close",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, description = "Commitment broadcast"))
+)]
+async fn force_close_channel(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<StatusCode, NodeError> {
+    let mut channel_manager = node.channel_manager.write().await;
+    channel_manager.force_close_channel(&channel_id).await?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    post, path = "/api/channels/{id}/fee",
+    params(("id" = String, Path, description = "Channel ID")),
+    request_body = UpdateFeeRequest,
+    responses((status = 200, body = PaymentChannel))
+)]
+async fn update_fee(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+    payload: Result<Json<UpdateFeeRequest>, JsonRejection>,
+) -> Result<Json<crate::channel::PaymentChannel>, NodeError> {
+    let Json(req) = payload?;
     let mut channel_manager = node.channel_manager.write().await;
 
-    match channel_manager.close_channel(&channel_id).await {
-        Ok(_) => {
-            // Note: P2P broadcasting is removed since we don't have access to P2P node
-            // In a full implementation, you'd send a message via a channel to the P2P task
-            println!(
-                "Channel closed: {} (P2P broadcast not implemented)",
-                channel_id
-            );
+    let channel = channel_manager
+        .update_fee(&channel_id, req.feerate_per_kw)
+        .await?;
+    Ok(Json(channel))
+}
 
-            Ok(StatusCode::OK)
+#[utoipa::path(
+    post, path = "/api/channels/{id}/bump-fee",
+    params(("id" = String, Path, description = "Channel ID")),
+    request_body = BumpFeeRequest,
+    responses((status = 200, body = BumpFeeResponse))
+)]
+async fn bump_fee(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+    payload: Result<Json<BumpFeeRequest>, JsonRejection>,
+) -> Result<Json<BumpFeeResponse>, NodeError> {
+    let Json(req) = payload?;
+    let channel_manager = node.channel_manager.read().await;
+
+    let txid = channel_manager
+        .bump_force_close(&channel_id, req.feerate_per_kw)
+        .await?;
+    Ok(Json(BumpFeeResponse { txid }))
+}
+
+#[utoipa::path(
+    post, path = "/api/channels/{id}/export",
+    params(("id" = String, Path, description = "Channel ID")),
+    responses((status = 200, body = ExportedBundle))
+)]
+async fn export_updates(
+    Path(channel_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<Json<ExportedBundle>, NodeError> {
+    let channel_manager = node.channel_manager.read().await;
+    let data = channel_manager.export_updates(&channel_id).await?;
+    Ok(Json(ExportedBundle::new(&data)))
+}
+
+#[utoipa::path(
+    post, path = "/api/import",
+    request_body = ImportBundleRequest,
+    responses((status = 200, body = ImportBundleResponse))
+)]
+async fn import_updates(
+    State(node): State<LightningNode>,
+    payload: Result<Json<ImportBundleRequest>, JsonRejection>,
+) -> Result<Json<ImportBundleResponse>, NodeError> {
+    let Json(req) = payload?;
+    let data = match (req.file, req.chunks) {
+        (Some(file), None) => hex::decode(file.trim())
+            .map_err(|_| NodeError::InvalidBundle("Bundle file is not valid hex".to_string()))?,
+        (None, Some(chunks)) => sneakernet::from_chunks(&chunks)?,
+        _ => {
+            return Err(NodeError::InvalidRequest(
+                "Provide either file or chunks".to_string(),
+            ));
         }
-        Err(e) => {
-            eprintln!("Failed to close channel: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+    };
+
+    let mut channel_manager = node.channel_manager.write().await;
+    let imported = channel_manager.import_bundle(&data).await?;
+    Ok(Json(ImportBundleResponse {
+        channel_id: imported.channel_id,
+        updates: imported.updates,
+        reply: imported.reply.as_deref().map(ExportedBundle::new),
+    }))
+}
+
+#[utoipa::path(
+    get, path = "/api/backup",
+    responses((status = 200, body = ChannelBackup))
+)]
+async fn export_backup(
+    State(node): State<LightningNode>,
+) -> Result<Json<ChannelBackup>, NodeError> {
+    let channel_manager = node.channel_manager.read().await;
+    let data = channel_manager.export_backup()?;
+
+    Ok(Json(ChannelBackup {
+        backup: hex::encode(data),
+    }))
+}
+
+/// The seed controls every channel, so only a token without caveats may export it.
+#[utoipa::path(
+    get, path = "/api/backup/seed",
+    responses((status = 200, body = NodeSeed))
+)]
+async fn export_seed(
+    State(node): State<LightningNode>,
+    Extension(auth): Extension<AuthContext>,
+) -> Result<Json<NodeSeed>, NodeError> {
+    auth.check_unrestricted()?;
+    Ok(Json(NodeSeed {
+        seed: hex::encode(node.key_manager.seed()),
+    }))
+}
+
+#[utoipa::path(
+    post, path = "/api/backup/restore",
+    request_body = ChannelBackup,
+    responses((status = 200, body = Vec<PaymentChannel>))
+)]
+async fn restore_backup(
+    State(node): State<LightningNode>,
+    payload: Result<Json<ChannelBackup>, JsonRejection>,
+) -> Result<Json<Vec<crate::channel::PaymentChannel>>, NodeError> {
+    let Json(req) = payload?;
+    let data = hex::decode(&req.backup)
+        .map_err(|_| NodeError::InvalidBackup("Backup is not valid hex".to_string()))?;
+    let mut channel_manager = node.channel_manager.write().await;
+
+    let channels = channel_manager.restore_backup(&data).await?;
+    println!(
+        "Restored {} channels from backup, force-close requested",
+        channels.len()
+    );
+    Ok(Json(channels))
+}
+
+#[utoipa::path(
+    get, path = "/api/tokens",
+    responses((status = 200, body = Vec<TokenRecord>))
+)]
+async fn list_tokens(
+    State(node): State<LightningNode>,
+) -> Result<Json<Vec<TokenRecord>>, NodeError> {
+    Ok(Json(node.auth.list().await?))
+}
+
+#[utoipa::path(
+    post, path = "/api/tokens",
+    request_body = MintTokenRequest,
+    responses((status = 201, body = MintTokenResponse))
+)]
+async fn mint_token(
+    State(node): State<LightningNode>,
+    Extension(auth): Extension<AuthContext>,
+    payload: Result<Json<MintTokenRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<MintTokenResponse>), NodeError> {
+    let Json(req) = payload?;
+    let expires_at = req
+        .expires_in_secs
+        .map(|secs| {
+            TimeDelta::try_seconds(secs)
+                .filter(|delta| *delta > TimeDelta::zero())
+                .and_then(|delta| Utc::now().checked_add_signed(delta))
+                .ok_or_else(|| {
+                    NodeError::InvalidRequest(format!(
+                        "expires_in_secs must be positive and in range, got {}",
+                        secs
+                    ))
+                })
+        })
+        .transpose()?;
+    let (expires_at, max_amount) = auth.attenuate(expires_at, req.max_amount);
+
+    let (record, token) = node.auth.mint(req.scope, expires_at, max_amount).await?;
+    println!("Minted {} API token {}", record.scope.as_str(), record.id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(MintTokenResponse { token, record }),
+    ))
+}
+
+#[utoipa::path(
+    delete, path = "/api/tokens/{id}",
+    params(("id" = String, Path, description = "Token ID")),
+    responses((status = 204, description = "Token revoked"))
+)]
+async fn revoke_token(
+    Path(token_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<StatusCode, NodeError> {
+    node.auth.revoke(&token_id).await?;
+    println!("Revoked API token {}", token_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get, path = "/api/peers",
+    responses((status = 200, body = Vec<PeerInfo>))
+)]
+async fn list_peers(State(node): State<LightningNode>) -> Result<Json<Vec<PeerInfo>>, NodeError> {
+    Ok(Json(node.p2p.peers().await?))
+}
+
+#[utoipa::path(
+    post, path = "/api/peers",
+    request_body = ConnectPeerRequest,
+    responses((status = 200, body = PeerInfo))
+)]
+async fn connect_peer(
+    State(node): State<LightningNode>,
+    payload: Result<Json<ConnectPeerRequest>, JsonRejection>,
+) -> Result<Json<PeerInfo>, NodeError> {
+    let Json(req) = payload?;
+    let peer = match (req.address, req.node_id) {
+        (Some(address), None) => {
+            let address: Multiaddr = address.parse().map_err(|e| {
+                NodeError::InvalidRequest(format!("Invalid address {}: {}", address, e))
+            })?;
+            node.p2p.connect(address).await?
         }
-    }
+        (None, Some(node_id)) => node.p2p.connect_node(node_id).await?,
+        _ => {
+            return Err(NodeError::InvalidRequest(
+                "Give either an address or a node_id".to_string(),
+            ));
+        }
+    };
+    println!("Connected to peer {} on request", peer.peer_id);
+    Ok(Json(peer))
+}
+
+#[utoipa::path(
+    delete, path = "/api/peers/{id}",
+    params(("id" = String, Path, description = "libp2p peer ID")),
+    responses((status = 204, description = "Disconnected and no longer redialed"))
+)]
+async fn disconnect_peer(
+    Path(peer_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<StatusCode, NodeError> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    node.p2p.disconnect(peer_id).await?;
+    println!("Disconnected peer {} on request", peer_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get, path = "/api/peers/lookup/{node_id}",
+    params(("node_id" = String, Path, description = "Lightning node ID")),
+    responses((status = 200, body = NodeRecord))
+)]
+async fn lookup_node(
+    Path(node_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<Json<NodeRecord>, NodeError> {
+    Ok(Json(node.p2p.find_node(node_id).await?))
+}
+
+#[utoipa::path(
+    get, path = "/api/peers/bans",
+    responses((status = 200, body = Vec<PeerBan>))
+)]
+async fn list_bans(State(node): State<LightningNode>) -> Result<Json<Vec<PeerBan>>, NodeError> {
+    Ok(Json(node.p2p.bans().await?))
+}
+
+#[utoipa::path(
+    post, path = "/api/peers/{id}/ban",
+    params(("id" = String, Path, description = "libp2p peer ID")),
+    request_body = BanPeerRequest,
+    responses((status = 200, body = PeerBan))
+)]
+async fn ban_peer(
+    Path(peer_id): Path<String>,
+    State(node): State<LightningNode>,
+    payload: Result<Json<BanPeerRequest>, JsonRejection>,
+) -> Result<Json<PeerBan>, NodeError> {
+    let Json(req) = payload?;
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    let ban = node
+        .p2p
+        .ban(
+            peer_id,
+            req.reason
+                .unwrap_or_else(|| "Banned by operator".to_string()),
+            req.duration_secs.map(std::time::Duration::from_secs),
+        )
+        .await?;
+    println!("Banned peer {} on request", peer_id);
+    Ok(Json(ban))
+}
+
+#[utoipa::path(
+    delete, path = "/api/peers/{id}/ban",
+    params(("id" = String, Path, description = "libp2p peer ID")),
+    responses((status = 204, description = "Ban lifted"))
+)]
+async fn unban_peer(
+    Path(peer_id): Path<String>,
+    State(node): State<LightningNode>,
+) -> Result<StatusCode, NodeError> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    node.p2p.unban(peer_id).await?;
+    println!("Unbanned peer {} on request", peer_id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, NodeError> {
+    peer_id
+        .parse()
+        .map_err(|_| NodeError::InvalidRequest(format!("Invalid peer ID {}", peer_id)))
+}
+
+#[utoipa::path(
+    post, path = "/api/invoices",
+    request_body = CreateInvoiceRequest,
+    responses((status = 201, body = Invoice))
+)]
+async fn create_invoice(
+    State(node): State<LightningNode>,
+    payload: Result<Json<CreateInvoiceRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<Invoice>), NodeError> {
+    let Json(req) = payload?;
+    let channel_manager = node.channel_manager.read().await;
+
+    let invoice = channel_manager
+        .create_invoice(req.amount, req.memo.unwrap_or_default())
+        .await?;
+    Ok((StatusCode::CREATED, Json(invoice)))
+}
+
+#[utoipa::path(
+    get, path = "/api/invoices",
+    responses((status = 200, body = Vec<Invoice>))
+)]
+async fn get_invoices(State(node): State<LightningNode>) -> Result<Json<Vec<Invoice>>, NodeError> {
+    let channel_manager = node.channel_manager.read().await;
+    Ok(Json(channel_manager.get_invoices().await?))
+}
+
+#[utoipa::path(
+    get, path = "/ws",
+    params(
+        EventStreamQuery,
+        ("access_token" = Option<String>, Query, description = "Bearer token for clients that cannot set headers")
+    ),
+    responses((status = 101, description = "WebSocket stream of EventRecord messages", body = EventRecord))
+)]
 async fn websocket_handler(
     ws: WebSocketUpgrade,
-    State(_node): State<LightningNode>,
-) -> axum::response::Response {
-    ws.on_upgrade(handle_socket)
-}
-
-async fn handle_socket(mut socket: WebSocket) {
-    // WebSocket handler for real-time updates
-    while let Some(msg) = socket.recv().await {
-        if let Ok(msg) = msg {
-            match msg {
-                Message::Text(text) => {
-                    println!("Received WebSocket message: {}", text);
-                    // Echo back for now
-                    if socket
-                        .send(Message::Text(format!("Echo: {}", text)))
-                        .await
-                        .is_err()
-                    {
+    State(node): State<LightningNode>,
+    query: Result<Query<EventStreamQuery>, QueryRejection>,
+) -> Result<Response, NodeError> {
+    let Query(query) = query?;
+    let channels: Option<HashSet<String>> = query.channels.map(|channels| {
+        channels
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect()
+    });
+
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, node.events, query.cursor, channels)))
+}
+
+/// Replays events after `cursor` (if given), then streams live ones. Every
+/// message carries its `id`; reconnect with `?cursor=<last id>` to resume.
+async fn handle_socket(
+    mut socket: WebSocket,
+    events: EventBus,
+    cursor: Option<i64>,
+    channels: Option<HashSet<String>>,
+) {
+    let mut events = events.follow(cursor, channels);
+
+    loop {
+        tokio::select! {
+            record = events.recv() => match record {
+                Some(record) => {
+                    let text = match serde_json::to_string(&record) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            error!("Failed to serialize event {}: {}", record.id, e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
                         break;
                     }
                 }
-                Message::Close(_) => break,
+                None => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
                 _ => {}
-            }
-        } else {
-            break;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelLimits;
+    use crate::crypto::KeyManager;
+    use axum::body::Body;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /// The API of a fresh node, with an admin token for it.
+    async fn api() -> (LightningNode, Router, String) {
+        let node = LightningNode::in_memory(1).await;
+        let (_, token) = node.auth.mint(Scope::Admin, None, None).await.unwrap();
+        let app = ApiServer::new(node.clone()).router();
+        (node, app, token)
+    }
+
+    async fn call(app: &Router, token: &str, method: Method, uri: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        if body.is_empty() {
+            return (status, Value::Null);
+        }
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Opens a channel to another node and pays `amounts` over it.
+    async fn channel_with_payments(node: &LightningNode, amounts: &[u64]) -> String {
+        let peer = KeyManager::from_seed([2; 32]).unwrap();
+        let mut channel_manager = node.channel_manager.write().await;
+        let channel = channel_manager
+            .open_channel(
+                peer.get_public_key().to_string(),
+                1_000_000,
+                CommitmentType::Legacy,
+                ChannelLimits::for_capacity(1_000_000),
+            )
+            .await
+            .unwrap();
+        for amount in amounts {
+            channel_manager
+                .send_payment(&channel.id, *amount, None)
+                .await
+                .unwrap();
+        }
+        channel.id
+    }
+
+    #[tokio::test]
+    async fn channel_detail_carries_its_commitments() {
+        let (node, app, token) = api().await;
+        let channel_id = channel_with_payments(&node, &[10_000, 5_000]).await;
+
+        let (status, body) = call(
+            &app,
+            &token,
+            Method::GET,
+            &format!("/api/channels/{}", channel_id),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], channel_id.as_str());
+        assert_eq!(body["sequence_number"], 2);
+
+        let commitments = body["commitments"].as_array().unwrap();
+        let latest = commitments.last().unwrap();
+        assert_eq!(latest["sequence"], 2);
+        assert_eq!(latest["my_balance"], body["my_balance"]);
+        assert_eq!(latest["peer_balance"], body["peer_balance"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_channel_is_not_found() {
+        let (_, app, token) = api().await;
+        for uri in ["/api/channels/missing", "/api/channels/missing/stats"] {
+            let (status, body) = call(&app, &token, Method::GET, uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+            assert_eq!(body["code"], "channel_not_found");
         }
     }
+
+    #[tokio::test]
+    async fn channel_stats_sum_the_payments() {
+        let (node, app, token) = api().await;
+        let channel_id = channel_with_payments(&node, &[10_000, 5_000]).await;
+
+        let (status, body) = call(
+            &app,
+            &token,
+            Method::GET,
+            &format!("/api/channels/{}/stats", channel_id),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["channel_id"], channel_id.as_str());
+        assert_eq!(body["payment_count"], 2);
+        assert_eq!(body["volume_out"], 15_000);
+        assert_eq!(body["volume_in"], 0);
+        assert!(body["last_activity"].is_string());
+    }
+
+    #[tokio::test]
+    async fn delete_closes_the_channel_once() {
+        let (node, app, token) = api().await;
+        let channel_id = channel_with_payments(&node, &[]).await;
+        let uri = format!("/api/channels/{}", channel_id);
+
+        let (status, _) = call(&app, &token, Method::DELETE, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(&app, &token, Method::GET, &uri).await;
+        assert_eq!(body["is_open"], false);
+        assert!(body["closing_txid"].is_string());
+
+        let (status, body) = call(&app, &token, Method::DELETE, &uri).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "channel_closed");
+    }
+
+    #[tokio::test]
+    async fn delete_needs_an_admin_token() {
+        let (node, app, _) = api().await;
+        let channel_id = channel_with_payments(&node, &[]).await;
+        let (_, readonly) = node.auth.mint(Scope::Readonly, None, None).await.unwrap();
+
+        let uri = format!("/api/channels/{}", channel_id);
+        let (status, body) = call(&app, &readonly, Method::DELETE, &uri).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "forbidden");
+        let (_, body) = call(&app, &readonly, Method::GET, &uri).await;
+        assert_eq!(body["is_open"], true);
+    }
+
+    #[test]
+    fn routes_require_their_scope() {
+        let cases = [
+            (Method::GET, "/api/node/info", Scope::Readonly),
+            (Method::GET, "/api/channels", Scope::Readonly),
+            (Method::GET, "/api/channels/chan/stats", Scope::Readonly),
+            (Method::GET, "/api/payments", Scope::Readonly),
+            (Method::GET, "/ws", Scope::Readonly),
+            (Method::POST, "/api/channels", Scope::Admin),
+            (Method::DELETE, "/api/channels/chan", Scope::Admin),
+            (Method::POST, "/api/channels/chan/payments", Scope::Admin),
+            (Method::POST, "/api/import", Scope::Admin),
+            (Method::GET, "/api/backup", Scope::Admin),
+            (Method::GET, "/api/backup/seed", Scope::Admin),
+            (Method::GET, "/api/tokens", Scope::Admin),
+            (Method::POST, "/api/peers", Scope::Admin),
+            (Method::GET, "/api/invoices", Scope::Invoice),
+            (Method::POST, "/api/invoices", Scope::Invoice),
+        ];
+        for (method, path, scope) in cases {
+            assert_eq!(required_scope(&method, path), scope, "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn invoice_tokens_cannot_spend() {
+        let spend = required_scope(&Method::POST, "/api/channels/chan/payments");
+        assert!(!Scope::Invoice.allows(spend));
+        assert!(Scope::Invoice.allows(required_scope(&Method::POST, "/api/invoices")));
+        assert!(!Scope::Readonly.allows(required_scope(&Method::POST, "/api/invoices")));
+    }
+
+    #[tokio::test]
+    async fn stalled_tls_handshake_is_dropped_while_serving_others() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = std::env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4()));
+        let (cert_path, key_path) = (dir.join("api.crt"), dir.join("api.key"));
+        crate::tls::ensure_self_signed(&cert_path, &key_path, vec!["localhost".into()]).unwrap();
+        let config = crate::tls::load_server_config(&cert_path, &key_path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        let timeout = std::time::Duration::from_millis(200);
+        tokio::spawn(serve_tls(listener, app, config, timeout));
+
+        let certificate = std::fs::read(&cert_path).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&certificate).unwrap())
+            .resolve("localhost", address)
+            .build()
+            .unwrap();
+        let url = format!("https://localhost:{}/", address.port());
+        let get = || async { client.get(&url).send().await.unwrap().text().await.unwrap() };
+
+        // Connects but never says hello
+        let mut stalled = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert_eq!(get().await, "ok");
+
+        let mut buffer = [0u8; 1];
+        let closed = tokio::time::timeout(timeout * 10, stalled.read(&mut buffer))
+            .await
+            .expect("stalled handshake was never dropped");
+        assert!(matches!(closed, Ok(0) | Err(_)));
+
+        assert_eq!(get().await, "ok");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served_as_prometheus_text() {
+        let (node, app, _) = api().await;
+        let (_, readonly) = node.auth.mint(Scope::Readonly, None, None).await.unwrap();
+
+        let (status, body) = call(&app, "", Method::GET, "/metrics").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "unauthorized");
+
+        let request = Request::builder()
+            .uri("/metrics")
+            .header(header::AUTHORIZATION, format!("Bearer {}", readonly))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains("# TYPE lightning_")
+        );
+    }
+
+    #[tokio::test]
+    async fn probes_answer_without_a_token() {
+        let (node, app, _) = api().await;
+
+        let (status, body) = call(&app, "", Method::GET, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "starting");
+        let (status, _) = call(&app, "", Method::GET, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        node.health.keys_unlocked();
+        node.health
+            .p2p_listening("/ip4/127.0.0.1/tcp/9735".to_string());
+        let (status, body) = call(&app, "", Method::GET, "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+
+        node.health.p2p_stopped("listener failed".to_string());
+        let (status, body) = call(&app, "", Method::GET, "/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["p2p"]["error"], "listener failed");
+    }
 }
@@ -0,0 +1,476 @@
+use crate::crypto::KeyManager;
+use crate::error::NodeError;
+use crate::storage::Database;
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const ROOT_KEY_PURPOSE: &str = "api-token-root";
+
+/// What a token may do. Each scope includes the ones below it, except that
+/// invoice tokens cannot spend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Readonly,
+    Invoice,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Readonly => "readonly",
+            Scope::Invoice => "invoice",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "readonly" => Some(Scope::Readonly),
+            "invoice" => Some(Scope::Invoice),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn allows(&self, required: Scope) -> bool {
+        match self {
+            Scope::Admin => true,
+            Scope::Invoice => required != Scope::Admin,
+            Scope::Readonly => required == Scope::Readonly,
+        }
+    }
+}
+
+/// Restrictions chained into a token's signature, written as `key=value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caveat {
+    Scope(Scope),
+    ExpiresAt(i64),
+    MaxAmount(u64),
+}
+
+impl Caveat {
+    pub fn encode(&self) -> String {
+        match self {
+            Caveat::Scope(scope) => format!("scope={}", scope.as_str()),
+            Caveat::ExpiresAt(timestamp) => format!("expires={}", timestamp),
+            Caveat::MaxAmount(amount) => format!("max_amount={}", amount),
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let (key, value) = value.split_once('=')?;
+        match key {
+            "scope" => Scope::parse(value).map(Caveat::Scope),
+            "expires" => value.parse().ok().map(Caveat::ExpiresAt),
+            "max_amount" => value.parse().ok().map(Caveat::MaxAmount),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized form handed to clients: the id and caveats in the clear, bound
+/// together by an HMAC chain rooted in a key derived from the node seed.
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenEnvelope {
+    id: String,
+    caveats: Vec<String>,
+    signature: String,
+}
+
+impl TokenEnvelope {
+    fn encode(&self) -> Result<String> {
+        Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?))
+    }
+
+    fn decode(token: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(token.trim()).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Minted token as stored by the daemon; the signature itself is never stored.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenRecord {
+    pub id: String,
+    pub scope: Scope,
+    pub caveats: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Permissions of the token that authenticated a request.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub token_id: String,
+    pub scope: Scope,
+    pub max_amount: Option<u64>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl AuthContext {
+    /// Caveats for a token minted with this one: never later or larger than ours,
+    /// so minting can't be used to shed an expiry or amount limit.
+    pub fn attenuate(
+        &self,
+        expires_at: Option<DateTime<Utc>>,
+        max_amount: Option<u64>,
+    ) -> (Option<DateTime<Utc>>, Option<u64>) {
+        let expires_at = match (expires_at, self.expires_at) {
+            (Some(requested), Some(ours)) => Some(requested.min(ours)),
+            (requested, ours) => requested.or(ours),
+        };
+        let max_amount = match (max_amount, self.max_amount) {
+            (Some(requested), Some(ours)) => Some(requested.min(ours)),
+            (requested, ours) => requested.or(ours),
+        };
+        (expires_at, max_amount)
+    }
+
+    pub fn check_amount(&self, amount: u64) -> Result<(), NodeError> {
+        match self.max_amount {
+            Some(max_amount) if amount > max_amount => Err(NodeError::Forbidden(format!(
+                "Amount {} sat exceeds this token's limit of {} sat",
+                amount, max_amount
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses tokens that carry an expiry or amount limit, for operations
+    /// those limits could not contain.
+    pub fn check_unrestricted(&self) -> Result<(), NodeError> {
+        if self.expires_at.is_some() || self.max_amount.is_some() {
+            return Err(NodeError::Forbidden(
+                "This endpoint needs a token without expiry or amount caveats".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// Mints, verifies and revokes API tokens.
+pub struct TokenAuthority {
+    root_key: [u8; 32],
+    database: Arc<Database>,
+}
+
+impl TokenAuthority {
+    pub fn new(key_manager: &KeyManager, database: Arc<Database>) -> Self {
+        Self {
+            root_key: key_manager.derive_key(ROOT_KEY_PURPOSE),
+            database,
+        }
+    }
+
+    fn sign(&self, id: &str, caveats: &[String]) -> Vec<u8> {
+        let mut signature = hmac(&self.root_key, id.as_bytes()).finalize().into_bytes();
+        for caveat in caveats {
+            signature = hmac(&signature, caveat.as_bytes()).finalize().into_bytes();
+        }
+        signature.to_vec()
+    }
+
+    pub async fn mint(
+        &self,
+        scope: Scope,
+        expires_at: Option<DateTime<Utc>>,
+        max_amount: Option<u64>,
+    ) -> Result<(TokenRecord, String)> {
+        let mut caveats = vec![Caveat::Scope(scope).encode()];
+        if let Some(expires_at) = expires_at {
+            caveats.push(Caveat::ExpiresAt(expires_at.timestamp()).encode());
+        }
+        if let Some(max_amount) = max_amount {
+            caveats.push(Caveat::MaxAmount(max_amount).encode());
+        }
+
+        let record = TokenRecord {
+            id: Uuid::new_v4().to_string(),
+            scope,
+            caveats,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        self.database.save_api_token(&record).await?;
+
+        let envelope = TokenEnvelope {
+            id: record.id.clone(),
+            signature: hex::encode(self.sign(&record.id, &record.caveats)),
+            caveats: record.caveats.clone(),
+        };
+        Ok((record, envelope.encode()?))
+    }
+
+    /// Verifies the signature chain, revocation and every caveat. Multiple scope
+    /// or amount caveats can only narrow what the token allows.
+    pub async fn authenticate(&self, token: &str) -> Result<AuthContext, NodeError> {
+        let invalid = || NodeError::Unauthorized("Invalid API token".to_string());
+
+        let envelope = TokenEnvelope::decode(token).ok_or_else(invalid)?;
+        let signature = hex::decode(&envelope.signature).map_err(|_| invalid())?;
+
+        // Recompute all but the last link, then compare the last in constant time
+        let (last, rest) = match envelope.caveats.split_last() {
+            Some((last, rest)) => (last.as_bytes(), rest),
+            None => return Err(invalid()),
+        };
+        let key = self.sign(&envelope.id, rest);
+        hmac(&key, last)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        let record = self
+            .database
+            .get_api_token(&envelope.id)
+            .await?
+            .ok_or_else(invalid)?;
+        if record.revoked_at.is_some() {
+            return Err(NodeError::Unauthorized(
+                "API token has been revoked".to_string(),
+            ));
+        }
+
+        let mut scope: Option<Scope> = None;
+        let mut max_amount: Option<u64> = None;
+        let mut expires_at: Option<i64> = None;
+        for caveat in &envelope.caveats {
+            match Caveat::parse(caveat).ok_or_else(invalid)? {
+                Caveat::Scope(s) => {
+                    scope = Some(match scope {
+                        Some(current) if s.allows(current) => current,
+                        _ => s,
+                    })
+                }
+                Caveat::ExpiresAt(timestamp) => {
+                    if Utc::now().timestamp() >= timestamp {
+                        return Err(NodeError::Unauthorized("API token has expired".to_string()));
+                    }
+                    expires_at = Some(expires_at.map_or(timestamp, |e| e.min(timestamp)))
+                }
+                Caveat::MaxAmount(amount) => {
+                    max_amount = Some(max_amount.map_or(amount, |m| m.min(amount)))
+                }
+            }
+        }
+
+        Ok(AuthContext {
+            token_id: envelope.id,
+            scope: scope.ok_or_else(invalid)?,
+            max_amount,
+            expires_at: expires_at.and_then(|t| DateTime::from_timestamp(t, 0)),
+        })
+    }
+
+    pub async fn revoke(&self, id: &str) -> Result<(), NodeError> {
+        if !self.database.revoke_api_token(id).await? {
+            return Err(NodeError::TokenNotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<TokenRecord>> {
+        self.database.get_api_tokens().await
+    }
+
+    /// Makes sure `path` holds a working admin token, minting a new one if the
+    /// file is missing or its token was revoked or expired.
+    pub async fn ensure_admin_token(&self, path: &Path) -> Result<()> {
+        if let Ok(existing) = std::fs::read_to_string(path)
+            && let Ok(auth) = self.authenticate(&existing).await
+            && auth.scope == Scope::Admin
+        {
+            return Ok(());
+        }
+
+        let (_, token) = self.mint(Scope::Admin, None, None).await?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_secret_file(path, token.as_bytes())
+    }
+}
+
+/// Writes a secret readable by the owner only. The file is created with mode
+/// 0600 under a temporary name and renamed into place, so it is never exposed
+/// with the default umask, not even when replacing an older file.
+pub fn write_secret_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    // Left behind by an interrupted write
+    let _ = std::fs::remove_file(&temporary);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn context(expires_at: Option<DateTime<Utc>>, max_amount: Option<u64>) -> AuthContext {
+        AuthContext {
+            token_id: "parent".to_string(),
+            scope: Scope::Admin,
+            max_amount,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn minted_caveats_never_exceed_the_callers() {
+        let soon = Utc::now() + TimeDelta::hours(1);
+        let later = soon + TimeDelta::days(30);
+        let caller = context(Some(soon), Some(10_000));
+
+        assert_eq!(caller.attenuate(None, None), (Some(soon), Some(10_000)));
+        assert_eq!(
+            caller.attenuate(Some(later), Some(50_000)),
+            (Some(soon), Some(10_000))
+        );
+        let earlier = soon - TimeDelta::minutes(5);
+        assert_eq!(
+            caller.attenuate(Some(earlier), Some(500)),
+            (Some(earlier), Some(500))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_files_are_private_from_the_start() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("secret-{}", Uuid::new_v4()));
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_secret_file(&path, b"token").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "token");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unrestricted_caller_mints_what_was_asked() {
+        let soon = Utc::now() + TimeDelta::hours(1);
+        let caller = context(None, None);
+
+        assert_eq!(caller.attenuate(None, None), (None, None));
+        assert_eq!(
+            caller.attenuate(Some(soon), Some(500)),
+            (Some(soon), Some(500))
+        );
+    }
+
+    async fn authority() -> TokenAuthority {
+        let key_manager = KeyManager::from_seed([7; 32]).unwrap();
+        let database = Arc::new(Database::in_memory().await.unwrap());
+        TokenAuthority::new(&key_manager, database)
+    }
+
+    /// Appends a caveat the way a holder can without the root key.
+    fn add_caveat(token: &str, caveat: Caveat) -> String {
+        let mut envelope = TokenEnvelope::decode(token).unwrap();
+        let signature = hex::decode(&envelope.signature).unwrap();
+        let caveat = caveat.encode();
+        envelope.signature =
+            hex::encode(hmac(&signature, caveat.as_bytes()).finalize().into_bytes());
+        envelope.caveats.push(caveat);
+        envelope.encode().unwrap()
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_rejected() {
+        let authority = authority().await;
+        let (_, token) = authority
+            .mint(Scope::Admin, Some(Utc::now() - TimeDelta::minutes(1)), None)
+            .await
+            .unwrap();
+
+        let error = authority.authenticate(&token).await.unwrap_err();
+        assert_eq!(error.to_string(), "API token has expired");
+    }
+
+    #[tokio::test]
+    async fn appended_caveats_only_narrow_the_token() {
+        let authority = authority().await;
+        let (_, token) = authority
+            .mint(Scope::Invoice, None, Some(1_000))
+            .await
+            .unwrap();
+
+        let token = add_caveat(&token, Caveat::Scope(Scope::Admin));
+        let token = add_caveat(&token, Caveat::MaxAmount(50_000));
+        let auth = authority.authenticate(&token).await.unwrap();
+        assert_eq!(auth.scope, Scope::Invoice);
+        assert_eq!(auth.max_amount, Some(1_000));
+
+        let narrowed = add_caveat(&token, Caveat::Scope(Scope::Readonly));
+        let auth = authority.authenticate(&narrowed).await.unwrap();
+        assert_eq!(auth.scope, Scope::Readonly);
+
+        let expired = add_caveat(&token, Caveat::ExpiresAt(Utc::now().timestamp() - 60));
+        let error = authority.authenticate(&expired).await.unwrap_err();
+        assert_eq!(error.to_string(), "API token has expired");
+    }
+
+    #[tokio::test]
+    async fn caveats_cannot_be_dropped_or_edited() {
+        let authority = authority().await;
+        let (_, token) = authority
+            .mint(Scope::Admin, None, Some(1_000))
+            .await
+            .unwrap();
+
+        let mut envelope = TokenEnvelope::decode(&token).unwrap();
+        envelope.caveats.pop();
+        let dropped = envelope.encode().unwrap();
+        assert!(authority.authenticate(&dropped).await.is_err());
+
+        let mut envelope = TokenEnvelope::decode(&token).unwrap();
+        envelope.caveats[1] = Caveat::MaxAmount(1_000_000).encode();
+        let edited = envelope.encode().unwrap();
+        assert!(authority.authenticate(&edited).await.is_err());
+    }
+
+    #[test]
+    fn only_unrestricted_tokens_pass_the_unrestricted_check() {
+        let soon = Utc::now() + TimeDelta::hours(1);
+        assert!(context(None, None).check_unrestricted().is_ok());
+        assert!(context(Some(soon), None).check_unrestricted().is_err());
+        assert!(context(None, Some(500)).check_unrestricted().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::channel::PaymentChannel;
+use crate::crypto::KeyManager;
+use anyhow::Result;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const BACKUP_VERSION: u8 = 1;
+const BACKUP_KEY_PURPOSE: &str = "static-channel-backup";
+
+/// Everything needed to ask a peer to close a channel after local state is lost.
+/// Balances are deliberately absent: they change on every payment and would make
+/// an old backup dangerous to act on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelBackupEntry {
+    pub channel_id: String,
+    pub peer_node_id: String,
+    pub funding_txid: String,
+    pub funding_vout: u32,
+    pub capacity: u64,
+    pub multisig_address: String,
+    /// Whether we funded the channel; backups written before this was recorded
+    /// only ever held channels we funded
+    #[serde(default = "funded_by_us")]
+    pub is_funder: bool,
+    /// Channel funding keys are currently the node key (family "node", index 0)
+    pub key_family: String,
+    pub key_index: u32,
+}
+
+fn funded_by_us() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticChannelBackup {
+    pub node_id: String,
+    pub created_at: DateTime<Utc>,
+    pub channels: Vec<ChannelBackupEntry>,
+}
+
+impl StaticChannelBackup {
+    pub fn from_channels<'a>(
+        node_id: String,
+        channels: impl IntoIterator<Item = &'a PaymentChannel>,
+    ) -> Self {
+        let channels = channels
+            .into_iter()
+            .filter(|c| c.is_open)
+            .map(|c| ChannelBackupEntry {
+                channel_id: c.id.clone(),
+                peer_node_id: c.peer_node_id.clone(),
+                funding_txid: c.funding_txid.clone(),
+                funding_vout: 0,
+                capacity: c.capacity,
+                multisig_address: c.multisig_address.clone(),
+                is_funder: c.is_funder,
+                key_family: "node".to_string(),
+                key_index: 0,
+            })
+            .collect();
+
+        StaticChannelBackup {
+            node_id,
+            created_at: Utc::now(),
+            channels,
+        }
+    }
+
+    /// Serializes as `version || nonce || ciphertext`, encrypted with a key
+    /// derived from the node seed.
+    pub fn encrypt(&self, key_manager: &KeyManager) -> Result<Vec<u8>> {
+        let key = key_manager.derive_key(BACKUP_KEY_PURPOSE);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+
+        let mut nonce = [0u8; 12];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let plaintext = serde_json::to_vec(self)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt channel backup"))?;
+
+        let mut data = vec![BACKUP_VERSION];
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    pub fn decrypt(key_manager: &KeyManager, data: &[u8]) -> Result<Self> {
+        if data.len() < 13 {
+            return Err(anyhow::anyhow!("Channel backup is truncated"));
+        }
+        if data[0] != BACKUP_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported channel backup version {}",
+                data[0]
+            ));
+        }
+
+        let key = key_manager.derive_key(BACKUP_KEY_PURPOSE);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&data[1..13]), &data[13..])
+            .map_err(|_| anyhow::anyhow!("Channel backup was not created with this node seed"))?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    /// Writes the encrypted backup atomically so a crash never leaves a torn file.
+    pub fn write_to_file(&self, key_manager: &KeyManager, path: &Path) -> Result<()> {
+        let data = self.encrypt(key_manager)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use crate::fees::sat_per_vbyte_to_per_kw;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Result of looking up who spent a transaction output.
+#[derive(Debug, Clone)]
+pub struct OutputSpend {
+    pub txid: String,
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraOutspend {
+    spent: bool,
+    txid: Option<String>,
+    status: Option<EsploraStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraBlock {
+    previousblockhash: Option<String>,
+}
+
+/// Minimal client for an Esplora-compatible block explorer API
+/// (e.g. a local electrs or mempool.space instance).
+#[derive(Clone)]
+pub struct ChainClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl ChainClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns the transaction spending `txid:vout`, if any.
+    pub async fn get_output_spend(&self, txid: &str, vout: u32) -> Result<Option<OutputSpend>> {
+        let url = format!("{}/tx/{}/outspend/{}", self.base_url, txid, vout);
+        let response = self.http.get(&url).send().await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            // The funding transaction is unknown to the backend (not yet broadcast)
+            return Ok(None);
+        }
+
+        let outspend: EsploraOutspend = response.error_for_status()?.json().await?;
+        if !outspend.spent {
+            return Ok(None);
+        }
+
+        let txid = outspend
+            .txid
+            .ok_or_else(|| anyhow::anyhow!("Backend reported spend without txid"))?;
+
+        Ok(Some(OutputSpend {
+            txid,
+            confirmed: outspend.status.map(|s| s.confirmed).unwrap_or(false),
+        }))
+    }
+
+    /// Broadcasts a raw transaction and returns the txid reported by the backend.
+    pub async fn broadcast(&self, raw_tx: &str) -> Result<String> {
+        let url = format!("{}/tx", self.base_url);
+        let response = self.http.post(&url).body(raw_tx.to_string()).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Broadcast failed ({}): {}", status, body));
+        }
+
+        Ok(response.text().await?.trim().to_string())
+    }
+
+    pub async fn get_tip_hash(&self) -> Result<String> {
+        let url = format!("{}/blocks/tip/hash", self.base_url);
+        let response = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(response.text().await?.trim().to_string())
+    }
+
+    /// Hash of the block before `block_hash`, `None` for the genesis block.
+    pub async fn get_block_parent(&self, block_hash: &str) -> Result<Option<String>> {
+        let url = format!("{}/block/{}", self.base_url, block_hash);
+        let response = self.http.get(&url).send().await?.error_for_status()?;
+        let block: EsploraBlock = response.json().await?;
+        Ok(block.previousblockhash)
+    }
+
+    pub async fn get_block_txids(&self, block_hash: &str) -> Result<Vec<String>> {
+        let url = format!("{}/block/{}/txids", self.base_url, block_hash);
+        let response = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        let url = format!("{}/mempool/txids", self.base_url);
+        let response = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    /// Feerate (sat/kw) expected to confirm within `target_blocks`.
+    pub async fn estimate_feerate_per_kw(&self, target_blocks: u16) -> Result<u64> {
+        let url = format!("{}/fee-estimates", self.base_url);
+        let response = self.http.get(&url).send().await?.error_for_status()?;
+        let estimates: HashMap<String, f64> = response.json().await?;
+
+        // Use the closest target that is at least as fast as requested
+        let sat_per_vbyte = estimates
+            .iter()
+            .filter_map(|(target, rate)| target.parse::<u16>().ok().map(|t| (t, *rate)))
+            .filter(|(target, _)| *target <= target_blocks)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate)
+            .ok_or_else(|| anyhow::anyhow!("No fee estimate for {} blocks", target_blocks))?;
+
+        Ok(sat_per_vbyte_to_per_kw(sat_per_vbyte))
+    }
+}
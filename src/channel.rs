@@ -1,13 +1,38 @@
-use crate::crypto::KeyManager;
+use crate::backup::StaticChannelBackup;
+use crate::chain::ChainClient;
+use crate::crypto::{self, KeyManager, SigningDomain, transaction_id};
+use crate::error::NodeError;
+use crate::events::{EventBus, NodeEvent};
+use crate::fees::{
+    ANCHOR_OUTPUT_VALUE, COMMITMENT_CONF_TARGET, CommitmentType, DEFAULT_FEERATE_PER_KW,
+    FEERATE_FLOOR_PER_KW, commitment_fee, cpfp_child_fee, penalty_fee,
+};
+use crate::metrics::METRICS;
+use crate::monitor::{cpfp_transaction, penalty_transaction};
+use crate::p2p::{MessageAuthor, P2PMessage, PeerStatus, p2p_keypair};
+use crate::scoring::{Misbehaviour, PeerViolation};
+use crate::sneakernet::UpdateBundle;
 use crate::storage::Database;
+use crate::watchtower::{BreachBlob, TowerClient};
+use crate::wire::{self, WireMessage};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PaymentChannel {
     pub id: String,
     pub peer_node_id: String,
@@ -19,21 +44,270 @@ pub struct PaymentChannel {
     pub is_open: bool,
     pub created_at: DateTime<Utc>,
     pub multisig_address: String,
+    pub closing_txid: Option<String>,
+    pub commitment_type: CommitmentType,
+    pub feerate_per_kw: u64,
+    /// On-chain fee reserved by the current commitment, paid by the funder
+    pub commitment_fee: u64,
+    pub is_funder: bool,
+    pub limits: ChannelLimits,
+    /// Why the channel no longer matches the peer's after reestablish, or why
+    /// the chain monitor flagged it; no updates are accepted until it is closed
+    #[serde(default)]
+    pub sync_error: Option<String>,
+    /// Whether the peer is connected and answering pings; not stored
+    #[serde(default)]
+    pub peer_online: bool,
+}
+
+impl PaymentChannel {
+    /// Funds not spendable by either side: commitment fee plus anchor outputs.
+    pub fn reserved_for_chain(&self) -> u64 {
+        self.commitment_fee + self.commitment_type.anchor_total()
+    }
+
+    /// Rejects updates on closed channels and on channels that failed reestablish.
+    pub fn ensure_operational(&self) -> Result<(), NodeError> {
+        if !self.is_open {
+            return Err(NodeError::ChannelClosed(self.id.clone()));
+        }
+        if self.sync_error.is_some() {
+            return Err(NodeError::ChannelOutOfSync(self.id.clone()));
+        }
+        Ok(())
+    }
+
+    /// Our commitment at the current state, as we sign it.
+    pub fn local_commitment_tx(&self) -> String {
+        self.commitment_tx(self.my_balance, self.peer_balance)
+    }
+
+    /// The peer's commitment at the current state, exactly as the peer builds
+    /// it. It is the only transaction we ever counter-sign.
+    pub fn remote_commitment_tx(&self) -> String {
+        self.commitment_tx(self.peer_balance, self.my_balance)
+    }
+
+    /// Canonical bytes of the peer's current commitment, which our
+    /// counter-signature covers.
+    fn remote_commitment_bytes(&self) -> Vec<u8> {
+        wire::encode_commitment(
+            &self.id,
+            self.sequence_number,
+            self.peer_balance,
+            self.my_balance,
+            self.commitment_fee,
+            &self.remote_commitment_tx(),
+        )
+    }
+
+    /// Simplified commitment paying `holder_balance` to the side that
+    /// broadcasts it. In reality, this would be a proper Bitcoin transaction.
+    fn commitment_tx(&self, holder_balance: u64, other_balance: u64) -> String {
+        // Anchor outputs (vout 2 and 3) let either side CPFP the commitment
+        let anchors = match self.commitment_type {
+            CommitmentType::Legacy => String::new(),
+            CommitmentType::Anchors => format!(
+                ",{{\"amount\":{},\"address\":\"my_anchor\"}},{{\"amount\":{},\"address\":\"peer_anchor\"}}",
+                ANCHOR_OUTPUT_VALUE, ANCHOR_OUTPUT_VALUE
+            ),
+        };
+
+        format!(
+            "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":0}}],\"outputs\":[{{\"amount\":{},\"address\":\"my_address\"}},{{\"amount\":{},\"address\":\"peer_address\"}}{}],\"fee\":{},\"sequence\":{}}}",
+            self.funding_txid,
+            holder_balance,
+            other_balance,
+            anchors,
+            self.commitment_fee,
+            self.sequence_number
+        )
+    }
+}
+
+/// BOLT 2 limits agreed when the channel is opened. Both sides are held to the
+/// same values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChannelLimits {
+    /// Balance a side must keep after each payment it sends, so cheating
+    /// always costs something
+    pub channel_reserve: u64,
+    /// Outputs below this are not economical to spend on-chain
+    pub dust_limit: u64,
+    /// Largest value that may be pending in a single update
+    pub max_in_flight: u64,
+    /// Most of our payments the peer may hold unacknowledged at once
+    pub max_accepted_htlcs: u16,
+    pub htlc_minimum: u64,
+}
+
+/// Standard output dust threshold.
+pub const DEFAULT_DUST_LIMIT: u64 = 546;
+
+/// Lowest dust limit BOLT 2 allows a peer to propose.
+const MIN_DUST_LIMIT: u64 = 354;
+
+/// BOLT 2 upper bound on `max_accepted_htlcs`.
+const MAX_ACCEPTED_HTLCS: u16 = 483;
+
+/// Smallest channel with room for a reserve of at least the minimum dust
+/// limit in each quarter.
+const MIN_CAPACITY: u64 = 4 * MIN_DUST_LIMIT;
+
+impl ChannelLimits {
+    /// Defaults: 1% reserve (never below dust, never above a quarter of the
+    /// capacity), whole capacity in flight.
+    pub fn for_capacity(capacity: u64) -> Self {
+        let channel_reserve = (capacity / 100).max(DEFAULT_DUST_LIMIT).min(capacity / 4);
+        ChannelLimits {
+            channel_reserve,
+            dust_limit: DEFAULT_DUST_LIMIT.min(channel_reserve),
+            max_in_flight: capacity,
+            max_accepted_htlcs: MAX_ACCEPTED_HTLCS,
+            htlc_minimum: 1,
+        }
+    }
+
+    pub fn validate(&self, capacity: u64) -> Result<()> {
+        if capacity < MIN_CAPACITY {
+            return Err(NodeError::LimitViolation(format!(
+                "Capacity {} sat is below the minimum of {} sat",
+                capacity, MIN_CAPACITY
+            ))
+            .into());
+        }
+        if self.dust_limit < MIN_DUST_LIMIT {
+            return Err(NodeError::LimitViolation(format!(
+                "Dust limit {} sat is below the minimum of {} sat",
+                self.dust_limit, MIN_DUST_LIMIT
+            ))
+            .into());
+        }
+        if self.channel_reserve < self.dust_limit {
+            return Err(NodeError::LimitViolation(format!(
+                "Channel reserve {} sat is below the dust limit of {} sat",
+                self.channel_reserve, self.dust_limit
+            ))
+            .into());
+        }
+        if self.channel_reserve > capacity / 4 {
+            return Err(NodeError::LimitViolation(format!(
+                "Channel reserve {} sat exceeds a quarter of the {} sat capacity",
+                self.channel_reserve, capacity
+            ))
+            .into());
+        }
+        if self.max_accepted_htlcs == 0 || self.max_accepted_htlcs > MAX_ACCEPTED_HTLCS {
+            return Err(NodeError::LimitViolation(format!(
+                "Max accepted HTLCs must be between 1 and {}",
+                MAX_ACCEPTED_HTLCS
+            ))
+            .into());
+        }
+        if self.htlc_minimum == 0 || self.htlc_minimum > self.max_in_flight {
+            return Err(NodeError::LimitViolation(format!(
+                "Minimum HTLC {} sat must be between 1 and the max in-flight value of {} sat",
+                self.htlc_minimum, self.max_in_flight
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks a payment of `amount` out of `sender_balance`. Payments settle
+    /// immediately, so a single update is the only value ever in flight.
+    pub fn check_payment(&self, amount: u64, sender_balance: u64) -> Result<()> {
+        if amount < self.dust_limit {
+            return Err(NodeError::LimitViolation(format!(
+                "Amount {} sat is below the dust limit of {} sat",
+                amount, self.dust_limit
+            ))
+            .into());
+        }
+        if amount < self.htlc_minimum {
+            return Err(NodeError::LimitViolation(format!(
+                "Amount {} sat is below the minimum HTLC of {} sat",
+                amount, self.htlc_minimum
+            ))
+            .into());
+        }
+        if amount > self.max_in_flight {
+            return Err(NodeError::LimitViolation(format!(
+                "Amount {} sat exceeds the max in-flight value of {} sat",
+                amount, self.max_in_flight
+            ))
+            .into());
+        }
+        if sender_balance < amount {
+            return Err(NodeError::InsufficientBalance(format!(
+                "Insufficient balance: {} sat available",
+                sender_balance
+            ))
+            .into());
+        }
+        if sender_balance - amount < self.channel_reserve {
+            return Err(NodeError::LimitViolation(format!(
+                "Payment would leave {} sat, below the channel reserve of {} sat",
+                sender_balance - amount,
+                self.channel_reserve
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks that another payment fits beside the `pending` ones the peer
+    /// has not acknowledged yet, which it holds for us like HTLCs.
+    pub fn check_pending(&self, pending: usize) -> Result<()> {
+        if pending >= usize::from(self.max_accepted_htlcs) {
+            return Err(NodeError::LimitViolation(format!(
+                "{} payments already await the peer's acknowledgment, the most it accepts",
+                pending
+            ))
+            .into());
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CommitmentTransaction {
     pub id: String,
     pub channel_id: String,
     pub sequence: u64,
     pub my_balance: u64,
     pub peer_balance: u64,
+    pub fee: u64,
     pub raw_tx: String,
     pub signature: String,
+    /// Peer's counter-signature, once it has acknowledged this state
+    #[serde(default)]
+    pub peer_signature: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Txid of the peer's commitment at this state, which the peer may broadcast
+    #[serde(default)]
+    pub remote_txid: Option<String>,
+}
+
+impl CommitmentTransaction {
+    pub fn txid(&self) -> String {
+        transaction_id(&self.raw_tx)
+    }
+
+    /// Canonical bytes our signature and the peer's counter-signature cover.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        wire::encode_commitment(
+            &self.channel_id,
+            self.sequence,
+            self.my_balance,
+            self.peer_balance,
+            self.fee,
+            &self.raw_tx,
+        )
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PaymentRecord {
     pub id: String,
     pub channel_id: String,
@@ -44,20 +318,139 @@ pub struct PaymentRecord {
     pub is_offline: bool,
 }
 
+/// A channel update we sent, kept until the peer acknowledges it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub channel_id: String,
+    pub sequence: u64,
+    /// "payment", "update_fee" or "commitment_signed"
+    pub kind: String,
+    pub payment_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of importing a peer's update bundle.
+#[derive(Debug, Clone)]
+pub struct ImportedBundle {
+    pub channel_id: String,
+    /// Messages in the bundle, including any already applied
+    pub updates: usize,
+    /// Encoded bundle to carry back to the peer
+    pub reply: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters and paging for payment history. Every field is optional; `from` is
+/// inclusive and `to` exclusive.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaymentFilter {
+    pub channel_id: Option<String>,
+    pub direction: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    pub is_offline: Option<bool>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Position of the last payment on a page. Pages are keyed on (timestamp, id)
+/// so payments recorded while paging are neither skipped nor repeated.
+#[derive(Debug, Clone)]
+pub struct PaymentCursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: String,
+}
+
+impl PaymentCursor {
+    pub fn encode(&self) -> String {
+        let timestamp = self.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", timestamp, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let (timestamp, id) = std::str::from_utf8(&bytes).ok()?.split_once('|')?;
+        Some(Self {
+            timestamp: DateTime::parse_from_rfc3339(timestamp)
+                .ok()?
+                .with_timezone(&Utc),
+            id: id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PaymentPage {
+    pub payments: Vec<PaymentRecord>,
+    pub next_cursor: Option<String>, // absent on the last page
+}
+
+/// Payment activity on a single channel.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChannelStats {
+    pub channel_id: String,
+    pub payment_count: u64,
+    pub volume_out: u64,
+    pub volume_in: u64,
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+/// Request for payment handed to a payer out of band; settled when a payment
+/// referencing its id arrives.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Invoice {
+    pub id: String,
+    pub amount: u64,
+    pub memo: String,
+    pub created_at: DateTime<Utc>,
+    pub settled_at: Option<DateTime<Utc>>,
+    pub payment_id: Option<String>,
+}
+
 pub struct ChannelManager {
     key_manager: Arc<KeyManager>,
     database: Arc<Database>,
     channels: HashMap<String, PaymentChannel>,
     commitment_txs: HashMap<String, Vec<CommitmentTransaction>>,
+    tower_client: Option<TowerClient>,
+    chain: Option<ChainClient>,
+    p2p_sender: Option<mpsc::UnboundedSender<P2PMessage>>,
+    backup_path: Option<PathBuf>,
+    events: Option<EventBus>,
+    // Connected peers by libp2p peer id, with their node id once identified
+    online_peers: HashMap<String, Option<String>>,
+    // Our own libp2p peer id, which a channel_open may name us by
+    local_peer_id: String,
 }
 
 impl ChannelManager {
     pub async fn new(key_manager: Arc<KeyManager>, database: Arc<Database>) -> Result<Self> {
         let mut manager = ChannelManager {
-            key_manager,
             database,
             channels: HashMap::new(),
             commitment_txs: HashMap::new(),
+            tower_client: None,
+            chain: None,
+            p2p_sender: None,
+            backup_path: None,
+            events: None,
+            online_peers: HashMap::new(),
+            local_peer_id: p2p_keypair(&key_manager)?.public().to_peer_id().to_string(),
+            key_manager,
         };
 
         // Load existing channels from database
@@ -66,6 +459,44 @@ impl ChannelManager {
         Ok(manager)
     }
 
+    /// Upload a breach remedy for every revoked state to these watchtowers.
+    pub fn with_tower_client(mut self, tower_client: TowerClient) -> Self {
+        self.tower_client = Some(tower_client);
+        self
+    }
+
+    /// Chain backend used to broadcast commitments on force-close.
+    pub fn with_chain_client(mut self, chain: ChainClient) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Queue for messages to be published by the P2P task.
+    pub fn with_p2p_sender(mut self, sender: mpsc::UnboundedSender<P2PMessage>) -> Self {
+        self.p2p_sender = Some(sender);
+        self
+    }
+
+    /// Keep an encrypted static channel backup at `path`, rewritten whenever
+    /// a channel is opened or closed.
+    pub fn with_backup_file(mut self, path: PathBuf) -> Result<Self> {
+        self.backup_path = Some(path);
+        self.write_backup()?;
+        Ok(self)
+    }
+
+    /// Publish channel, payment and invoice events on `events`.
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    async fn emit(&self, event: NodeEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event).await;
+        }
+    }
+
     async fn load_channels(&mut self) -> Result<()> {
         let channels = self.database.get_all_channels().await?;
         for channel in channels {
@@ -78,6 +509,7 @@ impl ChannelManager {
             self.commitment_txs.insert(channel_id.clone(), commitments);
         }
 
+        self.record_channel_metrics();
         Ok(())
     }
 
@@ -85,7 +517,11 @@ impl ChannelManager {
         &mut self,
         peer_node_id: String,
         capacity: u64,
+        commitment_type: CommitmentType,
+        limits: ChannelLimits,
     ) -> Result<PaymentChannel> {
+        limits.validate(capacity)?;
+
         let channel_id = Uuid::new_v4().to_string();
         let funding_txid = format!("funding_{}", Uuid::new_v4());
 
@@ -109,118 +545,326 @@ impl ChannelManager {
                         .create_multisig_address(&peer_pubkey)?
                         .to_string(),
                     Err(_) => {
-                        return Err(anyhow::anyhow!(
+                        return Err(NodeError::InvalidPubkey(format!(
                             "Invalid public key format: {}",
                             peer_node_id
-                        ));
+                        ))
+                        .into());
                     }
                 },
                 Err(_) => {
-                    return Err(anyhow::anyhow!(
+                    return Err(NodeError::InvalidPubkey(format!(
                         "Invalid hex encoding in peer node ID: {}",
                         peer_node_id
-                    ));
+                    ))
+                    .into());
                 }
             }
         };
 
+        // As the funder we pay the commitment fee and anchors out of our half
+        let feerate_per_kw = self.estimate_feerate().await;
+        let fee = commitment_fee(feerate_per_kw, commitment_type);
+        let reserved = fee + commitment_type.anchor_total();
+        if capacity / 2 <= reserved {
+            return Err(NodeError::InvalidRequest(format!(
+                "Capacity too small to cover the {} sat commitment fee and anchors",
+                reserved
+            ))
+            .into());
+        }
+        if capacity / 2 - reserved < limits.channel_reserve {
+            return Err(NodeError::LimitViolation(format!(
+                "Opening balance of {} sat would be below the channel reserve of {} sat",
+                capacity / 2 - reserved,
+                limits.channel_reserve
+            ))
+            .into());
+        }
+
+        let peer_online = self.is_peer_online(&peer_node_id);
         let channel = PaymentChannel {
             id: channel_id.clone(),
             peer_node_id,
             funding_txid,
             capacity,
-            my_balance: capacity / 2, // Split initial funding
-            peer_balance: capacity / 2,
+            my_balance: capacity / 2 - reserved, // Split initial funding
+            peer_balance: capacity - capacity / 2,
             sequence_number: 0,
             is_open: true,
             created_at: Utc::now(),
             multisig_address,
+            closing_txid: None,
+            commitment_type,
+            feerate_per_kw,
+            commitment_fee: fee,
+            is_funder: true,
+            limits,
+            sync_error: None,
+            peer_online,
         };
+        self.add_channel(&channel).await?;
 
-        // Save to database
-        self.database.save_channel(&channel).await?;
-        self.channels.insert(channel_id.clone(), channel.clone());
-        self.commitment_txs.insert(channel_id, Vec::new());
+        // The peer creates its side from this; it is replayed until acknowledged
+        let open = self.sign_update(P2PMessage::ChannelOpen {
+            channel_id: channel.id.clone(),
+            funding_txid: channel.funding_txid.clone(),
+            capacity,
+            initial_balance: channel.my_balance,
+            funder_key: self.key_manager.get_public_key().to_string(),
+            peer_node_id: channel.peer_node_id.clone(),
+            commitment_type,
+            feerate_per_kw,
+            limits: channel.limits.clone(),
+            signature: String::new(),
+        })?;
+        self.send_update(&channel.id, 0, None, open).await?;
 
         Ok(channel)
     }
 
-    pub async fn send_payment(&mut self, channel_id: &str, amount: u64) -> Result<PaymentRecord> {
-        let channel_snapshot: PaymentChannel;
+    /// Creates our side of a channel the peer funds from its signed
+    /// channel_open, sent by the node `funder_node_id`. Opens naming another
+    /// node, and replays of one we already took, are ignored.
+    async fn accept_channel_open(
+        &mut self,
+        funder_node_id: &str,
+        open: &WireMessage,
+    ) -> Result<()> {
+        let P2PMessage::ChannelOpen {
+            channel_id,
+            funding_txid,
+            capacity,
+            initial_balance,
+            funder_key,
+            peer_node_id,
+            commitment_type,
+            feerate_per_kw,
+            limits,
+            ..
+        } = open.message.clone()
+        else {
+            return Ok(());
+        };
 
+        // Gossip reaches every node; only the one named takes the channel
+        if self.channels.contains_key(&channel_id)
+            || !names_peer(
+                &peer_node_id,
+                &self.local_peer_id,
+                Some(&self.key_manager.get_node_id()),
+            )
         {
-            // limit scope of mutable borrow
-            let channel = self
-                .channels
-                .get_mut(channel_id)
-                .ok_or_else(|| anyhow::anyhow!("Channel not found"))?;
-
-            if !channel.is_open {
-                return Err(anyhow::anyhow!("Channel is not open"));
-            }
+            return Ok(());
+        }
 
-            if channel.my_balance < amount {
-                return Err(anyhow::anyhow!("Insufficient balance"));
-            }
+        let funder = funder_key
+            .parse::<bitcoin::secp256k1::PublicKey>()
+            .ok()
+            .filter(|key| crypto::node_id(key) == funder_node_id)
+            .ok_or_else(|| {
+                PeerViolation::new(
+                    Misbehaviour::ProtocolViolation,
+                    format!(
+                        "channel_open for {} names a funder key other than its sender's",
+                        channel_id
+                    ),
+                )
+            })?;
+        self.verify_signature(open, &funder)?;
+
+        limits.validate(capacity)?;
+        let estimate = self.estimate_feerate().await;
+        if feerate_per_kw < FEERATE_FLOOR_PER_KW || feerate_per_kw > estimate * 10 {
+            return Err(anyhow::anyhow!(
+                "Rejecting channel {} at {} sat/kw (estimate {})",
+                channel_id,
+                feerate_per_kw,
+                estimate
+            ));
+        }
+        let fee = commitment_fee(feerate_per_kw, commitment_type);
+        let reserved = fee + commitment_type.anchor_total();
+        if capacity / 2 <= reserved || initial_balance != capacity / 2 - reserved {
+            return Err(anyhow::anyhow!(
+                "Rejecting channel {}: funder balance {} does not match its half of {} sat",
+                channel_id,
+                initial_balance,
+                capacity
+            ));
+        }
+
+        let peer_node_id = funder.to_string();
+        let channel = PaymentChannel {
+            id: channel_id.clone(),
+            peer_online: self.is_peer_online(&peer_node_id),
+            peer_node_id,
+            funding_txid,
+            capacity,
+            my_balance: capacity - capacity / 2,
+            peer_balance: initial_balance,
+            sequence_number: 0,
+            is_open: true,
+            created_at: Utc::now(),
+            multisig_address: self
+                .key_manager
+                .create_multisig_address(&funder)?
+                .to_string(),
+            closing_txid: None,
+            commitment_type,
+            feerate_per_kw,
+            commitment_fee: fee,
+            is_funder: false,
+            limits,
+            sync_error: None,
+        };
+        self.add_channel(&channel).await?;
+        info!(
+            "Peer {} opened channel {} of {} sat",
+            channel.peer_node_id, channel_id, capacity
+        );
+        Ok(())
+    }
 
-            // update balances
-            channel.my_balance -= amount;
-            channel.peer_balance += amount;
-            channel.sequence_number += 1;
+    /// Stores a newly opened channel, funded by either side.
+    async fn add_channel(&mut self, channel: &PaymentChannel) -> Result<()> {
+        self.database.save_channel(channel).await?;
+        self.channels.insert(channel.id.clone(), channel.clone());
+        self.commitment_txs.insert(channel.id.clone(), Vec::new());
+        self.write_backup()?;
+        self.record_channel_metrics();
 
-            // snapshot so we can use it later
-            channel_snapshot = channel.clone();
+        self.emit(NodeEvent::ChannelOpened {
+            channel_id: channel.id.clone(),
+            peer_node_id: channel.peer_node_id.clone(),
+            capacity: channel.capacity,
+        })
+        .await;
+        Ok(())
+    }
 
-            // mutable borrow of `channel` ends here (scope closes)
-        }
+    /// Pays `amount` to the channel peer, optionally settling one of their invoices.
+    pub async fn send_payment(
+        &mut self,
+        channel_id: &str,
+        amount: u64,
+        invoice_id: Option<String>,
+    ) -> Result<PaymentRecord> {
+        let started = Instant::now();
+        let pending = self
+            .database
+            .get_pending_outbox(Some(channel_id))
+            .await?
+            .iter()
+            .filter(|entry| entry.kind == "payment")
+            .count();
 
-        // ✅ safe to borrow `&self` now
-        let commitment = self
-            .create_commitment_transaction(&channel_snapshot)
-            .await?;
+        let channel = self
+            .channels
+            .get(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+        channel.ensure_operational()?;
+        channel.limits.check_payment(amount, channel.my_balance)?;
+        channel.limits.check_pending(pending)?;
 
-        self.database
-            .save_commitment_transaction(&commitment)
-            .await?;
-        self.commitment_txs
-            .get_mut(channel_id)
-            .unwrap()
-            .push(commitment);
+        let commitment = self.commit_outgoing_payment(channel_id, amount).await?;
 
         let payment = PaymentRecord {
             id: Uuid::new_v4().to_string(),
             channel_id: channel_id.to_string(),
             amount,
             direction: "outgoing".to_string(),
-            sequence: channel_snapshot.sequence_number,
+            sequence: commitment.sequence,
             timestamp: Utc::now(),
             is_offline: true,
         };
 
         self.database.save_payment(&payment).await?;
 
-        // now we can safely borrow channel mutably again
-        if let Some(channel) = self.channels.get_mut(channel_id) {
-            self.database.update_channel(channel).await?;
-        }
+        self.send_payment_update(
+            channel_id,
+            amount,
+            commitment,
+            Some(&payment.id),
+            invoice_id,
+        )
+        .await?;
+
+        self.record_payment_metrics("outgoing", started);
+        self.emit(NodeEvent::PaymentSent {
+            channel_id: channel_id.to_string(),
+            payment_id: payment.id.clone(),
+            amount,
+        })
+        .await;
 
         Ok(payment)
     }
 
+    /// Moves `amount` to the peer at the next sequence and signs the new state.
+    async fn commit_outgoing_payment(
+        &mut self,
+        channel_id: &str,
+        amount: u64,
+    ) -> Result<CommitmentTransaction> {
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        channel.ensure_operational()?;
+        channel.limits.check_payment(amount, channel.my_balance)?;
+
+        channel.my_balance -= amount;
+        channel.peer_balance += amount;
+        channel.sequence_number += 1;
+        let channel_snapshot = channel.clone();
+
+        let commitment = self.commit_state(&channel_snapshot).await?;
+        self.database.update_channel(&channel_snapshot).await?;
+        Ok(commitment)
+    }
+
+    /// Signs the payment leading to `commitment` and queues it for the peer.
+    async fn send_payment_update(
+        &self,
+        channel_id: &str,
+        amount: u64,
+        commitment: CommitmentTransaction,
+        payment_id: Option<&str>,
+        invoice_id: Option<String>,
+    ) -> Result<()> {
+        let message = self.sign_update(P2PMessage::Payment {
+            channel_id: channel_id.to_string(),
+            amount,
+            sequence: commitment.sequence,
+            commitment_tx: commitment.raw_tx,
+            signature: String::new(),
+            invoice_id,
+        })?;
+        self.send_update(channel_id, commitment.sequence, payment_id, message)
+            .await
+    }
+
     pub async fn receive_payment(
         &mut self,
         channel_id: &str,
         amount: u64,
         sequence: u64,
+        invoice_id: Option<String>,
     ) -> Result<PaymentRecord> {
+        let started = Instant::now();
         let channel = self
             .channels
             .get_mut(channel_id)
-            .ok_or_else(|| anyhow::anyhow!("Channel not found"))?;
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
 
-        if !channel.is_open {
-            return Err(anyhow::anyhow!("Channel is not open"));
-        }
+        channel.ensure_operational()?;
+
+        channel
+            .limits
+            .check_payment(amount, channel.peer_balance)
+            .map_err(|e| anyhow::anyhow!("Rejected incoming payment: {}", e))?;
 
         // Update balances (from peer's payment)
         channel.peer_balance -= amount;
@@ -235,7 +879,8 @@ impl ChannelManager {
             direction: "incoming".to_string(),
             sequence,
             timestamp: Utc::now(),
-            is_offline: true,
+            // Received means both sides are in sync on it
+            is_offline: false,
         };
 
         // Save payment record
@@ -244,67 +889,2518 @@ impl ChannelManager {
         // Update channel in database
         self.database.update_channel(channel).await?;
 
+        self.record_payment_metrics("incoming", started);
+        self.emit(NodeEvent::PaymentReceived {
+            channel_id: channel_id.to_string(),
+            payment_id: payment.id.clone(),
+            amount,
+        })
+        .await;
+
+        if let Some(invoice_id) = invoice_id {
+            self.settle_invoice(&invoice_id, &payment).await?;
+        }
+
         Ok(payment)
     }
 
+    /// Signs and stores a commitment for the channel's current state, revoking the previous one.
+    async fn commit_state(&mut self, channel: &PaymentChannel) -> Result<CommitmentTransaction> {
+        let commitment = self.create_commitment_transaction(channel).await?;
+
+        self.database
+            .save_commitment_transaction(&commitment)
+            .await?;
+
+        let history = self.commitment_txs.entry(channel.id.clone()).or_default();
+
+        // The previous state is revoked by this update; let the towers punish it
+        if let (Some(tower_client), Some(revoked)) = (&self.tower_client, history.last())
+            && let Some(blob) = justice_blob(&self.key_manager, channel, revoked)?
+        {
+            tower_client.upload(blob);
+        }
+
+        history.push(commitment.clone());
+        Ok(commitment)
+    }
+
     async fn create_commitment_transaction(
         &self,
         channel: &PaymentChannel,
     ) -> Result<CommitmentTransaction> {
-        // Create a simplified commitment transaction
-        // In reality, this would be a proper Bitcoin transaction
-        let raw_tx = format!(
-            "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":0}}],\"outputs\":[{{\"amount\":{},\"address\":\"my_address\"}},{{\"amount\":{},\"address\":\"peer_address\"}}],\"sequence\":{}}}",
-            channel.funding_txid, channel.my_balance, channel.peer_balance, channel.sequence_number
-        );
-
-        let signature = hex::encode(
-            self.key_manager
-                .sign_message(raw_tx.as_bytes())?
-                .serialize_compact(),
-        );
-
-        Ok(CommitmentTransaction {
+        let mut commitment = CommitmentTransaction {
             id: Uuid::new_v4().to_string(),
             channel_id: channel.id.clone(),
             sequence: channel.sequence_number,
             my_balance: channel.my_balance,
             peer_balance: channel.peer_balance,
-            raw_tx,
-            signature,
+            fee: channel.commitment_fee,
+            raw_tx: channel.local_commitment_tx(),
+            signature: String::new(),
+            peer_signature: None,
             created_at: Utc::now(),
-        })
+            remote_txid: Some(transaction_id(&channel.remote_commitment_tx())),
+        };
+        commitment.signature = hex::encode(
+            self.key_manager
+                .sign(SigningDomain::Commitment, &commitment.signing_bytes())?
+                .serialize_compact(),
+        );
+
+        Ok(commitment)
     }
 
     pub async fn close_channel(&mut self, channel_id: &str) -> Result<()> {
         let channel = self
             .channels
             .get_mut(channel_id)
-            .ok_or_else(|| anyhow::anyhow!("Channel not found"))?;
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        channel.ensure_operational()?;
+
+        // Mutual close pays out the final balances directly, without the delays of a commitment
+        let closing_tx = format!(
+            "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":0}}],\"outputs\":[{{\"amount\":{},\"address\":\"my_address\"}},{{\"amount\":{},\"address\":\"peer_address\"}}],\"closing\":true}}",
+            channel.funding_txid, channel.my_balance, channel.peer_balance
+        );
 
         channel.is_open = false;
+        channel.closing_txid = Some(transaction_id(&closing_tx));
 
-        // In reality, broadcast the latest commitment transaction to Bitcoin network
-        println!(
+        // In reality, broadcast the closing transaction to Bitcoin network
+        info!(
             "Settling channel {} - Final balances: Me: {}, Peer: {}",
             channel_id, channel.my_balance, channel.peer_balance
         );
+        // Let the peer know the balances we settled on
+        let close = P2PMessage::ChannelClose {
+            channel_id: channel_id.to_string(),
+            final_balance_a: channel.my_balance,
+            final_balance_b: channel.peer_balance,
+        };
 
         // Update in database
         self.database.update_channel(channel).await?;
+        self.write_backup()?;
+        self.record_channel_metrics();
+
+        self.emit(NodeEvent::ChannelClosed {
+            channel_id: channel_id.to_string(),
+            reason: "cooperative".to_string(),
+        })
+        .await;
+        if let Some(sender) = &self.p2p_sender {
+            let _ = sender.send(close);
+        }
 
         Ok(())
     }
 
-    pub fn get_channel(&self, channel_id: &str) -> Option<&PaymentChannel> {
-        self.channels.get(channel_id)
-    }
+    /// Marks a channel closed after its funding output was spent on-chain.
+    pub async fn mark_channel_closed(&mut self, channel_id: &str) -> Result<()> {
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
 
-    pub fn get_all_channels(&self) -> Vec<&PaymentChannel> {
-        self.channels.values().collect()
-    }
+        let was_open = channel.is_open;
+        channel.is_open = false;
+        self.database.update_channel(channel).await?;
+        self.write_backup()?;
+        self.record_channel_metrics();
+
+        if was_open {
+            self.emit(NodeEvent::ChannelClosed {
+                channel_id: channel_id.to_string(),
+                reason: "on_chain".to_string(),
+            })
+            .await;
+        }
 
-    pub async fn get_channel_payments(&self, channel_id: &str) -> Result<Vec<PaymentRecord>> {
-        self.database.get_channel_payments(channel_id).await
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Unilaterally closes a channel by broadcasting our latest commitment.
+    pub async fn force_close_channel(&mut self, channel_id: &str) -> Result<()> {
+        let latest = self
+            .commitment_txs
+            .get(channel_id)
+            .and_then(|history| history.last())
+            .cloned();
+
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        if !channel.is_open {
+            return Err(NodeError::ChannelClosed(channel_id.to_string()).into());
+        }
+
+        match (&self.chain, latest) {
+            (Some(chain), Some(commitment)) => {
+                let txid = chain.broadcast(&commitment.raw_tx).await?;
+                info!(
+                    "Force-closed channel {} with commitment {} (seq {})",
+                    channel_id, txid, commitment.sequence
+                );
+            }
+            (None, Some(commitment)) => {
+                // In reality, broadcast the latest commitment transaction to Bitcoin network
+                info!(
+                    "Force-closing channel {} with commitment seq {} (no chain backend)",
+                    channel_id, commitment.sequence
+                );
+            }
+            (_, None) => {
+                info!(
+                    "Force-closing channel {} - no commitment yet, funding split as opened",
+                    channel_id
+                );
+            }
+        }
+
+        channel.is_open = false;
+        self.database.update_channel(channel).await?;
+        self.write_backup()?;
+        self.record_channel_metrics();
+
+        self.emit(NodeEvent::ChannelClosed {
+            channel_id: channel_id.to_string(),
+            reason: "force".to_string(),
+        })
+        .await;
+
+        Ok(())
+    }
+
+    /// Re-negotiates the commitment feerate (update_fee). Only the funder may
+    /// do this; `None` uses the chain backend's current estimate.
+    pub async fn update_fee(
+        &mut self,
+        channel_id: &str,
+        feerate_per_kw: Option<u64>,
+    ) -> Result<PaymentChannel> {
+        let feerate_per_kw = match feerate_per_kw {
+            Some(rate) => rate,
+            None => self.estimate_feerate().await,
+        };
+
+        if feerate_per_kw < FEERATE_FLOOR_PER_KW {
+            return Err(NodeError::InvalidRequest(format!(
+                "Feerate {} sat/kw is below the relay floor of {}",
+                feerate_per_kw, FEERATE_FLOOR_PER_KW
+            ))
+            .into());
+        }
+
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        channel.ensure_operational()?;
+
+        if !channel.is_funder {
+            return Err(NodeError::InvalidRequest(
+                "Only the channel funder can update the fee".to_string(),
+            )
+            .into());
+        }
+
+        let new_fee = commitment_fee(feerate_per_kw, channel.commitment_type);
+        let available = channel.my_balance + channel.commitment_fee;
+        if new_fee > available {
+            return Err(NodeError::InsufficientBalance(format!(
+                "Insufficient balance to pay a {} sat commitment fee",
+                new_fee
+            ))
+            .into());
+        }
+        if available - new_fee < channel.limits.channel_reserve {
+            return Err(NodeError::LimitViolation(format!(
+                "A {} sat commitment fee would take the balance below the channel reserve of {} sat",
+                new_fee,
+                channel.limits.channel_reserve
+            )).into());
+        }
+
+        channel.my_balance = available - new_fee;
+        channel.commitment_fee = new_fee;
+        channel.feerate_per_kw = feerate_per_kw;
+        channel.sequence_number += 1;
+        let channel_snapshot = channel.clone();
+
+        let commitment = self.commit_state(&channel_snapshot).await?;
+        self.database.update_channel(&channel_snapshot).await?;
+        self.record_channel_metrics();
+
+        self.send_update(
+            channel_id,
+            commitment.sequence,
+            None,
+            P2PMessage::UpdateFee {
+                channel_id: channel_id.to_string(),
+                feerate_per_kw,
+                sequence: commitment.sequence,
+            },
+        )
+        .await?;
+        self.send_update(
+            channel_id,
+            commitment.sequence,
+            None,
+            P2PMessage::CommitmentSigned {
+                channel_id: channel_id.to_string(),
+                signature: commitment.signature,
+                sequence: commitment.sequence,
+            },
+        )
+        .await?;
+
+        Ok(channel_snapshot)
+    }
+
+    /// CPFP-bumps a force-closed anchor channel so the commitment and child
+    /// together reach `target_feerate_per_kw`. Returns the child transaction id.
+    pub async fn bump_force_close(
+        &self,
+        channel_id: &str,
+        target_feerate_per_kw: u64,
+    ) -> Result<String> {
+        let channel = self
+            .channels
+            .get(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        if channel.is_open {
+            return Err(NodeError::ChannelStillOpen(channel_id.to_string()).into());
+        }
+
+        if channel.commitment_type != CommitmentType::Anchors {
+            return Err(NodeError::InvalidRequest(
+                "Only anchor channels can be fee-bumped after closing".to_string(),
+            )
+            .into());
+        }
+
+        let commitment = self
+            .commitment_txs
+            .get(channel_id)
+            .and_then(|history| history.last())
+            .ok_or_else(|| anyhow::anyhow!("Channel has no commitment to bump"))?;
+
+        let child_fee = cpfp_child_fee(
+            target_feerate_per_kw,
+            channel.commitment_type,
+            commitment.fee,
+        );
+        let child_tx = cpfp_transaction(
+            &self.key_manager,
+            &commitment.txid(),
+            commitment.my_balance,
+            child_fee,
+        )?;
+
+        match &self.chain {
+            Some(chain) => chain.broadcast(&child_tx).await,
+            None => Err(NodeError::ChainUnavailable.into()),
+        }
+    }
+
+    async fn estimate_feerate(&self) -> u64 {
+        match &self.chain {
+            Some(chain) => match chain.estimate_feerate_per_kw(COMMITMENT_CONF_TARGET).await {
+                Ok(rate) => rate,
+                Err(e) => {
+                    warn!("Fee estimation failed, using default: {}", e);
+                    DEFAULT_FEERATE_PER_KW
+                }
+            },
+            None => DEFAULT_FEERATE_PER_KW,
+        }
+    }
+
+    pub fn export_backup(&self) -> Result<Vec<u8>> {
+        self.static_backup().encrypt(&self.key_manager)
+    }
+
+    /// Recreates channels from a static backup and asks each peer to force-close,
+    /// which returns our balance on-chain. Channels we still know about are skipped.
+    pub async fn restore_backup(&mut self, data: &[u8]) -> Result<Vec<PaymentChannel>> {
+        let backup = StaticChannelBackup::decrypt(&self.key_manager, data)
+            .map_err(|e| NodeError::InvalidBackup(e.to_string()))?;
+        let mut restored = Vec::new();
+
+        for entry in backup.channels {
+            if self.channels.contains_key(&entry.channel_id) {
+                continue;
+            }
+
+            // Balances are unknown until the peer's commitment confirms
+            let channel = PaymentChannel {
+                id: entry.channel_id.clone(),
+                peer_node_id: entry.peer_node_id.clone(),
+                funding_txid: entry.funding_txid.clone(),
+                capacity: entry.capacity,
+                my_balance: 0,
+                peer_balance: 0,
+                sequence_number: 0,
+                is_open: false,
+                created_at: Utc::now(),
+                multisig_address: entry.multisig_address.clone(),
+                closing_txid: None,
+                commitment_type: CommitmentType::default(),
+                feerate_per_kw: 0,
+                commitment_fee: 0,
+                is_funder: entry.is_funder,
+                limits: ChannelLimits::for_capacity(entry.capacity),
+                sync_error: None,
+                peer_online: self.is_peer_online(&entry.peer_node_id),
+            };
+
+            self.database.save_channel(&channel).await?;
+            self.channels.insert(channel.id.clone(), channel.clone());
+            self.commitment_txs.insert(channel.id.clone(), Vec::new());
+
+            match &self.p2p_sender {
+                Some(sender) => {
+                    let _ = sender.send(self.force_close_request(&channel)?);
+                }
+                None => warn!(
+                    "P2P unavailable, cannot request force-close of channel {}",
+                    channel.id
+                ),
+            }
+
+            restored.push(channel);
+        }
+
+        self.record_channel_metrics();
+        Ok(restored)
+    }
+
+    /// Handles channel messages received from peers.
+    pub async fn handle_p2p_message(
+        &mut self,
+        author: &MessageAuthor,
+        received: WireMessage,
+    ) -> Result<()> {
+        let message = &received.message;
+        // Anyone can name a channel id; only its peer may update the channel
+        if let Some(channel) = message.channel_id().and_then(|id| self.channels.get(id))
+            && !names_peer(
+                &channel.peer_node_id,
+                &author.peer_id.to_string(),
+                Some(&author.node_id),
+            )
+        {
+            return Err(PeerViolation::new(
+                Misbehaviour::ProtocolViolation,
+                format!(
+                    "{} for channel {} from a node other than its peer",
+                    message.kind(),
+                    channel.id
+                ),
+            )
+            .into());
+        }
+
+        match message {
+            P2PMessage::ChannelOpen { channel_id, .. } => {
+                let channel_id = channel_id.clone();
+                self.accept_channel_open(&author.node_id, &received).await?;
+                // Ack, and reestablish so the funder retransmits updates it
+                // made before we had the channel (also when the open is a replay)
+                if let Some(channel) = self.channels.get(&channel_id) {
+                    let replies = self
+                        .ack_message(&channel_id)
+                        .into_iter()
+                        .chain([self.reestablish_message(channel)])
+                        .collect();
+                    self.send_replies(replies);
+                }
+            }
+            P2PMessage::ForceCloseRequest {
+                channel_id,
+                funding_txid,
+                ..
+            } => {
+                self.verify_update_signature(&received)?;
+                let matches = self
+                    .channels
+                    .get(channel_id)
+                    .is_some_and(|c| c.is_open && c.funding_txid == *funding_txid);
+
+                if matches {
+                    info!("Peer lost state for channel {}, force-closing", channel_id);
+                    self.force_close_channel(channel_id).await?;
+                }
+            }
+            P2PMessage::ChannelReestablish {
+                channel_id,
+                funding_txid,
+                sequence,
+                last_remote_sequence,
+                my_balance,
+                peer_balance,
+            } if self.channels.contains_key(channel_id) => {
+                self.handle_reestablish(
+                    channel_id,
+                    funding_txid,
+                    *sequence,
+                    *last_remote_sequence,
+                    (*my_balance, *peer_balance),
+                )
+                .await?
+            }
+            _ => {
+                let replies = self.apply_update(received).await?;
+                self.send_replies(replies);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_replies(&self, replies: Vec<P2PMessage>) {
+        if let Some(sender) = &self.p2p_sender {
+            for reply in replies {
+                let _ = sender.send(reply);
+            }
+        }
+    }
+
+    /// Applies a channel update from the peer, however it arrived, and returns
+    /// the replies owed: a counter-signature for a payment and an ack.
+    async fn apply_update(&mut self, update: WireMessage) -> Result<Vec<P2PMessage>> {
+        // Gossip reaches every peer; only the counterparty knows the channel
+        if !update
+            .message
+            .channel_id()
+            .is_some_and(|id| self.channels.contains_key(id))
+        {
+            return Ok(Vec::new());
+        }
+
+        self.verify_update_signature(&update)?;
+
+        let mut replies = Vec::new();
+        match update.message {
+            P2PMessage::Payment {
+                channel_id,
+                amount,
+                sequence,
+                commitment_tx,
+                invoice_id,
+                ..
+            } => {
+                let mut resend = Vec::new();
+                if self.is_concurrent_update(&channel_id, sequence) {
+                    // Both sides paid at once; the funder's update goes first
+                    if self.channels[&channel_id].is_funder {
+                        info!(
+                            "Ignoring update {} on channel {} made alongside ours, the peer resends it",
+                            sequence, channel_id
+                        );
+                        return Ok(Vec::new());
+                    }
+                    resend = self.roll_back_updates(&channel_id, sequence).await?;
+                }
+                let is_next = self.is_next_sequence(&channel_id, sequence)?;
+                if !self
+                    .check_payment_commitment(&channel_id, amount, sequence, &commitment_tx)
+                    .await?
+                {
+                    return Ok(Vec::new());
+                }
+                if is_next {
+                    self.receive_payment(&channel_id, amount, sequence, invoice_id)
+                        .await?;
+                }
+                // Only the latest state is counter-signed, never a revoked one
+                let channel = &self.channels[&channel_id];
+                if channel.sequence_number == sequence {
+                    let signature = self.key_manager.sign(
+                        SigningDomain::Commitment,
+                        &channel.remote_commitment_bytes(),
+                    )?;
+                    replies.push(P2PMessage::CommitmentSigned {
+                        channel_id: channel_id.clone(),
+                        signature: hex::encode(signature.serialize_compact()),
+                        sequence,
+                    });
+                }
+                replies.extend(self.ack_message(&channel_id));
+                // After the ack, which must not cover them
+                self.resend_payments(&channel_id, resend).await?;
+            }
+            P2PMessage::UpdateFee {
+                channel_id,
+                feerate_per_kw,
+                sequence,
+            } => {
+                // Only the funder sends update_fee, so it goes before our payments
+                let mut resend = Vec::new();
+                if self.is_concurrent_update(&channel_id, sequence)
+                    && !self.channels[&channel_id].is_funder
+                {
+                    resend = self.roll_back_updates(&channel_id, sequence).await?;
+                }
+                let is_next = self.is_next_sequence(&channel_id, sequence)?;
+                if is_next {
+                    self.apply_peer_fee_update(&channel_id, feerate_per_kw, sequence)
+                        .await?
+                }
+                replies.extend(self.ack_message(&channel_id));
+                // After the ack, which must not cover them
+                self.resend_payments(&channel_id, resend).await?;
+            }
+            P2PMessage::CommitmentSigned {
+                channel_id,
+                signature,
+                sequence,
+            } => {
+                self.save_peer_signature(&channel_id, sequence, &signature)
+                    .await?
+            }
+            P2PMessage::UpdateAck {
+                channel_id,
+                sequence,
+            } => self.acknowledge(&channel_id, sequence).await?,
+            _ => {}
+        }
+
+        Ok(replies)
+    }
+
+    /// Whether a peer update at `sequence` lands on a state we signed
+    /// ourselves, i.e. both sides updated the channel at the same time.
+    fn is_concurrent_update(&self, channel_id: &str, sequence: u64) -> bool {
+        sequence <= self.channels[channel_id].sequence_number
+            && self.local_sequences(channel_id).contains(&sequence)
+    }
+
+    /// Undoes our payments from `sequence` on, which the peer never applied as
+    /// it made its own update at that sequence, and returns them for resending.
+    async fn roll_back_updates(
+        &mut self,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<Vec<RolledBackPayment>> {
+        let mut payments = Vec::new();
+        for (payment_id, message) in self
+            .database
+            .get_pending_updates_from(channel_id, sequence)
+            .await?
+        {
+            match message {
+                P2PMessage::Payment {
+                    amount, invoice_id, ..
+                } => payments.push(RolledBackPayment {
+                    payment_id,
+                    amount,
+                    invoice_id,
+                }),
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "Cannot roll back our {} on channel {}",
+                        other.kind(),
+                        channel_id
+                    ));
+                }
+            }
+        }
+
+        let channel = self
+            .channels
+            .get_mut(channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+        // Every state from `sequence` on must be one of the payments undone
+        if payments.len() as u64 != channel.sequence_number + 1 - sequence {
+            return Err(anyhow::anyhow!(
+                "Channel {} has states after {} that are not ours to roll back",
+                channel_id,
+                sequence - 1
+            ));
+        }
+
+        let returned: u64 = payments.iter().map(|p| p.amount).sum();
+        channel.my_balance += returned;
+        channel.peer_balance -= returned;
+        channel.sequence_number = sequence - 1;
+        self.database
+            .delete_updates_from(channel_id, sequence)
+            .await?;
+        self.database.update_channel(channel).await?;
+        if let Some(history) = self.commitment_txs.get_mut(channel_id) {
+            history.retain(|c| c.sequence < sequence);
+        }
+
+        info!(
+            "Rolled back {} payments on channel {} to resend after the peer's update {}",
+            payments.len(),
+            channel_id,
+            sequence
+        );
+        Ok(payments)
+    }
+
+    /// Sends rolled-back payments again on top of the peer's updates, under
+    /// their original payment ids. One the channel can no longer afford is dropped.
+    async fn resend_payments(
+        &mut self,
+        channel_id: &str,
+        payments: Vec<RolledBackPayment>,
+    ) -> Result<()> {
+        for payment in payments {
+            let commitment = match self
+                .commit_outgoing_payment(channel_id, payment.amount)
+                .await
+            {
+                Ok(commitment) => commitment,
+                Err(e) => {
+                    warn!(
+                        "Dropping payment of {} sat on channel {} that collided with the peer's: {}",
+                        payment.amount, channel_id, e
+                    );
+                    if let Some(payment_id) = &payment.payment_id {
+                        self.database.delete_payment(payment_id).await?;
+                    }
+                    continue;
+                }
+            };
+            if let Some(payment_id) = &payment.payment_id {
+                self.database
+                    .update_payment_sequence(payment_id, commitment.sequence)
+                    .await?;
+            }
+            self.send_payment_update(
+                channel_id,
+                payment.amount,
+                commitment,
+                payment.payment_id.as_deref(),
+                payment.invoice_id,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Checks the peer's signature on an update that carries one. Channels
+    /// opened with a libp2p peer id have no key; gossip from them is only
+    /// accepted from that peer.
+    fn verify_update_signature(&self, update: &WireMessage) -> Result<()> {
+        let Some(channel) = update
+            .message
+            .channel_id()
+            .and_then(|id| self.channels.get(id))
+        else {
+            return Ok(());
+        };
+        match channel.peer_node_id.parse() {
+            Ok(peer_key) => self.verify_signature(update, &peer_key),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Checks that `key` signed the message as it was received, if it is one
+    /// that carries a signature.
+    fn verify_signature(
+        &self,
+        signed: &WireMessage,
+        key: &bitcoin::secp256k1::PublicKey,
+    ) -> Result<()> {
+        let message = &signed.message;
+        let Some(signature) = message.clone().signature_mut().map(std::mem::take) else {
+            return Ok(());
+        };
+
+        let valid = hex::decode(&signature)
+            .ok()
+            .and_then(|bytes| bitcoin::secp256k1::ecdsa::Signature::from_compact(&bytes).ok())
+            .is_some_and(|sig| {
+                self.key_manager.verify(
+                    SigningDomain::ChannelUpdate,
+                    &signed.signed_payload,
+                    &sig,
+                    key,
+                )
+            });
+        if !valid {
+            return Err(PeerViolation::new(
+                Misbehaviour::InvalidSignature,
+                format!(
+                    "Invalid signature on {} for channel {}",
+                    message.kind(),
+                    message.channel_id().unwrap_or_default()
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Refuses a payment at the latest or next sequence whose commitment isn't
+    /// the one we build for the peer at the state it leads to, so we never
+    /// counter-sign bytes the peer chose. Older retransmissions aren't signed.
+    /// A payment built on a state without our latest update was sent before
+    /// the peer had it and is ignored; reestablish sorts out which goes first.
+    /// Any other mismatch means the states diverged, which fails the channel
+    /// rather than blaming the peer. Returns whether the payment may be applied.
+    async fn check_payment_commitment(
+        &mut self,
+        channel_id: &str,
+        amount: u64,
+        sequence: u64,
+        commitment_tx: &str,
+    ) -> Result<bool> {
+        let mut expected = self.channels[channel_id].clone();
+        let current = expected.sequence_number;
+        if sequence == current + 1 {
+            expected.peer_balance = expected.peer_balance.saturating_sub(amount);
+            expected.my_balance = expected.my_balance.saturating_add(amount);
+            expected.sequence_number = sequence;
+        } else if sequence != current {
+            return Ok(true);
+        }
+
+        if commitment_tx == expected.remote_commitment_tx() {
+            return Ok(true);
+        }
+        if sequence == current + 1 && self.local_sequences(channel_id).contains(&current) {
+            info!(
+                "Ignoring update {} on channel {} made before the peer had our update {}",
+                sequence, channel_id, current
+            );
+            return Ok(false);
+        }
+        self.fail_channel(
+            channel_id,
+            format!(
+                "Peer's commitment for sequence {} does not match the channel state",
+                sequence
+            ),
+        )
+        .await?;
+        Ok(false)
+    }
+
+    /// Stores the peer's counter-signature of one of our commitments. The
+    /// funder's own signature sent with update_fee matches none and is skipped.
+    async fn save_peer_signature(
+        &mut self,
+        channel_id: &str,
+        sequence: u64,
+        signature: &str,
+    ) -> Result<()> {
+        let Some(channel) = self.channels.get(channel_id) else {
+            return Ok(());
+        };
+        let Some(commitment) = self
+            .commitment_txs
+            .get_mut(channel_id)
+            .and_then(|history| history.iter_mut().find(|c| c.sequence == sequence))
+        else {
+            return Ok(());
+        };
+
+        // Channels opened with a libp2p peer id have no key to check against
+        let Ok(peer_key) = channel
+            .peer_node_id
+            .parse::<bitcoin::secp256k1::PublicKey>()
+        else {
+            info!(
+                "Cannot verify counter-signature on channel {}: peer has no public key",
+                channel_id
+            );
+            return Ok(());
+        };
+        let valid = hex::decode(signature)
+            .ok()
+            .and_then(|bytes| bitcoin::secp256k1::ecdsa::Signature::from_compact(&bytes).ok())
+            .is_some_and(|sig| {
+                self.key_manager.verify(
+                    SigningDomain::Commitment,
+                    &commitment.signing_bytes(),
+                    &sig,
+                    &peer_key,
+                )
+            });
+        if !valid {
+            return Err(PeerViolation::new(
+                Misbehaviour::InvalidSignature,
+                format!(
+                    "Invalid counter-signature for sequence {} on channel {}",
+                    sequence, channel_id
+                ),
+            )
+            .into());
+        }
+
+        self.database
+            .save_peer_signature(&commitment.id, signature)
+            .await?;
+        commitment.peer_signature = Some(signature.to_string());
+        Ok(())
+    }
+
+    /// Signed bundle of the updates the peer has not acknowledged yet, to be
+    /// carried to it by hand.
+    pub async fn export_updates(&self, channel_id: &str) -> Result<Vec<u8>> {
+        if !self.channels.contains_key(channel_id) {
+            return Err(NodeError::ChannelNotFound(channel_id.to_string()).into());
+        }
+
+        let messages = self
+            .database
+            .get_pending_outbox_messages(channel_id)
+            .await?;
+        if messages.is_empty() {
+            return Err(NodeError::InvalidRequest(format!(
+                "Channel {} has no unacknowledged updates to export",
+                channel_id
+            ))
+            .into());
+        }
+
+        UpdateBundle::new(&self.key_manager, channel_id, messages).encode(&self.key_manager)
+    }
+
+    /// Applies a bundle exported by the channel peer. Returns how many updates
+    /// it carried and, if any need answering, the signed reply bundle.
+    pub async fn import_bundle(&mut self, data: &[u8]) -> Result<ImportedBundle> {
+        let bundle = UpdateBundle::decode(&self.key_manager, data)?;
+
+        // The first bundle of a channel the peer funds carries its channel_open
+        if let Some(open) = bundle.messages.iter().find(|m| {
+            matches!(&m.message, P2PMessage::ChannelOpen { channel_id, .. } if *channel_id == bundle.channel_id)
+        }) {
+            let signer = bundle
+                .public_key
+                .parse()
+                .map_err(|_| NodeError::InvalidBundle("Bundle signer key is invalid".into()))?;
+            self.accept_channel_open(&crypto::node_id(&signer), open)
+                .await?;
+        }
+
+        let channel = self
+            .channels
+            .get(&bundle.channel_id)
+            .ok_or_else(|| NodeError::ChannelNotFound(bundle.channel_id.clone()))?;
+        if !channel
+            .peer_node_id
+            .eq_ignore_ascii_case(&bundle.public_key)
+        {
+            return Err(NodeError::InvalidBundle(format!(
+                "Bundle was signed by {}, not the peer of channel {}",
+                bundle.public_key, bundle.channel_id
+            ))
+            .into());
+        }
+        if let Some(message) = bundle.messages.iter().map(|m| &m.message).find(|m| {
+            m.channel_id() != Some(&bundle.channel_id)
+                || !matches!(
+                    m,
+                    P2PMessage::ChannelOpen { .. }
+                        | P2PMessage::Payment { .. }
+                        | P2PMessage::UpdateFee { .. }
+                        | P2PMessage::CommitmentSigned { .. }
+                        | P2PMessage::UpdateAck { .. }
+                )
+        }) {
+            return Err(NodeError::InvalidBundle(format!(
+                "Bundle carries a {} message that cannot be imported",
+                message.kind()
+            ))
+            .into());
+        }
+
+        let mut replies = Vec::new();
+        for message in bundle.messages.iter().cloned() {
+            if let P2PMessage::ChannelOpen { .. } = message.message {
+                replies.extend(self.ack_message(&bundle.channel_id));
+            } else {
+                replies.extend(self.apply_update(message).await?);
+            }
+        }
+
+        let reply = if replies.is_empty() {
+            None
+        } else {
+            Some(
+                UpdateBundle::new(&self.key_manager, &bundle.channel_id, replies)
+                    .encode(&self.key_manager)?,
+            )
+        };
+        info!(
+            "Imported {} updates on channel {}",
+            bundle.messages.len(),
+            bundle.channel_id
+        );
+
+        Ok(ImportedBundle {
+            channel_id: bundle.channel_id,
+            updates: bundle.messages.len(),
+            reply,
+        })
+    }
+
+    /// Accepts the funder's new feerate if it is within sane bounds of our own estimate.
+    async fn apply_peer_fee_update(
+        &mut self,
+        channel_id: &str,
+        feerate_per_kw: u64,
+        sequence: u64,
+    ) -> Result<()> {
+        let estimate = self.estimate_feerate().await;
+
+        let Some(channel) = self.channels.get_mut(channel_id) else {
+            return Ok(());
+        };
+
+        channel.ensure_operational()?;
+        if channel.is_funder {
+            // Only the funder sends update_fee
+            return Err(PeerViolation::new(
+                Misbehaviour::ProtocolViolation,
+                format!("Unexpected update_fee for channel {}", channel_id),
+            )
+            .into());
+        }
+
+        if feerate_per_kw < FEERATE_FLOOR_PER_KW || feerate_per_kw > estimate * 10 {
+            return Err(anyhow::anyhow!(
+                "Rejecting update_fee of {} sat/kw for channel {} (estimate {})",
+                feerate_per_kw,
+                channel_id,
+                estimate
+            ));
+        }
+
+        let new_fee = commitment_fee(feerate_per_kw, channel.commitment_type);
+        let available = channel.peer_balance + channel.commitment_fee;
+        if new_fee > available {
+            return Err(anyhow::anyhow!(
+                "Peer cannot afford update_fee on channel {}",
+                channel_id
+            ));
+        }
+        if available - new_fee < channel.limits.channel_reserve {
+            return Err(anyhow::anyhow!(
+                "Peer update_fee on channel {} would breach the channel reserve",
+                channel_id
+            ));
+        }
+
+        channel.peer_balance = available - new_fee;
+        channel.commitment_fee = new_fee;
+        channel.feerate_per_kw = feerate_per_kw;
+        channel.sequence_number = sequence;
+        self.database.update_channel(channel).await?;
+        self.record_channel_metrics();
+
+        Ok(())
+    }
+
+    /// Whether a peer update with `sequence` applies next. Retransmissions we
+    /// already applied are skipped; a gap is an error until reestablish fills it.
+    fn is_next_sequence(&self, channel_id: &str, sequence: u64) -> Result<bool> {
+        let current = self
+            .channels
+            .get(channel_id)
+            .map(|c| c.sequence_number)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.to_string()))?;
+
+        if sequence <= current {
+            info!(
+                "Ignoring update {} on channel {}, already at {}",
+                sequence, channel_id, current
+            );
+            return Ok(false);
+        }
+        if sequence > current + 1 {
+            return Err(anyhow::anyhow!(
+                "Update {} on channel {} skips ahead of {}, waiting for reestablish",
+                sequence,
+                channel_id,
+                current
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Sends channel_reestablish for every open channel; called whenever a
+    /// peer (re)joins, as we cannot tell which channels it is party to. A
+    /// channel_open the peer has not acknowledged goes out first.
+    pub async fn reestablish_channels(&self) {
+        let Some(sender) = &self.p2p_sender else {
+            return;
+        };
+        // Failed channels are included so the peer learns of the divergence too
+        for channel in self.channels.values().filter(|c| c.is_open) {
+            if channel.is_funder {
+                match self.database.get_pending_outbox_messages(&channel.id).await {
+                    Ok(pending) => pending
+                        .into_iter()
+                        .filter(|m| matches!(m, P2PMessage::ChannelOpen { .. }))
+                        .for_each(|open| {
+                            let _ = sender.send(open);
+                        }),
+                    Err(e) => {
+                        error!("Failed to load the outbox of channel {}: {}", channel.id, e)
+                    }
+                }
+            }
+            let _ = sender.send(self.reestablish_message(channel));
+        }
+    }
+
+    fn reestablish_message(&self, channel: &PaymentChannel) -> P2PMessage {
+        P2PMessage::ChannelReestablish {
+            channel_id: channel.id.clone(),
+            funding_txid: channel.funding_txid.clone(),
+            sequence: channel.sequence_number,
+            last_remote_sequence: self.last_remote_sequence(channel),
+            my_balance: channel.my_balance,
+            peer_balance: channel.peer_balance,
+        }
+    }
+
+    /// Sequences of the states we signed, i.e. of the updates we sent.
+    fn local_sequences(&self, channel_id: &str) -> HashSet<u64> {
+        self.commitment_txs
+            .get(channel_id)
+            .map(|history| history.iter().map(|c| c.sequence).collect())
+            .unwrap_or_default()
+    }
+
+    /// Latest update the peer sent us: every state we did not sign came from them.
+    fn last_remote_sequence(&self, channel: &PaymentChannel) -> u64 {
+        let local = self.local_sequences(&channel.id);
+        (1..=channel.sequence_number)
+            .rev()
+            .find(|s| !local.contains(s))
+            .unwrap_or(0)
+    }
+
+    /// Compares the peer's view of a channel with ours. Updates the peer missed
+    /// are retransmitted; the peer retransmits ours in turn. If either side lost
+    /// an update it sent, the states can't be reconciled and the channel is failed.
+    async fn handle_reestablish(
+        &mut self,
+        channel_id: &str,
+        funding_txid: &str,
+        sequence: u64,
+        last_remote_sequence: u64,
+        (their_balance, their_peer_balance): (u64, u64),
+    ) -> Result<()> {
+        let Some(channel) = self.channels.get(channel_id).cloned() else {
+            return Ok(());
+        };
+        if !channel.is_open || channel.sync_error.is_some() || channel.funding_txid != funding_txid
+        {
+            return Ok(());
+        }
+
+        let local = self.local_sequences(channel_id);
+        let last_local = local.iter().copied().max().unwrap_or(0);
+
+        if last_remote_sequence > last_local {
+            // The peer holds a state we signed but no longer have; only its
+            // commitment is safe to broadcast now, as after a backup restore
+            self.fail_channel(
+                channel_id,
+                format!(
+                    "Lost local data: peer has our update {} but our latest is {}",
+                    last_remote_sequence, last_local
+                ),
+            )
+            .await?;
+            if let Some(sender) = &self.p2p_sender {
+                let _ = sender.send(self.force_close_request(&channel)?);
+            }
+            return Ok(());
+        }
+
+        // Both sides updated while apart: the peer holds a state of its own
+        // where we signed one too. The funder's updates go first and the
+        // fundee rolls its own back when they arrive
+        if let Some(collision) = local
+            .iter()
+            .copied()
+            .filter(|s| *s > last_remote_sequence && *s <= sequence)
+            .min()
+        {
+            if channel.is_funder {
+                self.retransmit(&channel, collision - 1).await?;
+            }
+            return Ok(());
+        }
+
+        if sequence < channel.sequence_number {
+            if let Some(lost) =
+                (sequence + 1..=channel.sequence_number).find(|s| !local.contains(s))
+            {
+                // The peer's own reestablish check will ask us to force-close
+                return self
+                    .fail_channel(
+                        channel_id,
+                        format!("Peer lost its update {} (peer at {})", lost, sequence),
+                    )
+                    .await;
+            }
+            self.retransmit(&channel, sequence).await?;
+        } else if sequence == channel.sequence_number
+            && (their_balance != channel.peer_balance || their_peer_balance != channel.my_balance)
+        {
+            return self
+                .fail_channel(
+                    channel_id,
+                    format!("Balances differ from the peer's at sequence {}", sequence),
+                )
+                .await;
+        }
+        // A peer ahead of us retransmits its updates when it reads our reestablish
+
+        // Whatever the peer has applied counts as delivered
+        self.acknowledge(channel_id, sequence).await
+    }
+
+    /// Resends the updates we made after `sequence` from the outbox, in the
+    /// order they were first sent.
+    async fn retransmit(&self, channel: &PaymentChannel, sequence: u64) -> Result<()> {
+        let Some(sender) = &self.p2p_sender else {
+            return Ok(());
+        };
+        let messages = self
+            .database
+            .get_outbox_messages_after(&channel.id, sequence)
+            .await?;
+        for message in &messages {
+            let _ = sender.send(message.clone());
+        }
+
+        info!(
+            "Retransmitted {} messages on channel {} after sequence {}",
+            messages.len(),
+            channel.id,
+            sequence
+        );
+        Ok(())
+    }
+
+    /// Asks the peer to broadcast its latest commitment of the channel, as
+    /// we can't trust ours; signed so no one else can ask.
+    fn force_close_request(&self, channel: &PaymentChannel) -> Result<P2PMessage> {
+        self.sign_update(P2PMessage::ForceCloseRequest {
+            channel_id: channel.id.clone(),
+            funding_txid: channel.funding_txid.clone(),
+            peer_node_id: channel.peer_node_id.clone(),
+            signature: String::new(),
+        })
+    }
+
+    /// Fills in our signature over the update's canonical encoding.
+    fn sign_update(&self, mut message: P2PMessage) -> Result<P2PMessage> {
+        let signature = self.key_manager.sign(
+            SigningDomain::ChannelUpdate,
+            &wire::signed_payload(&message),
+        )?;
+        if let Some(field) = message.signature_mut() {
+            *field = hex::encode(signature.serialize_compact());
+        }
+        Ok(message)
+    }
+
+    /// Stores a channel update in the outbox, then hands it to the P2P task.
+    /// It stays pending until the peer acknowledges its sequence.
+    async fn send_update(
+        &self,
+        channel_id: &str,
+        sequence: u64,
+        payment_id: Option<&str>,
+        message: P2PMessage,
+    ) -> Result<()> {
+        self.database
+            .save_outbox_message(channel_id, sequence, payment_id, &message)
+            .await?;
+        if let Some(sender) = &self.p2p_sender {
+            let _ = sender.send(message);
+        }
+        Ok(())
+    }
+
+    /// Tells the peer which of its updates we have applied.
+    fn ack_message(&self, channel_id: &str) -> Option<P2PMessage> {
+        self.channels
+            .get(channel_id)
+            .map(|channel| P2PMessage::UpdateAck {
+                channel_id: channel_id.to_string(),
+                sequence: channel.sequence_number,
+            })
+    }
+
+    /// The peer has every update up to `sequence`: clears them from the outbox
+    /// and marks the payments among them as no longer offline.
+    async fn acknowledge(&self, channel_id: &str, sequence: u64) -> Result<()> {
+        let Some(channel) = self.channels.get(channel_id) else {
+            return Ok(());
+        };
+        let sequence = sequence.min(channel.sequence_number);
+        let delivered = self
+            .database
+            .mark_outbox_delivered(channel_id, sequence)
+            .await?;
+        if delivered > 0 {
+            info!(
+                "Peer acknowledged {} updates on channel {} up to sequence {}",
+                delivered, channel_id, sequence
+            );
+        }
+        Ok(())
+    }
+
+    /// Stops all updates on a channel whose state can't be reconciled with the
+    /// peer's; only a force-close is accepted afterwards.
+    async fn fail_channel(&mut self, channel_id: &str, reason: String) -> Result<()> {
+        warn!("Channel {} is out of sync: {}", channel_id, reason);
+        if let Some(channel) = self.channels.get_mut(channel_id) {
+            channel.sync_error = Some(reason);
+            self.database.update_channel(channel).await?;
+        }
+        Ok(())
+    }
+
+    /// Flags a channel whose funding output was spent by a transaction we
+    /// have no record of. It stays open, refusing updates, for the operator
+    /// to investigate rather than being treated as closed.
+    pub async fn flag_unknown_spend(&mut self, channel_id: &str, spend_txid: &str) -> Result<()> {
+        self.fail_channel(
+            channel_id,
+            format!(
+                "Funding output spent by unknown transaction {}; needs operator attention",
+                spend_txid
+            ),
+        )
+        .await
+    }
+
+    /// Records a peer coming online or going offline on its channels. Returns
+    /// whether the peer has an open channel with us.
+    pub fn set_peer_status(&mut self, status: &PeerStatus) -> bool {
+        let peer_id = status.peer_id.to_string();
+        if status.online {
+            self.online_peers
+                .insert(peer_id.clone(), status.node_id.clone());
+        } else {
+            self.online_peers.remove(&peer_id);
+        }
+
+        let mut has_open_channel = false;
+        for channel in self.channels.values_mut() {
+            if names_peer(&channel.peer_node_id, &peer_id, status.node_id.as_deref()) {
+                channel.peer_online = status.online;
+                has_open_channel |= channel.is_open;
+            }
+        }
+        has_open_channel
+    }
+
+    fn is_peer_online(&self, peer_node_id: &str) -> bool {
+        self.online_peers
+            .iter()
+            .any(|(peer_id, node_id)| names_peer(peer_node_id, peer_id, node_id.as_deref()))
+    }
+
+    /// Refreshes the channel count and balance gauges.
+    fn record_channel_metrics(&self) {
+        let (mut open, mut closed, mut local, mut remote) = (0, 0, 0, 0);
+        for channel in self.channels.values() {
+            if channel.is_open {
+                open += 1;
+                local += channel.my_balance;
+                remote += channel.peer_balance;
+            } else {
+                closed += 1;
+            }
+        }
+
+        METRICS.channels.with_label_values(&["open"]).set(open);
+        METRICS.channels.with_label_values(&["closed"]).set(closed);
+        METRICS.local_balance.set(local as i64);
+        METRICS.remote_balance.set(remote as i64);
+    }
+
+    /// Counts a completed payment; failed ones are not timed.
+    fn record_payment_metrics(&self, direction: &str, started: Instant) {
+        self.record_channel_metrics();
+        METRICS.payments.with_label_values(&[direction]).inc();
+        METRICS
+            .payment_duration
+            .with_label_values(&[direction])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    fn static_backup(&self) -> StaticChannelBackup {
+        StaticChannelBackup::from_channels(self.key_manager.get_node_id(), self.channels.values())
+    }
+
+    fn write_backup(&self) -> Result<()> {
+        if let Some(path) = &self.backup_path {
+            self.static_backup()
+                .write_to_file(&self.key_manager, path)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_channel(&self, channel_id: &str) -> Option<&PaymentChannel> {
+        self.channels.get(channel_id)
+    }
+
+    pub fn get_all_channels(&self) -> Vec<&PaymentChannel> {
+        self.channels.values().collect()
+    }
+
+    /// One page of payment history matching `filter`.
+    pub async fn list_payments(&self, filter: &PaymentFilter) -> Result<PaymentPage> {
+        let invalid = |message: &str| -> anyhow::Error {
+            NodeError::InvalidRequest(message.to_string()).into()
+        };
+
+        if let Some(direction) = &filter.direction
+            && direction != "incoming"
+            && direction != "outgoing"
+        {
+            return Err(invalid("direction must be \"incoming\" or \"outgoing\""));
+        }
+        if let (Some(from), Some(to)) = (filter.from, filter.to)
+            && from >= to
+        {
+            return Err(invalid("from must be before to"));
+        }
+        if let (Some(min_amount), Some(max_amount)) = (filter.min_amount, filter.max_amount)
+            && min_amount > max_amount
+        {
+            return Err(invalid("min_amount must not exceed max_amount"));
+        }
+
+        let cursor = match &filter.cursor {
+            Some(cursor) => {
+                Some(PaymentCursor::decode(cursor).ok_or_else(|| invalid("Invalid cursor"))?)
+            }
+            None => None,
+        };
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        // Fetch one extra row to learn whether another page follows
+        let mut payments = self
+            .database
+            .get_payments(filter, cursor.as_ref(), limit + 1)
+            .await?;
+        let next_cursor = if payments.len() > limit as usize {
+            payments.truncate(limit as usize);
+            payments.last().map(|last| {
+                PaymentCursor {
+                    timestamp: last.timestamp,
+                    id: last.id.clone(),
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(PaymentPage {
+            payments,
+            next_cursor,
+        })
+    }
+
+    /// Every payment matching `filter`, in pages of `MAX_PAGE_SIZE`, capped at
+    /// `filter.limit` when set. Backs the unpaged per-channel listing.
+    pub async fn collect_payments(&self, filter: &PaymentFilter) -> Result<Vec<PaymentRecord>> {
+        let wanted = filter.limit.map(|limit| limit as usize);
+        let mut filter = filter.clone();
+        filter.limit = Some(MAX_PAGE_SIZE);
+
+        let mut payments = Vec::new();
+        loop {
+            let page = self.list_payments(&filter).await?;
+            payments.extend(page.payments);
+            if let Some(wanted) = wanted
+                && payments.len() >= wanted
+            {
+                payments.truncate(wanted);
+                break;
+            }
+            match page.next_cursor {
+                Some(cursor) => filter.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        Ok(payments)
+    }
+
+    /// Signed commitments for the channel, oldest first.
+    pub async fn get_channel_commitments(
+        &self,
+        channel_id: &str,
+    ) -> Result<Vec<CommitmentTransaction>> {
+        if !self.channels.contains_key(channel_id) {
+            return Err(NodeError::ChannelNotFound(channel_id.to_string()).into());
+        }
+        self.database.get_channel_commitments(channel_id).await
+    }
+
+    pub async fn get_channel_stats(&self, channel_id: &str) -> Result<ChannelStats> {
+        if !self.channels.contains_key(channel_id) {
+            return Err(NodeError::ChannelNotFound(channel_id.to_string()).into());
+        }
+        self.database.get_channel_stats(channel_id).await
+    }
+
+    pub async fn create_invoice(&self, amount: u64, memo: String) -> Result<Invoice> {
+        if amount == 0 {
+            return Err(
+                NodeError::InvalidRequest("Invoice amount must be positive".to_string()).into(),
+            );
+        }
+
+        let invoice = Invoice {
+            id: Uuid::new_v4().to_string(),
+            amount,
+            memo,
+            created_at: Utc::now(),
+            settled_at: None,
+            payment_id: None,
+        };
+        self.database.save_invoice(&invoice).await?;
+
+        Ok(invoice)
+    }
+
+    pub async fn get_invoices(&self) -> Result<Vec<Invoice>> {
+        self.database.get_invoices().await
+    }
+
+    /// Updates the peer has not acknowledged yet, oldest first.
+    pub async fn get_pending_outbox(&self, channel_id: Option<&str>) -> Result<Vec<OutboxEntry>> {
+        self.database.get_pending_outbox(channel_id).await
+    }
+
+    /// Settles an open invoice paid in full; anything else is left for the user to sort out.
+    async fn settle_invoice(&self, invoice_id: &str, payment: &PaymentRecord) -> Result<()> {
+        let Some(invoice) = self.database.get_invoice(invoice_id).await? else {
+            warn!(
+                "Payment {} references unknown invoice {}",
+                payment.id, invoice_id
+            );
+            return Ok(());
+        };
+
+        if invoice.settled_at.is_some() || payment.amount < invoice.amount {
+            warn!(
+                "Payment {} of {} sat does not settle invoice {}",
+                payment.id, payment.amount, invoice_id
+            );
+            return Ok(());
+        }
+
+        let settled = self
+            .database
+            .settle_invoice(invoice_id, &payment.id)
+            .await?;
+        if !settled {
+            warn!(
+                "Payment {} arrived after invoice {} was settled",
+                payment.id, invoice_id
+            );
+            return Ok(());
+        }
+        self.emit(NodeEvent::InvoiceSettled {
+            channel_id: payment.channel_id.clone(),
+            invoice_id: invoice_id.to_string(),
+            amount: payment.amount,
+        })
+        .await;
+
+        Ok(())
+    }
+}
+
+/// Whether a channel's `peer_node_id`, a public key or a libp2p peer id,
+/// names the peer with this peer id and announced node id.
+pub fn names_peer(peer_node_id: &str, peer_id: &str, node_id: Option<&str>) -> bool {
+    if peer_node_id == peer_id {
+        return true;
+    }
+    match (peer_node_id.parse(), node_id) {
+        (Ok(key), Some(node_id)) => crypto::node_id(&key) == node_id,
+        _ => false,
+    }
+}
+
+/// One of our payments undone after colliding with a concurrent update of the peer.
+struct RolledBackPayment {
+    payment_id: Option<String>,
+    amount: u64,
+    invoice_id: Option<String>,
+}
+
+/// Tower blob punishing a broadcast of the peer's `revoked` commitment, the
+/// only one it can sign. States saved before peer txids were tracked have none.
+fn justice_blob(
+    key_manager: &KeyManager,
+    channel: &PaymentChannel,
+    revoked: &CommitmentTransaction,
+) -> Result<Option<BreachBlob>> {
+    let Some(revoked_txid) = &revoked.remote_txid else {
+        return Ok(None);
+    };
+    let justice_tx = penalty_transaction(
+        key_manager,
+        revoked_txid,
+        revoked,
+        penalty_fee(channel.feerate_per_kw),
+    )?;
+    Ok(Some(BreachBlob::seal(revoked_txid, &justice_tx)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::TlvWriter;
+
+    /// A channel manager whose outgoing gossip is held until delivered.
+    struct TestNode {
+        manager: ChannelManager,
+        outbound: mpsc::UnboundedReceiver<P2PMessage>,
+        author: MessageAuthor,
+    }
+
+    async fn test_node(seed: u8) -> TestNode {
+        let key_manager = Arc::new(KeyManager::from_seed([seed; 32]).unwrap());
+        let database = Arc::new(Database::in_memory().await.unwrap());
+        let (sender, outbound) = mpsc::unbounded_channel();
+        let author = MessageAuthor {
+            peer_id: p2p_keypair(&key_manager).unwrap().public().to_peer_id(),
+            node_id: key_manager.get_node_id(),
+        };
+        let manager = ChannelManager::new(key_manager, database)
+            .await
+            .unwrap()
+            .with_p2p_sender(sender);
+        TestNode {
+            manager,
+            outbound,
+            author,
+        }
+    }
+
+    /// Reads `message` back off the wire, as a peer would receive it.
+    fn received(message: P2PMessage) -> WireMessage {
+        wire::decode_signed(&wire::encode(&message))
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Hands everything `from` sent to `to`, as gossip would.
+    async fn deliver(from: &mut TestNode, to: &mut TestNode) {
+        while let Ok(message) = from.outbound.try_recv() {
+            to.manager
+                .handle_p2p_message(&from.author, received(message))
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Loses everything `node` sent, as if it had no connection.
+    fn drop_outbound(node: &mut TestNode) {
+        while node.outbound.try_recv().is_ok() {}
+    }
+
+    async fn open_channel(funder: &mut TestNode, fundee: &TestNode) -> String {
+        funder
+            .manager
+            .open_channel(
+                fundee.manager.key_manager.get_public_key().to_string(),
+                1_000_000,
+                CommitmentType::Legacy,
+                ChannelLimits::for_capacity(1_000_000),
+            )
+            .await
+            .unwrap()
+            .id
+    }
+
+    /// Alice funds a channel with Bob and both sides have synced on it.
+    async fn open_synced_channel() -> (TestNode, TestNode, String) {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+        (alice, bob, channel_id)
+    }
+
+    /// Both sides reestablish, then exchange messages until neither has
+    /// anything left to send.
+    async fn reconnect(alice: &mut TestNode, bob: &mut TestNode) {
+        alice.manager.reestablish_channels().await;
+        bob.manager.reestablish_channels().await;
+        while !alice.outbound.is_empty() || !bob.outbound.is_empty() {
+            deliver(alice, bob).await;
+            deliver(bob, alice).await;
+        }
+    }
+
+    /// Rolls a channel back to its opening state, as restoring an old
+    /// database would.
+    fn lose_state(node: &mut TestNode, channel_id: &str, opened: &PaymentChannel) {
+        node.manager
+            .channels
+            .insert(channel_id.to_string(), opened.clone());
+        node.manager
+            .commitment_txs
+            .insert(channel_id.to_string(), Vec::new());
+    }
+
+    async fn pending_outbox(node: &TestNode, channel_id: &str) -> usize {
+        node.manager
+            .get_pending_outbox(Some(channel_id))
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn channel_open_creates_the_fundee_side() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 1);
+
+        deliver(&mut alice, &mut bob).await;
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert!(!fundee.is_funder);
+        assert_eq!(
+            fundee.peer_node_id,
+            alice.manager.key_manager.get_public_key().to_string()
+        );
+        assert_eq!(fundee.my_balance, funder.peer_balance);
+        assert_eq!(fundee.peer_balance, funder.my_balance);
+        assert_eq!(fundee.commitment_fee, funder.commitment_fee);
+        assert_eq!(fundee.multisig_address, funder.multisig_address);
+
+        // Bob's ack clears the channel_open from Alice's outbox
+        deliver(&mut bob, &mut alice).await;
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+        assert!(
+            alice
+                .manager
+                .get_channel(&channel_id)
+                .unwrap()
+                .sync_error
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_payment_is_replayed_and_acknowledged() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        drop_outbound(&mut alice);
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 1);
+
+        // Reconnect: Bob's reestablish shows he missed the payment, so Alice
+        // retransmits it; his counter-signature and ack come back
+        alice.manager.reestablish_channels().await;
+        bob.manager.reestablish_channels().await;
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert_eq!(fundee.sequence_number, 1);
+        assert_eq!(fundee.my_balance, funder.peer_balance);
+        assert_eq!(fundee.peer_balance, funder.my_balance);
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+        assert!(
+            alice.manager.commitment_txs[&channel_id]
+                .last()
+                .unwrap()
+                .peer_signature
+                .is_some()
+        );
+
+        let filter = PaymentFilter {
+            channel_id: Some(channel_id),
+            ..Default::default()
+        };
+        let payments = alice
+            .manager
+            .database
+            .get_payments(&filter, None, 10)
+            .await
+            .unwrap();
+        assert!(!payments[0].is_offline);
+    }
+
+    #[tokio::test]
+    async fn update_from_another_node_is_rejected() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let carol = test_node(3).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        let payment = alice.outbound.try_recv().unwrap();
+        let error = bob
+            .manager
+            .handle_p2p_message(&carol.author, received(payment))
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<PeerViolation>().is_some());
+        assert_eq!(
+            bob.manager
+                .get_channel(&channel_id)
+                .unwrap()
+                .sequence_number,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_payment_is_rejected() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        let Ok(P2PMessage::Payment {
+            amount,
+            sequence,
+            commitment_tx,
+            signature,
+            ..
+        }) = alice.outbound.try_recv()
+        else {
+            panic!("expected a payment");
+        };
+        // Claiming an invoice changes nothing but the signed bytes
+        let tampered = P2PMessage::Payment {
+            channel_id: channel_id.clone(),
+            amount,
+            sequence,
+            commitment_tx,
+            signature,
+            invoice_id: Some("invoice".to_string()),
+        };
+        let error = bob
+            .manager
+            .handle_p2p_message(&alice.author, received(tampered))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PeerViolation>(),
+            Some(PeerViolation {
+                kind: Misbehaviour::InvalidSignature,
+                ..
+            })
+        ));
+        assert_eq!(
+            bob.manager
+                .get_channel(&channel_id)
+                .unwrap()
+                .sequence_number,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_unknown_odd_field_is_rejected() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        let Ok(payment) = alice.outbound.try_recv() else {
+            panic!("expected a payment");
+        };
+        let P2PMessage::Payment {
+            amount,
+            sequence,
+            commitment_tx,
+            ..
+        } = &payment
+        else {
+            panic!("expected a payment");
+        };
+        // A newer Alice sends an optional field Bob doesn't know
+        let header = wire::encode(&payment)[..3].to_vec();
+        let raw = |signature: Option<&str>, hint: &str| {
+            let mut tlv = TlvWriter::new();
+            tlv.string(0, &channel_id);
+            tlv.u64(2, *amount);
+            tlv.u64(4, *sequence);
+            tlv.string(6, commitment_tx);
+            if let Some(signature) = signature {
+                tlv.string(8, signature);
+            }
+            tlv.string(11, hint);
+            [header.clone(), tlv.finish()].concat()
+        };
+        let signature = alice
+            .manager
+            .key_manager
+            .sign(SigningDomain::ChannelUpdate, &raw(None, "route hint"))
+            .unwrap();
+        let signature = hex::encode(signature.serialize_compact());
+
+        let tampered = wire::decode_signed(&raw(Some(&signature), "other hint"))
+            .unwrap()
+            .unwrap();
+        let error = bob
+            .manager
+            .handle_p2p_message(&alice.author, tampered)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PeerViolation>(),
+            Some(PeerViolation {
+                kind: Misbehaviour::InvalidSignature,
+                ..
+            })
+        ));
+
+        let genuine = wire::decode_signed(&raw(Some(&signature), "route hint"))
+            .unwrap()
+            .unwrap();
+        bob.manager
+            .handle_p2p_message(&alice.author, genuine)
+            .await
+            .unwrap();
+        assert_eq!(
+            bob.manager
+                .get_channel(&channel_id)
+                .unwrap()
+                .sequence_number,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn bundle_opens_the_channel_and_carries_payments() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        drop_outbound(&mut alice);
+
+        let bundle = alice.manager.export_updates(&channel_id).await.unwrap();
+        let imported = bob.manager.import_bundle(&bundle).await.unwrap();
+        assert_eq!(imported.updates, 2);
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert!(!fundee.is_funder);
+        assert_eq!(fundee.sequence_number, 1);
+
+        // Bob's reply carries his counter-signature and ack back
+        alice
+            .manager
+            .import_bundle(&imported.reply.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+        assert_eq!(
+            alice.manager.get_channel(&channel_id).unwrap().my_balance,
+            bob.manager.get_channel(&channel_id).unwrap().peer_balance
+        );
+    }
+
+    #[tokio::test]
+    async fn bundle_from_another_node_is_rejected() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let carol = test_node(3).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+
+        let ack = P2PMessage::UpdateAck {
+            channel_id: channel_id.clone(),
+            sequence: 0,
+        };
+        let bundle = UpdateBundle::new(&carol.manager.key_manager, &channel_id, vec![ack])
+            .encode(&carol.manager.key_manager)
+            .unwrap();
+        let error = bob.manager.import_bundle(&bundle).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<NodeError>(),
+            Some(NodeError::InvalidBundle(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reestablish_retransmits_every_missed_update() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        for amount in [10_000, 20_000] {
+            alice
+                .manager
+                .send_payment(&channel_id, amount, None)
+                .await
+                .unwrap();
+        }
+        drop_outbound(&mut alice);
+
+        reconnect(&mut alice, &mut bob).await;
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert_eq!(fundee.sequence_number, 2);
+        assert_eq!(
+            fundee.my_balance,
+            alice.manager.get_channel(&channel_id).unwrap().peer_balance
+        );
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn lost_local_data_fails_the_channel_and_asks_for_force_close() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        let opened = alice.manager.get_channel(&channel_id).unwrap().clone();
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+
+        // Alice comes back from an old backup; Bob holds her payment
+        lose_state(&mut alice, &channel_id, &opened);
+        reconnect(&mut alice, &mut bob).await;
+
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        assert!(
+            funder
+                .sync_error
+                .as_ref()
+                .unwrap()
+                .contains("Lost local data")
+        );
+        // Bob broadcast his latest commitment on her request
+        assert!(!bob.manager.get_channel(&channel_id).unwrap().is_open);
+    }
+
+    #[tokio::test]
+    async fn peer_that_lost_its_update_fails_the_channel() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        let opened = bob.manager.get_channel(&channel_id).unwrap().clone();
+        bob.manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        deliver(&mut bob, &mut alice).await;
+        deliver(&mut alice, &mut bob).await;
+
+        // Bob forgets the payment he made; Alice's state can't be rolled back
+        lose_state(&mut bob, &channel_id, &opened);
+        reconnect(&mut alice, &mut bob).await;
+
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        assert!(
+            funder
+                .sync_error
+                .as_ref()
+                .unwrap()
+                .contains("Peer lost its update 1")
+        );
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert!(
+            fundee
+                .sync_error
+                .as_ref()
+                .unwrap()
+                .contains("Lost local data")
+        );
+    }
+
+    #[tokio::test]
+    async fn balance_mismatch_at_the_same_sequence_fails_the_channel() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        bob.manager
+            .channels
+            .get_mut(&channel_id)
+            .unwrap()
+            .my_balance -= 1;
+
+        reconnect(&mut alice, &mut bob).await;
+        for node in [&alice, &bob] {
+            let channel = node.manager.get_channel(&channel_id).unwrap();
+            assert!(
+                channel
+                    .sync_error
+                    .as_ref()
+                    .unwrap()
+                    .contains("Balances differ")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fundee_applies_the_funders_update_fee() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        alice
+            .manager
+            .update_fee(&channel_id, Some(5_000))
+            .await
+            .unwrap();
+        deliver(&mut alice, &mut bob).await;
+
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert_eq!(fundee.feerate_per_kw, 5_000);
+        assert_eq!(fundee.commitment_fee, funder.commitment_fee);
+        assert_eq!(fundee.peer_balance, funder.my_balance);
+        assert_eq!(fundee.sequence_number, 1);
+
+        let error = bob
+            .manager
+            .update_fee(&channel_id, Some(5_000))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<NodeError>(),
+            Some(NodeError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn update_fee_from_the_fundee_is_a_violation() {
+        let (mut alice, bob, channel_id) = open_synced_channel().await;
+        let update = P2PMessage::UpdateFee {
+            channel_id: channel_id.clone(),
+            feerate_per_kw: 5_000,
+            sequence: 1,
+        };
+        let error = alice
+            .manager
+            .handle_p2p_message(&bob.author, received(update))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PeerViolation>(),
+            Some(PeerViolation {
+                kind: Misbehaviour::ProtocolViolation,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn default_limits_are_valid_for_every_usable_capacity() {
+        for capacity in [MIN_CAPACITY, 2_000, 2_183, 2_184, 100_000, 1_000_000] {
+            let limits = ChannelLimits::for_capacity(capacity);
+            assert!(limits.validate(capacity).is_ok(), "capacity {}", capacity);
+            assert!(limits.channel_reserve <= capacity / 4);
+        }
+
+        let error = ChannelLimits::for_capacity(MIN_CAPACITY - 1)
+            .validate(MIN_CAPACITY - 1)
+            .unwrap_err();
+        assert!(error.to_string().contains("below the minimum"));
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_payments_are_capped_by_max_accepted_htlcs() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let limits = ChannelLimits {
+            max_accepted_htlcs: 2,
+            ..ChannelLimits::for_capacity(1_000_000)
+        };
+        let channel_id = alice
+            .manager
+            .open_channel(
+                bob.manager.key_manager.get_public_key().to_string(),
+                1_000_000,
+                CommitmentType::Legacy,
+                limits,
+            )
+            .await
+            .unwrap()
+            .id;
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+
+        for _ in 0..2 {
+            alice
+                .manager
+                .send_payment(&channel_id, 1_000, None)
+                .await
+                .unwrap();
+        }
+        let error = alice
+            .manager
+            .send_payment(&channel_id, 1_000, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<NodeError>(),
+            Some(NodeError::LimitViolation(_))
+        ));
+
+        // Once Bob acknowledges them there is room again
+        deliver(&mut alice, &mut bob).await;
+        deliver(&mut bob, &mut alice).await;
+        alice
+            .manager
+            .send_payment(&channel_id, 1_000, None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn close_is_broadcast_to_the_peer() {
+        let mut alice = test_node(1).await;
+        let mut bob = test_node(2).await;
+        let channel_id = open_channel(&mut alice, &bob).await;
+        deliver(&mut alice, &mut bob).await;
+
+        alice.manager.close_channel(&channel_id).await.unwrap();
+        let channel = alice.manager.get_channel(&channel_id).unwrap();
+        let Ok(P2PMessage::ChannelClose {
+            channel_id: closed,
+            final_balance_a,
+            final_balance_b,
+        }) = alice.outbound.try_recv()
+        else {
+            panic!("expected a channel close");
+        };
+        assert_eq!(closed, channel_id);
+        assert_eq!(final_balance_a, channel.my_balance);
+        assert_eq!(final_balance_b, channel.peer_balance);
+    }
+
+    #[tokio::test]
+    async fn unsigned_force_close_request_is_rejected() {
+        let (alice, mut bob, channel_id) = open_synced_channel().await;
+        let channel = bob.manager.get_channel(&channel_id).unwrap().clone();
+        let forged = P2PMessage::ForceCloseRequest {
+            channel_id: channel_id.clone(),
+            funding_txid: channel.funding_txid.clone(),
+            peer_node_id: channel.peer_node_id.clone(),
+            signature: String::new(),
+        };
+
+        let error = bob
+            .manager
+            .handle_p2p_message(&alice.author, received(forged))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PeerViolation>(),
+            Some(PeerViolation {
+                kind: Misbehaviour::InvalidSignature,
+                ..
+            })
+        ));
+        assert!(bob.manager.get_channel(&channel_id).unwrap().is_open);
+    }
+
+    #[test]
+    fn payment_cursor_round_trips() {
+        let cursor = PaymentCursor {
+            timestamp: "2024-03-01T12:30:45.123456Z".parse().unwrap(),
+            id: "7d0f3c1e-payment|with-a-pipe".to_string(),
+        };
+
+        let decoded = PaymentCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.timestamp, cursor.timestamp);
+        assert_eq!(decoded.id, cursor.id);
+        assert!(PaymentCursor::decode("not a cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn paging_visits_payments_sharing_a_timestamp_once() {
+        let mut node = test_node(1).await;
+        let peer = test_node(2).await;
+        let channel_id = open_channel(&mut node, &peer).await;
+        let timestamp = Utc::now();
+        for i in 0..7 {
+            let payment = PaymentRecord {
+                id: format!("payment-{}", i),
+                channel_id: channel_id.clone(),
+                amount: 1_000,
+                direction: "incoming".to_string(),
+                sequence: i,
+                timestamp,
+                is_offline: false,
+            };
+            node.manager.database.save_payment(&payment).await.unwrap();
+        }
+
+        let mut filter = PaymentFilter {
+            limit: Some(3),
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let page = node.manager.list_payments(&filter).await.unwrap();
+            assert!(page.payments.len() <= 3);
+            seen.extend(page.payments.into_iter().map(|p| p.id));
+            match page.next_cursor {
+                Some(cursor) => filter.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        let mut expected: Vec<String> = (0..7).map(|i| format!("payment-{}", i)).collect();
+        expected.reverse();
+        assert_eq!(seen, expected);
+
+        // The unpaged listing collects the same payments, or caps them at limit
+        filter.cursor = None;
+        filter.limit = None;
+        assert_eq!(
+            node.manager.collect_payments(&filter).await.unwrap().len(),
+            7
+        );
+        filter.limit = Some(5);
+        assert_eq!(
+            node.manager.collect_payments(&filter).await.unwrap().len(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn names_peer_matches_a_peer_id_or_the_node_id_of_a_key() {
+        let peer = test_node(2).await;
+        let peer_id = peer.author.peer_id.to_string();
+        let node_id = peer.author.node_id.as_str();
+        let public_key = peer.manager.key_manager.get_public_key().to_string();
+        let other = test_node(3).await;
+
+        // Channels opened by peer id match that peer id only
+        assert!(names_peer(&peer_id, &peer_id, None));
+        assert!(!names_peer(
+            &peer_id,
+            &other.author.peer_id.to_string(),
+            Some(node_id)
+        ));
+        // Channels opened by public key match the node id it hashes to
+        assert!(names_peer(&public_key, &peer_id, Some(node_id)));
+        assert!(names_peer(&public_key, "any-peer", Some(node_id)));
+        assert!(!names_peer(
+            &public_key,
+            &peer_id,
+            Some(&other.author.node_id)
+        ));
+        // A peer that hasn't proven a node id can't match a key
+        assert!(!names_peer(&public_key, &peer_id, None));
+        assert!(!names_peer("not-a-key", &peer_id, Some(node_id)));
+    }
+
+    #[tokio::test]
+    async fn tower_blob_opens_with_the_peers_breach() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        let mut bob_commitments = Vec::new();
+        for amount in [10_000, 20_000] {
+            alice
+                .manager
+                .send_payment(&channel_id, amount, None)
+                .await
+                .unwrap();
+            deliver(&mut alice, &mut bob).await;
+            deliver(&mut bob, &mut alice).await;
+            // What Bob could broadcast at this state
+            let bob_channel = bob.manager.get_channel(&channel_id).unwrap();
+            bob_commitments.push(transaction_id(&bob_channel.local_commitment_tx()));
+        }
+
+        // State 1 is revoked by state 2
+        let channel = alice.manager.get_channel(&channel_id).unwrap();
+        let revoked = alice.manager.commitment_txs[&channel_id]
+            .iter()
+            .find(|c| c.sequence == 1)
+            .unwrap();
+        let bob_breach = &bob_commitments[0];
+        assert_eq!(revoked.remote_txid.as_ref(), Some(bob_breach));
+
+        let blob = justice_blob(&alice.manager.key_manager, channel, revoked)
+            .unwrap()
+            .unwrap();
+        assert_eq!(blob.hint, crate::watchtower::breach_hint(bob_breach));
+        let justice_tx = blob.open(bob_breach).unwrap();
+        assert!(justice_tx.contains(bob_breach.as_str()));
+        // Alice's own commitment of the state does not unlock it
+        assert!(blob.open(&revoked.txid()).is_err());
+    }
+
+    #[tokio::test]
+    async fn backup_restores_each_sides_channel() {
+        let (alice, bob, channel_id) = open_synced_channel().await;
+
+        for node in [&alice, &bob] {
+            let backup = node.manager.export_backup().unwrap();
+            let original = node.manager.get_channel(&channel_id).unwrap();
+
+            // Same seed, empty database: everything but the seed is lost
+            let mut restored_node = test_node(if original.is_funder { 1 } else { 2 }).await;
+            let restored = restored_node.manager.restore_backup(&backup).await.unwrap();
+            assert_eq!(restored.len(), 1);
+            let channel = &restored[0];
+            assert_eq!(channel.id, channel_id);
+            assert_eq!(channel.is_funder, original.is_funder);
+            assert_eq!(channel.peer_node_id, original.peer_node_id);
+            assert_eq!(channel.funding_txid, original.funding_txid);
+            assert_eq!(channel.capacity, original.capacity);
+            assert_eq!(channel.multisig_address, original.multisig_address);
+            assert!(!channel.is_open);
+            assert!(matches!(
+                restored_node.outbound.try_recv(),
+                Ok(P2PMessage::ForceCloseRequest { .. })
+            ));
+
+            // Restoring again leaves the known channel alone
+            let again = restored_node.manager.restore_backup(&backup).await.unwrap();
+            assert!(again.is_empty());
+        }
+        assert!(!bob.manager.get_channel(&channel_id).unwrap().is_funder);
+    }
+
+    #[tokio::test]
+    async fn concurrent_offline_payments_are_both_applied() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        let opened = alice.manager.get_channel(&channel_id).unwrap().clone();
+
+        // Both pay at sequence 1 while apart
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        let bob_payment = bob
+            .manager
+            .send_payment(&channel_id, 4_000, None)
+            .await
+            .unwrap();
+        drop_outbound(&mut alice);
+        drop_outbound(&mut bob);
+
+        // Alice funded the channel, so her payment stays at 1 and Bob's moves to 2
+        reconnect(&mut alice, &mut bob).await;
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        for channel in [funder, fundee] {
+            assert_eq!(channel.sequence_number, 2);
+            assert!(channel.sync_error.is_none());
+        }
+        assert_eq!(funder.my_balance, opened.my_balance - 6_000);
+        assert_eq!(fundee.my_balance, funder.peer_balance);
+        assert_eq!(fundee.peer_balance, funder.my_balance);
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+        assert_eq!(pending_outbox(&bob, &channel_id).await, 0);
+
+        // Bob's payment keeps its id at its new sequence, counter-signed by Alice
+        let filter = PaymentFilter {
+            channel_id: Some(channel_id.clone()),
+            ..Default::default()
+        };
+        let payments = bob.manager.collect_payments(&filter).await.unwrap();
+        let resent = payments.iter().find(|p| p.id == bob_payment.id).unwrap();
+        assert_eq!(resent.sequence, 2);
+        assert!(!resent.is_offline);
+        let history = &bob.manager.commitment_txs[&channel_id];
+        assert_eq!(history.iter().map(|c| c.sequence).collect::<Vec<_>>(), [2]);
+        assert!(history[0].peer_signature.is_some());
+    }
+
+    #[tokio::test]
+    async fn crossing_payments_are_both_applied() {
+        let (mut alice, mut bob, channel_id) = open_synced_channel().await;
+        alice
+            .manager
+            .send_payment(&channel_id, 10_000, None)
+            .await
+            .unwrap();
+        bob.manager
+            .send_payment(&channel_id, 4_000, None)
+            .await
+            .unwrap();
+
+        // Bob gets Alice's payment first, then Alice his original one
+        while !alice.outbound.is_empty() || !bob.outbound.is_empty() {
+            deliver(&mut alice, &mut bob).await;
+            deliver(&mut bob, &mut alice).await;
+        }
+        let funder = alice.manager.get_channel(&channel_id).unwrap();
+        let fundee = bob.manager.get_channel(&channel_id).unwrap();
+        assert_eq!(funder.sequence_number, 2);
+        assert_eq!(fundee.sequence_number, 2);
+        assert_eq!(fundee.my_balance, funder.peer_balance);
+        assert_eq!(pending_outbox(&alice, &channel_id).await, 0);
+        assert_eq!(pending_outbox(&bob, &channel_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn diverged_commitment_fails_the_channel_without_blaming_the_peer() {
+        let (alice, mut bob, channel_id) = open_synced_channel().await;
+        let payment = alice
+            .manager
+            .sign_update(P2PMessage::Payment {
+                channel_id: channel_id.clone(),
+                amount: 10_000,
+                sequence: 1,
+                commitment_tx: "another state".to_string(),
+                signature: String::new(),
+                invoice_id: None,
+            })
+            .unwrap();
+
+        // Not an error, so the peer isn't scored for it
+        bob.manager
+            .handle_p2p_message(&alice.author, received(payment))
+            .await
+            .unwrap();
+        let channel = bob.manager.get_channel(&channel_id).unwrap();
+        assert_eq!(channel.sequence_number, 0);
+        assert!(
+            channel
+                .sync_error
+                .as_ref()
+                .unwrap()
+                .contains("does not match the channel state")
+        );
+        assert!(bob.outbound.is_empty());
+    }
+}
@@ -1,28 +1,78 @@
 use anyhow::Result;
+use bitcoin::hashes::{Hash, sha256d};
 use bitcoin::key::CompressedPublicKey;
 use bitcoin::secp256k1::{
     Keypair, Message, PublicKey as SecpPublicKey, Secp256k1, SecretKey, ecdsa::Signature,
 };
-use bitcoin::{Address, Network, PrivateKey};
+use bitcoin::{Address, Network};
 use rand::rngs::OsRng;
 use sha2::{Digest, Sha256};
 
+/// What a node-key signature is for. The domain's tag is hashed in ahead of
+/// the message (a BIP 340 tagged hash), so a signature obtained for one
+/// purpose never verifies for another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningDomain {
+    /// A channel state, signed by its holder or counter-signed by the peer
+    Commitment,
+    /// An update sent to the channel peer, e.g. a payment
+    ChannelUpdate,
+    /// A sneakernet update bundle
+    UpdateBundle,
+    /// A DHT record of where a node can be reached
+    NodeRecord,
+    /// A sweep, penalty or CPFP transaction
+    Transaction,
+    /// An init binding a libp2p peer id to the node key
+    PeerIdentity,
+}
+
+impl SigningDomain {
+    fn tag(&self) -> &'static str {
+        match self {
+            SigningDomain::Commitment => "lightning-offline/commitment",
+            SigningDomain::ChannelUpdate => "lightning-offline/channel-update",
+            SigningDomain::UpdateBundle => "lightning-offline/update-bundle",
+            SigningDomain::NodeRecord => "lightning-offline/node-record",
+            SigningDomain::Transaction => "lightning-offline/transaction",
+            SigningDomain::PeerIdentity => "lightning-offline/peer-identity",
+        }
+    }
+
+    /// `sha256(sha256(tag) || sha256(tag) || message)`
+    fn hash(&self, message: &[u8]) -> [u8; 32] {
+        let tag = Sha256::digest(self.tag().as_bytes());
+        Sha256::new()
+            .chain_update(tag)
+            .chain_update(tag)
+            .chain_update(message)
+            .finalize()
+            .into()
+    }
+}
+
 pub struct KeyManager {
     secp: Secp256k1<bitcoin::secp256k1::All>,
+    seed: [u8; 32],
     private_key: SecretKey,
     public_key: SecpPublicKey,
     node_id: String,
     bitcoin_address: Address,
 }
 
+/// Generates a fresh 32-byte node seed.
+pub fn generate_seed() -> [u8; 32] {
+    use rand::RngCore;
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    seed
+}
+
 impl KeyManager {
-    pub fn new() -> Result<Self> {
+    /// Derives all node keys from `seed`, so the same seed always yields the same node.
+    pub fn from_seed(seed: [u8; 32]) -> Result<Self> {
         let secp = Secp256k1::new();
-        let mut rng = OsRng;
-        let mut sk_bytes = [0u8; 32];
-        use rand::RngCore;
-        rng.fill_bytes(&mut sk_bytes);
-        let secret_key = SecretKey::from_slice(&sk_bytes).expect("32 bytes, within curve order");
+        let secret_key = SecretKey::from_slice(&derive_from_seed(&seed, "node"))?;
 
         // ✅ Generate keypair using secp256k1 v0.29 API
         let keypair = Keypair::from_secret_key(&secp, &secret_key);
@@ -30,25 +80,17 @@ impl KeyManager {
         let private_key = keypair.secret_key();
         let public_key = keypair.public_key();
 
-        println!("Secret: {:?}", private_key.display_secret());
-        println!("Public: {:?}", public_key);
-
-        let compressed = CompressedPublicKey::from_slice(&public_key.serialize())?;
-
-        // Generate node ID from raw secp256k1 pubkey
-        let mut hasher = Sha256::new();
-        hasher.update(public_key.serialize());
-        let node_id = hex::encode(hasher.finalize());
+        let node_id = node_id(&public_key);
 
         // Wrap as CompressedPublicKey for Bitcoin types
-        let compressed = CompressedPublicKey::from_slice(&public_key.serialize()).unwrap();
+        let compressed = CompressedPublicKey::from_slice(&public_key.serialize())?;
 
         // Generate Bitcoin address (bech32 P2WPKH on regtest)
-        let bitcoin_private_key = PrivateKey::new(private_key, Network::Regtest);
         let bitcoin_address = Address::p2wpkh(&compressed, Network::Regtest);
 
         Ok(KeyManager {
             secp,
+            seed,
             private_key,
             public_key,
             node_id,
@@ -56,6 +98,16 @@ impl KeyManager {
         })
     }
 
+    /// Symmetric key for `purpose` (e.g. backup encryption), stable across restarts.
+    pub fn derive_key(&self, purpose: &str) -> [u8; 32] {
+        derive_from_seed(&self.seed, purpose)
+    }
+
+    /// The seed every key is derived from; only exported to admins for backups.
+    pub fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+
     pub fn get_node_id(&self) -> String {
         self.node_id.clone()
     }
@@ -68,29 +120,22 @@ impl KeyManager {
         self.bitcoin_address.to_string()
     }
 
-    pub fn sign_message(&self, message: &[u8]) -> Result<Signature> {
-        let mut hasher = Sha256::new();
-        hasher.update(message);
-        let hash = hasher.finalize();
-        let message = Message::from_digest_slice(&hash)?;
+    /// Signs `message` with the node key for `domain`.
+    pub fn sign(&self, domain: SigningDomain, message: &[u8]) -> Result<Signature> {
+        let message = Message::from_digest(domain.hash(message));
         Ok(self.secp.sign_ecdsa(&message, &self.private_key))
     }
 
-    pub fn verify_signature(
+    /// Checks a signature made with `sign` for the same domain.
+    pub fn verify(
         &self,
+        domain: SigningDomain,
         message: &[u8],
         signature: &Signature,
         pubkey: &SecpPublicKey,
     ) -> bool {
-        let mut hasher = Sha256::new();
-        hasher.update(message);
-        let hash = hasher.finalize();
-
-        if let Ok(message) = Message::from_digest_slice(&hash) {
-            self.secp.verify_ecdsa(&message, signature, pubkey).is_ok()
-        } else {
-            false
-        }
+        let message = Message::from_digest(domain.hash(message));
+        self.secp.verify_ecdsa(&message, signature, pubkey).is_ok()
     }
 
     pub fn create_multisig_address(&self, other_pubkey: &SecpPublicKey) -> Result<Address> {
@@ -107,10 +152,16 @@ impl KeyManager {
             inner: *other_pubkey,
         };
 
+        // Keys in sorted order (BIP 67), so both sides derive the same address
+        let (first, second) = if self_pubkey.inner.serialize() <= other_pubkey.inner.serialize() {
+            (self_pubkey, other_pubkey)
+        } else {
+            (other_pubkey, self_pubkey)
+        };
         let script = Builder::new()
             .push_opcode(OP_PUSHNUM_2)
-            .push_key(&self_pubkey)
-            .push_key(&other_pubkey)
+            .push_key(&first)
+            .push_key(&second)
             .push_opcode(OP_PUSHNUM_2)
             .push_opcode(OP_CHECKMULTISIG)
             .into_script();
@@ -118,3 +169,46 @@ impl KeyManager {
         Ok(Address::p2wsh(&script, Network::Regtest))
     }
 }
+
+fn derive_from_seed(seed: &[u8; 32], purpose: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(purpose.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Node id announced to peers: the SHA-256 of the compressed public key.
+pub fn node_id(public_key: &SecpPublicKey) -> String {
+    hex::encode(Sha256::digest(public_key.serialize()))
+}
+
+/// Identifier of a (simplified) transaction: the double-SHA256 of its serialization.
+pub fn transaction_id(raw_tx: &str) -> String {
+    sha256d::Hash::hash(raw_tx.as_bytes()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_only_verifies_in_its_own_domain() {
+        let key_manager = KeyManager::from_seed([7; 32]).unwrap();
+        let public_key = key_manager.get_public_key();
+        let message = b"channel state";
+
+        let signature = key_manager
+            .sign(SigningDomain::Commitment, message)
+            .unwrap();
+        assert!(key_manager.verify(SigningDomain::Commitment, message, &signature, &public_key));
+        for domain in [
+            SigningDomain::ChannelUpdate,
+            SigningDomain::UpdateBundle,
+            SigningDomain::NodeRecord,
+            SigningDomain::Transaction,
+            SigningDomain::PeerIdentity,
+        ] {
+            assert!(!key_manager.verify(domain, message, &signature, &public_key));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::crypto::{self, KeyManager, SigningDomain};
+use crate::wire::{TlvReader, TlvWriter};
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey as SecpPublicKey;
+use bitcoin::secp256k1::ecdsa::Signature;
+use chrono::{DateTime, Utc};
+use libp2p::kad::RecordKey;
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Our own Kademlia protocol, so the DHT only spans Lightning Offline nodes.
+pub const PROTOCOL: StreamProtocol = StreamProtocol::new("/lightning-offline/kad/1.0.0");
+
+/// How often we publish a fresh record; well within kad's 36 hour record TTL.
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Routing table refresh, so peers learned second-hand stay reachable.
+pub const BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Version 2 tags the signature as a node record's.
+const RECORD_VERSION: u8 = 2;
+const SIGNATURE_LEN: usize = 64;
+const KEY_PREFIX: &str = "/lightning-offline/node/";
+
+/// Where a node can be reached, published in the DHT under its node id and
+/// signed by its node key, so no other peer can point lookups elsewhere.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NodeRecord {
+    pub node_id: String,
+    /// Hex public key the node id is the hash of
+    pub public_key: String,
+    pub peer_id: String,
+    /// Dialable addresses without the `/p2p/<peer id>` suffix
+    pub addresses: Vec<String>,
+    pub published_at: DateTime<Utc>,
+}
+
+impl NodeRecord {
+    pub fn new(key_manager: &KeyManager, peer_id: PeerId, addresses: &[Multiaddr]) -> Self {
+        NodeRecord {
+            node_id: key_manager.get_node_id(),
+            public_key: key_manager.get_public_key().to_string(),
+            peer_id: peer_id.to_string(),
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            published_at: Utc::now(),
+        }
+    }
+
+    /// DHT key the record of `node_id` is stored under.
+    pub fn key(node_id: &str) -> RecordKey {
+        RecordKey::new(&format!("{}{}", KEY_PREFIX, node_id))
+    }
+
+    /// Serializes as `version || signature || tlv`, the signature covering
+    /// the TLV bytes.
+    pub fn encode(&self, key_manager: &KeyManager) -> Result<Vec<u8>> {
+        let mut tlv = TlvWriter::new();
+        tlv.string(0, &self.public_key);
+        tlv.string(2, &self.peer_id);
+        tlv.string(4, &self.addresses.join(","));
+        tlv.u64(6, self.published_at.timestamp_millis().max(0) as u64);
+        let tlv = tlv.finish();
+
+        let signature = key_manager.sign(SigningDomain::NodeRecord, &tlv)?;
+        let mut data = vec![RECORD_VERSION];
+        data.extend_from_slice(&signature.serialize_compact());
+        data.extend_from_slice(&tlv);
+        Ok(data)
+    }
+
+    /// Parses a record found under `key` and checks it was signed by the node
+    /// the key names.
+    pub fn decode(key_manager: &KeyManager, key: &RecordKey, data: &[u8]) -> Result<Self> {
+        if data.len() <= 1 + SIGNATURE_LEN {
+            return Err(anyhow::anyhow!("Node record is truncated"));
+        }
+        if data[0] != RECORD_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported node record version {}",
+                data[0]
+            ));
+        }
+        let signature = Signature::from_compact(&data[1..1 + SIGNATURE_LEN])
+            .map_err(|_| anyhow::anyhow!("Node record signature is malformed"))?;
+        let tlv = &data[1 + SIGNATURE_LEN..];
+
+        let mut reader = TlvReader::parse(tlv)?;
+        let public_key = reader.string(0)?;
+        let peer_id = reader.string(2)?;
+        let addresses = reader.string(4)?;
+        let published_at = i64::try_from(reader.u64(6)?)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(|| anyhow::anyhow!("Invalid publication time"))?;
+        reader.finish()?;
+
+        let signer: SecpPublicKey = public_key
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Node record names an invalid public key"))?;
+        let node_id = crypto::node_id(&signer);
+        if *key != Self::key(&node_id) {
+            return Err(anyhow::anyhow!(
+                "Node record of {} is stored under another node's key",
+                node_id
+            ));
+        }
+        if !key_manager.verify(SigningDomain::NodeRecord, tlv, &signature, &signer) {
+            return Err(anyhow::anyhow!(
+                "Node record signature does not match node {}",
+                node_id
+            ));
+        }
+        peer_id
+            .parse::<PeerId>()
+            .map_err(|_| anyhow::anyhow!("Node record names an invalid peer id"))?;
+
+        Ok(NodeRecord {
+            node_id,
+            public_key,
+            peer_id,
+            addresses: addresses
+                .split(',')
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect(),
+            published_at,
+        })
+    }
+
+    /// Addresses that parse, each ending in `/p2p/<peer id>` for dialing.
+    pub fn dial_addresses(&self) -> Vec<Multiaddr> {
+        self.addresses
+            .iter()
+            .filter_map(|a| format!("{}/p2p/{}", a, self.peer_id).parse().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_manager(byte: u8) -> KeyManager {
+        KeyManager::from_seed([byte; 32]).unwrap()
+    }
+
+    fn record(key_manager: &KeyManager) -> NodeRecord {
+        let addresses: Vec<Multiaddr> = vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()];
+        NodeRecord::new(key_manager, PeerId::random(), &addresses)
+    }
+
+    #[test]
+    fn signed_record_round_trips() {
+        let alice = key_manager(1);
+        let record = record(&alice);
+        let data = record.encode(&alice).unwrap();
+
+        let decoded =
+            NodeRecord::decode(&alice, &NodeRecord::key(&alice.get_node_id()), &data).unwrap();
+        assert_eq!(decoded.node_id, alice.get_node_id());
+        assert_eq!(decoded.peer_id, record.peer_id);
+        assert_eq!(decoded.addresses, record.addresses);
+        assert_eq!(
+            decoded.published_at.timestamp_millis(),
+            record.published_at.timestamp_millis()
+        );
+        assert_eq!(
+            decoded.dial_addresses()[0].to_string(),
+            format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", record.peer_id)
+        );
+    }
+
+    #[test]
+    fn tampered_record_is_rejected() {
+        let alice = key_manager(1);
+        let mut data = record(&alice).encode(&alice).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+
+        assert!(NodeRecord::decode(&alice, &NodeRecord::key(&alice.get_node_id()), &data).is_err());
+    }
+
+    #[test]
+    fn record_under_another_nodes_key_is_rejected() {
+        let alice = key_manager(1);
+        let mallory = key_manager(2);
+        let data = record(&mallory).encode(&mallory).unwrap();
+
+        assert!(NodeRecord::decode(&alice, &NodeRecord::key(&alice.get_node_id()), &data).is_err());
+    }
+}
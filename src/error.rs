@@ -0,0 +1,318 @@
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::ToSchema;
+
+/// Errors surfaced to API clients. Channel operations return these wrapped in
+/// `anyhow::Error`; anything else is reported as a storage or internal failure.
+#[derive(Debug, thiserror::Error)]
+pub enum NodeError {
+    #[error("Channel {0} not found")]
+    ChannelNotFound(String),
+    #[error("Channel {0} is not open")]
+    ChannelClosed(String),
+    #[error("Channel {0} must be force-closed first")]
+    ChannelStillOpen(String),
+    #[error("Channel {0} is out of sync with its peer and can only be force-closed")]
+    ChannelOutOfSync(String),
+    #[error("{0}")]
+    InsufficientBalance(String),
+    #[error("{0}")]
+    LimitViolation(String),
+    #[error("{0}")]
+    InvalidPubkey(String),
+    #[error("{0}")]
+    InvalidBackup(String),
+    #[error("{0}")]
+    InvalidBundle(String),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("API token {0} not found")]
+    TokenNotFound(String),
+    #[error("Peer {0} is not connected")]
+    PeerNotFound(String),
+    #[error("{0}")]
+    PeerUnreachable(String),
+    #[error("Node {0} not found in the DHT")]
+    NodeNotFound(String),
+    #[error("Peer {0} is banned")]
+    PeerBanned(String),
+    #[error("Peer {0} is not banned")]
+    PeerNotBanned(String),
+    #[error("P2P node is not running")]
+    P2PUnavailable,
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("No chain backend configured")]
+    ChainUnavailable,
+    #[error("Storage failure: {0}")]
+    Storage(#[from] sqlx::Error),
+    #[error("{0}")]
+    Internal(anyhow::Error),
+}
+
+/// JSON body returned for every failed API request.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
+impl NodeError {
+    /// Stable machine-readable code; never change an existing value.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NodeError::ChannelNotFound(_) => "channel_not_found",
+            NodeError::ChannelClosed(_) => "channel_closed",
+            NodeError::ChannelStillOpen(_) => "channel_still_open",
+            NodeError::ChannelOutOfSync(_) => "channel_out_of_sync",
+            NodeError::InsufficientBalance(_) => "insufficient_balance",
+            NodeError::LimitViolation(_) => "channel_limit_violated",
+            NodeError::InvalidPubkey(_) => "invalid_pubkey",
+            NodeError::InvalidBackup(_) => "invalid_backup",
+            NodeError::InvalidBundle(_) => "invalid_bundle",
+            NodeError::InvalidRequest(_) => "invalid_request",
+            NodeError::TokenNotFound(_) => "token_not_found",
+            NodeError::PeerNotFound(_) => "peer_not_found",
+            NodeError::PeerUnreachable(_) => "peer_unreachable",
+            NodeError::NodeNotFound(_) => "node_not_found",
+            NodeError::PeerBanned(_) => "peer_banned",
+            NodeError::PeerNotBanned(_) => "peer_not_banned",
+            NodeError::P2PUnavailable => "p2p_unavailable",
+            NodeError::Unauthorized(_) => "unauthorized",
+            NodeError::Forbidden(_) => "forbidden",
+            NodeError::ChainUnavailable => "chain_unavailable",
+            NodeError::Storage(_) => "storage_failure",
+            NodeError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            NodeError::ChannelNotFound(_)
+            | NodeError::TokenNotFound(_)
+            | NodeError::PeerNotFound(_)
+            | NodeError::PeerNotBanned(_)
+            | NodeError::NodeNotFound(_) => StatusCode::NOT_FOUND,
+            NodeError::ChannelClosed(_)
+            | NodeError::ChannelStillOpen(_)
+            | NodeError::ChannelOutOfSync(_)
+            | NodeError::PeerBanned(_) => StatusCode::CONFLICT,
+            NodeError::InsufficientBalance(_) | NodeError::LimitViolation(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            NodeError::InvalidPubkey(_)
+            | NodeError::InvalidBackup(_)
+            | NodeError::InvalidBundle(_)
+            | NodeError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            NodeError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            NodeError::Forbidden(_) => StatusCode::FORBIDDEN,
+            NodeError::PeerUnreachable(_) => StatusCode::BAD_GATEWAY,
+            NodeError::ChainUnavailable | NodeError::P2PUnavailable => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            NodeError::Storage(_) | NodeError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<anyhow::Error> for NodeError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<NodeError>() {
+            Ok(node_error) => return node_error,
+            Err(error) => error,
+        };
+        match error.downcast::<sqlx::Error>() {
+            Ok(sqlx_error) => NodeError::Storage(sqlx_error),
+            Err(error) => NodeError::Internal(error),
+        }
+    }
+}
+
+impl From<JsonRejection> for NodeError {
+    fn from(rejection: JsonRejection) -> Self {
+        NodeError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for NodeError {
+    fn from(rejection: QueryRejection) -> Self {
+        NodeError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl IntoResponse for NodeError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("API error: {}", self);
+        }
+
+        let body = ErrorResponse {
+            code: self.code().to_string(),
+            message: self.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<NodeError> for tonic::Status {
+    fn from(error: NodeError) -> Self {
+        let code = match &error {
+            NodeError::ChannelNotFound(_)
+            | NodeError::TokenNotFound(_)
+            | NodeError::PeerNotFound(_)
+            | NodeError::PeerNotBanned(_)
+            | NodeError::NodeNotFound(_) => tonic::Code::NotFound,
+            NodeError::ChannelClosed(_)
+            | NodeError::ChannelStillOpen(_)
+            | NodeError::ChannelOutOfSync(_)
+            | NodeError::PeerBanned(_)
+            | NodeError::InsufficientBalance(_)
+            | NodeError::LimitViolation(_) => tonic::Code::FailedPrecondition,
+            NodeError::InvalidPubkey(_)
+            | NodeError::InvalidBackup(_)
+            | NodeError::InvalidBundle(_)
+            | NodeError::InvalidRequest(_) => tonic::Code::InvalidArgument,
+            NodeError::Unauthorized(_) => tonic::Code::Unauthenticated,
+            NodeError::Forbidden(_) => tonic::Code::PermissionDenied,
+            NodeError::ChainUnavailable
+            | NodeError::PeerUnreachable(_)
+            | NodeError::P2PUnavailable => tonic::Code::Unavailable,
+            NodeError::Storage(_) | NodeError::Internal(_) => tonic::Code::Internal,
+        };
+        if code == tonic::Code::Internal {
+            error!("gRPC error: {}", error);
+        }
+
+        // Same codes as the REST error body, for clients that branch on them
+        let mut status = tonic::Status::new(code, error.to_string());
+        status.metadata_mut().insert(
+            "error-code",
+            tonic::metadata::MetadataValue::from_static(error.code()),
+        );
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_stay_stable() {
+        let cases = [
+            (NodeError::ChannelNotFound("c".into()), "channel_not_found"),
+            (
+                NodeError::ChannelOutOfSync("c".into()),
+                "channel_out_of_sync",
+            ),
+            (
+                NodeError::LimitViolation("l".into()),
+                "channel_limit_violated",
+            ),
+            (NodeError::P2PUnavailable, "p2p_unavailable"),
+            (
+                NodeError::Storage(sqlx::Error::RowNotFound),
+                "storage_failure",
+            ),
+            (
+                NodeError::Internal(anyhow::anyhow!("boom")),
+                "internal_error",
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.code(), code);
+        }
+    }
+
+    #[test]
+    fn http_and_grpc_statuses_agree() {
+        let cases = [
+            (
+                NodeError::ChannelNotFound("c".into()),
+                StatusCode::NOT_FOUND,
+                tonic::Code::NotFound,
+            ),
+            (
+                NodeError::ChannelStillOpen("c".into()),
+                StatusCode::CONFLICT,
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                NodeError::InsufficientBalance("b".into()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                NodeError::InvalidRequest("r".into()),
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                NodeError::Unauthorized("u".into()),
+                StatusCode::UNAUTHORIZED,
+                tonic::Code::Unauthenticated,
+            ),
+            (
+                NodeError::Forbidden("f".into()),
+                StatusCode::FORBIDDEN,
+                tonic::Code::PermissionDenied,
+            ),
+            (
+                NodeError::PeerUnreachable("p".into()),
+                StatusCode::BAD_GATEWAY,
+                tonic::Code::Unavailable,
+            ),
+            (
+                NodeError::ChainUnavailable,
+                StatusCode::SERVICE_UNAVAILABLE,
+                tonic::Code::Unavailable,
+            ),
+            (
+                NodeError::Internal(anyhow::anyhow!("boom")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                tonic::Code::Internal,
+            ),
+        ];
+        for (error, http, grpc) in cases {
+            let code = error.code();
+            assert_eq!(error.status(), http, "{}", code);
+
+            let status = tonic::Status::from(error);
+            assert_eq!(status.code(), grpc, "{}", code);
+            assert_eq!(status.metadata().get("error-code").unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn wrapped_errors_keep_their_kind() {
+        let error: anyhow::Error = NodeError::PeerBanned("peer".into()).into();
+        assert!(matches!(NodeError::from(error), NodeError::PeerBanned(_)));
+
+        let error: anyhow::Error = sqlx::Error::RowNotFound.into();
+        assert!(matches!(NodeError::from(error), NodeError::Storage(_)));
+
+        let error = anyhow::anyhow!("boom").context("while testing");
+        assert_eq!(NodeError::from(error).code(), "internal_error");
+    }
+
+    #[tokio::test]
+    async fn response_body_carries_the_code() {
+        let response = NodeError::ChannelNotFound("chan".into()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.code, "channel_not_found");
+        assert_eq!(body.message, "Channel chan not found");
+    }
+}
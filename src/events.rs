@@ -0,0 +1,273 @@
+use crate::storage::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, broadcast, mpsc};
+use tracing::error;
+use utoipa::ToSchema;
+
+/// Live subscribers that fall further behind than this re-read from the database.
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+    ChannelOpened {
+        channel_id: String,
+        peer_node_id: String,
+        capacity: u64,
+    },
+    ChannelClosed {
+        channel_id: String,
+        reason: String, // "cooperative", "force" or "on_chain"
+    },
+    PaymentSent {
+        channel_id: String,
+        payment_id: String,
+        amount: u64,
+    },
+    PaymentReceived {
+        channel_id: String,
+        payment_id: String,
+        amount: u64,
+    },
+    InvoiceSettled {
+        channel_id: String,
+        invoice_id: String,
+        amount: u64,
+    },
+    PeerConnected {
+        peer_id: String,
+    },
+    PeerDisconnected {
+        peer_id: String,
+    },
+}
+
+impl NodeEvent {
+    pub fn channel_id(&self) -> Option<&str> {
+        match self {
+            NodeEvent::ChannelOpened { channel_id, .. }
+            | NodeEvent::ChannelClosed { channel_id, .. }
+            | NodeEvent::PaymentSent { channel_id, .. }
+            | NodeEvent::PaymentReceived { channel_id, .. }
+            | NodeEvent::InvoiceSettled { channel_id, .. } => Some(channel_id),
+            NodeEvent::PeerConnected { .. } | NodeEvent::PeerDisconnected { .. } => None,
+        }
+    }
+}
+
+/// A published event. `id` increases monotonically and is the cursor clients
+/// resume from.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EventRecord {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: NodeEvent,
+}
+
+/// Persists events and fans them out to live subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    database: Arc<Database>,
+    sender: broadcast::Sender<EventRecord>,
+    // Keeps ids in broadcast order when several tasks publish at once
+    publish_lock: Arc<Mutex<()>>,
+}
+
+impl EventBus {
+    pub fn new(database: Arc<Database>) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self {
+            database,
+            sender,
+            publish_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Never fails the caller: a lost event must not undo a payment.
+    pub async fn publish(&self, event: NodeEvent) {
+        let _guard = self.publish_lock.lock().await;
+        match self.database.save_event(&event, Utc::now()).await {
+            Ok(record) => {
+                // No receivers is fine
+                let _ = self.sender.send(record);
+            }
+            Err(e) => error!("Failed to record event {:?}: {}", event, e),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.sender.subscribe()
+    }
+
+    /// Events after `cursor`, oldest first.
+    pub async fn replay(&self, cursor: i64) -> Result<Vec<EventRecord>> {
+        self.database.get_events_after(cursor).await
+    }
+
+    /// Replays events after `cursor` (if given), then follows live ones, keeping
+    /// only those for `channels` when set. Stops when the receiver is dropped.
+    pub fn follow(
+        &self,
+        cursor: Option<i64>,
+        channels: Option<HashSet<String>>,
+    ) -> mpsc::Receiver<EventRecord> {
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+        // Subscribe before replaying so nothing published in between is lost
+        let mut live = self.subscribe();
+        let bus = self.clone();
+
+        tokio::spawn(async move {
+            let mut follower = Follower {
+                sender,
+                channels,
+                last_sent: cursor.unwrap_or(0),
+            };
+            if cursor.is_some() && !follower.replay(&bus).await {
+                return;
+            }
+
+            loop {
+                let received = tokio::select! {
+                    received = live.recv() => received,
+                    _ = follower.sender.closed() => return,
+                };
+                let forwarded = match received {
+                    Ok(record) => record.id <= follower.last_sent || follower.send(record).await,
+                    Err(RecvError::Lagged(_)) => follower.replay(&bus).await,
+                    Err(RecvError::Closed) => false,
+                };
+                if !forwarded {
+                    return;
+                }
+            }
+        });
+
+        receiver
+    }
+}
+
+/// State of one `EventBus::follow` subscription.
+struct Follower {
+    sender: mpsc::Sender<EventRecord>,
+    channels: Option<HashSet<String>>,
+    last_sent: i64,
+}
+
+impl Follower {
+    async fn replay(&mut self, bus: &EventBus) -> bool {
+        match bus.replay(self.last_sent).await {
+            Ok(records) => {
+                for record in records {
+                    if !self.send(record).await {
+                        return false;
+                    }
+                }
+                true
+            }
+            Err(e) => {
+                error!("Failed to replay events: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Returns false once the subscriber has gone away.
+    async fn send(&mut self, record: EventRecord) -> bool {
+        self.last_sent = record.id;
+
+        if let Some(channels) = &self.channels {
+            match record.event.channel_id() {
+                Some(channel_id) if channels.contains(channel_id) => {}
+                _ => return true,
+            }
+        }
+        self.sender.send(record).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn payment(channel_id: &str, amount: u64) -> NodeEvent {
+        NodeEvent::PaymentSent {
+            channel_id: channel_id.to_string(),
+            payment_id: format!("payment-{}", amount),
+            amount,
+        }
+    }
+
+    async fn bus() -> EventBus {
+        EventBus::new(Arc::new(Database::in_memory().await.unwrap()))
+    }
+
+    async fn next_ids(receiver: &mut mpsc::Receiver<EventRecord>, count: usize) -> Vec<i64> {
+        let mut ids = Vec::new();
+        while ids.len() < count {
+            let record = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("follower stalled")
+                .expect("follower stopped");
+            ids.push(record.id);
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn resuming_from_a_cursor_has_no_gaps_or_duplicates() {
+        let bus = bus().await;
+        for amount in 1..=3 {
+            bus.publish(payment("chan", amount)).await;
+        }
+
+        let mut receiver = bus.follow(Some(1), None);
+        for amount in 4..=5 {
+            bus.publish(payment("chan", amount)).await;
+        }
+
+        assert_eq!(next_ids(&mut receiver, 4).await, vec![2, 3, 4, 5]);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn lagged_follower_catches_up_from_the_database() {
+        let bus = bus().await;
+        let mut receiver = bus.follow(None, None);
+
+        // Nothing is read until both the follower's queue and the live
+        // buffer have overflowed
+        let total = EVENT_BUFFER * 3;
+        for amount in 1..=total as u64 {
+            bus.publish(payment("chan", amount)).await;
+        }
+
+        let expected: Vec<i64> = (1..=total as i64).collect();
+        assert_eq!(next_ids(&mut receiver, total).await, expected);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_keeps_only_the_followed_channels() {
+        let bus = bus().await;
+        bus.publish(payment("a", 1)).await;
+        bus.publish(payment("b", 2)).await;
+        bus.publish(NodeEvent::PeerConnected {
+            peer_id: "peer".to_string(),
+        })
+        .await;
+
+        let channels = HashSet::from(["b".to_string()]);
+        let mut receiver = bus.follow(Some(0), Some(channels));
+        bus.publish(payment("a", 3)).await;
+        bus.publish(payment("b", 4)).await;
+
+        assert_eq!(next_ids(&mut receiver, 2).await, vec![2, 5]);
+    }
+}
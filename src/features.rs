@@ -0,0 +1,167 @@
+/// Version of the peer protocol spoken over the wire format; peers outside
+/// `MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION` are disconnected.
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Features exchanged in `init`. Each owns a pair of bits, as in BOLT #9: the
+/// even bit means the sender requires it, the odd bit that it merely supports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Reestablish and outbox replay after reconnecting
+    ChannelReestablish,
+    /// Anchor output commitments
+    Anchors,
+    Htlcs,
+    Splicing,
+}
+
+/// How this node supports a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Support {
+    Required,
+    Optional,
+    Unsupported,
+}
+
+impl Feature {
+    const ALL: [Feature; 4] = [
+        Feature::ChannelReestablish,
+        Feature::Anchors,
+        Feature::Htlcs,
+        Feature::Splicing,
+    ];
+
+    /// The even (required) bit; the optional bit is the one above it.
+    fn bit(self) -> u32 {
+        match self {
+            Feature::ChannelReestablish => 0,
+            Feature::Anchors => 2,
+            Feature::Htlcs => 4,
+            Feature::Splicing => 6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::ChannelReestablish => "channel_reestablish",
+            Feature::Anchors => "anchors",
+            Feature::Htlcs => "htlcs",
+            Feature::Splicing => "splicing",
+        }
+    }
+
+    fn local_support(self) -> Support {
+        match self {
+            Feature::ChannelReestablish => Support::Required,
+            Feature::Anchors => Support::Optional,
+            Feature::Htlcs | Feature::Splicing => Support::Unsupported,
+        }
+    }
+
+    fn is_set(self, features: u64) -> bool {
+        features & (0b11 << self.bit()) != 0
+    }
+}
+
+/// Feature bits this node sends in `init`.
+pub fn local_features() -> u64 {
+    Feature::ALL
+        .iter()
+        .fold(0, |bits, feature| match feature.local_support() {
+            Support::Required => bits | 1 << feature.bit(),
+            Support::Optional => bits | 1 << (feature.bit() + 1),
+            Support::Unsupported => bits,
+        })
+}
+
+/// Checks a peer's `init` against ours. Returns the features both sides
+/// support, or why the peer is incompatible.
+pub fn negotiate(protocol_version: u16, features: u64) -> Result<Vec<Feature>, String> {
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        return Err(format!(
+            "protocol version {} is outside {}..={}",
+            protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        ));
+    }
+
+    let ours = local_features();
+    for bit in (0..64).step_by(2).filter(|bit| features & 1 << bit != 0) {
+        match Feature::ALL.iter().find(|f| f.bit() == bit) {
+            None => return Err(format!("peer requires unknown feature bit {}", bit)),
+            Some(feature) if !feature.is_set(ours) => {
+                return Err(format!("peer requires {}", feature.name()));
+            }
+            Some(_) => {}
+        }
+    }
+    for feature in Feature::ALL {
+        if feature.local_support() == Support::Required && !feature.is_set(features) {
+            return Err(format!("peer lacks required {}", feature.name()));
+        }
+    }
+
+    Ok(Feature::ALL
+        .into_iter()
+        .filter(|f| f.is_set(ours) && f.is_set(features))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_features_negotiate_with_themselves() {
+        assert_eq!(
+            negotiate(PROTOCOL_VERSION, local_features()),
+            Ok(vec![Feature::ChannelReestablish, Feature::Anchors])
+        );
+    }
+
+    #[test]
+    fn unknown_even_bit_is_rejected() {
+        let features = local_features() | 1 << 20;
+        assert_eq!(
+            negotiate(PROTOCOL_VERSION, features),
+            Err("peer requires unknown feature bit 20".to_string())
+        );
+        // The odd bit of an unknown feature is fine to ignore
+        assert!(negotiate(PROTOCOL_VERSION, local_features() | 1 << 21).is_ok());
+    }
+
+    #[test]
+    fn feature_the_peer_requires_and_we_lack_is_rejected() {
+        let features = local_features() | 1 << Feature::Htlcs.bit();
+        assert_eq!(
+            negotiate(PROTOCOL_VERSION, features),
+            Err("peer requires htlcs".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_required_feature_is_rejected() {
+        let features = 1 << (Feature::Anchors.bit() + 1);
+        assert_eq!(
+            negotiate(PROTOCOL_VERSION, features),
+            Err("peer lacks required channel_reestablish".to_string())
+        );
+    }
+
+    #[test]
+    fn only_features_both_sides_support_are_enabled() {
+        // Optional channel_reestablish still satisfies our requirement;
+        // anchors is ours only and splicing theirs only
+        let features =
+            1 << (Feature::ChannelReestablish.bit() + 1) | 1 << (Feature::Splicing.bit() + 1);
+        assert_eq!(
+            negotiate(PROTOCOL_VERSION, features),
+            Ok(vec![Feature::ChannelReestablish])
+        );
+    }
+
+    #[test]
+    fn protocol_version_outside_the_range_is_rejected() {
+        assert!(negotiate(MIN_PROTOCOL_VERSION - 1, local_features()).is_err());
+        assert!(negotiate(PROTOCOL_VERSION + 1, local_features()).is_err());
+    }
+}
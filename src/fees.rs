@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Minimum relay feerate (1 sat/vB) expressed per kilo-weight.
+pub const FEERATE_FLOOR_PER_KW: u64 = 253;
+
+/// Used when no chain backend is configured (10 sat/vB).
+pub const DEFAULT_FEERATE_PER_KW: u64 = 2500;
+
+/// Confirmation target for commitment transactions.
+pub const COMMITMENT_CONF_TARGET: u16 = 6;
+
+/// Value of each anchor output; both are paid by the funder.
+pub const ANCHOR_OUTPUT_VALUE: u64 = 330;
+
+/// BOLT 3 commitment weights without HTLC outputs.
+const COMMITMENT_WEIGHT: u64 = 724;
+const ANCHOR_COMMITMENT_WEIGHT: u64 = 1124;
+
+/// Weight of a child spending our anchor and our main output.
+pub const ANCHOR_CPFP_WEIGHT: u64 = 700;
+
+/// Weight of a justice transaction claiming both main outputs of a revoked commitment.
+const PENALTY_WEIGHT: u64 = 900;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitmentType {
+    #[default]
+    Legacy,
+    /// Adds a 330 sat anchor output per side so either can CPFP a force-close
+    Anchors,
+}
+
+impl CommitmentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommitmentType::Legacy => "legacy",
+            CommitmentType::Anchors => "anchors",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "legacy" => Some(CommitmentType::Legacy),
+            "anchors" => Some(CommitmentType::Anchors),
+            _ => None,
+        }
+    }
+
+    pub fn weight(&self) -> u64 {
+        match self {
+            CommitmentType::Legacy => COMMITMENT_WEIGHT,
+            CommitmentType::Anchors => ANCHOR_COMMITMENT_WEIGHT,
+        }
+    }
+
+    /// Total value locked in anchor outputs.
+    pub fn anchor_total(&self) -> u64 {
+        match self {
+            CommitmentType::Legacy => 0,
+            CommitmentType::Anchors => 2 * ANCHOR_OUTPUT_VALUE,
+        }
+    }
+}
+
+/// On-chain fee reserved by a commitment at `feerate_per_kw`.
+pub fn commitment_fee(feerate_per_kw: u64, commitment_type: CommitmentType) -> u64 {
+    feerate_per_kw * commitment_type.weight() / 1000
+}
+
+/// Fee the anchor child must pay so parent and child together reach `target_feerate_per_kw`.
+pub fn cpfp_child_fee(
+    target_feerate_per_kw: u64,
+    commitment_type: CommitmentType,
+    parent_fee: u64,
+) -> u64 {
+    let package_weight = commitment_type.weight() + ANCHOR_CPFP_WEIGHT;
+    (target_feerate_per_kw * package_weight / 1000).saturating_sub(parent_fee)
+}
+
+/// Fee for a justice transaction at `feerate_per_kw`.
+pub fn penalty_fee(feerate_per_kw: u64) -> u64 {
+    feerate_per_kw * PENALTY_WEIGHT / 1000
+}
+
+/// Converts an estimator feerate in sat/vB to sat per kilo-weight.
+pub fn sat_per_vbyte_to_per_kw(sat_per_vbyte: f64) -> u64 {
+    ((sat_per_vbyte * 250.0).ceil() as u64).max(FEERATE_FLOOR_PER_KW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commitment_fee_scales_with_weight() {
+        assert_eq!(commitment_fee(2500, CommitmentType::Legacy), 1810);
+        assert_eq!(commitment_fee(2500, CommitmentType::Anchors), 2810);
+        // Rounds down to whole satoshis
+        assert_eq!(
+            commitment_fee(FEERATE_FLOOR_PER_KW, CommitmentType::Legacy),
+            183
+        );
+        assert_eq!(commitment_fee(0, CommitmentType::Anchors), 0);
+    }
+
+    #[test]
+    fn cpfp_child_pays_for_the_whole_package() {
+        let parent_fee = commitment_fee(2500, CommitmentType::Anchors);
+        // (1124 + 700) weight at 10,000 sat/kw, less what the parent pays
+        assert_eq!(
+            cpfp_child_fee(10_000, CommitmentType::Anchors, parent_fee),
+            18_240 - parent_fee
+        );
+        // A parent that already reaches the target needs nothing more
+        assert_eq!(cpfp_child_fee(253, CommitmentType::Anchors, parent_fee), 0);
+    }
+
+    #[test]
+    fn penalty_fee_scales_with_feerate() {
+        assert_eq!(penalty_fee(2500), 2250);
+        assert_eq!(penalty_fee(FEERATE_FLOOR_PER_KW), 227);
+    }
+
+    #[test]
+    fn sat_per_vbyte_converts_to_per_kw() {
+        assert_eq!(sat_per_vbyte_to_per_kw(10.0), 2500);
+        assert_eq!(sat_per_vbyte_to_per_kw(2.5), 625);
+        // Rounds up, so the converted rate is never below the estimate
+        assert_eq!(sat_per_vbyte_to_per_kw(1.013), 254);
+        // Never below the relay floor
+        assert_eq!(sat_per_vbyte_to_per_kw(1.0), FEERATE_FLOOR_PER_KW);
+        assert_eq!(sat_per_vbyte_to_per_kw(0.0), FEERATE_FLOOR_PER_KW);
+    }
+}
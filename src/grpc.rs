@@ -0,0 +1,727 @@
+use crate::LightningNode;
+use crate::auth::{AuthContext, Scope};
+use crate::channel::{
+    ChannelLimits, ChannelStats, CommitmentTransaction, Invoice, PaymentChannel, PaymentFilter,
+    PaymentRecord, SortOrder,
+};
+use crate::error::NodeError;
+use crate::events::{EventRecord, NodeEvent};
+use crate::features::Feature;
+use crate::fees::CommitmentType;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("lightning.v1");
+}
+
+use proto::event::Event as EventKind;
+use proto::lightning_server::{Lightning, LightningServer};
+
+/// Serves `proto/lightning.proto` next to the REST API, over the same node
+/// state and API tokens.
+pub struct GrpcServer {
+    node: LightningNode,
+    tls: Option<Identity>,
+}
+
+impl GrpcServer {
+    pub fn new(node: LightningNode) -> Self {
+        Self { node, tls: None }
+    }
+
+    /// Serve over TLS with this certificate, normally the REST API's.
+    pub fn with_tls(mut self, identity: Identity) -> Self {
+        self.tls = Some(identity);
+        self
+    }
+
+    pub async fn start(self, addr: &str) -> anyhow::Result<()> {
+        let address: SocketAddr = addr.parse()?;
+        let mut server = Server::builder();
+        if let Some(identity) = self.tls {
+            server = server.tls_config(ServerTlsConfig::new().identity(identity))?;
+            println!("gRPC server listening on https://{}", addr);
+        } else {
+            println!("gRPC server listening on {}", addr);
+        }
+
+        server
+            .add_service(LightningServer::new(LightningService { node: self.node }))
+            .serve(address)
+            .await?;
+        Ok(())
+    }
+}
+
+struct LightningService {
+    node: LightningNode,
+}
+
+impl LightningService {
+    /// Same bearer tokens and scopes as `require_token` on the REST API.
+    async fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        required: Scope,
+    ) -> Result<AuthContext, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| NodeError::Unauthorized("Missing bearer token".to_string()))?;
+
+        let auth = self.node.auth.authenticate(token).await?;
+        if !auth.scope.allows(required) {
+            return Err(NodeError::Forbidden(format!(
+                "A {} token cannot call this method; {} scope required",
+                auth.scope.as_str(),
+                required.as_str()
+            ))
+            .into());
+        }
+        Ok(auth)
+    }
+}
+
+type EventStream = std::pin::Pin<
+    Box<dyn tokio_stream::Stream<Item = Result<proto::Event, Status>> + Send + 'static>,
+>;
+
+#[tonic::async_trait]
+impl Lightning for LightningService {
+    async fn get_node_info(
+        &self,
+        request: Request<proto::GetNodeInfoRequest>,
+    ) -> Result<Response<proto::NodeInfo>, Status> {
+        self.authorize(&request, Scope::Readonly).await?;
+        let connected_peers = self
+            .node
+            .p2p
+            .peers()
+            .await
+            .map(|peers| peers.into_iter().map(|peer| peer.peer_id).collect())
+            .unwrap_or_default();
+
+        Ok(Response::new(proto::NodeInfo {
+            node_id: self.node.node_id.clone(),
+            public_key: hex::encode(self.node.key_manager.get_public_key().serialize()),
+            bitcoin_address: self.node.key_manager.get_bitcoin_address(),
+            connected_peers,
+        }))
+    }
+
+    async fn list_channels(
+        &self,
+        request: Request<proto::ListChannelsRequest>,
+    ) -> Result<Response<proto::ListChannelsResponse>, Status> {
+        self.authorize(&request, Scope::Readonly).await?;
+
+        let channel_manager = self.node.channel_manager.read().await;
+        let channels = channel_manager
+            .get_all_channels()
+            .into_iter()
+            .map(channel_message)
+            .collect();
+        Ok(Response::new(proto::ListChannelsResponse { channels }))
+    }
+
+    async fn get_channel(
+        &self,
+        request: Request<proto::GetChannelRequest>,
+    ) -> Result<Response<proto::ChannelDetail>, Status> {
+        self.authorize(&request, Scope::Readonly).await?;
+        let channel_id = request.into_inner().channel_id;
+
+        let channel_manager = self.node.channel_manager.read().await;
+        let channel = channel_manager
+            .get_channel(&channel_id)
+            .map(channel_message)
+            .ok_or_else(|| NodeError::ChannelNotFound(channel_id.clone()))?;
+        let commitments = channel_manager
+            .get_channel_commitments(&channel_id)
+            .await
+            .map_err(NodeError::from)?;
+        let stats = channel_manager
+            .get_channel_stats(&channel_id)
+            .await
+            .map_err(NodeError::from)?;
+
+        Ok(Response::new(proto::ChannelDetail {
+            channel: Some(channel),
+            commitments: commitments.iter().map(commitment_message).collect(),
+            stats: Some(stats_message(&stats)),
+        }))
+    }
+
+    async fn open_channel(
+        &self,
+        request: Request<proto::OpenChannelRequest>,
+    ) -> Result<Response<proto::Channel>, Status> {
+        let auth = self.authorize(&request, Scope::Admin).await?;
+        let req = request.into_inner();
+        auth.check_amount(req.capacity)?;
+
+        let commitment_type = match req.commitment_type() {
+            proto::CommitmentType::Legacy => CommitmentType::Legacy,
+            proto::CommitmentType::Anchors => CommitmentType::Anchors,
+        };
+        let limits = requested_limits(&req)?;
+        if commitment_type == CommitmentType::Anchors {
+            self.node
+                .p2p
+                .check_peer_supports(&req.peer_node_id, Feature::Anchors)
+                .await?;
+        }
+
+        let channel = self
+            .node
+            .channel_manager
+            .write()
+            .await
+            .open_channel(req.peer_node_id, req.capacity, commitment_type, limits)
+            .await
+            .map_err(NodeError::from)?;
+        self.node
+            .p2p
+            .keep_channel_peer_connected(&channel.peer_node_id)
+            .await;
+
+        println!("Channel opened over gRPC: {}", channel.id);
+        Ok(Response::new(channel_message(&channel)))
+    }
+
+    async fn close_channel(
+        &self,
+        request: Request<proto::CloseChannelRequest>,
+    ) -> Result<Response<proto::CloseChannelResponse>, Status> {
+        self.authorize(&request, Scope::Admin).await?;
+        let channel_id = request.into_inner().channel_id;
+
+        let mut channel_manager = self.node.channel_manager.write().await;
+        channel_manager
+            .close_channel(&channel_id)
+            .await
+            .map_err(NodeError::from)?;
+
+        println!("Channel closed over gRPC: {}", channel_id);
+        Ok(Response::new(proto::CloseChannelResponse {}))
+    }
+
+    async fn send_payment(
+        &self,
+        request: Request<proto::SendPaymentRequest>,
+    ) -> Result<Response<proto::Payment>, Status> {
+        let auth = self.authorize(&request, Scope::Admin).await?;
+        let req = request.into_inner();
+        auth.check_amount(req.amount)?;
+
+        let mut channel_manager = self.node.channel_manager.write().await;
+        let payment = channel_manager
+            .send_payment(&req.channel_id, req.amount, req.invoice_id)
+            .await
+            .map_err(NodeError::from)?;
+
+        println!(
+            "Payment sent over gRPC: {} sats on channel {}",
+            req.amount, req.channel_id
+        );
+        Ok(Response::new(payment_message(&payment)))
+    }
+
+    async fn list_payments(
+        &self,
+        request: Request<proto::ListPaymentsRequest>,
+    ) -> Result<Response<proto::ListPaymentsResponse>, Status> {
+        self.authorize(&request, Scope::Readonly).await?;
+        let req = request.into_inner();
+
+        let direction = match req.direction() {
+            proto::Direction::Unspecified => None,
+            proto::Direction::Outgoing => Some("outgoing".to_string()),
+            proto::Direction::Incoming => Some("incoming".to_string()),
+        };
+        let filter = PaymentFilter {
+            channel_id: req.channel_id,
+            direction,
+            from: req.from.map(datetime).transpose()?,
+            to: req.to.map(datetime).transpose()?,
+            min_amount: req.min_amount,
+            max_amount: req.max_amount,
+            is_offline: req.is_offline,
+            order: if req.oldest_first {
+                SortOrder::Asc
+            } else {
+                SortOrder::Desc
+            },
+            cursor: req.cursor,
+            limit: req.limit,
+        };
+
+        let channel_manager = self.node.channel_manager.read().await;
+        let page = channel_manager
+            .list_payments(&filter)
+            .await
+            .map_err(NodeError::from)?;
+
+        Ok(Response::new(proto::ListPaymentsResponse {
+            payments: page.payments.iter().map(payment_message).collect(),
+            next_cursor: page.next_cursor,
+        }))
+    }
+
+    async fn create_invoice(
+        &self,
+        request: Request<proto::CreateInvoiceRequest>,
+    ) -> Result<Response<proto::Invoice>, Status> {
+        self.authorize(&request, Scope::Invoice).await?;
+        let req = request.into_inner();
+
+        let channel_manager = self.node.channel_manager.read().await;
+        let invoice = channel_manager
+            .create_invoice(req.amount, req.memo)
+            .await
+            .map_err(NodeError::from)?;
+        Ok(Response::new(invoice_message(&invoice)))
+    }
+
+    async fn list_invoices(
+        &self,
+        request: Request<proto::ListInvoicesRequest>,
+    ) -> Result<Response<proto::ListInvoicesResponse>, Status> {
+        self.authorize(&request, Scope::Invoice).await?;
+
+        let channel_manager = self.node.channel_manager.read().await;
+        let invoices = channel_manager
+            .get_invoices()
+            .await
+            .map_err(NodeError::from)?;
+        Ok(Response::new(proto::ListInvoicesResponse {
+            invoices: invoices.iter().map(invoice_message).collect(),
+        }))
+    }
+
+    type SubscribeEventsStream = EventStream;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        self.authorize(&request, Scope::Readonly).await?;
+        let req = request.into_inner();
+
+        let channels = (!req.channel_ids.is_empty())
+            .then(|| req.channel_ids.into_iter().collect::<HashSet<_>>());
+        let events = self.node.events.follow(req.cursor, channels);
+
+        let stream = ReceiverStream::new(events).map(event_message).map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn requested_limits(req: &proto::OpenChannelRequest) -> Result<ChannelLimits, NodeError> {
+    let mut limits = ChannelLimits::for_capacity(req.capacity);
+    if let Some(channel_reserve) = req.channel_reserve {
+        limits.channel_reserve = channel_reserve;
+    }
+    if let Some(dust_limit) = req.dust_limit {
+        limits.dust_limit = dust_limit;
+    }
+    if let Some(max_in_flight) = req.max_in_flight {
+        limits.max_in_flight = max_in_flight;
+    }
+    if let Some(max_accepted_htlcs) = req.max_accepted_htlcs {
+        limits.max_accepted_htlcs = u16::try_from(max_accepted_htlcs).map_err(|_| {
+            NodeError::InvalidRequest(format!(
+                "max_accepted_htlcs {} is out of range",
+                max_accepted_htlcs
+            ))
+        })?;
+    }
+    if let Some(htlc_minimum) = req.htlc_minimum {
+        limits.htlc_minimum = htlc_minimum;
+    }
+    Ok(limits)
+}
+
+fn timestamp(time: &DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn datetime(time: prost_types::Timestamp) -> Result<DateTime<Utc>, NodeError> {
+    u32::try_from(time.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(time.seconds, nanos))
+        .ok_or_else(|| NodeError::InvalidRequest("Timestamp out of range".to_string()))
+}
+
+fn channel_message(channel: &PaymentChannel) -> proto::Channel {
+    proto::Channel {
+        id: channel.id.clone(),
+        peer_node_id: channel.peer_node_id.clone(),
+        funding_txid: channel.funding_txid.clone(),
+        capacity: channel.capacity,
+        my_balance: channel.my_balance,
+        peer_balance: channel.peer_balance,
+        sequence_number: channel.sequence_number,
+        is_open: channel.is_open,
+        created_at: Some(timestamp(&channel.created_at)),
+        multisig_address: channel.multisig_address.clone(),
+        closing_txid: channel.closing_txid.clone(),
+        commitment_type: match channel.commitment_type {
+            CommitmentType::Legacy => proto::CommitmentType::Legacy,
+            CommitmentType::Anchors => proto::CommitmentType::Anchors,
+        }
+        .into(),
+        feerate_per_kw: channel.feerate_per_kw,
+        commitment_fee: channel.commitment_fee,
+        is_funder: channel.is_funder,
+        limits: Some(proto::ChannelLimits {
+            channel_reserve: channel.limits.channel_reserve,
+            dust_limit: channel.limits.dust_limit,
+            max_in_flight: channel.limits.max_in_flight,
+            max_accepted_htlcs: channel.limits.max_accepted_htlcs.into(),
+            htlc_minimum: channel.limits.htlc_minimum,
+        }),
+        sync_error: channel.sync_error.clone(),
+        peer_online: channel.peer_online,
+    }
+}
+
+fn commitment_message(commitment: &CommitmentTransaction) -> proto::Commitment {
+    proto::Commitment {
+        id: commitment.id.clone(),
+        sequence: commitment.sequence,
+        my_balance: commitment.my_balance,
+        peer_balance: commitment.peer_balance,
+        fee: commitment.fee,
+        raw_tx: commitment.raw_tx.clone(),
+        signature: commitment.signature.clone(),
+        created_at: Some(timestamp(&commitment.created_at)),
+        peer_signature: commitment.peer_signature.clone(),
+    }
+}
+
+fn stats_message(stats: &ChannelStats) -> proto::ChannelStats {
+    proto::ChannelStats {
+        payment_count: stats.payment_count,
+        volume_out: stats.volume_out,
+        volume_in: stats.volume_in,
+        last_activity: stats.last_activity.as_ref().map(timestamp),
+    }
+}
+
+fn payment_message(payment: &PaymentRecord) -> proto::Payment {
+    let direction = match payment.direction.as_str() {
+        "outgoing" => proto::Direction::Outgoing,
+        "incoming" => proto::Direction::Incoming,
+        _ => proto::Direction::Unspecified,
+    };
+    proto::Payment {
+        id: payment.id.clone(),
+        channel_id: payment.channel_id.clone(),
+        amount: payment.amount,
+        direction: direction.into(),
+        sequence: payment.sequence,
+        timestamp: Some(timestamp(&payment.timestamp)),
+        is_offline: payment.is_offline,
+    }
+}
+
+fn invoice_message(invoice: &Invoice) -> proto::Invoice {
+    proto::Invoice {
+        id: invoice.id.clone(),
+        amount: invoice.amount,
+        memo: invoice.memo.clone(),
+        created_at: Some(timestamp(&invoice.created_at)),
+        settled_at: invoice.settled_at.as_ref().map(timestamp),
+        payment_id: invoice.payment_id.clone(),
+    }
+}
+
+fn event_message(record: EventRecord) -> proto::Event {
+    let event = match record.event {
+        NodeEvent::ChannelOpened {
+            channel_id,
+            peer_node_id,
+            capacity,
+        } => EventKind::ChannelOpened(proto::ChannelOpened {
+            channel_id,
+            peer_node_id,
+            capacity,
+        }),
+        NodeEvent::ChannelClosed { channel_id, reason } => {
+            EventKind::ChannelClosed(proto::ChannelClosed { channel_id, reason })
+        }
+        NodeEvent::PaymentSent {
+            channel_id,
+            payment_id,
+            amount,
+        } => EventKind::PaymentSent(proto::PaymentSent {
+            channel_id,
+            payment_id,
+            amount,
+        }),
+        NodeEvent::PaymentReceived {
+            channel_id,
+            payment_id,
+            amount,
+        } => EventKind::PaymentReceived(proto::PaymentReceived {
+            channel_id,
+            payment_id,
+            amount,
+        }),
+        NodeEvent::InvoiceSettled {
+            channel_id,
+            invoice_id,
+            amount,
+        } => EventKind::InvoiceSettled(proto::InvoiceSettled {
+            channel_id,
+            invoice_id,
+            amount,
+        }),
+        NodeEvent::PeerConnected { peer_id } => {
+            EventKind::PeerConnected(proto::PeerConnected { peer_id })
+        }
+        NodeEvent::PeerDisconnected { peer_id } => {
+            EventKind::PeerDisconnected(proto::PeerDisconnected { peer_id })
+        }
+    };
+
+    proto::Event {
+        id: record.id,
+        timestamp: Some(timestamp(&record.timestamp)),
+        event: Some(event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyManager;
+    use std::time::Duration;
+
+    async fn service() -> LightningService {
+        LightningService {
+            node: LightningNode::in_memory(1).await,
+        }
+    }
+
+    async fn token(service: &LightningService, scope: Scope, max_amount: Option<u64>) -> String {
+        let (_, token) = service
+            .node
+            .auth
+            .mint(scope, None, max_amount)
+            .await
+            .unwrap();
+        token
+    }
+
+    fn request<T>(token: &str, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    fn error_code(status: &Status) -> &str {
+        status
+            .metadata()
+            .get("error-code")
+            .unwrap()
+            .to_str()
+            .unwrap()
+    }
+
+    async fn open(service: &LightningService, token: &str) -> proto::Channel {
+        let peer = KeyManager::from_seed([2; 32]).unwrap();
+        service
+            .open_channel(request(
+                token,
+                proto::OpenChannelRequest {
+                    peer_node_id: peer.get_public_key().to_string(),
+                    capacity: 1_000_000,
+                    ..Default::default()
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+    }
+
+    #[tokio::test]
+    async fn calls_need_a_token_with_the_right_scope() {
+        let service = service().await;
+
+        let status = service
+            .list_channels(Request::new(proto::ListChannelsRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let readonly = token(&service, Scope::Readonly, None).await;
+        assert!(
+            service
+                .list_channels(request(&readonly, proto::ListChannelsRequest {}))
+                .await
+                .is_ok()
+        );
+        let status = service
+            .create_invoice(request(
+                &readonly,
+                proto::CreateInvoiceRequest {
+                    amount: 1_000,
+                    memo: String::new(),
+                },
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert_eq!(error_code(&status), "forbidden");
+    }
+
+    #[tokio::test]
+    async fn payments_show_up_in_the_channel_detail() {
+        let service = service().await;
+        let admin = token(&service, Scope::Admin, None).await;
+        let channel = open(&service, &admin).await;
+
+        let payment = service
+            .send_payment(request(
+                &admin,
+                proto::SendPaymentRequest {
+                    channel_id: channel.id.clone(),
+                    amount: 10_000,
+                    invoice_id: None,
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(payment.sequence, 1);
+
+        let detail = service
+            .get_channel(request(
+                &admin,
+                proto::GetChannelRequest {
+                    channel_id: channel.id.clone(),
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        let updated = detail.channel.unwrap();
+        assert_eq!(updated.my_balance, channel.my_balance - 10_000);
+        assert_eq!(detail.commitments.last().unwrap().sequence, 1);
+        let stats = detail.stats.unwrap();
+        assert_eq!(stats.payment_count, 1);
+        assert_eq!(stats.volume_out, 10_000);
+
+        let page = service
+            .list_payments(request(
+                &admin,
+                proto::ListPaymentsRequest {
+                    channel_id: Some(channel.id.clone()),
+                    ..Default::default()
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(page.payments.len(), 1);
+        assert_eq!(page.payments[0].id, payment.id);
+    }
+
+    #[tokio::test]
+    async fn token_amount_limit_applies_to_payments() {
+        let service = service().await;
+        let admin = token(&service, Scope::Admin, None).await;
+        let channel = open(&service, &admin).await;
+        let limited = token(&service, Scope::Admin, Some(5_000)).await;
+
+        let status = service
+            .send_payment(request(
+                &limited,
+                proto::SendPaymentRequest {
+                    channel_id: channel.id,
+                    amount: 10_000,
+                    invoice_id: None,
+                },
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn channel_errors_keep_their_codes() {
+        let service = service().await;
+        let admin = token(&service, Scope::Admin, None).await;
+
+        let status = service
+            .get_channel(request(
+                &admin,
+                proto::GetChannelRequest {
+                    channel_id: "missing".to_string(),
+                },
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(error_code(&status), "channel_not_found");
+
+        let channel = open(&service, &admin).await;
+        let close = || {
+            request(
+                &admin,
+                proto::CloseChannelRequest {
+                    channel_id: channel.id.clone(),
+                },
+            )
+        };
+        service.close_channel(close()).await.unwrap();
+        let status = service.close_channel(close()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(error_code(&status), "channel_closed");
+    }
+
+    #[tokio::test]
+    async fn event_stream_replays_from_the_cursor() {
+        let service = service().await;
+        let admin = token(&service, Scope::Admin, None).await;
+        let channel = open(&service, &admin).await;
+
+        let mut events = service
+            .subscribe_events(request(
+                &admin,
+                proto::SubscribeEventsRequest {
+                    cursor: Some(0),
+                    channel_ids: vec![channel.id.clone()],
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let Some(EventKind::ChannelOpened(opened)) = event.event else {
+            panic!("expected the channel open, got {:?}", event.event);
+        };
+        assert_eq!(opened.channel_id, channel.id);
+        assert_eq!(opened.capacity, 1_000_000);
+    }
+}
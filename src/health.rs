@@ -0,0 +1,322 @@
+use crate::storage::Database;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Ok,
+    /// Not done starting up, or never synced yet
+    Starting,
+    /// Not configured on this node
+    Disabled,
+    Down,
+}
+
+impl ComponentStatus {
+    fn is_ready(&self) -> bool {
+        matches!(self, ComponentStatus::Ok | ComponentStatus::Disabled)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DatabaseHealth {
+    pub status: ComponentStatus,
+    pub latency_ms: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct P2PHealth {
+    pub status: ComponentStatus,
+    pub listen_addresses: Vec<String>,
+    pub peers: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainHealth {
+    pub status: ComponentStatus,
+    pub backend: Option<String>,
+    /// Last successful poll of the chain backend
+    pub last_sync: Option<DateTime<Utc>>,
+    pub tip_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct KeysHealth {
+    pub status: ComponentStatus,
+}
+
+/// Body of `/health` and `/ready`; `status` is the worst component status.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthReport {
+    pub status: ComponentStatus,
+    pub database: DatabaseHealth,
+    pub p2p: P2PHealth,
+    pub chain: ChainHealth,
+    pub keys: KeysHealth,
+}
+
+impl HealthReport {
+    /// Whether the process works at all; a supervisor should restart it otherwise.
+    pub fn is_live(&self) -> bool {
+        self.database.status != ComponentStatus::Down && self.p2p.status != ComponentStatus::Down
+    }
+
+    /// Whether the node can serve payments right now.
+    pub fn is_ready(&self) -> bool {
+        self.database.status.is_ready()
+            && self.p2p.status.is_ready()
+            && self.chain.status.is_ready()
+            && self.keys.status.is_ready()
+    }
+}
+
+struct Subsystems {
+    p2p: P2PHealth,
+    chain: ChainHealth,
+    keys: KeysHealth,
+}
+
+/// Status reported by the background tasks, read by the health endpoints.
+#[derive(Clone)]
+pub struct HealthState {
+    database: Arc<Database>,
+    subsystems: Arc<RwLock<Subsystems>>,
+}
+
+impl HealthState {
+    pub fn new(database: Arc<Database>) -> Self {
+        Self {
+            database,
+            subsystems: Arc::new(RwLock::new(Subsystems {
+                p2p: P2PHealth {
+                    status: ComponentStatus::Starting,
+                    listen_addresses: Vec::new(),
+                    peers: 0,
+                    error: None,
+                },
+                chain: ChainHealth {
+                    status: ComponentStatus::Disabled,
+                    backend: None,
+                    last_sync: None,
+                    tip_hash: None,
+                    error: None,
+                },
+                keys: KeysHealth {
+                    status: ComponentStatus::Starting,
+                },
+            })),
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Subsystems)) {
+        // A panic elsewhere must not take the health endpoints down with it
+        let mut subsystems = self.subsystems.write().unwrap_or_else(|e| e.into_inner());
+        change(&mut subsystems);
+    }
+
+    pub fn keys_unlocked(&self) {
+        self.update(|s| s.keys.status = ComponentStatus::Ok);
+    }
+
+    pub fn p2p_listening(&self, address: String) {
+        self.update(|s| {
+            s.p2p.status = ComponentStatus::Ok;
+            s.p2p.listen_addresses.push(address);
+        });
+    }
+
+    pub fn p2p_listen_address_expired(&self, address: &str) {
+        self.update(|s| s.p2p.listen_addresses.retain(|a| a != address));
+    }
+
+    pub fn p2p_peers(&self, peers: usize) {
+        self.update(|s| s.p2p.peers = peers);
+    }
+
+    pub fn p2p_stopped(&self, error: String) {
+        self.update(|s| {
+            s.p2p.status = ComponentStatus::Down;
+            s.p2p.listen_addresses.clear();
+            s.p2p.peers = 0;
+            s.p2p.error = Some(error);
+        });
+    }
+
+    pub fn chain_configured(&self, backend: &str) {
+        self.update(|s| {
+            s.chain.status = ComponentStatus::Starting;
+            s.chain.backend = Some(backend.to_string());
+        });
+    }
+
+    pub fn chain_synced(&self, tip_hash: String) {
+        self.update(|s| {
+            s.chain.status = ComponentStatus::Ok;
+            s.chain.last_sync = Some(Utc::now());
+            s.chain.tip_hash = Some(tip_hash);
+            s.chain.error = None;
+        });
+    }
+
+    pub fn chain_failed(&self, error: String) {
+        self.update(|s| {
+            s.chain.status = ComponentStatus::Down;
+            s.chain.error = Some(error);
+        });
+    }
+
+    /// Pings the database and snapshots everything else.
+    pub async fn report(&self) -> HealthReport {
+        let started = Instant::now();
+        let ping = self.database.ping().await;
+        let database = DatabaseHealth {
+            status: if ping.is_ok() {
+                ComponentStatus::Ok
+            } else {
+                ComponentStatus::Down
+            },
+            latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            error: ping.err().map(|e| e.to_string()),
+        };
+
+        let (p2p, chain, keys) = {
+            let subsystems = self.subsystems.read().unwrap_or_else(|e| e.into_inner());
+            (
+                subsystems.p2p.clone(),
+                subsystems.chain.clone(),
+                subsystems.keys.clone(),
+            )
+        };
+
+        let statuses = [database.status, p2p.status, chain.status, keys.status];
+        let status = if statuses.contains(&ComponentStatus::Down) {
+            ComponentStatus::Down
+        } else if statuses.contains(&ComponentStatus::Starting) {
+            ComponentStatus::Starting
+        } else {
+            ComponentStatus::Ok
+        };
+
+        HealthReport {
+            status,
+            database,
+            p2p,
+            chain,
+            keys,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn health() -> (Arc<Database>, HealthState) {
+        let database = Arc::new(Database::in_memory().await.unwrap());
+        (database.clone(), HealthState::new(database))
+    }
+
+    /// Keys unlocked and P2P listening, with no chain backend.
+    async fn running() -> (Arc<Database>, HealthState) {
+        let (database, health) = health().await;
+        health.keys_unlocked();
+        health.p2p_listening("/ip4/127.0.0.1/tcp/9735".to_string());
+        (database, health)
+    }
+
+    #[tokio::test]
+    async fn starting_node_is_live_but_not_ready() {
+        let (_, health) = health().await;
+        let report = health.report().await;
+
+        assert_eq!(report.status, ComponentStatus::Starting);
+        assert_eq!(report.database.status, ComponentStatus::Ok);
+        assert_eq!(report.chain.status, ComponentStatus::Disabled);
+        assert!(report.is_live());
+        assert!(!report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn ready_without_a_chain_backend() {
+        let (_, health) = running().await;
+        health.p2p_peers(3);
+        let report = health.report().await;
+
+        assert_eq!(report.status, ComponentStatus::Ok);
+        assert_eq!(report.p2p.peers, 3);
+        assert_eq!(report.p2p.listen_addresses, ["/ip4/127.0.0.1/tcp/9735"]);
+        assert!(report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn chain_backend_gates_readiness_only() {
+        let (_, health) = running().await;
+
+        health.chain_configured("esplora");
+        let report = health.report().await;
+        assert_eq!(report.chain.status, ComponentStatus::Starting);
+        assert_eq!(report.chain.backend.as_deref(), Some("esplora"));
+        assert!(!report.is_ready());
+
+        health.chain_synced("tip".to_string());
+        let report = health.report().await;
+        assert_eq!(report.chain.tip_hash.as_deref(), Some("tip"));
+        assert!(report.chain.last_sync.is_some());
+        assert!(report.is_ready());
+
+        health.chain_failed("timed out".to_string());
+        let report = health.report().await;
+        assert_eq!(report.status, ComponentStatus::Down);
+        assert_eq!(report.chain.error.as_deref(), Some("timed out"));
+        assert!(report.is_live());
+        assert!(!report.is_ready());
+
+        // The next successful poll clears the error
+        health.chain_synced("next".to_string());
+        let report = health.report().await;
+        assert_eq!(report.chain.error, None);
+        assert!(report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn stopped_p2p_is_not_live() {
+        let (_, health) = running().await;
+        health.p2p_peers(2);
+
+        health.p2p_stopped("listener failed".to_string());
+        let report = health.report().await;
+        assert_eq!(report.p2p.status, ComponentStatus::Down);
+        assert!(report.p2p.listen_addresses.is_empty());
+        assert_eq!(report.p2p.peers, 0);
+        assert!(!report.is_live());
+    }
+
+    #[tokio::test]
+    async fn expired_listen_address_is_dropped() {
+        let (_, health) = running().await;
+        health.p2p_listening("/ip4/10.0.0.2/tcp/9735".to_string());
+
+        health.p2p_listen_address_expired("/ip4/127.0.0.1/tcp/9735");
+        let report = health.report().await;
+        assert_eq!(report.p2p.listen_addresses, ["/ip4/10.0.0.2/tcp/9735"]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_database_is_not_live() {
+        let (database, health) = running().await;
+        database.close().await;
+
+        let report = health.report().await;
+        assert_eq!(report.database.status, ComponentStatus::Down);
+        assert!(report.database.error.is_some());
+        assert_eq!(report.status, ComponentStatus::Down);
+        assert!(!report.is_live());
+    }
+}
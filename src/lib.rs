@@ -0,0 +1,74 @@
+//! Offline-first Lightning payment channel node: channel state, the P2P
+//! layer, chain monitoring and the REST/gRPC APIs. The binary in `main.rs`
+//! wires these together from the environment.
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub mod api;
+pub mod auth;
+pub mod backup;
+pub mod chain;
+pub mod channel;
+pub mod crypto;
+pub mod dht;
+pub mod error;
+pub mod events;
+pub mod features;
+pub mod fees;
+pub mod grpc;
+pub mod health;
+pub mod metrics;
+pub mod monitor;
+pub mod openapi;
+pub mod p2p;
+pub mod scoring;
+pub mod sneakernet;
+pub mod storage;
+pub mod tls;
+pub mod watchtower;
+pub mod wire;
+
+use auth::TokenAuthority;
+use channel::ChannelManager;
+use crypto::KeyManager;
+use events::EventBus;
+use health::HealthState;
+use p2p::P2PHandle;
+use storage::Database;
+
+#[derive(Clone)]
+pub struct LightningNode {
+    pub node_id: String,
+    pub key_manager: Arc<KeyManager>,
+    pub channel_manager: Arc<RwLock<ChannelManager>>,
+    pub database: Arc<Database>,
+    pub auth: Arc<TokenAuthority>,
+    pub events: EventBus,
+    pub health: HealthState,
+    pub p2p: P2PHandle,
+}
+
+#[cfg(test)]
+impl LightningNode {
+    /// A node on an in-memory database whose P2P task isn't running.
+    pub(crate) async fn in_memory(seed: u8) -> Self {
+        let key_manager = Arc::new(KeyManager::from_seed([seed; 32]).unwrap());
+        let database = Arc::new(Database::in_memory().await.unwrap());
+        let events = EventBus::new(database.clone());
+        let channel_manager = ChannelManager::new(key_manager.clone(), database.clone())
+            .await
+            .unwrap()
+            .with_event_bus(events.clone());
+        LightningNode {
+            node_id: key_manager.get_node_id(),
+            auth: Arc::new(TokenAuthority::new(&key_manager, database.clone())),
+            health: HealthState::new(database.clone()),
+            channel_manager: Arc::new(RwLock::new(channel_manager)),
+            key_manager,
+            database,
+            events,
+            p2p: P2PHandle::detached(),
+        }
+    }
+}
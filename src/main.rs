@@ -1,29 +1,28 @@
 use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, mpsc};
 use tokio::task::LocalSet;
 use tracing::{error, info, warn};
 use tracing_subscriber::fmt::init;
 
-mod api;
-mod channel;
-mod crypto;
-mod p2p;
-mod storage;
-
-use api::ApiServer;
-use channel::ChannelManager;
-use crypto::KeyManager;
-use p2p::P2PNode;
-use storage::Database;
-
-#[derive(Clone)]
-pub struct LightningNode {
-    pub node_id: String,
-    pub key_manager: Arc<KeyManager>,
-    pub channel_manager: Arc<RwLock<ChannelManager>>,
-    pub database: Arc<Database>,
-}
+use lightning_offline::LightningNode;
+use lightning_offline::api::ApiServer;
+use lightning_offline::auth::TokenAuthority;
+use lightning_offline::chain::ChainClient;
+use lightning_offline::channel::ChannelManager;
+use lightning_offline::crypto::{self, KeyManager};
+use lightning_offline::events::EventBus;
+use lightning_offline::grpc::GrpcServer;
+use lightning_offline::health::HealthState;
+use lightning_offline::monitor::ChainMonitor;
+use lightning_offline::p2p::{self, P2PNode};
+use lightning_offline::scoring::PeerViolation;
+use lightning_offline::storage::Database;
+use lightning_offline::tls;
+use lightning_offline::watchtower::{TowerClient, Watchtower};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -41,10 +40,96 @@ async fn main() -> anyhow::Result<()> {
 
             let api_port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
 
+            // Interface for the REST API; anything but loopback should use TLS
+            let api_host = env::var("API_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+            let api_address = format!("{}:{}", api_host, api_port);
+
+            // TLS_CERT_FILE/TLS_KEY_FILE use your own certificate, API_TLS=true a self-signed one
+            let tls_cert_file = env::var("TLS_CERT_FILE").ok().map(PathBuf::from);
+            let tls_key_file = env::var("TLS_KEY_FILE").ok().map(PathBuf::from);
+            let api_tls = tls_cert_file.is_some()
+                || env::var("API_TLS").is_ok_and(|v| v == "true" || v == "1");
+
+            // gRPC service on the API host; off unless set
+            let grpc_port = env::var("GRPC_PORT").ok();
+
+            // P2P_LISTEN_ADDRESSES (comma-separated multiaddrs) overrides P2P_PORT
             let p2p_port = env::var("P2P_PORT").unwrap_or_else(|_| "4001".to_string());
+            let p2p_listen_addresses = p2p::parse_multiaddrs(
+                &env::var("P2P_LISTEN_ADDRESSES")
+                    .unwrap_or_else(|_| format!("/ip4/0.0.0.0/tcp/{}", p2p_port)),
+            )?;
+
+            // Peers to dial at startup, e.g. /ip4/10.0.2.5/tcp/4001/p2p/12D3KooW...
+            let bootstrap_peers =
+                p2p::parse_multiaddrs(&env::var("BOOTSTRAP_PEERS").unwrap_or_default())?;
+
+            // Addresses other nodes can reach us at, published in the DHT along
+            // with the listen addresses
+            let external_addresses =
+                p2p::parse_multiaddrs(&env::var("P2P_EXTERNAL_ADDRESSES").unwrap_or_default())?;
+
+            // mDNS finds peers on the local network; off where only the DHT should
+            let mdns_enabled = env::var("P2P_MDNS").map_or(true, |v| v != "false" && v != "0");
+
+            // Seconds between liveness pings, and without a pong before a peer is
+            // considered offline
+            let ping_interval = env::var("P2P_PING_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .map_or(p2p::DEFAULT_PING_INTERVAL, Duration::from_secs);
+            let ping_timeout = env::var("P2P_PING_TIMEOUT")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .map_or(p2p::DEFAULT_PING_TIMEOUT, Duration::from_secs);
+
+            // Esplora-compatible API used to watch funding outputs, e.g. http://127.0.0.1:3002
+            let chain_backend_url = env::var("CHAIN_BACKEND_URL").ok();
+
+            let chain_poll_interval = env::var("CHAIN_POLL_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(30);
+
+            // "node" (default) or "watchtower"
+            let node_mode = env::var("NODE_MODE").unwrap_or_else(|_| "node".to_string());
+
+            // Comma-separated watchtower URLs to upload breach remedies to
+            let watchtowers: Vec<String> = env::var("WATCHTOWERS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            // Watchtower mode: client tokens allowed to upload blobs, and each one's quota
+            let tower_client_tokens: Vec<String> = env::var("TOWER_CLIENT_TOKENS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let tower_max_blobs = env::var("TOWER_MAX_BLOBS_PER_CLIENT")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(100_000);
+
+            // Encrypted static channel backup, next to the database by default
+            let backup_path = env::var("CHANNEL_BACKUP_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| default_data_path(&database_url, "backup"));
+
+            // Admin API token for lightning-cli, next to the database by default
+            let token_path = env::var("API_TOKEN_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| default_data_path(&database_url, "token"));
 
             info!("Using database: {}", database_url);
-            info!("API server will bind to: 127.0.0.1:{}", api_port);
+            info!("API server will bind to: {}", api_address);
 
             let database = match Database::new(&database_url).await {
                 Ok(db) => Arc::new(db),
@@ -59,8 +144,36 @@ async fn main() -> anyhow::Result<()> {
                 return Err(e);
             }
 
-            // Initialize key manager
-            let key_manager = match KeyManager::new() {
+            let health = HealthState::new(database.clone());
+
+            if node_mode == "watchtower" {
+                let url = chain_backend_url.ok_or_else(|| {
+                    anyhow::anyhow!("CHAIN_BACKEND_URL is required in watchtower mode")
+                })?;
+                if tower_client_tokens.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "TOWER_CLIENT_TOKENS is required in watchtower mode"
+                    ));
+                }
+                let tower = Watchtower::new(
+                    database,
+                    ChainClient::new(&url),
+                    &tower_client_tokens,
+                    tower_max_blobs,
+                );
+                return run_watchtower(
+                    tower,
+                    api_address,
+                    Duration::from_secs(chain_poll_interval),
+                )
+                .await;
+            }
+
+            // Initialize key manager from the persisted (or NODE_SEED) seed
+            let key_manager = match load_node_seed(&database)
+                .await
+                .and_then(KeyManager::from_seed)
+            {
                 Ok(km) => Arc::new(km),
                 Err(e) => {
                     error!("Failed to initialize key manager: {}", e);
@@ -68,32 +181,188 @@ async fn main() -> anyhow::Result<()> {
                 }
             };
 
+            health.keys_unlocked();
             let node_id = key_manager.get_node_id();
             info!("Node ID: {}", node_id);
 
-            // Initialize P2P node wrapped in Arc<RwLock> so it can be shared
-            let p2p_node = Arc::new(RwLock::new(P2PNode::new(key_manager.clone()).await?));
+            // Channels between the P2P task and the channel manager
+            let (inbound_sender, mut inbound_receiver) = mpsc::unbounded_channel();
+            let (outbound_sender, outbound_receiver) = mpsc::unbounded_channel();
+            let (peer_joined_sender, mut peer_joined_receiver) = mpsc::unbounded_channel();
+            let (peer_status_sender, mut peer_status_receiver) = mpsc::unbounded_channel();
+
+            let events = EventBus::new(database.clone());
+
+            // Initialize P2P node; it is owned by the P2P task below
+            let mut p2p_node = P2PNode::new(key_manager.clone(), inbound_sender, outbound_receiver)
+                .await?
+                .with_event_bus(events.clone())
+                .with_health(health.clone())
+                .with_database(database.clone())
+                .with_peer_joined_sender(peer_joined_sender)
+                .with_peer_status_sender(peer_status_sender)
+                .with_ping(ping_interval, ping_timeout)
+                .with_mdns(mdns_enabled)
+                .with_external_addresses(external_addresses)
+                .with_listen_addresses(p2p_listen_addresses)
+                .with_bootstrap_peers(bootstrap_peers)?;
 
             // Initialize channel manager
-            let channel_manager =
+            let mut channel_manager =
                 match ChannelManager::new(key_manager.clone(), database.clone()).await {
-                    Ok(cm) => Arc::new(RwLock::new(cm)),
+                    Ok(cm) => cm
+                        .with_p2p_sender(outbound_sender)
+                        .with_event_bus(events.clone()),
                     Err(e) => {
                         error!("Failed to initialize channel manager: {}", e);
                         return Err(e);
                     }
                 };
 
+            if !watchtowers.is_empty() {
+                info!(
+                    "Uploading breach remedies to watchtowers: {:?}",
+                    watchtowers
+                );
+                channel_manager = channel_manager.with_tower_client(TowerClient::new(watchtowers));
+            }
+
+            if let Some(url) = &chain_backend_url {
+                channel_manager = channel_manager.with_chain_client(ChainClient::new(url));
+            }
+
+            info!("Writing static channel backup to {}", backup_path.display());
+            let channel_manager =
+                Arc::new(RwLock::new(channel_manager.with_backup_file(backup_path)?));
+
+            // Dispatch messages received from peers to the channel manager, and
+            // penalize the author of any the manager finds invalid
+            let inbound_channel_manager = channel_manager.clone();
+            let inbound_p2p = p2p_node.handle();
+            tokio::task::spawn_local(async move {
+                while let Some((author, message)) = inbound_receiver.recv().await {
+                    let result = inbound_channel_manager
+                        .write()
+                        .await
+                        .handle_p2p_message(&author, message)
+                        .await;
+                    if let Err(e) = result {
+                        error!(
+                            "Failed to handle P2P message from {}: {}",
+                            author.peer_id, e
+                        );
+                        if let Some(violation) = e.downcast_ref::<PeerViolation>() {
+                            inbound_p2p
+                                .report_misbehaviour(
+                                    author.peer_id,
+                                    violation.kind,
+                                    violation.reason.clone(),
+                                )
+                                .await;
+                        }
+                    }
+                }
+            });
+
+            // Reconcile channel state with every peer that (re)joins
+            let reestablish_channel_manager = channel_manager.clone();
+            tokio::task::spawn_local(async move {
+                while let Some(peer_id) = peer_joined_receiver.recv().await {
+                    info!("Reestablishing channels with {}", peer_id);
+                    reestablish_channel_manager
+                        .read()
+                        .await
+                        .reestablish_channels()
+                        .await;
+                }
+            });
+
+            // Track which channel peers are online, and keep redialing those we
+            // have open channels with
+            let status_channel_manager = channel_manager.clone();
+            let status_p2p = p2p_node.handle();
+            tokio::task::spawn_local(async move {
+                while let Some(status) = peer_status_receiver.recv().await {
+                    let has_open_channel = status_channel_manager
+                        .write()
+                        .await
+                        .set_peer_status(&status);
+                    if status.online && has_open_channel {
+                        status_p2p.keep_connected(status.peer_id).await;
+                    }
+                }
+            });
+
+            let auth = Arc::new(TokenAuthority::new(&key_manager, database.clone()));
+            auth.ensure_admin_token(&token_path).await?;
+            info!("Admin API token at {}", token_path.display());
+
             let lightning_node = LightningNode {
                 node_id,
-                key_manager,
-                channel_manager,
-                database,
+                key_manager: key_manager.clone(),
+                channel_manager: channel_manager.clone(),
+                database: database.clone(),
+                auth,
+                events,
+                health: health.clone(),
+                p2p: p2p_node.handle(),
             };
 
+            // Watch funding outputs for unilateral closes and breaches. Like the
+            // P2P task below, a failure is reported on /health rather than ending
+            // the process, so the API stays up to say what went wrong.
+            match chain_backend_url {
+                Some(url) => {
+                    let chain_monitor = ChainMonitor::new(
+                        key_manager,
+                        database,
+                        channel_manager,
+                        ChainClient::new(&url),
+                    )
+                    .with_health(health.clone());
+                    let monitor_health = health.clone();
+                    tokio::task::spawn_local(async move {
+                        if let Err(e) = chain_monitor
+                            .run(Duration::from_secs(chain_poll_interval))
+                            .await
+                        {
+                            error!("Chain monitor error: {}", e);
+                            monitor_health.chain_failed(e.to_string());
+                        }
+                    });
+                }
+                None => {
+                    warn!("CHAIN_BACKEND_URL not set, channel breach detection is disabled");
+                }
+            }
+
             // Start API server with configured port
-            let api_server = ApiServer::new(lightning_node.clone());
-            let api_address = format!("127.0.0.1:{}", api_port);
+            let mut api_server = ApiServer::new(lightning_node.clone());
+            let mut grpc_server = GrpcServer::new(lightning_node.clone());
+            if api_tls {
+                let cert_path =
+                    tls_cert_file.unwrap_or_else(|| default_data_path(&database_url, "cert"));
+                let key_path =
+                    tls_key_file.unwrap_or_else(|| default_data_path(&database_url, "key"));
+                if tls::ensure_self_signed(&cert_path, &key_path, tls_names(&api_host))? {
+                    info!(
+                        "Generated self-signed TLS certificate {}",
+                        cert_path.display()
+                    );
+                }
+                info!(
+                    "TLS certificate {} (SHA-256 {})",
+                    cert_path.display(),
+                    tls::certificate_fingerprint(&cert_path)?
+                );
+                api_server = api_server.with_tls(tls::load_server_config(&cert_path, &key_path)?);
+                grpc_server = grpc_server.with_tls(tls::load_grpc_identity(&cert_path, &key_path)?);
+            } else if api_host != "127.0.0.1" && api_host != "localhost" && api_host != "::1" {
+                warn!(
+                    "API bound to {} without TLS; set API_TLS=true to encrypt it",
+                    api_host
+                );
+            }
             let api_handle = tokio::task::spawn_local(async move {
                 match api_server.start(&api_address).await {
                     Ok(_) => info!("API server stopped gracefully"),
@@ -101,14 +370,27 @@ async fn main() -> anyhow::Result<()> {
                 }
             });
 
-            // Start P2P networking in its own task with cloned reference
-            let p2p_for_task = p2p_node.clone();
-            let p2p_handle = tokio::task::spawn_local(async move {
-                let mut p2p = p2p_for_task.write().await;
-                match p2p.start_listening().await {
-                    Ok(_) => info!("P2P node stopped gracefully"),
-                    Err(e) => error!("P2P node error: {}", e),
+            let grpc_handle = match grpc_port {
+                Some(port) => {
+                    let grpc_address = format!("{}:{}", api_host, port);
+                    tokio::task::spawn_local(async move {
+                        match grpc_server.start(&grpc_address).await {
+                            Ok(_) => info!("gRPC server stopped gracefully"),
+                            Err(e) => error!("gRPC server error: {}", e),
+                        }
+                    })
                 }
+                None => tokio::task::spawn_local(std::future::pending::<()>()),
+            };
+
+            // Start P2P networking in its own task
+            tokio::task::spawn_local(async move {
+                let error = match p2p_node.start_listening().await {
+                    Ok(_) => "P2P node stopped".to_string(),
+                    Err(e) => format!("P2P node error: {}", e),
+                };
+                error!("{}", error);
+                health.p2p_stopped(error);
             });
 
             // Wait for all services
@@ -119,10 +401,10 @@ async fn main() -> anyhow::Result<()> {
                         Err(e) => error!("API server task failed: {}", e),
                     }
                 },
-                result = p2p_handle => {
+                result = grpc_handle => {
                     match result {
-                        Ok(_) => warn!("P2P node task completed"),
-                        Err(e) => error!("P2P node task failed: {}", e),
+                        Ok(_) => warn!("gRPC server task completed"),
+                        Err(e) => error!("gRPC server task failed: {}", e),
                     }
                 },
                 _ = tokio::signal::ctrl_c() => info!("Received shutdown signal"),
@@ -133,3 +415,104 @@ async fn main() -> anyhow::Result<()> {
         })
         .await
 }
+
+async fn run_watchtower(
+    tower: Watchtower,
+    address: String,
+    poll_interval: Duration,
+) -> anyhow::Result<()> {
+    info!("Running in watchtower mode");
+    let tower = Rc::new(tower);
+
+    let server = tower.clone();
+    let server_handle = tokio::task::spawn_local(async move {
+        if let Err(e) = server.start(&address).await {
+            error!("Watchtower server error: {}", e);
+        }
+    });
+
+    let watcher = tower.clone();
+    let watch_handle = tokio::task::spawn_local(async move {
+        if let Err(e) = watcher.watch(poll_interval).await {
+            error!("Watchtower error: {}", e);
+        }
+    });
+
+    tokio::select! {
+        _ = server_handle => warn!("Watchtower server task completed"),
+        _ = watch_handle => warn!("Watchtower scan task completed"),
+        _ = tokio::signal::ctrl_c() => info!("Received shutdown signal"),
+    }
+
+    info!("Watchtower shutting down");
+    Ok(())
+}
+
+/// Loads the node seed from the database, creating it on first start.
+/// `NODE_SEED` (hex) restores an existing node identity onto a fresh database.
+async fn load_node_seed(database: &Database) -> anyhow::Result<[u8; 32]> {
+    let env_seed = env::var("NODE_SEED")
+        .ok()
+        .map(|s| parse_seed(&s))
+        .transpose()?;
+    let stored = database
+        .get_node_seed()
+        .await?
+        .map(|s| parse_seed(&s))
+        .transpose()?;
+
+    match (env_seed, stored) {
+        (Some(env_seed), Some(stored)) if env_seed != stored => Err(anyhow::anyhow!(
+            "NODE_SEED does not match the seed stored in the database"
+        )),
+        (_, Some(stored)) => Ok(stored),
+        (Some(env_seed), None) => {
+            database.save_node_seed(&hex::encode(env_seed)).await?;
+            Ok(env_seed)
+        }
+        (None, None) => {
+            let seed = crypto::generate_seed();
+            database.save_node_seed(&hex::encode(seed)).await?;
+            warn!(
+                "Generated a new node seed; export it with GET /api/backup/seed \
+                 (lightning-cli backup seed) and keep it with your channel backups"
+            );
+            Ok(seed)
+        }
+    }
+}
+
+fn parse_seed(seed_hex: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(seed_hex.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Node seed must be 32 bytes"))
+}
+
+/// Names the self-signed certificate is valid for: loopback, the bind
+/// address and anything listed in `TLS_EXTRA_NAMES` (e.g. a shop LAN IP).
+fn tls_names(api_host: &str) -> Vec<String> {
+    let extra = env::var("TLS_EXTRA_NAMES").unwrap_or_default();
+    let mut names: Vec<String> = Vec::new();
+    for name in ["localhost", "127.0.0.1", "::1", api_host]
+        .into_iter()
+        .chain(extra.split(','))
+        .map(str::trim)
+    {
+        if !name.is_empty() && name != "0.0.0.0" && name != "::" && !names.iter().any(|n| n == name)
+        {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// `sqlite:./data/alice.db?mode=rwc`, `backup` -> `./data/alice.backup`
+fn default_data_path(database_url: &str, extension: &str) -> PathBuf {
+    let path = database_url
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:")
+        .split('?')
+        .next()
+        .unwrap_or("lightning.db");
+    PathBuf::from(path).with_extension(extension)
+}
@@ -0,0 +1,204 @@
+use prometheus::{
+    HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+/// Process-wide metrics, served at `/metrics` in the Prometheus text format.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Channels by `state` ("open" or "closed")
+    pub channels: IntGaugeVec,
+    /// Our balance across open channels, in satoshis
+    pub local_balance: IntGauge,
+    /// Peers' balance across open channels, in satoshis
+    pub remote_balance: IntGauge,
+    /// Payments by `direction` ("outgoing" or "incoming")
+    pub payments: IntCounterVec,
+    pub payment_duration: HistogramVec,
+    pub peers: IntGauge,
+    /// Gossipsub messages by `direction` ("in" or "out")
+    pub gossip_messages: IntCounterVec,
+    /// Peer misbehaviour by `kind`, e.g. "invalid_signature"
+    pub peer_misbehaviour: IntCounterVec,
+    /// Time spent in each `Database` method, by `query`
+    pub db_query_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("lightning".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let channels = IntGaugeVec::new(
+            Opts::new("channels", "Payment channels by state"),
+            &["state"],
+        )
+        .unwrap();
+        let local_balance = IntGauge::new(
+            "local_balance_sats",
+            "Our balance across open channels in satoshis",
+        )
+        .unwrap();
+        let remote_balance = IntGauge::new(
+            "remote_balance_sats",
+            "Peer balance across open channels in satoshis",
+        )
+        .unwrap();
+        let payments = IntCounterVec::new(
+            Opts::new("payments_total", "Payments sent and received"),
+            &["direction"],
+        )
+        .unwrap();
+        let payment_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "payment_duration_seconds",
+                "Time to sign, store and hand off a payment",
+            )
+            .buckets(vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+            ]),
+            &["direction"],
+        )
+        .unwrap();
+        let peers = IntGauge::new("p2p_peers", "Connected P2P peers").unwrap();
+        let gossip_messages = IntCounterVec::new(
+            Opts::new(
+                "gossip_messages_total",
+                "Gossipsub messages published and received",
+            ),
+            &["direction"],
+        )
+        .unwrap();
+        let peer_misbehaviour = IntCounterVec::new(
+            Opts::new("peer_misbehaviour_total", "Penalized peer messages by kind"),
+            &["kind"],
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "Database call latency").buckets(vec![
+                0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+            ]),
+            &["query"],
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(channels.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(local_balance.clone()),
+            Box::new(remote_balance.clone()),
+            Box::new(payments.clone()),
+            Box::new(payment_duration.clone()),
+            Box::new(peers.clone()),
+            Box::new(gossip_messages.clone()),
+            Box::new(peer_misbehaviour.clone()),
+            Box::new(db_query_duration.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Self {
+            registry,
+            channels,
+            local_balance,
+            remote_balance,
+            payments,
+            payment_duration,
+            peers,
+            gossip_messages,
+            peer_misbehaviour,
+            db_query_duration,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+/// Observes the time until the returned timer is dropped.
+pub fn db_timer(query: &str) -> HistogramTimer {
+    METRICS
+        .db_query_duration
+        .with_label_values(&[query])
+        .start_timer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LightningNode;
+    use crate::channel::ChannelLimits;
+    use crate::crypto::KeyManager;
+    use crate::fees::CommitmentType;
+
+    #[test]
+    fn render_uses_the_prometheus_text_format() {
+        let metrics = Metrics::new();
+        metrics.channels.with_label_values(&["open"]).set(2);
+        metrics.local_balance.set(5_000);
+        metrics.payments.with_label_values(&["outgoing"]).inc();
+        metrics
+            .payment_duration
+            .with_label_values(&["outgoing"])
+            .observe(0.003);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE lightning_channels gauge"));
+        assert!(text.contains("lightning_channels{state=\"open\"} 2"));
+        assert!(text.contains("lightning_local_balance_sats 5000"));
+        assert!(text.contains("# TYPE lightning_payments_total counter"));
+        assert!(text.contains("lightning_payments_total{direction=\"outgoing\"} 1"));
+        assert!(text.contains(
+            "lightning_payment_duration_seconds_bucket{direction=\"outgoing\",le=\"0.005\"} 1"
+        ));
+        assert!(
+            text.contains("lightning_payment_duration_seconds_count{direction=\"outgoing\"} 1")
+        );
+    }
+
+    #[test]
+    fn db_timer_observes_on_drop() {
+        let histogram = METRICS
+            .db_query_duration
+            .with_label_values(&["db_timer_test"]);
+        drop(db_timer("db_timer_test"));
+        assert_eq!(histogram.get_sample_count(), 1);
+        assert!(
+            METRICS
+                .render()
+                .contains("lightning_db_query_duration_seconds_count{query=\"db_timer_test\"} 1")
+        );
+    }
+
+    #[tokio::test]
+    async fn payments_are_counted() {
+        let node = LightningNode::in_memory(1).await;
+        let peer = KeyManager::from_seed([2; 32]).unwrap();
+        let mut channel_manager = node.channel_manager.write().await;
+        let channel = channel_manager
+            .open_channel(
+                peer.get_public_key().to_string(),
+                1_000_000,
+                CommitmentType::Legacy,
+                ChannelLimits::for_capacity(1_000_000),
+            )
+            .await
+            .unwrap();
+
+        // Other tests pay concurrently, so only a lower bound holds
+        let outgoing = METRICS.payments.with_label_values(&["outgoing"]);
+        let before = outgoing.get();
+        channel_manager
+            .send_payment(&channel.id, 10_000, None)
+            .await
+            .unwrap();
+        assert!(outgoing.get() > before);
+    }
+}
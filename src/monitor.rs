@@ -0,0 +1,539 @@
+use crate::chain::{ChainClient, OutputSpend};
+use crate::channel::{ChannelManager, CommitmentTransaction, PaymentChannel};
+use crate::crypto::{KeyManager, SigningDomain};
+use crate::fees::{ANCHOR_OUTPUT_VALUE, penalty_fee};
+use crate::health::HealthState;
+use crate::storage::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpendKind {
+    /// Mutual close transaction we signed in `close_channel`
+    Cooperative,
+    /// The latest commitment transaction (unilateral close)
+    Current,
+    /// An old commitment transaction the peer should no longer broadcast
+    Revoked,
+    /// Something we have no record of
+    Unknown,
+}
+
+impl SpendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpendKind::Cooperative => "cooperative",
+            SpendKind::Current => "current",
+            SpendKind::Revoked => "revoked",
+            SpendKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "cooperative" => Some(SpendKind::Cooperative),
+            "current" => Some(SpendKind::Current),
+            "revoked" => Some(SpendKind::Revoked),
+            "unknown" => Some(SpendKind::Unknown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelMonitor {
+    pub channel_id: String,
+    pub funding_txid: String,
+    pub funding_vout: u32,
+    pub is_resolved: bool,
+    pub spend_txid: Option<String>,
+    pub spend_kind: Option<SpendKind>,
+    pub resolution_txid: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Watches every channel's funding outpoint and reacts when it is spent.
+pub struct ChainMonitor {
+    key_manager: Arc<KeyManager>,
+    database: Arc<Database>,
+    channel_manager: Arc<RwLock<ChannelManager>>,
+    chain: ChainClient,
+    health: Option<HealthState>,
+}
+
+impl ChainMonitor {
+    pub fn new(
+        key_manager: Arc<KeyManager>,
+        database: Arc<Database>,
+        channel_manager: Arc<RwLock<ChannelManager>>,
+        chain: ChainClient,
+    ) -> Self {
+        Self {
+            key_manager,
+            database,
+            channel_manager,
+            chain,
+            health: None,
+        }
+    }
+
+    /// Report each poll's outcome as the chain sync status.
+    pub fn with_health(mut self, health: HealthState) -> Self {
+        health.chain_configured(self.chain.base_url());
+        self.health = Some(health);
+        self
+    }
+
+    pub async fn run(&self, poll_interval: Duration) -> Result<()> {
+        info!("Chain monitor polling {}", self.chain.base_url());
+        let mut interval = tokio::time::interval(poll_interval);
+
+        loop {
+            interval.tick().await;
+            // Fetch the tip first so an unreachable backend shows even without channels
+            let synced = match self.chain.get_tip_hash().await {
+                Ok(tip) => self.poll().await.map(|_| tip),
+                Err(e) => Err(e),
+            };
+            match synced {
+                Ok(tip) => {
+                    if let Some(health) = &self.health {
+                        health.chain_synced(tip);
+                    }
+                }
+                Err(e) => {
+                    error!("Chain monitor poll failed: {}", e);
+                    if let Some(health) = &self.health {
+                        health.chain_failed(e.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Registers monitors for new channels and checks all unresolved ones.
+    pub async fn poll(&self) -> Result<()> {
+        let channels: Vec<PaymentChannel> = {
+            let channel_manager = self.channel_manager.read().await;
+            channel_manager
+                .get_all_channels()
+                .into_iter()
+                .cloned()
+                .collect()
+        };
+
+        for channel in &channels {
+            if self
+                .database
+                .get_channel_monitor(&channel.id)
+                .await?
+                .is_none()
+            {
+                let monitor = ChannelMonitor {
+                    channel_id: channel.id.clone(),
+                    funding_txid: channel.funding_txid.clone(),
+                    funding_vout: 0,
+                    is_resolved: false,
+                    spend_txid: None,
+                    spend_kind: None,
+                    resolution_txid: None,
+                    updated_at: Utc::now(),
+                };
+                self.database.save_channel_monitor(&monitor).await?;
+            }
+        }
+
+        for monitor in self.database.get_unresolved_monitors().await? {
+            let spend = match self
+                .chain
+                .get_output_spend(&monitor.funding_txid, monitor.funding_vout)
+                .await
+            {
+                Ok(Some(spend)) => spend,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(
+                        "Failed to query funding output of channel {}: {}",
+                        monitor.channel_id, e
+                    );
+                    continue;
+                }
+            };
+
+            let Some(channel) = channels.iter().find(|c| c.id == monitor.channel_id) else {
+                continue;
+            };
+
+            self.handle_spend(monitor, channel, &spend).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_spend(
+        &self,
+        mut monitor: ChannelMonitor,
+        channel: &PaymentChannel,
+        spend: &OutputSpend,
+    ) -> Result<()> {
+        let spend_txid = spend.txid.as_str();
+        let commitments = self.database.get_channel_commitments(&channel.id).await?;
+        let (kind, matched) = classify_spend(channel, &commitments, spend_txid);
+
+        let flagged = monitor.spend_kind == Some(SpendKind::Unknown)
+            && monitor.spend_txid.as_deref() == Some(spend_txid);
+        if kind == SpendKind::Unknown && flagged {
+            return Ok(());
+        }
+
+        info!(
+            "Funding output of channel {} spent by {} ({}, {})",
+            channel.id,
+            spend_txid,
+            kind.as_str(),
+            if spend.confirmed {
+                "confirmed"
+            } else {
+                "unconfirmed"
+            }
+        );
+
+        let resolution_tx = match (kind, matched) {
+            (SpendKind::Current, Some(commitment)) => Some(sweep_transaction(
+                &self.key_manager,
+                spend_txid,
+                commitment,
+                // Our output is the holder's on our commitment, the other on theirs
+                if commitment.txid() == spend_txid {
+                    0
+                } else {
+                    1
+                },
+            )?),
+            (SpendKind::Revoked, Some(commitment)) => {
+                warn!(
+                    "Peer broadcast revoked state {} of channel {}, publishing penalty",
+                    commitment.sequence, channel.id
+                );
+                Some(penalty_transaction(
+                    &self.key_manager,
+                    spend_txid,
+                    commitment,
+                    penalty_fee(channel.feerate_per_kw),
+                )?)
+            }
+            _ => None,
+        };
+
+        // Record the spend before broadcasting so it is visible even while the
+        // resolution keeps failing to broadcast
+        monitor.spend_txid = Some(spend_txid.to_string());
+        monitor.spend_kind = Some(kind);
+        monitor.updated_at = Utc::now();
+        self.database.update_channel_monitor(&monitor).await?;
+
+        // Nothing we know how to claim: leave the monitor unresolved and the
+        // channel open but refusing updates until the operator looks at it
+        if kind == SpendKind::Unknown {
+            error!(
+                "Channel {} funding spent by unknown transaction {}, flagged for the operator",
+                channel.id, spend_txid
+            );
+            let mut channel_manager = self.channel_manager.write().await;
+            channel_manager
+                .flag_unknown_spend(&channel.id, spend_txid)
+                .await?;
+            return Ok(());
+        }
+
+        if let Some(raw_tx) = resolution_tx {
+            match self.chain.broadcast(&raw_tx).await {
+                Ok(txid) => monitor.resolution_txid = Some(txid),
+                Err(e) => {
+                    // Leave the monitor unresolved so the next poll retries
+                    error!("Failed to broadcast resolution for {}: {}", channel.id, e);
+                    return Ok(());
+                }
+            }
+        }
+
+        monitor.is_resolved = true;
+        monitor.updated_at = Utc::now();
+        self.database.update_channel_monitor(&monitor).await?;
+
+        if channel.is_open {
+            let mut channel_manager = self.channel_manager.write().await;
+            channel_manager.mark_channel_closed(&channel.id).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sweeps our output (`vout`) of a unilaterally closed commitment to our wallet address.
+pub fn sweep_transaction(
+    key_manager: &KeyManager,
+    commitment_txid: &str,
+    commitment: &CommitmentTransaction,
+    vout: u32,
+) -> Result<String> {
+    let unsigned = format!(
+        "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":{}}}],\"outputs\":[{{\"amount\":{},\"address\":\"{}\"}}]}}",
+        commitment_txid,
+        vout,
+        commitment.my_balance,
+        key_manager.get_bitcoin_address()
+    );
+    sign_transaction(key_manager, unsigned)
+}
+
+/// Claims both outputs of a revoked commitment to our wallet address, paying `fee`.
+pub fn penalty_transaction(
+    key_manager: &KeyManager,
+    commitment_txid: &str,
+    commitment: &CommitmentTransaction,
+    fee: u64,
+) -> Result<String> {
+    let input_value = commitment.my_balance + commitment.peer_balance;
+    if fee >= input_value {
+        return Err(anyhow::anyhow!(
+            "Revoked outputs of {} sat cannot pay a {} sat penalty fee",
+            input_value,
+            fee
+        ));
+    }
+
+    let unsigned = format!(
+        "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":0}},{{\"txid\":\"{}\",\"vout\":1}}],\"outputs\":[{{\"amount\":{},\"address\":\"{}\"}}],\"fee\":{}}}",
+        commitment_txid,
+        commitment_txid,
+        input_value - fee,
+        key_manager.get_bitcoin_address(),
+        fee
+    );
+    sign_transaction(key_manager, unsigned)
+}
+
+/// Child spending our main output and our anchor (vout 2) of a broadcast
+/// anchor commitment, paying `child_fee` to pull the commitment into a block.
+pub fn cpfp_transaction(
+    key_manager: &KeyManager,
+    commitment_txid: &str,
+    my_balance: u64,
+    child_fee: u64,
+) -> Result<String> {
+    let input_value = my_balance + ANCHOR_OUTPUT_VALUE;
+    if child_fee >= input_value {
+        return Err(anyhow::anyhow!(
+            "Our {} sat output cannot pay a {} sat CPFP fee",
+            input_value,
+            child_fee
+        ));
+    }
+
+    let unsigned = format!(
+        "{{\"version\":2,\"inputs\":[{{\"txid\":\"{}\",\"vout\":0}},{{\"txid\":\"{}\",\"vout\":2}}],\"outputs\":[{{\"amount\":{},\"address\":\"{}\"}}],\"fee\":{}}}",
+        commitment_txid,
+        commitment_txid,
+        input_value - child_fee,
+        key_manager.get_bitcoin_address(),
+        child_fee
+    );
+    sign_transaction(key_manager, unsigned)
+}
+
+fn sign_transaction(key_manager: &KeyManager, unsigned: String) -> Result<String> {
+    let signature = hex::encode(
+        key_manager
+            .sign(SigningDomain::Transaction, unsigned.as_bytes())?
+            .serialize_compact(),
+    );
+    Ok(format!(
+        "{{\"tx\":{},\"signature\":\"{}\"}}",
+        unsigned, signature
+    ))
+}
+
+/// Matches a spend of the funding output against the channel's known transactions.
+pub fn classify_spend<'a>(
+    channel: &PaymentChannel,
+    commitments: &'a [CommitmentTransaction],
+    spend_txid: &str,
+) -> (SpendKind, Option<&'a CommitmentTransaction>) {
+    if channel.closing_txid.as_deref() == Some(spend_txid) {
+        return (SpendKind::Cooperative, None);
+    }
+
+    let latest_sequence = commitments.iter().map(|c| c.sequence).max();
+
+    // Either side may broadcast its own commitment of any state
+    let matched = commitments
+        .iter()
+        .find(|c| c.txid() == spend_txid || c.remote_txid.as_deref() == Some(spend_txid));
+    match matched {
+        Some(c) if Some(c.sequence) == latest_sequence => (SpendKind::Current, Some(c)),
+        Some(c) => (SpendKind::Revoked, Some(c)),
+        None => (SpendKind::Unknown, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::ChannelLimits;
+    use crate::crypto::transaction_id;
+    use crate::fees::CommitmentType;
+
+    fn commitment(sequence: u64) -> CommitmentTransaction {
+        CommitmentTransaction {
+            id: format!("commitment-{}", sequence),
+            channel_id: "channel".to_string(),
+            sequence,
+            my_balance: 500_000 - sequence * 1_000,
+            peer_balance: 500_000 + sequence * 1_000,
+            fee: 0,
+            raw_tx: format!("raw-{}", sequence),
+            signature: String::new(),
+            peer_signature: None,
+            created_at: Utc::now(),
+            remote_txid: Some(transaction_id(&format!("remote-raw-{}", sequence))),
+        }
+    }
+
+    async fn manager_with_channel() -> (Arc<RwLock<ChannelManager>>, Arc<Database>, PaymentChannel)
+    {
+        let key_manager = Arc::new(KeyManager::from_seed([1; 32]).unwrap());
+        let peer = KeyManager::from_seed([2; 32]).unwrap();
+        let database = Arc::new(Database::in_memory().await.unwrap());
+        let mut manager = ChannelManager::new(key_manager, database.clone())
+            .await
+            .unwrap();
+        let channel = manager
+            .open_channel(
+                peer.get_public_key().to_string(),
+                1_000_000,
+                CommitmentType::Legacy,
+                ChannelLimits::for_capacity(1_000_000),
+            )
+            .await
+            .unwrap();
+        (Arc::new(RwLock::new(manager)), database, channel)
+    }
+
+    #[tokio::test]
+    async fn spends_are_classified_against_known_transactions() {
+        let (_, _, mut channel) = manager_with_channel().await;
+        channel.closing_txid = Some("closing".to_string());
+        let commitments = [commitment(0), commitment(1), commitment(2)];
+
+        let cases = [
+            ("closing".to_string(), SpendKind::Cooperative, None),
+            (commitments[2].txid(), SpendKind::Current, Some(2)),
+            (commitments[1].txid(), SpendKind::Revoked, Some(1)),
+            (commitments[0].txid(), SpendKind::Revoked, Some(0)),
+            ("stranger".to_string(), SpendKind::Unknown, None),
+        ];
+        for (spend_txid, kind, sequence) in &cases {
+            let (classified, matched) = classify_spend(&channel, &commitments, spend_txid);
+            assert_eq!(classified, *kind, "spend {}", spend_txid);
+            assert_eq!(
+                matched.map(|c| c.sequence),
+                *sequence,
+                "spend {}",
+                spend_txid
+            );
+        }
+
+        // Without commitments or a close, every spend is unknown
+        assert_eq!(
+            classify_spend(&channel, &[], &commitments[2].txid()).0,
+            SpendKind::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn peers_revoked_commitment_is_punished() {
+        let (_, _, channel) = manager_with_channel().await;
+        let commitments = [commitment(0), commitment(1), commitment(2)];
+        let peer_txid = |sequence: usize| commitments[sequence].remote_txid.clone().unwrap();
+
+        let (kind, matched) = classify_spend(&channel, &commitments, &peer_txid(1));
+        assert_eq!(kind, SpendKind::Revoked);
+        assert_eq!(matched.map(|c| c.sequence), Some(1));
+        let (kind, matched) = classify_spend(&channel, &commitments, &peer_txid(2));
+        assert_eq!(kind, SpendKind::Current);
+        assert_eq!(matched.map(|c| c.sequence), Some(2));
+
+        // The justice transaction claims both outputs less its own fee
+        let key_manager = KeyManager::from_seed([1; 32]).unwrap();
+        let fee = penalty_fee(channel.feerate_per_kw);
+        let justice_tx =
+            penalty_transaction(&key_manager, &peer_txid(1), &commitments[1], fee).unwrap();
+        assert!(justice_tx.contains(&format!("\"amount\":{}", 1_000_000 - fee)));
+        assert!(justice_tx.contains(&format!("\"fee\":{}", fee)));
+        assert!(
+            penalty_transaction(&key_manager, &peer_txid(1), &commitments[1], 1_000_000).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_spend_flags_the_channel_instead_of_closing_it() {
+        let (channel_manager, database, channel) = manager_with_channel().await;
+        let monitor = ChainMonitor::new(
+            Arc::new(KeyManager::from_seed([1; 32]).unwrap()),
+            database.clone(),
+            channel_manager.clone(),
+            ChainClient::new("http://127.0.0.1:9"),
+        );
+        let record = ChannelMonitor {
+            channel_id: channel.id.clone(),
+            funding_txid: channel.funding_txid.clone(),
+            funding_vout: 0,
+            is_resolved: false,
+            spend_txid: None,
+            spend_kind: None,
+            resolution_txid: None,
+            updated_at: Utc::now(),
+        };
+        database.save_channel_monitor(&record).await.unwrap();
+        let spend = OutputSpend {
+            txid: "stranger".to_string(),
+            confirmed: true,
+        };
+
+        monitor
+            .handle_spend(record, &channel, &spend)
+            .await
+            .unwrap();
+        // Seen again on the next poll: already flagged, nothing changes
+        let record = database
+            .get_channel_monitor(&channel.id)
+            .await
+            .unwrap()
+            .unwrap();
+        monitor
+            .handle_spend(record, &channel, &spend)
+            .await
+            .unwrap();
+
+        let record = database
+            .get_channel_monitor(&channel.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!record.is_resolved);
+        assert_eq!(record.spend_kind, Some(SpendKind::Unknown));
+        assert_eq!(record.spend_txid.as_deref(), Some("stranger"));
+
+        let channel_manager = channel_manager.read().await;
+        let flagged = channel_manager.get_channel(&channel.id).unwrap();
+        assert!(flagged.is_open);
+        assert!(flagged.sync_error.as_ref().unwrap().contains("stranger"));
+    }
+}
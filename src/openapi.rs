@@ -0,0 +1,247 @@
+use crate::api::{self, required_scope};
+use crate::auth::{Scope, TokenRecord};
+use crate::channel::{
+    ChannelLimits, ChannelStats, CommitmentTransaction, Invoice, OutboxEntry, PaymentChannel,
+    PaymentPage, PaymentRecord, SortOrder,
+};
+use crate::dht::NodeRecord;
+use crate::error::ErrorResponse;
+use crate::events::{EventRecord, NodeEvent};
+use crate::fees::CommitmentType;
+use crate::health::{
+    ChainHealth, ComponentStatus, DatabaseHealth, HealthReport, KeysHealth, P2PHealth,
+};
+use crate::p2p::PeerInfo;
+use crate::scoring::PeerBan;
+use crate::sneakernet::ExportedBundle;
+use axum::http::Method;
+use utoipa::openapi::path::PathItemType;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, Ref, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+/// The REST API as served by `ApiServer`. Handlers describe themselves with
+/// `#[utoipa::path]`; every new route must be listed here too (a test checks).
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Lightning Offline Node API",
+        description = "REST and WebSocket API of a Lightning Offline node",
+        license(name = "MIT")
+    ),
+    paths(
+        api::get_node_info,
+        api::get_channels,
+        api::open_channel,
+        api::get_channel,
+        api::delete_channel,
+        api::get_channel_stats,
+        api::send_payment,
+        api::get_payments,
+        api::list_payments,
+        api::get_outbox,
+        api::close_channel,
+        api::force_close_channel,
+        api::update_fee,
+        api::bump_fee,
+        api::export_updates,
+        api::import_updates,
+        api::export_backup,
+        api::export_seed,
+        api::restore_backup,
+        api::list_tokens,
+        api::mint_token,
+        api::revoke_token,
+        api::list_peers,
+        api::connect_peer,
+        api::disconnect_peer,
+        api::lookup_node,
+        api::list_bans,
+        api::ban_peer,
+        api::unban_peer,
+        api::get_invoices,
+        api::create_invoice,
+        api::websocket_handler,
+        api::metrics,
+        api::health,
+        api::ready,
+    ),
+    components(schemas(
+        api::NodeInfo,
+        api::OpenChannelRequest,
+        api::ChannelDetail,
+        api::SendPaymentRequest,
+        api::CreateInvoiceRequest,
+        api::ConnectPeerRequest,
+        api::BanPeerRequest,
+        api::UpdateFeeRequest,
+        api::BumpFeeRequest,
+        api::BumpFeeResponse,
+        api::ChannelBackup,
+        api::NodeSeed,
+        api::ImportBundleRequest,
+        api::ImportBundleResponse,
+        ExportedBundle,
+        api::MintTokenRequest,
+        api::MintTokenResponse,
+        PaymentChannel,
+        ChannelLimits,
+        CommitmentType,
+        CommitmentTransaction,
+        ChannelStats,
+        PaymentRecord,
+        PaymentPage,
+        OutboxEntry,
+        SortOrder,
+        Invoice,
+        Scope,
+        TokenRecord,
+        PeerInfo,
+        PeerBan,
+        NodeRecord,
+        ErrorResponse,
+        NodeEvent,
+        EventRecord,
+        HealthReport,
+        DatabaseHealth,
+        P2PHealth,
+        ChainHealth,
+        KeysHealth,
+        ComponentStatus,
+    )),
+    modifiers(&Authentication),
+    security(("bearer_token" = [])),
+)]
+struct ApiDoc;
+
+/// Declares the bearer token scheme and, on every authenticated operation,
+/// the scope `require_token` enforces and the JSON error body.
+struct Authentication;
+
+impl Modify for Authentication {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            for (kind, operation) in item.operations.iter_mut() {
+                let method = match kind {
+                    PathItemType::Get => Method::GET,
+                    PathItemType::Post => Method::POST,
+                    PathItemType::Put => Method::PUT,
+                    PathItemType::Delete => Method::DELETE,
+                    PathItemType::Patch => Method::PATCH,
+                    _ => continue,
+                };
+                // Operations declared with `security(())` are served without a token
+                let public = operation.security.as_ref().is_some_and(|requirements| {
+                    requirements.iter().all(|r| *r == Default::default())
+                });
+                if public {
+                    continue;
+                }
+                let scope = required_scope(&method, path);
+
+                operation
+                    .extensions
+                    .get_or_insert_with(Default::default)
+                    .insert("x-required-scope".to_string(), scope.as_str().into());
+                operation.responses.responses.insert(
+                    "default".to_string(),
+                    ResponseBuilder::new()
+                        .description("Error")
+                        .content(
+                            "application/json",
+                            Content::new(Ref::from_schema_name("ErrorResponse")),
+                        )
+                        .build()
+                        .into(),
+                );
+            }
+        }
+    }
+}
+
+/// The OpenAPI 3 document served at `/api/openapi.json`.
+pub fn document() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/web/openapi.json");
+
+    /// Run with `UPDATE_OPENAPI_SNAPSHOT=1` after an intended API change.
+    #[test]
+    fn served_schema_matches_snapshot() {
+        let served = document().to_pretty_json().unwrap() + "\n";
+
+        if std::env::var_os("UPDATE_OPENAPI_SNAPSHOT").is_some() {
+            std::fs::write(SNAPSHOT, &served).unwrap();
+            return;
+        }
+
+        let snapshot = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+        assert!(
+            snapshot == served,
+            "The OpenAPI document no longer matches web/openapi.json. If the API change is \
+             intended, rerun with UPDATE_OPENAPI_SNAPSHOT=1 and commit the new snapshot."
+        );
+    }
+
+    /// Every `.route(path, method(handler))` in api.rs, as (method, OpenAPI path).
+    fn routed_operations() -> Vec<(String, String)> {
+        include_str!("api.rs")
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix(".route(\"")?;
+                let (path, rest) = rest.split_once("\", ")?;
+                let (method, _) = rest.split_once('(')?;
+                let path: Vec<String> = path
+                    .split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => segment.to_string(),
+                    })
+                    .collect();
+                Some((method.to_string(), path.join("/")))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let document = document();
+        let routes = routed_operations();
+        assert!(routes.len() > 30, "api.rs routes could not be parsed");
+
+        for (method, path) in routes {
+            // The document itself is the only undocumented route
+            if path == "/api/openapi.json" {
+                continue;
+            }
+            let kind = match method.as_str() {
+                "get" => PathItemType::Get,
+                "post" => PathItemType::Post,
+                "delete" => PathItemType::Delete,
+                other => panic!("Unexpected method {} on {}", other, path),
+            };
+            let documented = document
+                .paths
+                .paths
+                .get(&path)
+                .is_some_and(|item| item.operations.contains_key(&kind));
+            assert!(
+                documented,
+                "{} {} is routed in api.rs but missing from ApiDoc",
+                method.to_uppercase(),
+                path
+            );
+        }
+    }
+}
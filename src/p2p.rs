@@ -1,31 +1,89 @@
-use crate::crypto::KeyManager;
+use crate::channel::{ChannelLimits, names_peer};
+use crate::crypto::{self, KeyManager, SigningDomain};
+use crate::dht::{self, NodeRecord};
+use crate::error::NodeError;
+use crate::events::{EventBus, NodeEvent};
+use crate::features::{self, Feature, PROTOCOL_VERSION};
+use crate::fees::CommitmentType;
+use crate::health::HealthState;
+use crate::metrics::METRICS;
+use crate::scoring::{self, BAN_THRESHOLD, Misbehaviour, PeerBan, PeerScore};
+use crate::storage::Database;
+use crate::wire::{self, WireMessage};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use futures::StreamExt; // Add this import for select_next_some
+use libp2p::core::ConnectedPoint;
+use libp2p::kad::store::{MemoryStore, RecordStore};
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{
+    ConnectionId,
+    behaviour::toggle::Toggle,
+    dial_opts::{DialOpts, PeerCondition},
+};
 use libp2p::{
-    Multiaddr, PeerId, Swarm, Transport, gossipsub, mdns, noise,
-    swarm::{NetworkBehaviour, SwarmEvent},
-    tcp, yamux,
+    Multiaddr, PeerId, Swarm, Transport, gossipsub, identify, kad, mdns, noise, ping,
+    swarm::SwarmEvent, tcp, yamux,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Identify agent prefix; the node id follows the last `/`.
+const AGENT_PREFIX: &str = "lightning-offline/";
+
+/// How long `P2PHandle::connect` waits for a dial to succeed or fail.
+const DIAL_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Channel messages held per author until its signed init arrives, and how
+/// many authors may have messages held; anything past either cap is dropped.
+const MAX_HELD_PER_AUTHOR: usize = 16;
+const MAX_HELD_AUTHORS: usize = 64;
+
+/// Redial backoff for known peers: doubles from the first delay up to the cap.
+const REDIAL_FIRST_DELAY: Duration = Duration::from_secs(1);
+const REDIAL_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Liveness pings on every connection; one unanswered within the timeout
+/// closes the connection, so a half-open one doesn't look usable.
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(15);
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(20);
 
 #[derive(libp2p::swarm::NetworkBehaviour)]
 pub struct Behaviour {
     pub gossipsub: gossipsub::Behaviour,
-    pub mdns: mdns::tokio::Behaviour,
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
+    pub identify: identify::Behaviour,
+    pub ping: ping::Behaviour,
+    pub kademlia: kad::Behaviour<MemoryStore>,
 }
 
 // The derive macro will automatically generate a BehaviourEvent enum
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Sent to peers in the binary encoding of `wire`; stored as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum P2PMessage {
+    /// Funder proposes a channel to the node it names, which creates its side
+    /// of the channel and acknowledges sequence 0
     ChannelOpen {
         channel_id: String,
         funding_txid: String,
         capacity: u64,
+        /// Funder's opening balance, net of the commitment fee and anchors
         initial_balance: u64,
+        /// Funder's node public key
+        funder_key: String,
+        /// Fundee as the funder named it: a public key or libp2p peer id
+        peer_node_id: String,
+        commitment_type: CommitmentType,
+        feerate_per_kw: u64,
+        limits: ChannelLimits,
+        signature: String,
     },
     ChannelClose {
         channel_id: String,
@@ -38,27 +96,431 @@ pub enum P2PMessage {
         sequence: u64,
         commitment_tx: String,
         signature: String,
+        #[serde(default)]
+        invoice_id: Option<String>,
     },
     CommitmentSigned {
         channel_id: String,
         signature: String,
         sequence: u64,
     },
+    /// Funder changes the feerate of the next commitment
+    UpdateFee {
+        channel_id: String,
+        feerate_per_kw: u64,
+        sequence: u64,
+    },
+    /// Sent after restoring from a static channel backup: the peer should
+    /// broadcast its latest commitment so our funds can be recovered on-chain
+    ForceCloseRequest {
+        channel_id: String,
+        funding_txid: String,
+        peer_node_id: String,
+        /// By the requesting node, so only the channel peer can ask
+        signature: String,
+    },
+    /// Sent for every open channel whenever a peer joins the topic, so both
+    /// sides can retransmit updates the other missed. Balances are the
+    /// sender's view: `my_balance` is the sender's own.
+    ChannelReestablish {
+        channel_id: String,
+        funding_txid: String,
+        /// Sequence of the sender's latest state
+        sequence: u64,
+        /// Latest sequence the sender applied from the recipient's updates
+        last_remote_sequence: u64,
+        my_balance: u64,
+        peer_balance: u64,
+    },
+    /// Receiver has applied every update up to `sequence`
+    UpdateAck { channel_id: String, sequence: u64 },
+    /// Sent whenever a peer joins; see `features` for how it is negotiated
+    Init {
+        protocol_version: u16,
+        features: u64,
+        /// Sender's node public key, binding its libp2p peer id to the node
+        #[serde(default)]
+        node_key: Option<String>,
+        /// Node key signature over `init_payload`
+        #[serde(default)]
+        signature: Option<String>,
+    },
+}
+
+impl P2PMessage {
+    /// Snake-case name of the variant, as shown in the outbox.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            P2PMessage::ChannelOpen { .. } => "channel_open",
+            P2PMessage::ChannelClose { .. } => "channel_close",
+            P2PMessage::Payment { .. } => "payment",
+            P2PMessage::CommitmentSigned { .. } => "commitment_signed",
+            P2PMessage::UpdateFee { .. } => "update_fee",
+            P2PMessage::ForceCloseRequest { .. } => "force_close_request",
+            P2PMessage::ChannelReestablish { .. } => "channel_reestablish",
+            P2PMessage::UpdateAck { .. } => "update_ack",
+            P2PMessage::Init { .. } => "init",
+        }
+    }
+
+    /// The sender's signature over `wire::signed_payload`, for messages
+    /// that carry one.
+    pub fn signature_mut(&mut self) -> Option<&mut String> {
+        match self {
+            P2PMessage::ChannelOpen { signature, .. }
+            | P2PMessage::Payment { signature, .. }
+            | P2PMessage::ForceCloseRequest { signature, .. } => Some(signature),
+            _ => None,
+        }
+    }
+
+    pub fn channel_id(&self) -> Option<&str> {
+        match self {
+            P2PMessage::ChannelOpen { channel_id, .. }
+            | P2PMessage::ChannelClose { channel_id, .. }
+            | P2PMessage::Payment { channel_id, .. }
+            | P2PMessage::CommitmentSigned { channel_id, .. }
+            | P2PMessage::UpdateFee { channel_id, .. }
+            | P2PMessage::ForceCloseRequest { channel_id, .. }
+            | P2PMessage::ChannelReestablish { channel_id, .. }
+            | P2PMessage::UpdateAck { channel_id, .. } => Some(channel_id),
+            P2PMessage::Init { .. } => None,
+        }
+    }
+}
+
+/// Our libp2p identity, derived from the node seed so peers can find us
+/// again after a restart.
+pub fn p2p_keypair(key_manager: &KeyManager) -> Result<libp2p::identity::Keypair> {
+    libp2p::identity::Keypair::ed25519_from_bytes(key_manager.derive_key("p2p"))
+        .map_err(|e| anyhow::anyhow!("Failed to derive P2P key: {}", e))
+}
+
+/// What an init's signature covers: the init without its signature (its
+/// `signed_payload`), then the libp2p peer id publishing it, so no other
+/// peer can replay it.
+pub fn init_payload(mut signed_payload: Vec<u8>, peer_id: &PeerId) -> Vec<u8> {
+    signed_payload.extend_from_slice(&peer_id.to_bytes());
+    signed_payload
+}
+
+/// Author of a gossiped message: the libp2p peer that signed it, and the
+/// node id it proved with a signed init.
+#[derive(Debug, Clone)]
+pub struct MessageAuthor {
+    pub peer_id: PeerId,
+    pub node_id: String,
+}
+
+/// A connected peer.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    /// Lightning node id, once the peer has identified itself
+    pub node_id: Option<String>,
+    /// Remote address of each open connection
+    pub addresses: Vec<String>,
+    pub connected_since: DateTime<Utc>,
+    /// Round-trip time of the last ping; until the first one, the time the
+    /// connection took to set up (TCP, noise and yamux handshakes)
+    pub latency_ms: f64,
+    /// From the peer's init, once received
+    pub protocol_version: Option<u16>,
+    /// Optional features both sides support, e.g. "anchors"
+    pub features: Vec<String>,
+}
+
+/// A peer coming online, once it has identified itself, or going offline
+/// after its last connection closed or stopped answering pings.
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+    pub peer_id: PeerId,
+    pub node_id: Option<String>,
+    pub online: bool,
+}
+
+/// A peer we have dialed before, redialed on startup and whenever it drops.
+#[derive(Debug, Clone)]
+pub struct KnownPeer {
+    pub peer_id: String,
+    /// Dialable address without the `/p2p/<peer id>` suffix
+    pub address: String,
+    pub last_connected_at: Option<DateTime<Utc>>,
+}
+
+/// Next redial of a disconnected known peer.
+struct Redial {
+    address: Multiaddr,
+    attempts: u32,
+    due: Instant,
+}
+
+impl Redial {
+    fn new(address: Multiaddr, delay: Duration) -> Self {
+        Self {
+            address,
+            attempts: 0,
+            due: Instant::now() + delay,
+        }
+    }
+
+    /// Pushes the next attempt back by the current backoff.
+    fn backoff(&mut self) {
+        let delay = REDIAL_FIRST_DELAY
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(REDIAL_MAX_DELAY);
+        self.attempts += 1;
+        self.due = Instant::now() + delay;
+    }
+}
+
+/// A DHT lookup started by `P2PCommand::FindNode`, answered when the query ends.
+struct Lookup {
+    node_id: String,
+    found: Option<NodeRecord>,
+    reply: oneshot::Sender<Result<NodeRecord, NodeError>>,
+}
+
+/// Parses a comma-separated list of multiaddrs, as in `BOOTSTRAP_PEERS`.
+pub fn parse_multiaddrs(list: &str) -> Result<Vec<Multiaddr>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(|a| {
+            a.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid multiaddr {}: {}", a, e))
+        })
+        .collect()
+}
+
+/// Splits `/ip4/../tcp/../p2p/<id>` into the peer id and the dialable address.
+fn split_peer_address(mut address: Multiaddr) -> Option<(PeerId, Multiaddr)> {
+    match address.pop() {
+        Some(Protocol::P2p(peer_id)) => Some((peer_id, address)),
+        _ => None,
+    }
+}
+
+/// Requests from the API to the P2P task, answered on the enclosed sender.
+pub enum P2PCommand {
+    ListPeers(oneshot::Sender<Vec<PeerInfo>>),
+    Connect(Multiaddr, oneshot::Sender<Result<PeerInfo, NodeError>>),
+    Disconnect(PeerId, oneshot::Sender<Result<(), NodeError>>),
+    ListBans(oneshot::Sender<Vec<PeerBan>>),
+    /// A duration of `None` bans for good
+    Ban(
+        PeerId,
+        String,
+        Option<Duration>,
+        oneshot::Sender<Result<PeerBan, NodeError>>,
+    ),
+    Unban(PeerId, oneshot::Sender<Result<(), NodeError>>),
+    /// Reported by the channel manager for messages it rejected; not answered
+    Misbehaved(PeerId, Misbehaviour, String),
+    /// Redial the peer whenever it drops, as for a known peer; not answered
+    KeepConnected(PeerId),
+    /// Looks up a node's signed record in the DHT
+    FindNode(String, oneshot::Sender<Result<NodeRecord, NodeError>>),
+}
+
+/// Cloneable handle for querying and steering the P2P task.
+#[derive(Clone)]
+pub struct P2PHandle {
+    commands: mpsc::Sender<P2PCommand>,
+}
+
+impl P2PHandle {
+    pub async fn peers(&self) -> Result<Vec<PeerInfo>, NodeError> {
+        self.request(P2PCommand::ListPeers).await
+    }
+
+    /// Dials `address` and waits until the connection is up.
+    pub async fn connect(&self, address: Multiaddr) -> Result<PeerInfo, NodeError> {
+        let request = self.request(|reply| P2PCommand::Connect(address, reply));
+        match tokio::time::timeout(DIAL_TIMEOUT, request).await {
+            Ok(result) => result?,
+            Err(_) => Err(NodeError::PeerUnreachable("Dial timed out".to_string())),
+        }
+    }
+
+    /// Finds where a node can be reached by its node id, through the DHT.
+    pub async fn find_node(&self, node_id: String) -> Result<NodeRecord, NodeError> {
+        self.request(|reply| P2PCommand::FindNode(node_id, reply))
+            .await?
+    }
+
+    /// Looks the node up in the DHT and dials its addresses until one works.
+    pub async fn connect_node(&self, node_id: String) -> Result<PeerInfo, NodeError> {
+        let record = self.find_node(node_id).await?;
+        let mut error = NodeError::PeerUnreachable(format!(
+            "Node {} published no dialable address",
+            record.node_id
+        ));
+        for address in record.dial_addresses() {
+            match self.connect(address).await {
+                Ok(peer) => return Ok(peer),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    /// Closes all connections to the peer and forgets it as a known peer.
+    pub async fn disconnect(&self, peer_id: PeerId) -> Result<(), NodeError> {
+        self.request(|reply| P2PCommand::Disconnect(peer_id, reply))
+            .await?
+    }
+
+    /// Active bans, oldest first.
+    pub async fn bans(&self) -> Result<Vec<PeerBan>, NodeError> {
+        self.request(P2PCommand::ListBans).await
+    }
+
+    /// Disconnects the peer and refuses it until the ban expires or is lifted.
+    pub async fn ban(
+        &self,
+        peer_id: PeerId,
+        reason: String,
+        duration: Option<Duration>,
+    ) -> Result<PeerBan, NodeError> {
+        self.request(|reply| P2PCommand::Ban(peer_id, reason, duration, reply))
+            .await?
+    }
+
+    /// Lifts a ban and clears the peer's misbehaviour score.
+    pub async fn unban(&self, peer_id: PeerId) -> Result<(), NodeError> {
+        self.request(|reply| P2PCommand::Unban(peer_id, reply))
+            .await?
+    }
+
+    /// Penalizes the author of a message that turned out to be invalid.
+    pub async fn report_misbehaviour(&self, peer_id: PeerId, kind: Misbehaviour, reason: String) {
+        let _ = self
+            .commands
+            .send(P2PCommand::Misbehaved(peer_id, kind, reason))
+            .await;
+    }
+
+    /// Makes the peer a known peer, so it is redialed whenever it drops, even
+    /// if it was the one that dialed us.
+    pub async fn keep_connected(&self, peer_id: PeerId) {
+        let _ = self.commands.send(P2PCommand::KeepConnected(peer_id)).await;
+    }
+
+    /// Keeps the peer of a channel we just opened connected. Peers that come
+    /// online later are kept connected once their status reports an open channel.
+    pub async fn keep_channel_peer_connected(&self, peer_node_id: &str) {
+        let Ok(peers) = self.peers().await else {
+            return;
+        };
+        let peer_id = peers
+            .iter()
+            .find(|p| names_peer(peer_node_id, &p.peer_id, p.node_id.as_deref()))
+            .and_then(|p| p.peer_id.parse().ok());
+        if let Some(peer_id) = peer_id {
+            self.keep_connected(peer_id).await;
+        }
+    }
+
+    /// Rejects a feature the channel peer did not negotiate. A connected peer
+    /// must have sent init first; one that isn't connected can't be checked
+    /// and is let through.
+    pub async fn check_peer_supports(
+        &self,
+        peer_node_id: &str,
+        feature: Feature,
+    ) -> Result<(), NodeError> {
+        let Ok(peers) = self.peers().await else {
+            return Ok(());
+        };
+        let peer = peers
+            .iter()
+            .find(|p| names_peer(peer_node_id, &p.peer_id, p.node_id.as_deref()));
+
+        match peer {
+            Some(peer) if peer.protocol_version.is_none() => Err(NodeError::InvalidRequest(
+                format!("Peer {} has not sent init yet, retry shortly", peer_node_id),
+            )),
+            Some(peer) if !peer.features.iter().any(|f| f == feature.name()) => {
+                Err(NodeError::InvalidRequest(format!(
+                    "Peer {} does not support {}",
+                    peer_node_id,
+                    feature.name()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// A handle whose P2P task is gone, for tests of what depends on it.
+    #[cfg(test)]
+    pub(crate) fn detached() -> Self {
+        let (commands, _) = mpsc::channel(1);
+        Self { commands }
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> P2PCommand,
+    ) -> Result<T, NodeError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| NodeError::P2PUnavailable)?;
+        response.await.map_err(|_| NodeError::P2PUnavailable)
+    }
 }
 
 pub struct P2PNode {
     swarm: Swarm<Behaviour>,
     key_manager: Arc<KeyManager>,
-    message_sender: mpsc::UnboundedSender<P2PMessage>,
-    peers: HashMap<PeerId, String>, // peer_id -> node_id mapping
+    message_sender: mpsc::UnboundedSender<(MessageAuthor, WireMessage)>,
+    outbound_receiver: mpsc::UnboundedReceiver<P2PMessage>,
+    command_sender: mpsc::Sender<P2PCommand>,
+    command_receiver: mpsc::Receiver<P2PCommand>,
+    pending_messages: Vec<P2PMessage>, // published once a peer connects
+    peers: HashMap<PeerId, PeerInfo>,
+    // Peers to keep connected to, with their last dialed address
+    known_peers: HashMap<PeerId, Multiaddr>,
+    redials: HashMap<PeerId, Redial>,
+    listen_addresses: Vec<Multiaddr>,
+    bootstrap_peers: Vec<Multiaddr>,
+    database: Option<Arc<Database>>,
+    peer_joined: Option<mpsc::UnboundedSender<PeerId>>,
+    peer_status: Option<mpsc::UnboundedSender<PeerStatus>>,
+    // Addresses each connected peer listens on, from identify
+    peer_listen_addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    lookups: HashMap<kad::QueryId, Lookup>,
+    // Whether the first DHT bootstrap has run; later ones are on a timer
+    dht_bootstrapped: bool,
+    // Peers whose init we rejected, with the reason; never redialed
+    incompatible: HashMap<PeerId, String>,
+    // Node id each message author proved with a signed init
+    node_ids: HashMap<PeerId, String>,
+    // Channel messages that arrived before their author's init
+    awaiting_init: HashMap<PeerId, VecDeque<WireMessage>>,
+    // Misbehaviour score and rate limit of every message author seen
+    scores: HashMap<PeerId, PeerScore>,
+    // Active bans; expired ones are dropped on the redial tick
+    bans: HashMap<PeerId, PeerBan>,
+    // Dials started by `P2PCommand::Connect`, answered once they resolve
+    pending_dials: HashMap<ConnectionId, oneshot::Sender<Result<PeerInfo, NodeError>>>,
+    events: Option<EventBus>,
+    health: Option<HealthState>,
 }
 
 impl P2PNode {
-    pub async fn new(key_manager: Arc<KeyManager>) -> Result<Self> {
-        // Create a random PeerId (in production, derive from node keys)
-        let local_key = libp2p::identity::Keypair::generate_ed25519();
+    /// Received messages are forwarded to `message_sender` with their author;
+    /// messages sent on the other end of `outbound_receiver` are published to peers.
+    pub async fn new(
+        key_manager: Arc<KeyManager>,
+        message_sender: mpsc::UnboundedSender<(MessageAuthor, WireMessage)>,
+        outbound_receiver: mpsc::UnboundedReceiver<P2PMessage>,
+    ) -> Result<Self> {
+        let local_key = p2p_keypair(&key_manager)?;
         let local_peer_id = PeerId::from(local_key.public());
-        println!("Local peer id: {local_peer_id}");
+        info!("Local peer id: {local_peer_id}");
 
         // Set up transport
         let transport = tcp::tokio::Transport::default()
@@ -70,10 +532,11 @@ impl P2PNode {
         // Create gossipsub topic for Lightning messages
         let gossipsub_topic = gossipsub::IdentTopic::new("lightning-offline");
 
-        // Set up gossipsub
+        // Set up gossipsub; messages are only relayed once we have decoded them
         let gossipsub_config = gossipsub::ConfigBuilder::default()
             .heartbeat_interval(std::time::Duration::from_secs(10))
             .validation_mode(gossipsub::ValidationMode::Strict)
+            .validate_messages()
             .build()
             .expect("Valid config");
 
@@ -83,6 +546,11 @@ impl P2PNode {
         )
         .map_err(|e| anyhow::anyhow!("Failed to create gossipsub behaviour: {}", e))?;
 
+        let (score_params, score_thresholds) = scoring::gossipsub_params(gossipsub_topic.hash());
+        gossipsub
+            .with_peer_score(score_params, score_thresholds)
+            .map_err(|e| anyhow::anyhow!("Invalid gossipsub scoring parameters: {}", e))?;
+
         gossipsub
             .subscribe(&gossipsub_topic)
             .map_err(|e| anyhow::anyhow!("Failed to subscribe to gossipsub topic: {}", e))?;
@@ -91,7 +559,41 @@ impl P2PNode {
         let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
             .map_err(|e| anyhow::anyhow!("Failed to create mDNS behaviour: {}", e))?;
 
-        let behaviour = Behaviour { gossipsub, mdns };
+        // Kademlia finds peers beyond the broadcast domain by node id. Records
+        // are only stored once their signature checks out.
+        let mut kad_config = kad::Config::default();
+        kad_config
+            .set_protocol_names(vec![dht::PROTOCOL])
+            .set_record_filtering(kad::StoreInserts::FilterBoth);
+        let mut kademlia =
+            kad::Behaviour::with_config(local_peer_id, MemoryStore::new(local_peer_id), kad_config);
+        // Answer queries without a confirmed external address, e.g. on loopback
+        kademlia.set_mode(Some(kad::Mode::Server));
+
+        // Announce our Lightning node id so peers can map connections to nodes
+        let identify = identify::Behaviour::new(
+            identify::Config::new("/lightning-offline/1.0.0".to_string(), local_key.public())
+                .with_agent_version(format!(
+                    "{}{}/{}",
+                    AGENT_PREFIX,
+                    env!("CARGO_PKG_VERSION"),
+                    key_manager.get_node_id()
+                )),
+        );
+
+        let ping = ping::Behaviour::new(
+            ping::Config::new()
+                .with_interval(DEFAULT_PING_INTERVAL)
+                .with_timeout(DEFAULT_PING_TIMEOUT),
+        );
+
+        let behaviour = Behaviour {
+            gossipsub,
+            mdns: Some(mdns).into(),
+            identify,
+            ping,
+            kademlia,
+        };
         let swarm = Swarm::new(
             transport,
             behaviour,
@@ -99,38 +601,279 @@ impl P2PNode {
             libp2p::swarm::Config::with_tokio_executor(),
         );
 
-        let (message_sender, _) = mpsc::unbounded_channel();
+        let (command_sender, command_receiver) = mpsc::channel(32);
 
         Ok(P2PNode {
             swarm,
             key_manager,
             message_sender,
+            outbound_receiver,
+            command_sender,
+            command_receiver,
+            pending_messages: Vec::new(),
             peers: HashMap::new(),
+            known_peers: HashMap::new(),
+            redials: HashMap::new(),
+            listen_addresses: vec!["/ip4/0.0.0.0/tcp/0".parse()?],
+            bootstrap_peers: Vec::new(),
+            database: None,
+            peer_joined: None,
+            peer_status: None,
+            peer_listen_addresses: HashMap::new(),
+            lookups: HashMap::new(),
+            dht_bootstrapped: false,
+            incompatible: HashMap::new(),
+            node_ids: HashMap::new(),
+            awaiting_init: HashMap::new(),
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+            pending_dials: HashMap::new(),
+            events: None,
+            health: None,
         })
     }
 
+    /// Publish peer connect/disconnect events on `events`.
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Handle for the API; it keeps working for as long as the P2P task runs.
+    pub fn handle(&self) -> P2PHandle {
+        P2PHandle {
+            commands: self.command_sender.clone(),
+        }
+    }
+
+    /// Report listen addresses and peer count to the health endpoints.
+    pub fn with_health(mut self, health: HealthState) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Replaces the default `/ip4/0.0.0.0/tcp/0`.
+    pub fn with_listen_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+        self.listen_addresses = addresses;
+        self
+    }
+
+    /// Peers dialed at startup and kept connected, each ending in `/p2p/<peer id>`.
+    pub fn with_bootstrap_peers(mut self, peers: Vec<Multiaddr>) -> Result<Self> {
+        for peer in &peers {
+            if split_peer_address(peer.clone()).is_none() {
+                anyhow::bail!("Bootstrap peer {} has no /p2p/<peer id> suffix", peer);
+            }
+        }
+        self.bootstrap_peers = peers;
+        Ok(self)
+    }
+
+    /// Persist peers we dial so they are redialed after a restart.
+    pub fn with_database(mut self, database: Arc<Database>) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Notified whenever a peer subscribes to the channel topic, i.e. from
+    /// when it can receive channel messages.
+    pub fn with_peer_joined_sender(mut self, sender: mpsc::UnboundedSender<PeerId>) -> Self {
+        self.peer_joined = Some(sender);
+        self
+    }
+
+    /// Notified whenever a peer comes online or goes offline.
+    pub fn with_peer_status_sender(mut self, sender: mpsc::UnboundedSender<PeerStatus>) -> Self {
+        self.peer_status = Some(sender);
+        self
+    }
+
+    /// Turns mDNS discovery off, e.g. where peers are found through the DHT only.
+    pub fn with_mdns(mut self, enabled: bool) -> Self {
+        if !enabled {
+            self.swarm.behaviour_mut().mdns = None.into();
+        }
+        self
+    }
+
+    /// Addresses to publish in the DHT besides our listen addresses, e.g. a
+    /// forwarded port on a router.
+    pub fn with_external_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+        for address in addresses {
+            self.swarm.add_external_address(address);
+        }
+        self
+    }
+
+    /// Replaces the default ping interval and timeout.
+    pub fn with_ping(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.swarm.behaviour_mut().ping = ping::Behaviour::new(
+            ping::Config::new()
+                .with_interval(interval)
+                .with_timeout(timeout),
+        );
+        self
+    }
+
+    fn send_peer_status(&self, peer_id: PeerId, node_id: Option<String>, online: bool) {
+        if let Some(sender) = &self.peer_status {
+            let _ = sender.send(PeerStatus {
+                peer_id,
+                node_id,
+                online,
+            });
+        }
+    }
+
+    async fn emit(&self, event: NodeEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event).await;
+        }
+    }
+
     pub async fn start_listening(&mut self) -> Result<()> {
-        // Listen on multiple addresses
-        let addr: Multiaddr = "/ip4/0.0.0.0/tcp/0"
-            .parse()
-            .map_err(|e| anyhow::anyhow!("Failed to parse address: {}", e))?;
-        self.swarm.listen_on(addr)?;
+        for address in self.listen_addresses.clone() {
+            self.swarm
+                .listen_on(address.clone())
+                .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+        }
+
+        self.load_bans().await?;
+        self.load_known_peers().await?;
+        let mut redial_timer = tokio::time::interval(REDIAL_FIRST_DELAY);
+        let mut bootstrap_timer = tokio::time::interval(dht::BOOTSTRAP_INTERVAL);
+        let mut republish_timer = tokio::time::interval(dht::REPUBLISH_INTERVAL);
 
         loop {
-            match self.swarm.select_next_some().await {
+            let event = tokio::select! {
+                event = self.swarm.select_next_some() => event,
+                Some(message) = self.outbound_receiver.recv() => {
+                    self.send_message(message).await;
+                    continue;
+                }
+                Some(command) = self.command_receiver.recv() => {
+                    self.handle_command(command).await;
+                    continue;
+                }
+                _ = redial_timer.tick() => {
+                    self.expire_bans();
+                    self.redial_due_peers();
+                    continue;
+                }
+                _ = bootstrap_timer.tick() => {
+                    // Fails only while the routing table is empty
+                    let _ = self.swarm.behaviour_mut().kademlia.bootstrap();
+                    continue;
+                }
+                _ = republish_timer.tick() => {
+                    self.publish_node_record();
+                    continue;
+                }
+            };
+
+            match event {
                 SwarmEvent::NewListenAddr { address, .. } => {
-                    println!("Listening on {address}");
+                    info!("Listening on {address}");
+                    if let Some(health) = &self.health {
+                        health.p2p_listening(address.to_string());
+                    }
+                }
+                SwarmEvent::ExpiredListenAddr { address, .. } => {
+                    if let Some(health) = &self.health {
+                        health.p2p_listen_address_expired(&address.to_string());
+                    }
                 }
                 SwarmEvent::Behaviour(event) => {
                     self.handle_behaviour_event(event).await;
                 }
-                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
-                    println!("Connected to {peer_id}");
-                    self.peers.insert(peer_id, peer_id.to_string());
+                SwarmEvent::ConnectionEstablished {
+                    peer_id,
+                    connection_id,
+                    endpoint,
+                    established_in,
+                    ..
+                } => {
+                    if let Some(ban) = self.bans.get(&peer_id) {
+                        info!("Refusing banned peer {peer_id}: {}", ban.reason);
+                        if let Some(reply) = self.pending_dials.remove(&connection_id) {
+                            let _ = reply.send(Err(NodeError::PeerBanned(peer_id.to_string())));
+                        }
+                        let _ = self.swarm.disconnect_peer_id(peer_id);
+                        continue;
+                    }
+                    info!("Connected to {peer_id}");
+                    let address = endpoint.get_remote_address().to_string();
+                    let is_new = !self.peers.contains_key(&peer_id);
+                    let peer = self.peers.entry(peer_id).or_insert_with(|| PeerInfo {
+                        peer_id: peer_id.to_string(),
+                        node_id: None,
+                        addresses: Vec::new(),
+                        connected_since: Utc::now(),
+                        latency_ms: established_in.as_secs_f64() * 1000.0,
+                        protocol_version: None,
+                        features: Vec::new(),
+                    });
+                    peer.addresses.push(address);
+                    let peer = peer.clone();
+
+                    if let Some(reply) = self.pending_dials.remove(&connection_id) {
+                        let _ = reply.send(Ok(peer));
+                    }
+                    if let ConnectedPoint::Dialer { address, .. } = endpoint {
+                        self.remember_peer(peer_id, address).await;
+                    }
+                    if is_new {
+                        self.record_peer_count();
+                        self.emit(NodeEvent::PeerConnected {
+                            peer_id: peer_id.to_string(),
+                        })
+                        .await;
+                    }
+
+                    for message in std::mem::take(&mut self.pending_messages) {
+                        self.send_message(message).await;
+                    }
                 }
-                SwarmEvent::ConnectionClosed { peer_id, .. } => {
-                    println!("Disconnected from {peer_id}");
-                    self.peers.remove(&peer_id);
+                SwarmEvent::OutgoingConnectionError {
+                    connection_id,
+                    error,
+                    ..
+                } => {
+                    if let Some(reply) = self.pending_dials.remove(&connection_id) {
+                        let _ = reply.send(Err(NodeError::PeerUnreachable(error.to_string())));
+                    }
+                }
+                SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    endpoint,
+                    num_established,
+                    ..
+                } => {
+                    info!("Disconnected from {peer_id}");
+                    if let Some(peer) = self.peers.get_mut(&peer_id) {
+                        let address = endpoint.get_remote_address().to_string();
+                        if let Some(index) = peer.addresses.iter().position(|a| *a == address) {
+                            peer.addresses.remove(index);
+                        }
+                    }
+                    if num_established == 0
+                        && let Some(peer) = self.peers.remove(&peer_id)
+                    {
+                        self.peer_listen_addresses.remove(&peer_id);
+                        self.send_peer_status(peer_id, peer.node_id, false);
+                        if let Some(address) = self.known_peers.get(&peer_id)
+                            && !self.incompatible.contains_key(&peer_id)
+                            && !self.bans.contains_key(&peer_id)
+                        {
+                            self.redials
+                                .insert(peer_id, Redial::new(address.clone(), REDIAL_FIRST_DELAY));
+                        }
+                        self.record_peer_count();
+                        self.emit(NodeEvent::PeerDisconnected {
+                            peer_id: peer_id.to_string(),
+                        })
+                        .await;
+                    }
                 }
                 _ => {}
             }
@@ -141,7 +884,11 @@ impl P2PNode {
         match event {
             BehaviourEvent::Mdns(mdns::Event::Discovered(list)) => {
                 for (peer_id, _multiaddr) in list {
-                    println!("mDNS discovered a new peer: {peer_id}");
+                    if self.incompatible.contains_key(&peer_id) || self.bans.contains_key(&peer_id)
+                    {
+                        continue;
+                    }
+                    info!("mDNS discovered a new peer: {peer_id}");
                     self.swarm
                         .behaviour_mut()
                         .gossipsub
@@ -151,47 +898,869 @@ impl P2PNode {
             }
             BehaviourEvent::Mdns(mdns::Event::Expired(list)) => {
                 for (peer_id, _multiaddr) in list {
-                    println!("mDNS discover peer has expired: {peer_id}");
+                    info!("mDNS discover peer has expired: {peer_id}");
                     self.swarm
                         .behaviour_mut()
                         .gossipsub
                         .remove_explicit_peer(&peer_id);
                 }
             }
+            BehaviourEvent::Identify(identify::Event::Received { peer_id, info }) => {
+                let node_id = info
+                    .agent_version
+                    .strip_prefix(AGENT_PREFIX)
+                    .and_then(|rest| rest.rsplit_once('/'))
+                    .map(|(_, node_id)| node_id.to_string());
+                if info.protocols.contains(&dht::PROTOCOL) {
+                    for address in &info.listen_addrs {
+                        self.swarm
+                            .behaviour_mut()
+                            .kademlia
+                            .add_address(&peer_id, address.clone());
+                    }
+                }
+                if let Some(peer) = self.peers.get_mut(&peer_id) {
+                    peer.node_id = node_id.clone();
+                    self.peer_listen_addresses
+                        .insert(peer_id, info.listen_addrs);
+                    self.send_peer_status(peer_id, node_id, true);
+                }
+            }
+            BehaviourEvent::Kademlia(event) => self.handle_kademlia_event(event).await,
+            BehaviourEvent::Ping(ping::Event {
+                peer,
+                connection,
+                result,
+            }) => match result {
+                Ok(rtt) => {
+                    if let Some(peer) = self.peers.get_mut(&peer) {
+                        peer.latency_ms = rtt.as_secs_f64() * 1000.0;
+                    }
+                }
+                Err(e) => {
+                    // Closing the dead connection marks the peer offline once
+                    // it was the last one, and schedules a redial
+                    warn!("Ping to {} failed, closing connection: {}", peer, e);
+                    self.swarm.close_connection(connection);
+                }
+            },
+            BehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { peer_id, .. }) => {
+                // Gossip has no per-peer channel, so everyone gets it again
+                let init = self.signed_init();
+                if let Err(e) = self.broadcast_message(init).await {
+                    error!("Failed to send init: {}", e);
+                }
+                if let Some(sender) = &self.peer_joined {
+                    let _ = sender.send(peer_id);
+                }
+            }
             BehaviourEvent::Gossipsub(gossipsub::Event::Message {
                 propagation_source,
-                message_id: _,
+                message_id,
                 message,
             }) => {
-                if let Ok(p2p_message) = serde_json::from_slice::<P2PMessage>(&message.data) {
-                    println!(
-                        "Received message from {}: {:?}",
-                        propagation_source, p2p_message
-                    );
-                    let _ = self.message_sender.send(p2p_message);
+                METRICS.gossip_messages.with_label_values(&["in"]).inc();
+                // Strict validation makes every message signed by its author
+                let author = message.source.unwrap_or(propagation_source);
+                let acceptance = self.validate_message(author, &message.data).await;
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .report_message_validation_result(&message_id, &propagation_source, acceptance);
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_kademlia_event(&mut self, event: kad::Event) {
+        match event {
+            kad::Event::RoutingUpdated {
+                is_new_peer: true, ..
+            } if !self.dht_bootstrapped => {
+                // First DHT peer: fill the routing table, then tell it where we are
+                self.dht_bootstrapped = true;
+                let _ = self.swarm.behaviour_mut().kademlia.bootstrap();
+            }
+            kad::Event::InboundRequest {
+                request:
+                    kad::InboundRequest::PutRecord {
+                        source,
+                        record: Some(record),
+                        ..
+                    },
+            } => self.store_node_record(source, record).await,
+            kad::Event::OutboundQueryProgressed {
+                result: kad::QueryResult::Bootstrap(Ok(_)),
+                step,
+                ..
+            } if step.last => self.publish_node_record(),
+            kad::Event::OutboundQueryProgressed {
+                result: kad::QueryResult::PutRecord(Err(e)),
+                ..
+            } => error!("Failed to publish node record: {}", e),
+            kad::Event::OutboundQueryProgressed {
+                id,
+                result: kad::QueryResult::GetRecord(result),
+                step,
+                ..
+            } => {
+                let Some(lookup) = self.lookups.get_mut(&id) else {
+                    return;
+                };
+                let finished = match result {
+                    Ok(kad::GetRecordOk::FoundRecord(kad::PeerRecord { record, .. })) => {
+                        match NodeRecord::decode(&self.key_manager, &record.key, &record.value) {
+                            Ok(found)
+                                if lookup
+                                    .found
+                                    .as_ref()
+                                    .is_none_or(|f| f.published_at < found.published_at) =>
+                            {
+                                lookup.found = Some(found);
+                            }
+                            Ok(_) => {}
+                            Err(e) => warn!("Ignoring invalid node record: {}", e),
+                        }
+                        step.last
+                    }
+                    Ok(kad::GetRecordOk::FinishedWithNoAdditionalRecord { .. }) | Err(_) => true,
+                };
+                if finished && let Some(lookup) = self.lookups.remove(&id) {
+                    let result = lookup.found.ok_or(NodeError::NodeNotFound(lookup.node_id));
+                    let _ = lookup.reply.send(result);
                 }
             }
             _ => {}
         }
     }
 
+    /// Stores a record another node asked us to hold, if it is signed by the
+    /// node it describes and newer than the one we have.
+    async fn store_node_record(&mut self, source: PeerId, record: kad::Record) {
+        let incoming = match NodeRecord::decode(&self.key_manager, &record.key, &record.value) {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                self.misbehaved(source, Misbehaviour::InvalidSignature, e.to_string())
+                    .await;
+                return;
+            }
+        };
+
+        let store = self.swarm.behaviour_mut().kademlia.store_mut();
+        let newer = store.get(&record.key).is_none_or(|existing| {
+            NodeRecord::decode(&self.key_manager, &existing.key, &existing.value)
+                .map_or(true, |existing| {
+                    existing.published_at < incoming.published_at
+                })
+        });
+        if newer && let Err(e) = store.put(record) {
+            error!("Failed to store node record of {}: {}", incoming.node_id, e);
+        }
+    }
+
+    /// Publishes where we can be reached under our node id.
+    fn publish_node_record(&mut self) {
+        let addresses: Vec<Multiaddr> = self
+            .swarm
+            .external_addresses()
+            .chain(self.swarm.listeners())
+            .cloned()
+            .collect();
+        if addresses.is_empty() {
+            return;
+        }
+
+        let record = NodeRecord::new(&self.key_manager, *self.swarm.local_peer_id(), &addresses);
+        let value = match record.encode(&self.key_manager) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Failed to sign node record: {}", e);
+                return;
+            }
+        };
+        let record = kad::Record::new(NodeRecord::key(&record.node_id), value);
+        if let Err(e) = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .put_record(record, kad::Quorum::One)
+        {
+            error!("Failed to store our node record: {}", e);
+        }
+    }
+
+    /// Decodes and dispatches a gossiped message, and decides whether gossipsub
+    /// relays it. Messages from banned, incompatible or flooding authors are
+    /// dropped; malformed ones also count against the author's gossipsub score.
+    async fn validate_message(
+        &mut self,
+        author: PeerId,
+        data: &[u8],
+    ) -> gossipsub::MessageAcceptance {
+        if self.bans.contains_key(&author) || self.incompatible.contains_key(&author) {
+            return gossipsub::MessageAcceptance::Ignore;
+        }
+        let allowed = self.scores.entry(author).or_default().allow_message();
+        if !allowed {
+            self.misbehaved(
+                author,
+                Misbehaviour::RateLimited,
+                "Message rate limit exceeded".to_string(),
+            )
+            .await;
+            return gossipsub::MessageAcceptance::Ignore;
+        }
+
+        match wire::decode_signed(data) {
+            Ok(Some(
+                init @ WireMessage {
+                    message: P2PMessage::Init { .. },
+                    ..
+                },
+            )) => {
+                if let Err(e) = self.verify_init(author, &init) {
+                    warn!("Dropping init from {}: {}", author, e);
+                    self.misbehaved(author, Misbehaviour::InvalidSignature, e.to_string())
+                        .await;
+                    return gossipsub::MessageAcceptance::Reject;
+                }
+                if let P2PMessage::Init {
+                    protocol_version,
+                    features,
+                    ..
+                } = init.message
+                {
+                    self.handle_init(author, protocol_version, features);
+                }
+                self.release_held_messages(author);
+                gossipsub::MessageAcceptance::Accept
+            }
+            Ok(Some(p2p_message)) => {
+                // Channel messages are only trusted from a proven node; gossip
+                // may deliver them ahead of its init, so hold them until then
+                let Some(node_id) = self.node_ids.get(&author).cloned() else {
+                    self.hold_until_init(author, p2p_message);
+                    return gossipsub::MessageAcceptance::Ignore;
+                };
+                info!(
+                    "Received message from {}: {:?}",
+                    author, p2p_message.message
+                );
+                let author = MessageAuthor {
+                    peer_id: author,
+                    node_id,
+                };
+                let _ = self.message_sender.send((author, p2p_message));
+                gossipsub::MessageAcceptance::Accept
+            }
+            // Optional message type from a newer node
+            Ok(None) => gossipsub::MessageAcceptance::Accept,
+            Err(e) => {
+                warn!("Dropping malformed message from {}: {}", author, e);
+                self.misbehaved(author, Misbehaviour::MalformedMessage, e.to_string())
+                    .await;
+                gossipsub::MessageAcceptance::Reject
+            }
+        }
+    }
+
+    /// Lowers the peer's score and bans it once the score falls to the threshold.
+    async fn misbehaved(&mut self, peer_id: PeerId, kind: Misbehaviour, reason: String) {
+        METRICS
+            .peer_misbehaviour
+            .with_label_values(&[kind.name()])
+            .inc();
+        if self.bans.contains_key(&peer_id) {
+            return;
+        }
+        let score = self.scores.entry(peer_id).or_default().penalize(kind);
+        warn!(
+            "Peer {} misbehaved ({}), score {:.1}: {}",
+            peer_id,
+            kind.name(),
+            score,
+            reason
+        );
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .set_application_score(&peer_id, score);
+
+        if score <= BAN_THRESHOLD {
+            let reason = format!("{}: {}", kind.name(), reason);
+            if let Err(e) = self.ban_peer(peer_id, reason, None, true).await {
+                error!("Failed to ban peer {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    /// Bans the peer for `duration`, or for as long as `scoring::ban_duration`
+    /// says when `automatic`, and cuts it off at once.
+    async fn ban_peer(
+        &mut self,
+        peer_id: PeerId,
+        reason: String,
+        duration: Option<Duration>,
+        automatic: bool,
+    ) -> Result<PeerBan> {
+        let previous_bans = match &self.database {
+            Some(database) => database
+                .get_peer_ban(&peer_id.to_string())
+                .await?
+                .map_or(0, |ban| ban.ban_count),
+            None => self.bans.get(&peer_id).map_or(0, |ban| ban.ban_count),
+        };
+        let duration = if automatic {
+            scoring::ban_duration(previous_bans)
+        } else {
+            duration
+        };
+        let ban = PeerBan::new(peer_id.to_string(), reason, previous_bans, duration);
+        if let Some(database) = &self.database {
+            database.save_peer_ban(&ban).await?;
+        }
+
+        match ban.expires_at {
+            Some(expires_at) => {
+                warn!("Banned {} until {}: {}", peer_id, expires_at, ban.reason)
+            }
+            None => warn!("Banned {} permanently: {}", peer_id, ban.reason),
+        }
+        self.bans.insert(peer_id, ban.clone());
+        self.redials.remove(&peer_id);
+        let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+        gossipsub.remove_explicit_peer(&peer_id);
+        gossipsub.blacklist_peer(&peer_id);
+        let _ = self.swarm.disconnect_peer_id(peer_id);
+        Ok(ban)
+    }
+
+    /// Restores the bans still in force after a restart.
+    async fn load_bans(&mut self) -> Result<()> {
+        let Some(database) = &self.database else {
+            return Ok(());
+        };
+        for ban in database.get_active_peer_bans().await? {
+            match ban.peer_id.parse::<PeerId>() {
+                Ok(peer_id) => {
+                    self.swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .blacklist_peer(&peer_id);
+                    self.bans.insert(peer_id, ban);
+                }
+                Err(_) => warn!("Skipping malformed peer ban {}", ban.peer_id),
+            }
+        }
+        Ok(())
+    }
+
+    /// Lifts expired bans; known peers among them are redialed.
+    fn expire_bans(&mut self) {
+        let expired: Vec<PeerId> = self
+            .bans
+            .iter()
+            .filter(|(_, ban)| !ban.is_active())
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired {
+            info!("Ban of {} expired", peer_id);
+            self.lift_ban(peer_id);
+        }
+    }
+
+    fn lift_ban(&mut self, peer_id: PeerId) {
+        self.bans.remove(&peer_id);
+        self.scores.remove(&peer_id);
+        let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+        gossipsub.remove_blacklisted_peer(&peer_id);
+        gossipsub.set_application_score(&peer_id, 0.0);
+        if let Some(address) = self.known_peers.get(&peer_id) {
+            self.redials
+                .insert(peer_id, Redial::new(address.clone(), Duration::ZERO));
+        }
+    }
+
+    /// Our init, signed over our libp2p peer id.
+    fn signed_init(&self) -> P2PMessage {
+        let mut init = P2PMessage::Init {
+            protocol_version: PROTOCOL_VERSION,
+            features: features::local_features(),
+            node_key: Some(self.key_manager.get_public_key().to_string()),
+            signature: None,
+        };
+        let payload = init_payload(wire::signed_payload(&init), self.swarm.local_peer_id());
+        match self.key_manager.sign(SigningDomain::PeerIdentity, &payload) {
+            Ok(sig) => {
+                if let P2PMessage::Init { signature, .. } = &mut init {
+                    *signature = Some(hex::encode(sig.serialize_compact()));
+                }
+            }
+            Err(e) => error!("Failed to sign init: {}", e),
+        }
+        init
+    }
+
+    /// Queues a channel message from an author whose init hasn't arrived.
+    fn hold_until_init(&mut self, author: PeerId, message: WireMessage) {
+        let kind = message.message.kind();
+        if !self.awaiting_init.contains_key(&author) && self.awaiting_init.len() >= MAX_HELD_AUTHORS
+        {
+            info!("Dropping {} from {}: no signed init yet", kind, author);
+            return;
+        }
+        let held = self.awaiting_init.entry(author).or_default();
+        if held.len() >= MAX_HELD_PER_AUTHOR {
+            info!("Dropping {} from {}: no signed init yet", kind, author);
+            return;
+        }
+        info!("Holding {} from {} until its init", kind, author);
+        held.push_back(message);
+    }
+
+    /// Dispatches messages held for `author` once its init proved a node id.
+    /// An init without one (an older node), or an incompatible peer, drops them.
+    fn release_held_messages(&mut self, author: PeerId) {
+        let Some(held) = self.awaiting_init.remove(&author) else {
+            return;
+        };
+        let node_id = self
+            .node_ids
+            .get(&author)
+            .filter(|_| !self.incompatible.contains_key(&author));
+        let Some(node_id) = node_id else {
+            info!(
+                "Dropping {} held messages from {}: init proves no node id",
+                held.len(),
+                author
+            );
+            return;
+        };
+        for message in held {
+            let author = MessageAuthor {
+                peer_id: author,
+                node_id: node_id.clone(),
+            };
+            let _ = self.message_sender.send((author, message));
+        }
+    }
+
+    /// Checks the node key signature of an init and remembers the node id
+    /// it proves for `author`. Inits from older nodes carry neither.
+    fn verify_init(&mut self, author: PeerId, init: &WireMessage) -> Result<()> {
+        let P2PMessage::Init {
+            node_key: Some(node_key),
+            signature,
+            ..
+        } = &init.message
+        else {
+            return Ok(());
+        };
+        let public_key: bitcoin::secp256k1::PublicKey = node_key
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid node key in init"))?;
+        let valid = signature
+            .as_deref()
+            .and_then(|sig| hex::decode(sig).ok())
+            .and_then(|bytes| bitcoin::secp256k1::ecdsa::Signature::from_compact(&bytes).ok())
+            .is_some_and(|sig| {
+                self.key_manager.verify(
+                    SigningDomain::PeerIdentity,
+                    &init_payload(init.signed_payload.clone(), &author),
+                    &sig,
+                    &public_key,
+                )
+            });
+        if !valid {
+            return Err(anyhow::anyhow!("Invalid init signature"));
+        }
+
+        self.node_ids.insert(author, crypto::node_id(&public_key));
+        Ok(())
+    }
+
+    /// Records what a peer supports, or disconnects it if we can't work together.
+    fn handle_init(&mut self, peer_id: PeerId, protocol_version: u16, peer_features: u64) {
+        // Relayed inits from peers we aren't connected to need nothing from us
+        let Some(peer) = self.peers.get_mut(&peer_id) else {
+            return;
+        };
+
+        match features::negotiate(protocol_version, peer_features) {
+            Ok(enabled) => {
+                peer.protocol_version = Some(protocol_version);
+                peer.features = enabled.iter().map(|f| f.name().to_string()).collect();
+                info!(
+                    "Peer {} speaks protocol {} with features {:?}",
+                    peer_id, protocol_version, peer.features
+                );
+            }
+            Err(reason) => {
+                warn!("Disconnecting incompatible peer {}: {}", peer_id, reason);
+                self.redials.remove(&peer_id);
+                self.incompatible.insert(peer_id, reason);
+                let _ = self.swarm.disconnect_peer_id(peer_id);
+            }
+        }
+    }
+
+    async fn send_message(&mut self, message: P2PMessage) {
+        if let P2PMessage::ForceCloseRequest { peer_node_id, .. } = &message {
+            // Restored nodes have no connections yet; reach the peer directly
+            self.dial_channel_peer(peer_node_id);
+        }
+
+        if self.peers.is_empty() {
+            self.pending_messages.push(message);
+            return;
+        }
+
+        if let Err(e) = self.broadcast_message(message).await {
+            error!("Failed to send message: {}", e);
+        }
+    }
+
+    /// Dials a channel peer we aren't connected to: directly when named by
+    /// libp2p peer id, through a DHT lookup of its node id when named by key.
+    fn dial_channel_peer(&mut self, peer_node_id: &str) {
+        if let Ok(peer_id) = peer_node_id.parse::<PeerId>() {
+            if !self.peers.contains_key(&peer_id) {
+                let _ = self.swarm.dial(peer_id);
+            }
+            return;
+        }
+        let Ok(public_key) = peer_node_id.parse::<bitcoin::secp256k1::PublicKey>() else {
+            return;
+        };
+        let node_id = crypto::node_id(&public_key);
+        if self
+            .peers
+            .values()
+            .any(|peer| peer.node_id.as_deref() == Some(node_id.as_str()))
+        {
+            return;
+        }
+
+        let handle = self.handle();
+        tokio::spawn(async move {
+            if let Err(e) = handle.connect_node(node_id.clone()).await {
+                warn!("Failed to reach channel peer {}: {}", node_id, e);
+            }
+        });
+    }
+
     pub async fn broadcast_message(&mut self, message: P2PMessage) -> Result<()> {
         let topic = gossipsub::IdentTopic::new("lightning-offline");
-        let serialized = serde_json::to_vec(&message)?;
+        let serialized = wire::encode(&message);
 
-        if let Err(e) = self
+        match self
             .swarm
             .behaviour_mut()
             .gossipsub
             .publish(topic, serialized)
         {
-            eprintln!("Failed to publish message: {:?}", e);
+            Ok(_) => METRICS.gossip_messages.with_label_values(&["out"]).inc(),
+            Err(e) => warn!("Failed to publish message: {:?}", e),
+        }
+
+        Ok(())
+    }
+
+    fn record_peer_count(&self) {
+        METRICS.peers.set(self.peers.len() as i64);
+        if let Some(health) = &self.health {
+            health.p2p_peers(self.peers.len());
+        }
+    }
+
+    /// Schedules a dial for every persisted and bootstrap peer.
+    async fn load_known_peers(&mut self) -> Result<()> {
+        if let Some(database) = &self.database {
+            for peer in database.get_known_peers().await? {
+                match (
+                    peer.peer_id.parse::<PeerId>(),
+                    peer.address.parse::<Multiaddr>(),
+                ) {
+                    (Ok(peer_id), Ok(address)) => {
+                        self.known_peers.insert(peer_id, address);
+                    }
+                    _ => warn!("Skipping malformed known peer {}", peer.peer_id),
+                }
+            }
+        }
+
+        for (peer_id, address) in self
+            .bootstrap_peers
+            .clone()
+            .into_iter()
+            .filter_map(split_peer_address)
+        {
+            if let Some(database) = &self.database {
+                database
+                    .save_known_peer(&peer_id.to_string(), &address.to_string(), false)
+                    .await?;
+            }
+            self.known_peers.insert(peer_id, address);
         }
 
+        for (peer_id, address) in &self.known_peers {
+            self.redials
+                .insert(*peer_id, Redial::new(address.clone(), Duration::ZERO));
+        }
+        info!("Dialing {} known peers", self.known_peers.len());
         Ok(())
     }
 
-    pub fn get_connected_peers(&self) -> Vec<String> {
-        self.peers.values().cloned().collect()
+    /// Records a successful outbound connection so the peer is redialed later.
+    async fn remember_peer(&mut self, peer_id: PeerId, mut address: Multiaddr) {
+        if let Some(Protocol::P2p(_)) = address.iter().last() {
+            address.pop();
+        }
+        self.redials.remove(&peer_id);
+        self.known_peers.insert(peer_id, address.clone());
+        if let Some(database) = &self.database
+            && let Err(e) = database
+                .save_known_peer(&peer_id.to_string(), &address.to_string(), true)
+                .await
+        {
+            error!("Failed to save known peer {}: {}", peer_id, e);
+        }
+    }
+
+    /// An address the peer listens on, preferring the IP it connected from,
+    /// since an inbound connection's remote port is not one we can dial.
+    fn dialable_address(&self, peer_id: &PeerId) -> Option<Multiaddr> {
+        let listen_addresses = self.peer_listen_addresses.get(peer_id)?;
+        let ip = |address: &Multiaddr| {
+            address.iter().find_map(|protocol| match protocol {
+                Protocol::Ip4(ip) => Some(std::net::IpAddr::from(ip)),
+                Protocol::Ip6(ip) => Some(std::net::IpAddr::from(ip)),
+                _ => None,
+            })
+        };
+        let remote_ips: Vec<_> = self
+            .peers
+            .get(peer_id)
+            .into_iter()
+            .flat_map(|peer| &peer.addresses)
+            .filter_map(|address| address.parse::<Multiaddr>().ok())
+            .filter_map(|address| ip(&address))
+            .collect();
+
+        listen_addresses
+            .iter()
+            .find(|address| ip(address).is_some_and(|ip| remote_ips.contains(&ip)))
+            .or_else(|| {
+                listen_addresses
+                    .iter()
+                    .find(|address| ip(address).is_some_and(|ip| !ip.is_loopback()))
+            })
+            .or(listen_addresses.first())
+            .cloned()
+    }
+
+    fn redial_due_peers(&mut self) {
+        // Peers that reconnected to us on their own need no redial
+        let peers = &self.peers;
+        self.redials
+            .retain(|peer_id, _| !peers.contains_key(peer_id));
+
+        let now = Instant::now();
+        for (peer_id, redial) in self.redials.iter_mut() {
+            if redial.due > now {
+                continue;
+            }
+            redial.backoff();
+            info!("Redialing {} (attempt {})", peer_id, redial.attempts);
+            // Addresses the DHT learned for the peer are tried too, in case it moved
+            let opts = DialOpts::peer_id(*peer_id)
+                .addresses(vec![redial.address.clone()])
+                .extend_addresses_through_behaviour()
+                .condition(PeerCondition::DisconnectedAndNotDialing)
+                .build();
+            // Already dialing or connected is fine; a failed dial waits for the next backoff
+            let _ = self.swarm.dial(opts);
+        }
+    }
+
+    async fn handle_command(&mut self, command: P2PCommand) {
+        match command {
+            P2PCommand::ListPeers(reply) => {
+                let _ = reply.send(self.peers.values().cloned().collect());
+            }
+            P2PCommand::Connect(address, reply) => {
+                // An explicit dial gives an incompatible peer another chance, a
+                // banned one has to be unbanned first
+                if let Some((peer_id, _)) = split_peer_address(address.clone()) {
+                    if self.bans.contains_key(&peer_id) {
+                        let _ = reply.send(Err(NodeError::PeerBanned(peer_id.to_string())));
+                        return;
+                    }
+                    self.incompatible.remove(&peer_id);
+                }
+                let opts = DialOpts::from(address);
+                let connection_id = opts.connection_id();
+                match self.swarm.dial(opts) {
+                    Ok(()) => {
+                        self.pending_dials.insert(connection_id, reply);
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(NodeError::PeerUnreachable(e.to_string())));
+                    }
+                }
+            }
+            P2PCommand::Disconnect(peer_id, reply) => {
+                // Forget the peer too, or the redial loop would reconnect it
+                let was_known = self.known_peers.remove(&peer_id).is_some();
+                self.redials.remove(&peer_id);
+                if was_known
+                    && let Some(database) = &self.database
+                    && let Err(e) = database.delete_known_peer(&peer_id.to_string()).await
+                {
+                    error!("Failed to forget known peer {}: {}", peer_id, e);
+                }
+
+                let result = if self.swarm.disconnect_peer_id(peer_id).is_ok() {
+                    self.swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .remove_explicit_peer(&peer_id);
+                    Ok(())
+                } else if was_known {
+                    Ok(())
+                } else {
+                    Err(NodeError::PeerNotFound(peer_id.to_string()))
+                };
+                let _ = reply.send(result);
+            }
+            P2PCommand::ListBans(reply) => {
+                let mut bans: Vec<PeerBan> = self.bans.values().cloned().collect();
+                bans.sort_by_key(|ban| ban.banned_at);
+                let _ = reply.send(bans);
+            }
+            P2PCommand::Ban(peer_id, reason, duration, reply) => {
+                let result = self
+                    .ban_peer(peer_id, reason, duration, false)
+                    .await
+                    .map_err(NodeError::from);
+                let _ = reply.send(result);
+            }
+            P2PCommand::Unban(peer_id, reply) => {
+                if !self.bans.contains_key(&peer_id) {
+                    let _ = reply.send(Err(NodeError::PeerNotBanned(peer_id.to_string())));
+                    return;
+                }
+                // Deleting the row also resets the escalation of later bans
+                if let Some(database) = &self.database
+                    && let Err(e) = database.delete_peer_ban(&peer_id.to_string()).await
+                {
+                    let _ = reply.send(Err(e.into()));
+                    return;
+                }
+                self.lift_ban(peer_id);
+                info!("Unbanned {}", peer_id);
+                let _ = reply.send(Ok(()));
+            }
+            P2PCommand::Misbehaved(peer_id, kind, reason) => {
+                self.misbehaved(peer_id, kind, reason).await;
+            }
+            P2PCommand::FindNode(node_id, reply) => {
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_record(NodeRecord::key(&node_id));
+                self.lookups.insert(
+                    query_id,
+                    Lookup {
+                        node_id,
+                        found: None,
+                        reply,
+                    },
+                );
+            }
+            P2PCommand::KeepConnected(peer_id) => {
+                if self.known_peers.contains_key(&peer_id) {
+                    return;
+                }
+                match self.dialable_address(&peer_id) {
+                    Some(address) => {
+                        info!("Keeping {} connected at {}", peer_id, address);
+                        self.remember_peer(peer_id, address).await;
+                    }
+                    None => warn!("No dialable address known for {}", peer_id),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/9735".parse().unwrap()
+    }
+
+    /// How far `backoff` pushed the next attempt.
+    fn backoff_delay(redial: &mut Redial) -> Duration {
+        let before = Instant::now();
+        redial.backoff();
+        redial.due - before
+    }
+
+    fn assert_close(delay: Duration, expected: Duration) {
+        assert!(
+            delay >= expected && delay < expected + Duration::from_millis(100),
+            "expected {:?}, got {:?}",
+            expected,
+            delay
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut redial = Redial::new(address(), Duration::ZERO);
+        for (attempt, seconds) in [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]
+            .into_iter()
+            .enumerate()
+        {
+            assert_close(backoff_delay(&mut redial), Duration::from_secs(seconds));
+            assert_eq!(redial.attempts, attempt as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn backoff_stays_capped_after_many_attempts() {
+        let mut redial = Redial::new(address(), Duration::ZERO);
+        redial.attempts = 40;
+        assert_close(backoff_delay(&mut redial), REDIAL_MAX_DELAY);
+        redial.attempts = u32::MAX - 1;
+        assert_close(backoff_delay(&mut redial), REDIAL_MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn connecting_resets_the_backoff() {
+        let key_manager = Arc::new(KeyManager::from_seed([1; 32]).unwrap());
+        let (message_sender, _messages) = mpsc::unbounded_channel();
+        let (_outbound, outbound_receiver) = mpsc::unbounded_channel();
+        let mut node = P2PNode::new(key_manager, message_sender, outbound_receiver)
+            .await
+            .unwrap()
+            .with_mdns(false);
+        let peer_id = PeerId::random();
+
+        let mut redial = Redial::new(address(), Duration::ZERO);
+        for _ in 0..5 {
+            redial.backoff();
+        }
+        node.redials.insert(peer_id, redial);
+
+        // The next disconnect schedules a fresh redial from the first delay
+        node.remember_peer(peer_id, address()).await;
+        assert!(!node.redials.contains_key(&peer_id));
+        assert_eq!(node.known_peers.get(&peer_id), Some(&address()));
     }
 }
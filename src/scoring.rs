@@ -0,0 +1,237 @@
+use chrono::{DateTime, Utc};
+use libp2p::gossipsub::{PeerScoreParams, PeerScoreThresholds, TopicHash, TopicScoreParams};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// A peer whose score falls to this is banned.
+pub const BAN_THRESHOLD: f64 = -100.0;
+/// Points a penalized peer earns back per second of good behaviour.
+const SCORE_RECOVERY_PER_SEC: f64 = 0.1;
+
+/// Token bucket per message author: bursts of reestablish and outbox replay
+/// on reconnect fit, a flood does not.
+const MESSAGE_BURST: f64 = 100.0;
+const MESSAGES_PER_SEC: f64 = 10.0;
+
+/// Each automatic ban lasts this long times 6^(earlier bans); the one after
+/// `TEMPORARY_BANS` is permanent.
+const FIRST_BAN: Duration = Duration::from_secs(600);
+const TEMPORARY_BANS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// Undecodable message
+    MalformedMessage,
+    /// Message beyond the rate limit
+    RateLimited,
+    /// Channel update that breaks the protocol, e.g. an update_fee from the fundee
+    ProtocolViolation,
+    InvalidSignature,
+}
+
+impl Misbehaviour {
+    fn penalty(self) -> f64 {
+        match self {
+            Misbehaviour::RateLimited => 2.0,
+            Misbehaviour::MalformedMessage => 20.0,
+            Misbehaviour::ProtocolViolation => 25.0,
+            Misbehaviour::InvalidSignature => 50.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Misbehaviour::MalformedMessage => "malformed_message",
+            Misbehaviour::RateLimited => "rate_limited",
+            Misbehaviour::ProtocolViolation => "protocol_violation",
+            Misbehaviour::InvalidSignature => "invalid_signature",
+        }
+    }
+}
+
+/// Error for a peer message that is the peer's fault, so it can be penalized.
+#[derive(Debug, thiserror::Error)]
+#[error("{reason}")]
+pub struct PeerViolation {
+    pub kind: Misbehaviour,
+    pub reason: String,
+}
+
+impl PeerViolation {
+    pub fn new(kind: Misbehaviour, reason: String) -> Self {
+        PeerViolation { kind, reason }
+    }
+}
+
+/// Misbehaviour score and message budget of one peer.
+#[derive(Debug, Clone)]
+pub struct PeerScore {
+    score: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Default for PeerScore {
+    fn default() -> Self {
+        PeerScore {
+            score: 0.0,
+            tokens: MESSAGE_BURST,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl PeerScore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn refresh(&mut self) {
+        let elapsed = self.updated.elapsed().as_secs_f64();
+        self.updated = Instant::now();
+        self.tokens = (self.tokens + elapsed * MESSAGES_PER_SEC).min(MESSAGE_BURST);
+        self.score = (self.score + elapsed * SCORE_RECOVERY_PER_SEC).min(0.0);
+    }
+
+    /// Spends a token for one message; false once the peer is over its rate.
+    pub fn allow_message(&mut self) -> bool {
+        self.refresh();
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Applies the penalty and returns the new score.
+    pub fn penalize(&mut self, kind: Misbehaviour) -> f64 {
+        self.refresh();
+        self.score -= kind.penalty();
+        self.score
+    }
+}
+
+/// A banned peer. Expired bans are kept so repeat offenders get longer ones.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerBan {
+    pub peer_id: String,
+    pub reason: String,
+    /// Bans so far, including this one
+    pub ban_count: u32,
+    pub banned_at: DateTime<Utc>,
+    /// Absent for a permanent ban
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl PeerBan {
+    /// `duration` of `None` bans for good, as does one too long to represent.
+    pub fn new(
+        peer_id: String,
+        reason: String,
+        previous_bans: u32,
+        duration: Option<Duration>,
+    ) -> Self {
+        let banned_at = Utc::now();
+        PeerBan {
+            peer_id,
+            reason,
+            ban_count: previous_bans + 1,
+            banned_at,
+            expires_at: duration.and_then(|d| {
+                chrono::Duration::from_std(d)
+                    .ok()
+                    .and_then(|d| banned_at.checked_add_signed(d))
+            }),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| expires_at > Utc::now())
+    }
+}
+
+/// Length of an automatic ban for a peer banned `previous_bans` times before.
+pub fn ban_duration(previous_bans: u32) -> Option<Duration> {
+    (previous_bans < TEMPORARY_BANS).then(|| FIRST_BAN * 6u32.pow(previous_bans))
+}
+
+/// Gossipsub scoring for a small network with little traffic: quiet mesh
+/// peers and peers sharing a LAN address are normal, so only invalid
+/// messages and our own misbehaviour score (the application score) count.
+/// A peer we would ban is graylisted by gossipsub too.
+pub fn gossipsub_params(topic: TopicHash) -> (PeerScoreParams, PeerScoreThresholds) {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: -10.0,
+        ..Default::default()
+    };
+    let params = PeerScoreParams {
+        topics: [(topic, topic_params)].into_iter().collect(),
+        app_specific_weight: 1.0,
+        ip_colocation_factor_weight: 0.0,
+        ..Default::default()
+    };
+    let thresholds = PeerScoreThresholds {
+        gossip_threshold: BAN_THRESHOLD / 4.0,
+        publish_threshold: BAN_THRESHOLD / 2.0,
+        graylist_threshold: BAN_THRESHOLD,
+        ..Default::default()
+    };
+    (params, thresholds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_allows_a_burst_then_refuses() {
+        let mut score = PeerScore::new();
+        for _ in 0..MESSAGE_BURST as usize {
+            assert!(score.allow_message());
+        }
+        assert!(!score.allow_message());
+    }
+
+    #[test]
+    fn repeated_misbehaviour_reaches_the_ban_threshold() {
+        let mut score = PeerScore::new();
+        assert!(score.penalize(Misbehaviour::InvalidSignature) > BAN_THRESHOLD);
+        assert!(score.penalize(Misbehaviour::ProtocolViolation) > BAN_THRESHOLD);
+        assert!(score.penalize(Misbehaviour::InvalidSignature) <= BAN_THRESHOLD);
+    }
+
+    #[test]
+    fn bans_escalate_to_permanent() {
+        assert_eq!(ban_duration(0), Some(Duration::from_secs(600)));
+        assert_eq!(ban_duration(1), Some(Duration::from_secs(3600)));
+        assert_eq!(ban_duration(2), None);
+
+        let ban = PeerBan::new("peer".to_string(), "spam".to_string(), 2, None);
+        assert_eq!(ban.ban_count, 3);
+        assert!(ban.is_active());
+    }
+
+    #[test]
+    fn overlong_ban_is_permanent() {
+        let ban = PeerBan::new(
+            "peer".to_string(),
+            "operator".to_string(),
+            0,
+            Some(Duration::from_secs(u64::MAX)),
+        );
+        assert_eq!(ban.expires_at, None);
+        assert!(ban.is_active());
+    }
+
+    #[test]
+    fn gossipsub_params_are_valid() {
+        let (params, thresholds) = gossipsub_params(TopicHash::from_raw("lightning-offline"));
+        params.validate().unwrap();
+        thresholds.validate().unwrap();
+    }
+}
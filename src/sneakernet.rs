@@ -0,0 +1,370 @@
+use crate::crypto::{KeyManager, SigningDomain};
+use crate::error::NodeError;
+use crate::p2p::P2PMessage;
+use crate::wire::{self, TlvReader, TlvWriter, WireMessage};
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey as SecpPublicKey;
+use bitcoin::secp256k1::ecdsa::Signature;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use utoipa::ToSchema;
+
+/// Version 2 signs the canonical TLV encoding instead of deflated JSON;
+/// version 3 tags the signature as a bundle's.
+const BUNDLE_VERSION: u8 = 3;
+const SIGNATURE_LEN: usize = 64;
+/// Refuse payloads that inflate beyond this, whatever the file claims.
+const MAX_PAYLOAD_SIZE: u64 = 1024 * 1024;
+
+const CHUNK_PREFIX: &str = "LNO";
+/// Bundle bytes per QR chunk. Base45 turns them into 600 characters, all in
+/// the QR alphanumeric set, which fits a version 15 code at medium correction.
+const CHUNK_SIZE: usize = 400;
+
+/// Channel updates carried between two nodes that cannot reach each other,
+/// on a file or as a series of QR codes.
+#[derive(Debug, Clone)]
+pub struct UpdateBundle {
+    pub channel_id: String,
+    /// Hex public key of the node that signed the bundle
+    pub public_key: String,
+    /// Messages with the bytes their signatures cover as they were read
+    pub messages: Vec<WireMessage>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UpdateBundle {
+    pub fn new(key_manager: &KeyManager, channel_id: &str, messages: Vec<P2PMessage>) -> Self {
+        UpdateBundle {
+            channel_id: channel_id.to_string(),
+            public_key: key_manager.get_public_key().to_string(),
+            messages: messages.into_iter().map(WireMessage::new).collect(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Serializes as `version || signature || deflate(tlv)`, the signature
+    /// covering the TLV bytes. Messages use their wire encoding, each
+    /// prefixed with its length.
+    pub fn encode(&self, key_manager: &KeyManager) -> Result<Vec<u8>> {
+        let mut messages = Vec::new();
+        for message in &self.messages {
+            let encoded = wire::encode(&message.message);
+            wire::write_bigsize(&mut messages, encoded.len() as u64);
+            messages.extend_from_slice(&encoded);
+        }
+
+        let mut tlv = TlvWriter::new();
+        tlv.string(0, &self.channel_id);
+        tlv.string(2, &self.public_key);
+        tlv.u64(4, self.created_at.timestamp_millis().max(0) as u64);
+        tlv.bytes(6, &messages);
+        let tlv = tlv.finish();
+
+        let signature = key_manager.sign(SigningDomain::UpdateBundle, &tlv)?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&tlv)?;
+        let payload = encoder.finish()?;
+
+        let mut data = vec![BUNDLE_VERSION];
+        data.extend_from_slice(&signature.serialize_compact());
+        data.extend_from_slice(&payload);
+        Ok(data)
+    }
+
+    /// Parses a bundle and checks it was signed by the key it names.
+    pub fn decode(key_manager: &KeyManager, data: &[u8]) -> Result<Self, NodeError> {
+        if data.len() <= 1 + SIGNATURE_LEN {
+            return Err(invalid("Update bundle is truncated"));
+        }
+        if data[0] != BUNDLE_VERSION {
+            return Err(invalid(&format!(
+                "Unsupported update bundle version {}",
+                data[0]
+            )));
+        }
+
+        let signature = Signature::from_compact(&data[1..1 + SIGNATURE_LEN])
+            .map_err(|_| invalid("Update bundle signature is malformed"))?;
+
+        let mut tlv = Vec::new();
+        DeflateDecoder::new(&data[1 + SIGNATURE_LEN..])
+            .take(MAX_PAYLOAD_SIZE)
+            .read_to_end(&mut tlv)
+            .map_err(|_| invalid("Update bundle payload is corrupt"))?;
+        let bundle = Self::parse(&tlv)
+            .map_err(|e| invalid(&format!("Update bundle payload is invalid: {}", e)))?;
+
+        let public_key: SecpPublicKey = bundle
+            .public_key
+            .parse()
+            .map_err(|_| invalid("Update bundle names an invalid public key"))?;
+        if !key_manager.verify(SigningDomain::UpdateBundle, &tlv, &signature, &public_key) {
+            return Err(invalid("Update bundle signature does not match its sender"));
+        }
+
+        Ok(bundle)
+    }
+
+    fn parse(tlv: &[u8]) -> Result<Self> {
+        let mut reader = TlvReader::parse(tlv)?;
+        let channel_id = reader.string(0)?;
+        let public_key = reader.string(2)?;
+        let created_at = i64::try_from(reader.u64(4)?)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(|| anyhow::anyhow!("Invalid creation time"))?;
+
+        let mut stream = reader.bytes(6)?;
+        let mut messages = Vec::new();
+        while !stream.is_empty() {
+            let length = wire::read_bigsize(&mut stream)?;
+            if length > stream.len() as u64 {
+                return Err(anyhow::anyhow!("Message overruns the bundle"));
+            }
+            let (message, rest) = stream.split_at(length as usize);
+            // Optional messages this node doesn't know are skipped
+            messages.extend(wire::decode_signed(message)?);
+            stream = rest;
+        }
+        reader.finish()?;
+
+        Ok(UpdateBundle {
+            channel_id,
+            public_key,
+            messages,
+            created_at,
+        })
+    }
+}
+
+/// An encoded bundle in both transport forms.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExportedBundle {
+    /// Hex-encoded bundle file
+    pub file: String,
+    /// The same bundle as QR-sized chunks, to be imported together
+    pub chunks: Vec<String>,
+}
+
+impl ExportedBundle {
+    pub fn new(data: &[u8]) -> Self {
+        ExportedBundle {
+            file: hex::encode(data),
+            chunks: to_chunks(data),
+        }
+    }
+}
+
+/// Splits a bundle into `LNO:<id>:<index>/<total>:<base45>` strings, where
+/// `id` ties the chunks of one bundle together and catches corruption.
+pub fn to_chunks(data: &[u8]) -> Vec<String> {
+    let id = bundle_id(data);
+    let parts: Vec<&[u8]> = data.chunks(CHUNK_SIZE).collect();
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            format!(
+                "{}:{}:{}/{}:{}",
+                CHUNK_PREFIX,
+                id,
+                i + 1,
+                parts.len(),
+                base45::encode(part)
+            )
+        })
+        .collect()
+}
+
+/// Reassembles a bundle from its chunks, given in any order.
+pub fn from_chunks(chunks: &[String]) -> Result<Vec<u8>, NodeError> {
+    let mut id = None;
+    let mut parts: Vec<Option<Vec<u8>>> = Vec::new();
+
+    for chunk in chunks {
+        let fields: Vec<&str> = chunk.trim().splitn(4, ':').collect();
+        let [prefix, chunk_id, position, payload] = fields[..] else {
+            return Err(invalid("Chunk is not in LNO:<id>:<n>/<total>:<data> form"));
+        };
+        if prefix != CHUNK_PREFIX {
+            return Err(invalid(&format!(
+                "Chunk does not start with {}",
+                CHUNK_PREFIX
+            )));
+        }
+        if *id.get_or_insert(chunk_id) != chunk_id {
+            return Err(invalid("Chunks belong to different bundles"));
+        }
+
+        let (index, total) = position
+            .split_once('/')
+            .and_then(|(i, n)| Some((i.parse::<usize>().ok()?, n.parse::<usize>().ok()?)))
+            .filter(|(i, n)| *i >= 1 && i <= n)
+            .ok_or_else(|| invalid(&format!("Invalid chunk position {}", position)))?;
+        if total > chunks.len() {
+            return Err(invalid(&format!(
+                "Only {} of {} chunks given",
+                chunks.len(),
+                total
+            )));
+        }
+        if parts.is_empty() {
+            parts.resize(total, None);
+        } else if parts.len() != total {
+            return Err(invalid("Chunks disagree on the chunk count"));
+        }
+
+        let data = base45::decode(payload)
+            .map_err(|_| invalid(&format!("Chunk {} is not valid base45", position)))?;
+        parts[index - 1] = Some(data);
+    }
+
+    if parts.is_empty() {
+        return Err(invalid("No chunks given"));
+    }
+    let missing: Vec<String> = (1..=parts.len())
+        .filter(|i| parts[i - 1].is_none())
+        .map(|i| i.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(invalid(&format!(
+            "Missing chunks {} of {}",
+            missing.join(", "),
+            parts.len()
+        )));
+    }
+
+    let data: Vec<u8> = parts.into_iter().flatten().flatten().collect();
+    if id != Some(bundle_id(&data).as_str()) {
+        return Err(invalid("Reassembled bundle does not match its chunk id"));
+    }
+    Ok(data)
+}
+
+/// First bytes of the bundle hash, in uppercase hex so it stays QR alphanumeric.
+fn bundle_id(data: &[u8]) -> String {
+    hex::encode_upper(&Sha256::digest(data)[..4])
+}
+
+fn invalid(message: &str) -> NodeError {
+    NodeError::InvalidBundle(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_manager(seed: u8) -> KeyManager {
+        KeyManager::from_seed([seed; 32]).unwrap()
+    }
+
+    fn bundle(key_manager: &KeyManager, messages: usize) -> Vec<u8> {
+        let messages = (1..=messages as u64)
+            .map(|sequence| P2PMessage::Payment {
+                channel_id: "chan".to_string(),
+                amount: 1_000 * sequence,
+                sequence,
+                // Random bytes don't deflate, so large bundles span several chunks
+                commitment_tx: hex::encode(Sha256::digest(sequence.to_be_bytes())),
+                signature: "ab".repeat(64),
+                invoice_id: None,
+            })
+            .collect();
+        UpdateBundle::new(key_manager, "chan", messages)
+            .encode(key_manager)
+            .unwrap()
+    }
+
+    #[test]
+    fn bundle_round_trips() {
+        let alice = key_manager(1);
+        let data = bundle(&alice, 3);
+
+        let decoded = UpdateBundle::decode(&alice, &data).unwrap();
+        assert_eq!(decoded.channel_id, "chan");
+        assert_eq!(decoded.public_key, alice.get_public_key().to_string());
+        assert_eq!(decoded.messages.len(), 3);
+        assert!(matches!(
+            decoded.messages[2].message,
+            P2PMessage::Payment {
+                sequence: 3,
+                amount: 3_000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn tampered_bundle_is_rejected() {
+        let alice = key_manager(1);
+        let mut data = bundle(&alice, 1);
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(UpdateBundle::decode(&alice, &data).is_err());
+
+        // Re-signed by another key while still naming Alice's
+        let mut data = bundle(&alice, 1);
+        let mut tlv = Vec::new();
+        DeflateDecoder::new(&data[1 + SIGNATURE_LEN..])
+            .read_to_end(&mut tlv)
+            .unwrap();
+        let forged = key_manager(2)
+            .sign(SigningDomain::UpdateBundle, &tlv)
+            .unwrap();
+        data[1..1 + SIGNATURE_LEN].copy_from_slice(&forged.serialize_compact());
+        assert!(UpdateBundle::decode(&alice, &data).is_err());
+    }
+
+    #[test]
+    fn chunks_reassemble_in_any_order() {
+        let data = bundle(&key_manager(1), 40);
+        let mut chunks = to_chunks(&data);
+        assert!(chunks.len() > 2);
+
+        chunks.reverse();
+        assert_eq!(from_chunks(&chunks).unwrap(), data);
+    }
+
+    #[test]
+    fn missing_chunk_is_reported() {
+        let data = bundle(&key_manager(1), 40);
+        let mut chunks = to_chunks(&data);
+        chunks.remove(1);
+        assert!(from_chunks(&chunks).is_err());
+
+        // A duplicate in place of the missing chunk leaves a gap
+        chunks.push(chunks[0].clone());
+        let error = from_chunks(&chunks).unwrap_err();
+        assert!(error.to_string().contains("Missing chunks 2"));
+    }
+
+    #[test]
+    fn chunks_of_different_bundles_are_rejected() {
+        let alice = key_manager(1);
+        let mut chunks = to_chunks(&bundle(&alice, 40));
+        chunks[1] = to_chunks(&bundle(&alice, 41)).remove(1);
+
+        let error = from_chunks(&chunks).unwrap_err();
+        assert!(error.to_string().contains("different bundles"));
+    }
+
+    #[test]
+    fn tampered_chunk_is_rejected() {
+        let data = bundle(&key_manager(1), 40);
+        let mut chunks = to_chunks(&data);
+        // base45 has ':' in its alphabet, so only the first three separate fields
+        let fields: Vec<&str> = chunks[0].splitn(4, ':').collect();
+        let mut part = base45::decode(fields[3]).unwrap();
+        part[0] ^= 1;
+        chunks[0] = format!("{}:{}", fields[..3].join(":"), base45::encode(&part));
+
+        let error = from_chunks(&chunks).unwrap_err();
+        assert!(error.to_string().contains("does not match its chunk id"));
+    }
+}
@@ -1,6 +1,18 @@
-use crate::channel::{CommitmentTransaction, PaymentChannel, PaymentRecord};
+use crate::auth::{Scope, TokenRecord};
+use crate::channel::{
+    ChannelLimits, ChannelStats, CommitmentTransaction, Invoice, OutboxEntry, PaymentChannel,
+    PaymentCursor, PaymentFilter, PaymentRecord, SortOrder,
+};
+use crate::events::{EventRecord, NodeEvent};
+use crate::fees::CommitmentType;
+use crate::metrics;
+use crate::monitor::{ChannelMonitor, SpendKind};
+use crate::p2p::{KnownPeer, P2PMessage};
+use crate::scoring::PeerBan;
+use crate::watchtower::TowerBlob;
 use anyhow::Result;
-use sqlx::{Row, Sqlite, sqlite::SqlitePool};
+use chrono::{DateTime, Utc};
+use sqlx::{QueryBuilder, Row, Sqlite, sqlite::SqlitePool};
 
 pub struct Database {
     pool: SqlitePool,
@@ -13,16 +25,45 @@ impl Database {
         Ok(Database { pool })
     }
 
+    /// Private in-memory database with the schema applied, for tests.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self> {
+        // Every connection to :memory: is its own database, so keep exactly one
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        let database = Database { pool };
+        database.migrate().await?;
+        Ok(database)
+    }
+
+    /// Closes every connection, as if the database had gone away, for tests.
+    #[cfg(test)]
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Cheapest round trip to the database, for health checks.
+    pub async fn ping(&self) -> Result<()> {
+        let _timer = metrics::db_timer("ping");
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
     pub async fn migrate(&self) -> Result<()> {
         sqlx::migrate!("./migrations").run(&self.pool).await?;
         Ok(())
     }
 
     pub async fn save_channel(&self, channel: &PaymentChannel) -> Result<()> {
+        let _timer = metrics::db_timer("save_channel");
         sqlx::query(
             r#"
-            INSERT INTO channels (id, peer_node_id, funding_txid, capacity, my_balance, peer_balance, sequence_number, is_open, created_at, multisig_address)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            INSERT INTO channels (id, peer_node_id, funding_txid, capacity, my_balance, peer_balance, sequence_number, is_open, created_at, multisig_address, closing_txid, commitment_type, feerate_per_kw, commitment_fee, is_funder, channel_reserve, dust_limit, max_in_flight, max_accepted_htlcs, htlc_minimum)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
             "#
         )
         .bind(&channel.id)
@@ -33,8 +74,18 @@ impl Database {
         .bind(channel.peer_balance as i64)
         .bind(channel.sequence_number as i64)
         .bind(channel.is_open)
-        .bind(&channel.created_at)
+        .bind(channel.created_at)
         .bind(&channel.multisig_address)
+        .bind(&channel.closing_txid)
+        .bind(channel.commitment_type.as_str())
+        .bind(channel.feerate_per_kw as i64)
+        .bind(channel.commitment_fee as i64)
+        .bind(channel.is_funder)
+        .bind(channel.limits.channel_reserve as i64)
+        .bind(channel.limits.dust_limit as i64)
+        .bind(channel.limits.max_in_flight as i64)
+        .bind(channel.limits.max_accepted_htlcs as i64)
+        .bind(channel.limits.htlc_minimum as i64)
         .execute(&self.pool)
         .await?;
 
@@ -42,17 +93,22 @@ impl Database {
     }
 
     pub async fn update_channel(&self, channel: &PaymentChannel) -> Result<()> {
+        let _timer = metrics::db_timer("update_channel");
         sqlx::query(
             r#"
             UPDATE channels 
-            SET my_balance = ?1, peer_balance = ?2, sequence_number = ?3, is_open = ?4
-            WHERE id = ?5
+            SET my_balance = ?1, peer_balance = ?2, sequence_number = ?3, is_open = ?4, closing_txid = ?5, feerate_per_kw = ?6, commitment_fee = ?7, sync_error = ?8
+            WHERE id = ?9
             "#,
         )
         .bind(channel.my_balance as i64)
         .bind(channel.peer_balance as i64)
         .bind(channel.sequence_number as i64)
         .bind(channel.is_open)
+        .bind(&channel.closing_txid)
+        .bind(channel.feerate_per_kw as i64)
+        .bind(channel.commitment_fee as i64)
+        .bind(&channel.sync_error)
         .bind(&channel.id)
         .execute(&self.pool)
         .await?;
@@ -61,8 +117,9 @@ impl Database {
     }
 
     pub async fn get_all_channels(&self) -> Result<Vec<PaymentChannel>> {
+        let _timer = metrics::db_timer("get_all_channels");
         let rows = sqlx::query(
-            "SELECT id, peer_node_id, funding_txid, capacity, my_balance, peer_balance, sequence_number, is_open, created_at, multisig_address FROM channels"
+            "SELECT id, peer_node_id, funding_txid, capacity, my_balance, peer_balance, sequence_number, is_open, created_at, multisig_address, closing_txid, commitment_type, feerate_per_kw, commitment_fee, is_funder, channel_reserve, dust_limit, max_in_flight, max_accepted_htlcs, htlc_minimum, sync_error FROM channels"
         )
         .fetch_all(&self.pool)
         .await?;
@@ -80,6 +137,21 @@ impl Database {
                 is_open: row.get("is_open"),
                 created_at: row.get("created_at"),
                 multisig_address: row.get("multisig_address"),
+                closing_txid: row.get("closing_txid"),
+                commitment_type: CommitmentType::parse(row.get("commitment_type"))
+                    .unwrap_or_default(),
+                feerate_per_kw: row.get::<i64, _>("feerate_per_kw") as u64,
+                commitment_fee: row.get::<i64, _>("commitment_fee") as u64,
+                is_funder: row.get("is_funder"),
+                limits: ChannelLimits {
+                    channel_reserve: row.get::<i64, _>("channel_reserve") as u64,
+                    dust_limit: row.get::<i64, _>("dust_limit") as u64,
+                    max_in_flight: row.get::<i64, _>("max_in_flight") as u64,
+                    max_accepted_htlcs: row.get::<i64, _>("max_accepted_htlcs") as u16,
+                    htlc_minimum: row.get::<i64, _>("htlc_minimum") as u64,
+                },
+                sync_error: row.get("sync_error"),
+                peer_online: false,
             });
         }
 
@@ -90,10 +162,11 @@ impl Database {
         &self,
         commitment: &CommitmentTransaction,
     ) -> Result<()> {
+        let _timer = metrics::db_timer("save_commitment_transaction");
         sqlx::query(
             r#"
-            INSERT INTO commitment_transactions (id, channel_id, sequence, my_balance, peer_balance, raw_tx, signature, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            INSERT INTO commitment_transactions (id, channel_id, sequence, my_balance, peer_balance, fee, raw_tx, signature, created_at, remote_txid)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
             "#
         )
         .bind(&commitment.id)
@@ -101,21 +174,35 @@ impl Database {
         .bind(commitment.sequence as i64)
         .bind(commitment.my_balance as i64)
         .bind(commitment.peer_balance as i64)
+        .bind(commitment.fee as i64)
         .bind(&commitment.raw_tx)
         .bind(&commitment.signature)
-        .bind(&commitment.created_at)
+        .bind(commitment.created_at)
+        .bind(&commitment.remote_txid)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    pub async fn save_peer_signature(&self, commitment_id: &str, signature: &str) -> Result<()> {
+        let _timer = metrics::db_timer("save_peer_signature");
+        sqlx::query("UPDATE commitment_transactions SET peer_signature = ?1 WHERE id = ?2")
+            .bind(signature)
+            .bind(commitment_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn get_channel_commitments(
         &self,
         channel_id: &str,
     ) -> Result<Vec<CommitmentTransaction>> {
+        let _timer = metrics::db_timer("get_channel_commitments");
         let rows = sqlx::query(
-            "SELECT id, channel_id, sequence, my_balance, peer_balance, raw_tx, signature, created_at FROM commitment_transactions WHERE channel_id = ?1 ORDER BY sequence"
+            "SELECT id, channel_id, sequence, my_balance, peer_balance, fee, raw_tx, signature, peer_signature, created_at, remote_txid FROM commitment_transactions WHERE channel_id = ?1 ORDER BY sequence"
         )
         .bind(channel_id)
         .fetch_all(&self.pool)
@@ -129,9 +216,12 @@ impl Database {
                 sequence: row.get::<i64, _>("sequence") as u64,
                 my_balance: row.get::<i64, _>("my_balance") as u64,
                 peer_balance: row.get::<i64, _>("peer_balance") as u64,
+                fee: row.get::<i64, _>("fee") as u64,
                 raw_tx: row.get("raw_tx"),
                 signature: row.get("signature"),
+                peer_signature: row.get("peer_signature"),
                 created_at: row.get("created_at"),
+                remote_txid: row.get("remote_txid"),
             });
         }
 
@@ -139,6 +229,7 @@ impl Database {
     }
 
     pub async fn save_payment(&self, payment: &PaymentRecord) -> Result<()> {
+        let _timer = metrics::db_timer("save_payment");
         sqlx::query(
             r#"
             INSERT INTO payments (id, channel_id, amount, direction, sequence, timestamp, is_offline)
@@ -150,7 +241,7 @@ impl Database {
         .bind(payment.amount as i64)
         .bind(&payment.direction)
         .bind(payment.sequence as i64)
-        .bind(&payment.timestamp)
+        .bind(payment.timestamp)
         .bind(payment.is_offline)
         .execute(&self.pool)
         .await?;
@@ -158,27 +249,767 @@ impl Database {
         Ok(())
     }
 
-    pub async fn get_channel_payments(&self, channel_id: &str) -> Result<Vec<PaymentRecord>> {
-        let rows = sqlx::query(
-            "SELECT id, channel_id, amount, direction, sequence, timestamp, is_offline FROM payments WHERE channel_id = ?1 ORDER BY timestamp DESC"
+    pub async fn update_payment_sequence(&self, payment_id: &str, sequence: u64) -> Result<()> {
+        let _timer = metrics::db_timer("update_payment_sequence");
+        sqlx::query("UPDATE payments SET sequence = ?1 WHERE id = ?2")
+            .bind(sequence as i64)
+            .bind(payment_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_payment(&self, payment_id: &str) -> Result<()> {
+        let _timer = metrics::db_timer("delete_payment");
+        sqlx::query("DELETE FROM payments WHERE id = ?1")
+            .bind(payment_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Payments matching `filter`, starting after `cursor`. Timestamps are stored
+    /// as UTC RFC 3339 text, which sorts and compares correctly as strings.
+    pub async fn get_payments(
+        &self,
+        filter: &PaymentFilter,
+        cursor: Option<&PaymentCursor>,
+        limit: u32,
+    ) -> Result<Vec<PaymentRecord>> {
+        let _timer = metrics::db_timer("get_payments");
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, channel_id, amount, direction, sequence, timestamp, is_offline FROM payments WHERE 1 = 1",
+        );
+
+        if let Some(channel_id) = &filter.channel_id {
+            query
+                .push(" AND channel_id = ")
+                .push_bind(channel_id.clone());
+        }
+        if let Some(direction) = &filter.direction {
+            query.push(" AND direction = ").push_bind(direction.clone());
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND timestamp < ").push_bind(to);
+        }
+        if let Some(min_amount) = filter.min_amount {
+            query.push(" AND amount >= ").push_bind(min_amount as i64);
+        }
+        if let Some(max_amount) = filter.max_amount {
+            query.push(" AND amount <= ").push_bind(max_amount as i64);
+        }
+        if let Some(is_offline) = filter.is_offline {
+            query.push(" AND is_offline = ").push_bind(is_offline);
+        }
+
+        let (after, order) = match filter.order {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+        if let Some(cursor) = cursor {
+            query
+                .push(format!(" AND (timestamp {} ", after))
+                .push_bind(cursor.timestamp)
+                .push(" OR (timestamp = ")
+                .push_bind(cursor.timestamp)
+                .push(format!(" AND id {} ", after))
+                .push_bind(cursor.id.clone())
+                .push("))");
+        }
+
+        query
+            .push(format!(" ORDER BY timestamp {0}, id {0} LIMIT ", order))
+            .push_bind(limit as i64);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        Ok(rows.iter().map(Self::payment_from_row).collect())
+    }
+
+    fn payment_from_row(row: &sqlx::sqlite::SqliteRow) -> PaymentRecord {
+        PaymentRecord {
+            id: row.get("id"),
+            channel_id: row.get("channel_id"),
+            amount: row.get::<i64, _>("amount") as u64,
+            direction: row.get("direction"),
+            sequence: row.get::<i64, _>("sequence") as u64,
+            timestamp: row.get("timestamp"),
+            is_offline: row.get("is_offline"),
+        }
+    }
+
+    pub async fn get_channel_stats(&self, channel_id: &str) -> Result<ChannelStats> {
+        let _timer = metrics::db_timer("get_channel_stats");
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS payment_count,
+                   COALESCE(SUM(CASE WHEN direction = 'outgoing' THEN amount END), 0) AS volume_out,
+                   COALESCE(SUM(CASE WHEN direction = 'incoming' THEN amount END), 0) AS volume_in,
+                   MAX(timestamp) AS last_activity
+            FROM payments WHERE channel_id = ?1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ChannelStats {
+            channel_id: channel_id.to_string(),
+            payment_count: row.get::<i64, _>("payment_count") as u64,
+            volume_out: row.get::<i64, _>("volume_out") as u64,
+            volume_in: row.get::<i64, _>("volume_in") as u64,
+            last_activity: row.get("last_activity"),
+        })
+    }
+
+    pub async fn save_channel_monitor(&self, monitor: &ChannelMonitor) -> Result<()> {
+        let _timer = metrics::db_timer("save_channel_monitor");
+        sqlx::query(
+            r#"
+            INSERT INTO channel_monitors (channel_id, funding_txid, funding_vout, is_resolved, spend_txid, spend_kind, resolution_txid, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#
+        )
+        .bind(&monitor.channel_id)
+        .bind(&monitor.funding_txid)
+        .bind(monitor.funding_vout as i64)
+        .bind(monitor.is_resolved)
+        .bind(&monitor.spend_txid)
+        .bind(monitor.spend_kind.map(|k| k.as_str()))
+        .bind(&monitor.resolution_txid)
+        .bind(monitor.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_channel_monitor(&self, monitor: &ChannelMonitor) -> Result<()> {
+        let _timer = metrics::db_timer("update_channel_monitor");
+        sqlx::query(
+            r#"
+            UPDATE channel_monitors
+            SET is_resolved = ?1, spend_txid = ?2, spend_kind = ?3, resolution_txid = ?4, updated_at = ?5
+            WHERE channel_id = ?6
+            "#,
+        )
+        .bind(monitor.is_resolved)
+        .bind(&monitor.spend_txid)
+        .bind(monitor.spend_kind.map(|k| k.as_str()))
+        .bind(&monitor.resolution_txid)
+        .bind(monitor.updated_at)
+        .bind(&monitor.channel_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_channel_monitor(&self, channel_id: &str) -> Result<Option<ChannelMonitor>> {
+        let _timer = metrics::db_timer("get_channel_monitor");
+        let row = sqlx::query(
+            "SELECT channel_id, funding_txid, funding_vout, is_resolved, spend_txid, spend_kind, resolution_txid, updated_at FROM channel_monitors WHERE channel_id = ?1"
         )
         .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| Self::monitor_from_row(&row)))
+    }
+
+    pub async fn get_unresolved_monitors(&self) -> Result<Vec<ChannelMonitor>> {
+        let _timer = metrics::db_timer("get_unresolved_monitors");
+        let rows = sqlx::query(
+            "SELECT channel_id, funding_txid, funding_vout, is_resolved, spend_txid, spend_kind, resolution_txid, updated_at FROM channel_monitors WHERE is_resolved = FALSE"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(Self::monitor_from_row).collect())
+    }
+
+    fn monitor_from_row(row: &sqlx::sqlite::SqliteRow) -> ChannelMonitor {
+        ChannelMonitor {
+            channel_id: row.get("channel_id"),
+            funding_txid: row.get("funding_txid"),
+            funding_vout: row.get::<i64, _>("funding_vout") as u32,
+            is_resolved: row.get("is_resolved"),
+            spend_txid: row.get("spend_txid"),
+            spend_kind: row
+                .get::<Option<String>, _>("spend_kind")
+                .and_then(|k| SpendKind::parse(&k)),
+            resolution_txid: row.get("resolution_txid"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+
+    pub async fn save_tower_blob(&self, blob: &TowerBlob) -> Result<()> {
+        let _timer = metrics::db_timer("save_tower_blob");
+        sqlx::query(
+            r#"
+            INSERT INTO tower_blobs (id, client, hint, blob, received_at, breach_txid, justice_txid)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&blob.id)
+        .bind(&blob.client)
+        .bind(&blob.hint)
+        .bind(&blob.blob)
+        .bind(blob.received_at)
+        .bind(&blob.breach_txid)
+        .bind(&blob.justice_txid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Blobs matching `hint` whose justice transaction has not been published yet.
+    pub async fn get_pending_tower_blobs(&self, hint: &str) -> Result<Vec<TowerBlob>> {
+        let _timer = metrics::db_timer("get_pending_tower_blobs");
+        let rows = sqlx::query(
+            "SELECT id, client, hint, blob, received_at, breach_txid, justice_txid FROM tower_blobs WHERE hint = ?1 AND justice_txid IS NULL"
+        )
+        .bind(hint)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut payments = Vec::new();
+        let mut blobs = Vec::new();
         for row in rows {
-            payments.push(PaymentRecord {
+            blobs.push(TowerBlob {
+                id: row.get("id"),
+                client: row.get("client"),
+                hint: row.get("hint"),
+                blob: row.get("blob"),
+                received_at: row.get("received_at"),
+                breach_txid: row.get("breach_txid"),
+                justice_txid: row.get("justice_txid"),
+            });
+        }
+
+        Ok(blobs)
+    }
+
+    /// Number of blobs stored for `client`, published or not.
+    pub async fn count_tower_blobs(&self, client: &str) -> Result<u64> {
+        let _timer = metrics::db_timer("count_tower_blobs");
+        let row = sqlx::query("SELECT COUNT(*) AS blob_count FROM tower_blobs WHERE client = ?1")
+            .bind(client)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get::<i64, _>("blob_count") as u64)
+    }
+
+    pub async fn mark_tower_blob_published(
+        &self,
+        id: &str,
+        breach_txid: &str,
+        justice_txid: &str,
+    ) -> Result<()> {
+        let _timer = metrics::db_timer("mark_tower_blob_published");
+        sqlx::query("UPDATE tower_blobs SET breach_txid = ?1, justice_txid = ?2 WHERE id = ?3")
+            .bind(breach_txid)
+            .bind(justice_txid)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_node_seed(&self) -> Result<Option<String>> {
+        let _timer = metrics::db_timer("get_node_seed");
+        let row = sqlx::query("SELECT seed FROM node_keys WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("seed")))
+    }
+
+    pub async fn save_node_seed(&self, seed: &str) -> Result<()> {
+        let _timer = metrics::db_timer("save_node_seed");
+        sqlx::query("INSERT INTO node_keys (id, seed, created_at) VALUES (1, ?1, ?2)")
+            .bind(seed)
+            .bind(chrono::Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn save_api_token(&self, token: &TokenRecord) -> Result<()> {
+        let _timer = metrics::db_timer("save_api_token");
+        sqlx::query(
+            "INSERT INTO api_tokens (id, scope, caveats, created_at, revoked_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&token.id)
+        .bind(token.scope.as_str())
+        .bind(serde_json::to_string(&token.caveats)?)
+        .bind(token.created_at)
+        .bind(token.revoked_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_api_token(&self, id: &str) -> Result<Option<TokenRecord>> {
+        let _timer = metrics::db_timer("get_api_token");
+        let row = sqlx::query(
+            "SELECT id, scope, caveats, created_at, revoked_at FROM api_tokens WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| Self::token_from_row(&row)).transpose()
+    }
+
+    pub async fn get_api_tokens(&self) -> Result<Vec<TokenRecord>> {
+        let _timer = metrics::db_timer("get_api_tokens");
+        let rows = sqlx::query(
+            "SELECT id, scope, caveats, created_at, revoked_at FROM api_tokens ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(Self::token_from_row).collect()
+    }
+
+    /// Returns false if no such token exists.
+    pub async fn revoke_api_token(&self, id: &str) -> Result<bool> {
+        let _timer = metrics::db_timer("revoke_api_token");
+        let result = sqlx::query(
+            "UPDATE api_tokens SET revoked_at = COALESCE(revoked_at, ?1) WHERE id = ?2",
+        )
+        .bind(chrono::Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn save_event(
+        &self,
+        event: &NodeEvent,
+        timestamp: DateTime<Utc>,
+    ) -> Result<EventRecord> {
+        let _timer = metrics::db_timer("save_event");
+        let result =
+            sqlx::query("INSERT INTO events (channel_id, event, created_at) VALUES (?1, ?2, ?3)")
+                .bind(event.channel_id())
+                .bind(serde_json::to_string(event)?)
+                .bind(timestamp)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(EventRecord {
+            id: result.last_insert_rowid(),
+            timestamp,
+            event: event.clone(),
+        })
+    }
+
+    pub async fn get_events_after(&self, cursor: i64) -> Result<Vec<EventRecord>> {
+        let _timer = metrics::db_timer("get_events_after");
+        let rows =
+            sqlx::query("SELECT id, event, created_at FROM events WHERE id > ?1 ORDER BY id")
+                .bind(cursor)
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(EventRecord {
+                    id: row.get("id"),
+                    timestamp: row.get("created_at"),
+                    event: serde_json::from_str(row.get("event"))?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn save_invoice(&self, invoice: &Invoice) -> Result<()> {
+        let _timer = metrics::db_timer("save_invoice");
+        sqlx::query(
+            "INSERT INTO invoices (id, amount, memo, created_at, settled_at, payment_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(&invoice.id)
+        .bind(invoice.amount as i64)
+        .bind(&invoice.memo)
+        .bind(invoice.created_at)
+        .bind(invoice.settled_at)
+        .bind(&invoice.payment_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_invoice(&self, id: &str) -> Result<Option<Invoice>> {
+        let _timer = metrics::db_timer("get_invoice");
+        let row = sqlx::query(
+            "SELECT id, amount, memo, created_at, settled_at, payment_id FROM invoices WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| Self::invoice_from_row(&row)))
+    }
+
+    pub async fn get_invoices(&self) -> Result<Vec<Invoice>> {
+        let _timer = metrics::db_timer("get_invoices");
+        let rows = sqlx::query(
+            "SELECT id, amount, memo, created_at, settled_at, payment_id FROM invoices ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(Self::invoice_from_row).collect())
+    }
+
+    /// Marks an open invoice paid by `payment_id`. Returns false if it was
+    /// already settled, so a second payment can never claim it.
+    pub async fn settle_invoice(&self, id: &str, payment_id: &str) -> Result<bool> {
+        let _timer = metrics::db_timer("settle_invoice");
+        let result = sqlx::query(
+            "UPDATE invoices SET settled_at = ?1, payment_id = ?2 WHERE id = ?3 AND settled_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(payment_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Inserts or updates the peer's address; `connected` also bumps `last_connected_at`.
+    pub async fn save_known_peer(
+        &self,
+        peer_id: &str,
+        address: &str,
+        connected: bool,
+    ) -> Result<()> {
+        let _timer = metrics::db_timer("save_known_peer");
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO known_peers (peer_id, address, created_at, last_connected_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(peer_id) DO UPDATE SET
+                address = excluded.address,
+                last_connected_at = COALESCE(excluded.last_connected_at, known_peers.last_connected_at)
+            "#,
+        )
+        .bind(peer_id)
+        .bind(address)
+        .bind(now)
+        .bind(connected.then_some(now))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_known_peers(&self) -> Result<Vec<KnownPeer>> {
+        let _timer = metrics::db_timer("get_known_peers");
+        let rows = sqlx::query(
+            "SELECT peer_id, address, last_connected_at FROM known_peers ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| KnownPeer {
+                peer_id: row.get("peer_id"),
+                address: row.get("address"),
+                last_connected_at: row.get("last_connected_at"),
+            })
+            .collect())
+    }
+
+    pub async fn delete_known_peer(&self, peer_id: &str) -> Result<()> {
+        let _timer = metrics::db_timer("delete_known_peer");
+        sqlx::query("DELETE FROM known_peers WHERE peer_id = ?1")
+            .bind(peer_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn save_peer_ban(&self, ban: &PeerBan) -> Result<()> {
+        let _timer = metrics::db_timer("save_peer_ban");
+        sqlx::query(
+            r#"
+            INSERT INTO peer_bans (peer_id, reason, ban_count, banned_at, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(peer_id) DO UPDATE SET
+                reason = excluded.reason,
+                ban_count = excluded.ban_count,
+                banned_at = excluded.banned_at,
+                expires_at = excluded.expires_at
+            "#,
+        )
+        .bind(&ban.peer_id)
+        .bind(&ban.reason)
+        .bind(ban.ban_count as i64)
+        .bind(ban.banned_at)
+        .bind(ban.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The latest ban of a peer, expired or not.
+    pub async fn get_peer_ban(&self, peer_id: &str) -> Result<Option<PeerBan>> {
+        let _timer = metrics::db_timer("get_peer_ban");
+        let row = sqlx::query(
+            "SELECT peer_id, reason, ban_count, banned_at, expires_at FROM peer_bans WHERE peer_id = ?1",
+        )
+        .bind(peer_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(Self::peer_ban_from_row))
+    }
+
+    pub async fn get_active_peer_bans(&self) -> Result<Vec<PeerBan>> {
+        let _timer = metrics::db_timer("get_active_peer_bans");
+        let rows = sqlx::query(
+            r#"
+            SELECT peer_id, reason, ban_count, banned_at, expires_at FROM peer_bans
+            WHERE expires_at IS NULL OR expires_at > ?1
+            ORDER BY banned_at
+            "#,
+        )
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(Self::peer_ban_from_row).collect())
+    }
+
+    pub async fn delete_peer_ban(&self, peer_id: &str) -> Result<()> {
+        let _timer = metrics::db_timer("delete_peer_ban");
+        sqlx::query("DELETE FROM peer_bans WHERE peer_id = ?1")
+            .bind(peer_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn save_outbox_message(
+        &self,
+        channel_id: &str,
+        sequence: u64,
+        payment_id: Option<&str>,
+        message: &P2PMessage,
+    ) -> Result<()> {
+        let _timer = metrics::db_timer("save_outbox_message");
+        sqlx::query(
+            r#"
+            INSERT INTO outbox (channel_id, sequence, kind, payment_id, message, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(channel_id)
+        .bind(sequence as i64)
+        .bind(message.kind())
+        .bind(payment_id)
+        .bind(serde_json::to_string(message)?)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Undelivered updates, oldest first, optionally for one channel.
+    pub async fn get_pending_outbox(&self, channel_id: Option<&str>) -> Result<Vec<OutboxEntry>> {
+        let _timer = metrics::db_timer("get_pending_outbox");
+        let rows = sqlx::query(
+            r#"
+            SELECT id, channel_id, sequence, kind, payment_id, created_at, delivered_at FROM outbox
+            WHERE delivered_at IS NULL AND (?1 IS NULL OR channel_id = ?1)
+            ORDER BY id
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| OutboxEntry {
                 id: row.get("id"),
                 channel_id: row.get("channel_id"),
-                amount: row.get::<i64, _>("amount") as u64,
-                direction: row.get("direction"),
                 sequence: row.get::<i64, _>("sequence") as u64,
-                timestamp: row.get("timestamp"),
-                is_offline: row.get("is_offline"),
-            });
+                kind: row.get("kind"),
+                payment_id: row.get("payment_id"),
+                created_at: row.get("created_at"),
+                delivered_at: row.get("delivered_at"),
+            })
+            .collect())
+    }
+
+    /// Undelivered updates on a channel, in send order.
+    pub async fn get_pending_outbox_messages(&self, channel_id: &str) -> Result<Vec<P2PMessage>> {
+        let _timer = metrics::db_timer("get_pending_outbox_messages");
+        let rows = sqlx::query(
+            "SELECT message FROM outbox WHERE channel_id = ?1 AND delivered_at IS NULL ORDER BY id",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok(serde_json::from_str(row.get("message"))?))
+            .collect()
+    }
+
+    /// Our undelivered updates on a channel from `sequence` on, each with the
+    /// payment it carries, in send order.
+    pub async fn get_pending_updates_from(
+        &self,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<Vec<(Option<String>, P2PMessage)>> {
+        let _timer = metrics::db_timer("get_pending_updates_from");
+        let rows = sqlx::query(
+            r#"
+            SELECT payment_id, message FROM outbox
+            WHERE channel_id = ?1 AND sequence >= ?2 AND delivered_at IS NULL
+            ORDER BY id
+            "#,
+        )
+        .bind(channel_id)
+        .bind(sequence as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok((
+                    row.get("payment_id"),
+                    serde_json::from_str(row.get("message"))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Forgets the updates we made on a channel from `sequence` on, along with
+    /// the commitments we signed for them.
+    pub async fn delete_updates_from(&self, channel_id: &str, sequence: u64) -> Result<()> {
+        let _timer = metrics::db_timer("delete_updates_from");
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM outbox WHERE channel_id = ?1 AND sequence >= ?2")
+            .bind(channel_id)
+            .bind(sequence as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM commitment_transactions WHERE channel_id = ?1 AND sequence >= ?2")
+            .bind(channel_id)
+            .bind(sequence as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Every update sent on a channel after `sequence`, delivered or not, in send order.
+    pub async fn get_outbox_messages_after(
+        &self,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<Vec<P2PMessage>> {
+        let _timer = metrics::db_timer("get_outbox_messages_after");
+        let rows = sqlx::query(
+            "SELECT message FROM outbox WHERE channel_id = ?1 AND sequence > ?2 ORDER BY id",
+        )
+        .bind(channel_id)
+        .bind(sequence as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok(serde_json::from_str(row.get("message"))?))
+            .collect()
+    }
+
+    /// Marks updates up to `sequence` delivered and our payments among them
+    /// online. Returns how many outbox entries were newly delivered.
+    pub async fn mark_outbox_delivered(&self, channel_id: &str, sequence: u64) -> Result<u64> {
+        let _timer = metrics::db_timer("mark_outbox_delivered");
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            r#"
+            UPDATE outbox SET delivered_at = ?1
+            WHERE channel_id = ?2 AND sequence <= ?3 AND delivered_at IS NULL
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(channel_id)
+        .bind(sequence as i64)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE payments SET is_offline = 0
+            WHERE channel_id = ?1 AND direction = 'outgoing' AND sequence <= ?2 AND is_offline = 1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(sequence as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    fn invoice_from_row(row: &sqlx::sqlite::SqliteRow) -> Invoice {
+        Invoice {
+            id: row.get("id"),
+            amount: row.get::<i64, _>("amount") as u64,
+            memo: row.get("memo"),
+            created_at: row.get("created_at"),
+            settled_at: row.get("settled_at"),
+            payment_id: row.get("payment_id"),
         }
+    }
+
+    fn token_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<TokenRecord> {
+        Ok(TokenRecord {
+            id: row.get("id"),
+            scope: Scope::parse(row.get("scope"))
+                .ok_or_else(|| anyhow::anyhow!("Unknown token scope"))?,
+            caveats: serde_json::from_str(row.get("caveats"))?,
+            created_at: row.get("created_at"),
+            revoked_at: row.get("revoked_at"),
+        })
+    }
 
-        Ok(payments)
+    fn peer_ban_from_row(row: &sqlx::sqlite::SqliteRow) -> PeerBan {
+        PeerBan {
+            peer_id: row.get("peer_id"),
+            reason: row.get("reason"),
+            ban_count: row.get::<i64, _>("ban_count") as u32,
+            banned_at: row.get("banned_at"),
+            expires_at: row.get("expires_at"),
+        }
     }
 }
@@ -0,0 +1,57 @@
+use crate::auth::write_secret_file;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{ServerConfig, crypto::ring};
+
+/// Writes a self-signed certificate for `names` (DNS names or IPs) unless one
+/// already exists at `cert_path`. Returns true if a new certificate was created.
+pub fn ensure_self_signed(cert_path: &Path, key_path: &Path, names: Vec<String>) -> Result<bool> {
+    if cert_path.exists() {
+        return Ok(false);
+    }
+
+    let cert = rcgen::generate_simple_self_signed(names)?;
+    if let Some(parent) = cert_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(cert_path, cert.serialize_pem()?)?;
+    write_secret_file(key_path, cert.serialize_private_key_pem().as_bytes())?;
+
+    Ok(true)
+}
+
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", cert_path.display(), e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid certificate in {}: {}", cert_path.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| anyhow::anyhow!("Invalid private key in {}: {}", key_path.display(), e))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(Arc::new(config))
+}
+
+/// SHA-256 of the first certificate in `cert_path`, for out-of-band pinning.
+pub fn certificate_fingerprint(cert_path: &Path) -> Result<String> {
+    let cert = CertificateDer::from_pem_file(cert_path)
+        .map_err(|e| anyhow::anyhow!("Invalid certificate in {}: {}", cert_path.display(), e))?;
+    Ok(hex::encode(Sha256::digest(cert.as_ref())))
+}
+
+/// The same certificate and key for the gRPC server.
+pub fn load_grpc_identity(cert_path: &Path, key_path: &Path) -> Result<tonic::transport::Identity> {
+    let cert = std::fs::read(cert_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", cert_path.display(), e))?;
+    let key = std::fs::read(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", key_path.display(), e))?;
+    Ok(tonic::transport::Identity::from_pem(cert, key))
+}
@@ -0,0 +1,420 @@
+use crate::chain::ChainClient;
+use crate::storage::Database;
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    response::Json,
+    routing::post,
+};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Number of hex characters of the commitment txid used as the lookup hint.
+const HINT_LENGTH: usize = 32;
+
+/// Blocks walked back from the tip per poll; after a longer outage older
+/// blocks are skipped (their breaches are past the CSV delay anyway).
+const MAX_CATCHUP_BLOCKS: usize = 144;
+
+/// Encrypted justice transaction, only decryptable once the breaching
+/// commitment txid is known.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreachBlob {
+    pub hint: String,
+    pub blob: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TowerBlob {
+    pub id: String,
+    pub client: String,
+    pub hint: String,
+    pub blob: String,
+    pub received_at: DateTime<Utc>,
+    pub breach_txid: Option<String>,
+    pub justice_txid: Option<String>,
+}
+
+pub fn breach_hint(commitment_txid: &str) -> String {
+    commitment_txid[..HINT_LENGTH.min(commitment_txid.len())].to_string()
+}
+
+fn blob_key(commitment_txid: &str) -> Key {
+    let mut hasher = Sha256::new();
+    hasher.update(commitment_txid.as_bytes());
+    Key::clone_from_slice(&hasher.finalize())
+}
+
+impl BreachBlob {
+    /// Encrypts `justice_tx` under a key derived from the full commitment txid.
+    pub fn seal(commitment_txid: &str, justice_tx: &str) -> Result<Self> {
+        let cipher = ChaCha20Poly1305::new(&blob_key(commitment_txid));
+
+        let mut nonce_bytes = [0u8; 12];
+        rand::rngs::OsRng.fill_bytes(&mut nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), justice_tx.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt breach blob"))?;
+
+        let mut blob = nonce_bytes.to_vec();
+        blob.extend_from_slice(&ciphertext);
+
+        Ok(BreachBlob {
+            hint: breach_hint(commitment_txid),
+            blob: hex::encode(blob),
+        })
+    }
+
+    /// Decrypts the justice transaction using the txid seen on-chain.
+    pub fn open(&self, commitment_txid: &str) -> Result<String> {
+        let bytes = hex::decode(&self.blob)?;
+        if bytes.len() < 12 {
+            return Err(anyhow::anyhow!("Breach blob too short"));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(12);
+        let cipher = ChaCha20Poly1305::new(&blob_key(commitment_txid));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Breach blob does not match txid"))?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// Short identifier of a tower client token, stored with its blobs instead of the token.
+fn client_id(token: &str) -> String {
+    hex::encode(&Sha256::digest(token.as_bytes())[..8])
+}
+
+/// Uploads breach blobs to the configured watchtowers.
+#[derive(Clone)]
+pub struct TowerClient {
+    http: reqwest::Client,
+    towers: Vec<(String, Option<String>)>,
+}
+
+impl TowerClient {
+    /// Tower URLs may carry the client token as the user part,
+    /// e.g. `http://TOKEN@127.0.0.1:3010`.
+    pub fn new(towers: Vec<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            towers: towers.into_iter().map(|t| split_token(&t)).collect(),
+        }
+    }
+
+    /// Sends the blob to every tower in the background so channel updates are not
+    /// held up by unreachable towers.
+    pub fn upload(&self, blob: BreachBlob) {
+        for (tower, token) in &self.towers {
+            let url = format!("{}/api/tower/blobs", tower);
+            let mut request = self.http.post(&url).json(&blob);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+
+            tokio::spawn(async move {
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => {
+                        warn!("Watchtower {} rejected blob: {}", url, response.status())
+                    }
+                    Err(e) => warn!("Failed to upload blob to watchtower {}: {}", url, e),
+                }
+            });
+        }
+    }
+}
+
+/// Splits `http://TOKEN@host` into the bare tower URL and its client token.
+fn split_token(tower: &str) -> (String, Option<String>) {
+    let Ok(mut url) = reqwest::Url::parse(tower) else {
+        return (tower.trim_end_matches('/').to_string(), None);
+    };
+    let token = Some(url.username().to_string()).filter(|t| !t.is_empty());
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    (url.as_str().trim_end_matches('/').to_string(), token)
+}
+
+/// Watchtower mode: stores breach blobs for clients and publishes the justice
+/// transaction when a matching commitment appears on-chain.
+pub struct Watchtower {
+    database: Arc<Database>,
+    chain: ChainClient,
+    blobs: BlobStore,
+}
+
+/// Accepts blobs only from known client tokens, up to a quota per client.
+#[derive(Clone)]
+struct BlobStore {
+    database: Arc<Database>,
+    clients: Arc<HashSet<String>>,
+    max_blobs_per_client: u64,
+}
+
+impl Watchtower {
+    pub fn new(
+        database: Arc<Database>,
+        chain: ChainClient,
+        client_tokens: &[String],
+        max_blobs_per_client: u64,
+    ) -> Self {
+        let blobs = BlobStore {
+            database: database.clone(),
+            clients: Arc::new(client_tokens.iter().map(|t| client_id(t)).collect()),
+            max_blobs_per_client,
+        };
+        Self {
+            database,
+            chain,
+            blobs,
+        }
+    }
+
+    pub async fn start(&self, addr: &str) -> Result<()> {
+        let app = Router::new()
+            .route("/api/tower/blobs", post(store_blob))
+            .with_state(self.blobs.clone());
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Watchtower listening on {}", addr);
+        axum::serve(listener, app).await?;
+        Ok(())
+    }
+
+    pub async fn watch(&self, poll_interval: Duration) -> Result<()> {
+        info!("Watchtower scanning {}", self.chain.base_url());
+        let mut interval = tokio::time::interval(poll_interval);
+        // Recently scanned block hashes, newest first
+        let mut scanned: VecDeque<String> = VecDeque::new();
+
+        loop {
+            interval.tick().await;
+
+            let mut txids: HashSet<String> = match self.chain.get_mempool_txids().await {
+                Ok(txids) => txids.into_iter().collect(),
+                Err(e) => {
+                    warn!("Failed to fetch mempool: {}", e);
+                    continue;
+                }
+            };
+
+            // Also scan blocks mined since the last poll so breaches are not missed
+            match self.new_blocks(&scanned).await {
+                Ok(blocks) => {
+                    for (hash, block_txids) in blocks.into_iter().rev() {
+                        txids.extend(block_txids);
+                        scanned.push_front(hash);
+                    }
+                    scanned.truncate(MAX_CATCHUP_BLOCKS);
+                }
+                Err(e) => warn!("Failed to fetch new blocks: {}", e),
+            }
+
+            for txid in txids {
+                if let Err(e) = self.check_transaction(&txid).await {
+                    error!("Failed to handle transaction {}: {}", txid, e);
+                }
+            }
+        }
+    }
+
+    /// Walks back from the tip to the newest block already scanned, so every
+    /// block since the last poll is returned (tip first), reorgs included.
+    /// On the first poll only the tip is scanned.
+    async fn new_blocks(&self, scanned: &VecDeque<String>) -> Result<Vec<(String, Vec<String>)>> {
+        let mut blocks = Vec::new();
+        let mut hash = Some(self.chain.get_tip_hash().await?);
+
+        while let Some(current) = hash.take() {
+            if scanned.contains(&current) {
+                break;
+            }
+            let txids = self.chain.get_block_txids(&current).await?;
+            blocks.push((current.clone(), txids));
+
+            if scanned.is_empty() {
+                break;
+            }
+            if blocks.len() == MAX_CATCHUP_BLOCKS {
+                warn!(
+                    "More than {} blocks since the last scan, skipping older ones",
+                    MAX_CATCHUP_BLOCKS
+                );
+                break;
+            }
+            hash = self.chain.get_block_parent(&current).await?;
+        }
+
+        Ok(blocks)
+    }
+
+    async fn check_transaction(&self, txid: &str) -> Result<()> {
+        let candidates = self
+            .database
+            .get_pending_tower_blobs(&breach_hint(txid))
+            .await?;
+
+        for candidate in candidates {
+            let blob = BreachBlob {
+                hint: candidate.hint.clone(),
+                blob: candidate.blob.clone(),
+            };
+
+            // Hint collisions simply fail to decrypt
+            let Ok(justice_tx) = blob.open(txid) else {
+                continue;
+            };
+
+            warn!("Breach detected: {} matches blob {}", txid, candidate.id);
+            // Left pending on failure, so the next poll that sees the breach retries
+            let justice_txid = match self.chain.broadcast(&justice_tx).await {
+                Ok(justice_txid) => justice_txid,
+                Err(e) => {
+                    error!(
+                        "Failed to publish justice transaction for blob {}: {}",
+                        candidate.id, e
+                    );
+                    continue;
+                }
+            };
+            info!("Published justice transaction {}", justice_txid);
+
+            self.database
+                .mark_tower_blob_published(&candidate.id, txid, &justice_txid)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn store_blob(
+    State(store): State<BlobStore>,
+    headers: HeaderMap,
+    Json(req): Json<BreachBlob>,
+) -> StatusCode {
+    let client = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| client_id(token.trim()));
+    let Some(client) = client.filter(|c| store.clients.contains(c)) else {
+        return StatusCode::UNAUTHORIZED;
+    };
+
+    if req.hint.len() != HINT_LENGTH || hex::decode(&req.blob).is_err() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    match store.database.count_tower_blobs(&client).await {
+        Ok(count) if count >= store.max_blobs_per_client => {
+            warn!(
+                "Tower client {} reached its quota of {} blobs",
+                client, count
+            );
+            return StatusCode::TOO_MANY_REQUESTS;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Failed to count breach blobs: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    let blob = TowerBlob {
+        id: Uuid::new_v4().to_string(),
+        client,
+        hint: req.hint,
+        blob: req.blob,
+        received_at: Utc::now(),
+        breach_txid: None,
+        justice_txid: None,
+    };
+
+    match store.database.save_tower_blob(&blob).await {
+        Ok(_) => StatusCode::CREATED,
+        Err(e) => {
+            error!("Failed to store breach blob: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    async fn store(max_blobs_per_client: u64) -> BlobStore {
+        BlobStore {
+            database: Arc::new(Database::in_memory().await.unwrap()),
+            clients: Arc::new(HashSet::from([client_id("secret")])),
+            max_blobs_per_client,
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        headers.insert(AUTHORIZATION, value);
+        headers
+    }
+
+    fn blob() -> BreachBlob {
+        BreachBlob::seal(&"ab".repeat(32), "0200000001").unwrap()
+    }
+
+    #[tokio::test]
+    async fn blobs_need_a_known_client_token() {
+        let store = store(10).await;
+
+        let status = store_blob(State(store.clone()), HeaderMap::new(), Json(blob())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let status = store_blob(State(store.clone()), bearer("guess"), Json(blob())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let status = store_blob(State(store), bearer("secret"), Json(blob())).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn uploads_past_the_quota_are_refused() {
+        let store = store(2).await;
+
+        for _ in 0..2 {
+            let status = store_blob(State(store.clone()), bearer("secret"), Json(blob())).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let status = store_blob(State(store), bearer("secret"), Json(blob())).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn tower_urls_carry_the_client_token() {
+        assert_eq!(
+            split_token("http://secret@127.0.0.1:3010/"),
+            (
+                "http://127.0.0.1:3010".to_string(),
+                Some("secret".to_string())
+            )
+        );
+        assert_eq!(
+            split_token("http://127.0.0.1:3010"),
+            ("http://127.0.0.1:3010".to_string(), None)
+        );
+    }
+}
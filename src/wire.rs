@@ -0,0 +1,740 @@
+use crate::channel::ChannelLimits;
+use crate::fees::CommitmentType;
+use crate::p2p::P2PMessage;
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+
+pub const WIRE_VERSION: u8 = 1;
+
+// Message type registry; never reuse a number.
+const CHANNEL_OPEN: u16 = 2;
+const CHANNEL_CLOSE: u16 = 4;
+const PAYMENT: u16 = 6;
+const COMMITMENT_SIGNED: u16 = 8;
+const UPDATE_FEE: u16 = 10;
+const FORCE_CLOSE_REQUEST: u16 = 12;
+const CHANNEL_REESTABLISH: u16 = 14;
+const UPDATE_ACK: u16 = 16;
+const INIT: u16 = 18;
+
+/// Encodes a message for the wire, modelled on BOLT #1:
+/// `version (u8) || type (u16) || tlv_stream`. Each TLV record is
+/// `type (bigsize) || length (bigsize) || value`, in increasing type order.
+///
+/// Even types must be understood: an unknown even message type or field is
+/// an error. Odd types are optional and skipped when unknown, so new optional
+/// fields and messages don't break older nodes.
+pub fn encode(message: &P2PMessage) -> Vec<u8> {
+    encode_message(message, true)
+}
+
+/// The canonical encoding of a message without its signature field, which is
+/// what that signature covers.
+pub fn signed_payload(message: &P2PMessage) -> Vec<u8> {
+    encode_message(message, false)
+}
+
+/// A message with the exact bytes its signature covers. For a received
+/// message that is everything received but the signature record, so optional
+/// fields this node doesn't know are still covered.
+#[derive(Debug, Clone)]
+pub struct WireMessage {
+    pub message: P2PMessage,
+    pub signed_payload: Vec<u8>,
+}
+
+impl WireMessage {
+    /// A message built locally, which has no fields beyond the known ones.
+    pub fn new(message: P2PMessage) -> Self {
+        WireMessage {
+            signed_payload: signed_payload(&message),
+            message,
+        }
+    }
+}
+
+/// Type of the signature record of messages that carry one.
+fn signature_field(message_type: u16) -> Option<u64> {
+    match message_type {
+        CHANNEL_OPEN => Some(26),
+        PAYMENT => Some(8),
+        FORCE_CLOSE_REQUEST => Some(6),
+        INIT => Some(5),
+        _ => None,
+    }
+}
+
+/// Canonical form of a commitment for signing: its holder's balances at
+/// `sequence`, and the transaction itself.
+pub fn encode_commitment(
+    channel_id: &str,
+    sequence: u64,
+    holder_balance: u64,
+    other_balance: u64,
+    fee: u64,
+    raw_tx: &str,
+) -> Vec<u8> {
+    let mut tlv = TlvWriter::new();
+    tlv.string(0, channel_id);
+    tlv.u64(2, sequence);
+    tlv.u64(4, holder_balance);
+    tlv.u64(6, other_balance);
+    tlv.u64(8, fee);
+    tlv.string(10, raw_tx);
+    tlv.finish()
+}
+
+fn encode_message(message: &P2PMessage, with_signature: bool) -> Vec<u8> {
+    let mut tlv = TlvWriter::new();
+    let message_type = match message {
+        P2PMessage::ChannelOpen {
+            channel_id,
+            funding_txid,
+            capacity,
+            initial_balance,
+            funder_key,
+            peer_node_id,
+            commitment_type,
+            feerate_per_kw,
+            limits,
+            signature,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.string(2, funding_txid);
+            tlv.u64(4, *capacity);
+            tlv.u64(6, *initial_balance);
+            tlv.string(8, funder_key);
+            tlv.string(10, peer_node_id);
+            tlv.string(12, commitment_type.as_str());
+            tlv.u64(14, *feerate_per_kw);
+            tlv.u64(16, limits.channel_reserve);
+            tlv.u64(18, limits.dust_limit);
+            tlv.u64(20, limits.max_in_flight);
+            tlv.u64(22, u64::from(limits.max_accepted_htlcs));
+            tlv.u64(24, limits.htlc_minimum);
+            if with_signature {
+                tlv.string(26, signature);
+            }
+            CHANNEL_OPEN
+        }
+        P2PMessage::ChannelClose {
+            channel_id,
+            final_balance_a,
+            final_balance_b,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.u64(2, *final_balance_a);
+            tlv.u64(4, *final_balance_b);
+            CHANNEL_CLOSE
+        }
+        P2PMessage::Payment {
+            channel_id,
+            amount,
+            sequence,
+            commitment_tx,
+            signature,
+            invoice_id,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.u64(2, *amount);
+            tlv.u64(4, *sequence);
+            tlv.string(6, commitment_tx);
+            if with_signature {
+                tlv.string(8, signature);
+            }
+            if let Some(invoice_id) = invoice_id {
+                tlv.string(9, invoice_id);
+            }
+            PAYMENT
+        }
+        P2PMessage::CommitmentSigned {
+            channel_id,
+            signature,
+            sequence,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.string(2, signature);
+            tlv.u64(4, *sequence);
+            COMMITMENT_SIGNED
+        }
+        P2PMessage::UpdateFee {
+            channel_id,
+            feerate_per_kw,
+            sequence,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.u64(2, *feerate_per_kw);
+            tlv.u64(4, *sequence);
+            UPDATE_FEE
+        }
+        P2PMessage::ForceCloseRequest {
+            channel_id,
+            funding_txid,
+            peer_node_id,
+            signature,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.string(2, funding_txid);
+            tlv.string(4, peer_node_id);
+            if with_signature {
+                tlv.string(6, signature);
+            }
+            FORCE_CLOSE_REQUEST
+        }
+        P2PMessage::ChannelReestablish {
+            channel_id,
+            funding_txid,
+            sequence,
+            last_remote_sequence,
+            my_balance,
+            peer_balance,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.string(2, funding_txid);
+            tlv.u64(4, *sequence);
+            tlv.u64(6, *last_remote_sequence);
+            tlv.u64(8, *my_balance);
+            tlv.u64(10, *peer_balance);
+            CHANNEL_REESTABLISH
+        }
+        P2PMessage::UpdateAck {
+            channel_id,
+            sequence,
+        } => {
+            tlv.string(0, channel_id);
+            tlv.u64(2, *sequence);
+            UPDATE_ACK
+        }
+        P2PMessage::Init {
+            protocol_version,
+            features,
+            node_key,
+            signature,
+        } => {
+            tlv.u64(0, u64::from(*protocol_version));
+            tlv.u64(2, *features);
+            if let Some(node_key) = node_key {
+                tlv.string(3, node_key);
+            }
+            if let Some(signature) = signature.as_ref().filter(|_| with_signature) {
+                tlv.string(5, signature);
+            }
+            INIT
+        }
+    };
+
+    let mut data = vec![WIRE_VERSION];
+    data.extend_from_slice(&message_type.to_be_bytes());
+    data.extend_from_slice(&tlv.finish());
+    data
+}
+
+/// Decodes a message from the wire. `None` is an unknown odd (optional)
+/// message type, which the caller should ignore.
+pub fn decode(data: &[u8]) -> Result<Option<P2PMessage>> {
+    let [version, t0, t1, stream @ ..] = data else {
+        return Err(anyhow!("Message is truncated"));
+    };
+    if *version != WIRE_VERSION {
+        return Err(anyhow!("Unsupported wire version {}", version));
+    }
+    let message_type = u16::from_be_bytes([*t0, *t1]);
+
+    let mut tlv = TlvReader::parse(stream)?;
+    let message = match message_type {
+        CHANNEL_OPEN => P2PMessage::ChannelOpen {
+            channel_id: tlv.string(0)?,
+            funding_txid: tlv.string(2)?,
+            capacity: tlv.u64(4)?,
+            initial_balance: tlv.u64(6)?,
+            funder_key: tlv.string(8)?,
+            peer_node_id: tlv.string(10)?,
+            commitment_type: CommitmentType::parse(&tlv.string(12)?)
+                .ok_or_else(|| anyhow!("Unknown commitment type"))?,
+            feerate_per_kw: tlv.u64(14)?,
+            limits: ChannelLimits {
+                channel_reserve: tlv.u64(16)?,
+                dust_limit: tlv.u64(18)?,
+                max_in_flight: tlv.u64(20)?,
+                max_accepted_htlcs: u16::try_from(tlv.u64(22)?)
+                    .map_err(|_| anyhow!("Max accepted HTLCs out of range"))?,
+                htlc_minimum: tlv.u64(24)?,
+            },
+            signature: tlv.string(26)?,
+        },
+        CHANNEL_CLOSE => P2PMessage::ChannelClose {
+            channel_id: tlv.string(0)?,
+            final_balance_a: tlv.u64(2)?,
+            final_balance_b: tlv.u64(4)?,
+        },
+        PAYMENT => P2PMessage::Payment {
+            channel_id: tlv.string(0)?,
+            amount: tlv.u64(2)?,
+            sequence: tlv.u64(4)?,
+            commitment_tx: tlv.string(6)?,
+            signature: tlv.string(8)?,
+            invoice_id: tlv.optional_string(9)?,
+        },
+        COMMITMENT_SIGNED => P2PMessage::CommitmentSigned {
+            channel_id: tlv.string(0)?,
+            signature: tlv.string(2)?,
+            sequence: tlv.u64(4)?,
+        },
+        UPDATE_FEE => P2PMessage::UpdateFee {
+            channel_id: tlv.string(0)?,
+            feerate_per_kw: tlv.u64(2)?,
+            sequence: tlv.u64(4)?,
+        },
+        FORCE_CLOSE_REQUEST => P2PMessage::ForceCloseRequest {
+            channel_id: tlv.string(0)?,
+            funding_txid: tlv.string(2)?,
+            peer_node_id: tlv.string(4)?,
+            signature: tlv.string(6)?,
+        },
+        CHANNEL_REESTABLISH => P2PMessage::ChannelReestablish {
+            channel_id: tlv.string(0)?,
+            funding_txid: tlv.string(2)?,
+            sequence: tlv.u64(4)?,
+            last_remote_sequence: tlv.u64(6)?,
+            my_balance: tlv.u64(8)?,
+            peer_balance: tlv.u64(10)?,
+        },
+        UPDATE_ACK => P2PMessage::UpdateAck {
+            channel_id: tlv.string(0)?,
+            sequence: tlv.u64(2)?,
+        },
+        INIT => P2PMessage::Init {
+            protocol_version: u16::try_from(tlv.u64(0)?)
+                .map_err(|_| anyhow!("Protocol version out of range"))?,
+            features: tlv.u64(2)?,
+            node_key: tlv.optional_string(3)?,
+            signature: tlv.optional_string(5)?,
+        },
+        odd if odd % 2 == 1 => return Ok(None),
+        even => return Err(anyhow!("Unknown required message type {}", even)),
+    };
+    tlv.finish()?;
+
+    Ok(Some(message))
+}
+
+/// Decodes a message like `decode`, keeping the received bytes its signature covers.
+pub fn decode_signed(data: &[u8]) -> Result<Option<WireMessage>> {
+    let Some(message) = decode(data)? else {
+        return Ok(None);
+    };
+    // `decode` checked the header and every record
+    let (header, mut stream) = data.split_at(3);
+    let signature_type = signature_field(u16::from_be_bytes([header[1], header[2]]));
+
+    let mut signed_payload = header.to_vec();
+    while !stream.is_empty() {
+        let record = stream;
+        let tlv_type = read_bigsize(&mut stream)?;
+        let length = read_bigsize(&mut stream)? as usize;
+        stream = &stream[length..];
+        if Some(tlv_type) != signature_type {
+            signed_payload.extend_from_slice(&record[..record.len() - stream.len()]);
+        }
+    }
+
+    Ok(Some(WireMessage {
+        message,
+        signed_payload,
+    }))
+}
+
+/// Builds a TLV stream; records must be written in increasing type order.
+#[derive(Default)]
+pub struct TlvWriter {
+    data: Vec<u8>,
+    last_type: Option<u64>,
+}
+
+impl TlvWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&mut self, tlv_type: u64, value: &[u8]) {
+        assert!(
+            self.last_type.is_none_or(|last| tlv_type > last),
+            "TLV type {} written out of order",
+            tlv_type
+        );
+        self.last_type = Some(tlv_type);
+
+        write_bigsize(&mut self.data, tlv_type);
+        write_bigsize(&mut self.data, value.len() as u64);
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn string(&mut self, tlv_type: u64, value: &str) {
+        self.bytes(tlv_type, value.as_bytes());
+    }
+
+    /// Big-endian with leading zero bytes dropped (BOLT `tu64`).
+    pub fn u64(&mut self, tlv_type: u64, value: u64) {
+        let bytes = value.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        self.bytes(tlv_type, &bytes[skip..]);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// A parsed TLV stream. Fields are taken out by type; `finish` then rejects
+/// any even type left over.
+pub struct TlvReader<'a> {
+    records: BTreeMap<u64, &'a [u8]>,
+}
+
+impl<'a> TlvReader<'a> {
+    pub fn parse(mut stream: &'a [u8]) -> Result<Self> {
+        let mut records = BTreeMap::new();
+        let mut last_type = None;
+
+        while !stream.is_empty() {
+            let tlv_type = read_bigsize(&mut stream)?;
+            if last_type.is_some_and(|last| tlv_type <= last) {
+                return Err(anyhow!("TLV type {} is out of order or repeated", tlv_type));
+            }
+            last_type = Some(tlv_type);
+
+            let length = read_bigsize(&mut stream)?;
+            if length > stream.len() as u64 {
+                return Err(anyhow!("TLV record {} overruns the message", tlv_type));
+            }
+            let (value, rest) = stream.split_at(length as usize);
+            records.insert(tlv_type, value);
+            stream = rest;
+        }
+
+        Ok(TlvReader { records })
+    }
+
+    pub fn optional_bytes(&mut self, tlv_type: u64) -> Option<&'a [u8]> {
+        self.records.remove(&tlv_type)
+    }
+
+    pub fn bytes(&mut self, tlv_type: u64) -> Result<&'a [u8]> {
+        self.optional_bytes(tlv_type)
+            .ok_or_else(|| anyhow!("Missing TLV field {}", tlv_type))
+    }
+
+    pub fn optional_string(&mut self, tlv_type: u64) -> Result<Option<String>> {
+        self.optional_bytes(tlv_type)
+            .map(|value| {
+                String::from_utf8(value.to_vec())
+                    .map_err(|_| anyhow!("TLV field {} is not UTF-8", tlv_type))
+            })
+            .transpose()
+    }
+
+    pub fn string(&mut self, tlv_type: u64) -> Result<String> {
+        self.optional_string(tlv_type)?
+            .ok_or_else(|| anyhow!("Missing TLV field {}", tlv_type))
+    }
+
+    pub fn u64(&mut self, tlv_type: u64) -> Result<u64> {
+        let value = self.bytes(tlv_type)?;
+        if value.len() > 8 || value.first() == Some(&0) {
+            return Err(anyhow!("TLV field {} is not a minimal tu64", tlv_type));
+        }
+        Ok(value.iter().fold(0, |n, b| (n << 8) | u64::from(*b)))
+    }
+
+    /// Fails on unknown even fields; unknown odd fields are ignored.
+    pub fn finish(self) -> Result<()> {
+        match self.records.keys().find(|t| *t % 2 == 0) {
+            Some(tlv_type) => Err(anyhow!("Unknown required TLV field {}", tlv_type)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// BOLT #1 BigSize: one byte below 0xfd, else a marker and a u16/u32/u64.
+pub fn write_bigsize(data: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => data.push(value as u8),
+        0xfd..=0xffff => {
+            data.push(0xfd);
+            data.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            data.push(0xfe);
+            data.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            data.push(0xff);
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Reads a BigSize, rejecting non-minimal encodings.
+pub fn read_bigsize(stream: &mut &[u8]) -> Result<u64> {
+    let (&marker, rest) = stream
+        .split_first()
+        .ok_or_else(|| anyhow!("BigSize is truncated"))?;
+    let (width, min) = match marker {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x10000),
+        0xff => (8, 0x1_0000_0000),
+        _ => {
+            *stream = rest;
+            return Ok(u64::from(marker));
+        }
+    };
+    if rest.len() < width {
+        return Err(anyhow!("BigSize is truncated"));
+    }
+
+    let value = rest[..width]
+        .iter()
+        .fold(0, |n, b| (n << 8) | u64::from(*b));
+    if value < min {
+        return Err(anyhow!("BigSize {} is not minimally encoded", value));
+    }
+    *stream = &rest[width..];
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_messages() -> Vec<P2PMessage> {
+        vec![
+            P2PMessage::ChannelOpen {
+                channel_id: "chan".to_string(),
+                funding_txid: "funding_1".to_string(),
+                capacity: 1_000_000,
+                initial_balance: 0,
+                funder_key: "02".repeat(33),
+                peer_node_id: "03".repeat(33),
+                commitment_type: CommitmentType::Anchors,
+                feerate_per_kw: 253,
+                limits: ChannelLimits::for_capacity(1_000_000),
+                signature: "ab".repeat(64),
+            },
+            P2PMessage::ChannelClose {
+                channel_id: "chan".to_string(),
+                final_balance_a: 600_000,
+                final_balance_b: 400_000,
+            },
+            P2PMessage::Payment {
+                channel_id: "chan".to_string(),
+                amount: 2_000,
+                sequence: 7,
+                commitment_tx: "{\"version\":2}".to_string(),
+                signature: "ab".repeat(64),
+                invoice_id: Some("invoice".to_string()),
+            },
+            P2PMessage::Payment {
+                channel_id: "chan".to_string(),
+                amount: u64::MAX,
+                sequence: 0,
+                commitment_tx: String::new(),
+                signature: "ab".repeat(64),
+                invoice_id: None,
+            },
+            P2PMessage::CommitmentSigned {
+                channel_id: "chan".to_string(),
+                signature: "cd".repeat(64),
+                sequence: 8,
+            },
+            P2PMessage::UpdateFee {
+                channel_id: "chan".to_string(),
+                feerate_per_kw: 253,
+                sequence: 9,
+            },
+            P2PMessage::ForceCloseRequest {
+                channel_id: "chan".to_string(),
+                funding_txid: "funding_1".to_string(),
+                peer_node_id: "02".repeat(33),
+                signature: "ab".repeat(64),
+            },
+            P2PMessage::ChannelReestablish {
+                channel_id: "chan".to_string(),
+                funding_txid: "funding_1".to_string(),
+                sequence: 300,
+                last_remote_sequence: 70_000,
+                my_balance: 5_000_000_000,
+                peer_balance: 1,
+            },
+            P2PMessage::UpdateAck {
+                channel_id: "chan".to_string(),
+                sequence: 12,
+            },
+            P2PMessage::Init {
+                protocol_version: 1,
+                features: 0b1001,
+                node_key: None,
+                signature: None,
+            },
+            P2PMessage::Init {
+                protocol_version: 1,
+                features: 0b1001,
+                node_key: Some("02".repeat(33)),
+                signature: Some("ef".repeat(64)),
+            },
+        ]
+    }
+
+    /// A message of `message_type` holding the given raw TLV records.
+    fn raw_message(message_type: u16, records: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut tlv = TlvWriter::new();
+        for (tlv_type, value) in records {
+            tlv.bytes(*tlv_type, value);
+        }
+        let mut data = vec![WIRE_VERSION];
+        data.extend_from_slice(&message_type.to_be_bytes());
+        data.extend_from_slice(&tlv.finish());
+        data
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for message in all_messages() {
+            let decoded = decode(&encode(&message)).unwrap();
+            assert_eq!(decoded, Some(message));
+        }
+    }
+
+    #[test]
+    fn encoding_is_smaller_than_json() {
+        for message in all_messages() {
+            assert!(encode(&message).len() < serde_json::to_vec(&message).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn bigsize_round_trips_at_every_width() {
+        for value in [
+            0,
+            0xfc,
+            0xfd,
+            0xffff,
+            0x10000,
+            0xffff_ffff,
+            0x1_0000_0000,
+            u64::MAX,
+        ] {
+            let mut data = Vec::new();
+            write_bigsize(&mut data, value);
+            let mut stream = data.as_slice();
+            assert_eq!(read_bigsize(&mut stream).unwrap(), value);
+            assert!(stream.is_empty());
+        }
+    }
+
+    #[test]
+    fn non_minimal_encodings_are_rejected() {
+        let mut stream: &[u8] = &[0xfd, 0x00, 0xfc];
+        assert!(read_bigsize(&mut stream).is_err());
+
+        let data = raw_message(UPDATE_ACK, &[(0, b"chan"), (2, &[0, 5])]);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn unknown_odd_field_is_skipped() {
+        let data = raw_message(UPDATE_ACK, &[(0, b"chan"), (2, &[5]), (3, b"future")]);
+        assert_eq!(
+            decode(&data).unwrap(),
+            Some(P2PMessage::UpdateAck {
+                channel_id: "chan".to_string(),
+                sequence: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_even_field_is_rejected() {
+        let data = raw_message(UPDATE_ACK, &[(0, b"chan"), (2, &[5]), (4, b"future")]);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn missing_required_field_is_rejected() {
+        let data = raw_message(UPDATE_ACK, &[(0, b"chan")]);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn unknown_message_types_follow_parity() {
+        assert_eq!(decode(&raw_message(101, &[(0, b"x")])).unwrap(), None);
+        assert!(decode(&raw_message(100, &[(0, b"x")])).is_err());
+    }
+
+    #[test]
+    fn out_of_order_and_truncated_streams_are_rejected() {
+        let mut data = raw_message(UPDATE_ACK, &[]);
+        data.extend_from_slice(&[2, 1, 5, 0, 4, b'c', b'h', b'a', b'n']);
+        assert!(decode(&data).is_err());
+
+        let mut data = encode(&all_messages()[0]);
+        data.pop();
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn signed_payload_covers_everything_but_the_signature() {
+        let payment = all_messages()[2].clone();
+        let mut resigned = payment.clone();
+        if let P2PMessage::Payment { signature, .. } = &mut resigned {
+            *signature = "ef".repeat(64);
+        }
+        assert_eq!(signed_payload(&payment), signed_payload(&resigned));
+
+        let mut changed = payment.clone();
+        if let P2PMessage::Payment { amount, .. } = &mut changed {
+            *amount += 1;
+        }
+        assert_ne!(signed_payload(&payment), signed_payload(&changed));
+    }
+
+    #[test]
+    fn received_payload_matches_the_local_one() {
+        for message in all_messages() {
+            let received = decode_signed(&encode(&message)).unwrap().unwrap();
+            assert_eq!(received.message, message);
+            assert_eq!(received.signed_payload, signed_payload(&message));
+        }
+    }
+
+    #[test]
+    fn received_payload_keeps_unknown_odd_fields() {
+        let signature = "ab".repeat(64);
+        let records: [(u64, &[u8]); 6] = [
+            (0, b"chan"),
+            (2, &[5]),
+            (4, &[7]),
+            (6, b"tx"),
+            (8, signature.as_bytes()),
+            (11, b"future"),
+        ];
+        let received = decode_signed(&raw_message(PAYMENT, &records))
+            .unwrap()
+            .unwrap();
+        let mut without_signature = records.to_vec();
+        without_signature.remove(4);
+        assert_eq!(
+            received.signed_payload,
+            raw_message(PAYMENT, &without_signature)
+        );
+        // The decoded struct alone would drop the field
+        assert_ne!(received.signed_payload, signed_payload(&received.message));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut data = encode(&all_messages()[0]);
+        data[0] = WIRE_VERSION + 1;
+        assert!(decode(&data).is_err());
+        assert!(decode(b"{\"UpdateAck\":{}}").is_err());
+    }
+}
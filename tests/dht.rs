@@ -0,0 +1,86 @@
+//! Nodes on loopback (mDNS off) find each other's records through the DHT alone.
+
+use libp2p::Multiaddr;
+use lightning_offline::crypto::KeyManager;
+use lightning_offline::p2p::{P2PHandle, P2PNode};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::LocalSet;
+
+/// A loopback port nothing listens on right now.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Starts a node with a fixed seed on `port`, bootstrapping from `bootstrap`.
+async fn start_node(seed: u8, port: u16, bootstrap: Vec<Multiaddr>) -> (String, P2PHandle) {
+    let key_manager = Arc::new(KeyManager::from_seed([seed; 32]).unwrap());
+    let node_id = key_manager.get_node_id();
+    let (inbound_sender, inbound_receiver) = mpsc::unbounded_channel();
+    let (outbound_sender, outbound_receiver) = mpsc::unbounded_channel();
+    let listen: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+
+    let mut node = P2PNode::new(key_manager, inbound_sender, outbound_receiver)
+        .await
+        .unwrap()
+        .with_mdns(false)
+        .with_listen_addresses(vec![listen])
+        .with_bootstrap_peers(bootstrap)
+        .unwrap();
+    let handle = node.handle();
+    tokio::task::spawn_local(async move {
+        // Keep the channel ends alive for as long as the node runs
+        let _channels = (inbound_receiver, outbound_sender);
+        node.start_listening().await.unwrap();
+    });
+    (node_id, handle)
+}
+
+#[tokio::test]
+async fn node_record_is_resolved_through_the_dht() {
+    LocalSet::new()
+        .run_until(async {
+            let hub_port = free_port();
+            let (_, hub) = start_node(1, hub_port, vec![]).await;
+            let hub_peer_id =
+                lightning_offline::p2p::p2p_keypair(&KeyManager::from_seed([1; 32]).unwrap())
+                    .unwrap()
+                    .public()
+                    .to_peer_id();
+            let bootstrap: Multiaddr =
+                format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", hub_port, hub_peer_id)
+                    .parse()
+                    .unwrap();
+
+            let alice_port = free_port();
+            let (alice_id, _alice) = start_node(2, alice_port, vec![bootstrap.clone()]).await;
+            let (_, bob) = start_node(3, free_port(), vec![bootstrap]).await;
+
+            // Alice publishes once her first DHT bootstrap completes
+            let record = tokio::time::timeout(Duration::from_secs(30), async {
+                loop {
+                    match bob.find_node(alice_id.clone()).await {
+                        Ok(record) => break record,
+                        Err(_) => tokio::time::sleep(Duration::from_millis(250)).await,
+                    }
+                }
+            })
+            .await
+            .expect("Alice's record never reached the DHT");
+
+            assert_eq!(record.node_id, alice_id);
+            assert!(
+                record
+                    .addresses
+                    .contains(&format!("/ip4/127.0.0.1/tcp/{}", alice_port))
+            );
+            assert!(hub.peers().await.unwrap().len() >= 2);
+        })
+        .await;
+}